@@ -1,31 +1,212 @@
-pub use remote_fs_protocol::{DirectoryEntry, RemoteMetadata};
-use remote_fs_protocol::{PROTOCOL_VERSION, PROTOCOL_VERSION_HEADER, RenameRequest, headers};
-use reqwest::header::{HeaderMap, HeaderValue};
+mod circuit_breaker;
+mod single_flight;
+
+use crate::{logging, metrics};
+use circuit_breaker::CircuitBreaker;
+use remote_fs_protocol::{
+    CopyRequest, CopyResult, ErrorBody, FallocateRequest, LinkRequest, MAX_CHECKSUMMED_READ,
+    MknodRequest, PROTOCOL_VERSION, PROTOCOL_VERSION_HEADER, RenameRequest, SeekQuery, SeekResult,
+    SymlinkRequest, TempWriteHandle, TempWriteRequest, error_codes, headers,
+};
+pub use remote_fs_protocol::{
+    DirectoryEntry, EntryType, FilesystemStats, NameEntry, RemoteMetadata, SeekWhence,
+};
+use reqwest::{
+    StatusCode,
+    header::{ETAG, HeaderMap, HeaderValue, IF_MATCH, IF_NONE_MATCH},
+};
+use serde::de::DeserializeOwned;
+use sha2::{Digest, Sha256};
+use single_flight::SingleFlight;
 use std::{
     fmt::Write,
     io,
-    path::Path,
-    sync::OnceLock,
-    time::{Duration, SystemTime, UNIX_EPOCH},
+    ops::ControlFlow,
+    path::{Path, PathBuf},
+    sync::{Arc, OnceLock},
+    time::{Duration, Instant, SystemTime, UNIX_EPOCH},
 };
-use tokio::io::AsyncReadExt;
+use tokio::{io::AsyncReadExt, sync::Semaphore};
 use tokio_util::io::ReaderStream;
 
 const CONNECT_TIMEOUT: Duration = Duration::from_secs(10);
-const REQUEST_TIMEOUT: Duration = Duration::from_secs(300);
+// Idle pooled connections are kept warm between bursts of FUSE operations and
+// probed so a silently dropped peer is noticed before the next request.
+const POOL_IDLE_TIMEOUT: Duration = Duration::from_secs(90);
+const TCP_KEEPALIVE: Duration = Duration::from_secs(30);
+const DEFAULT_REQUEST_TIMEOUT: Duration = Duration::from_secs(30);
+// Streaming a whole journaled file can legitimately outlast the per-request
+// timeout, so full-file uploads get at least this long.
+const UPLOAD_TIMEOUT: Duration = Duration::from_secs(300);
+const DEFAULT_RETRIES: u32 = 3;
+const DEFAULT_MAX_CONCURRENCY: usize = 64;
+// JSON bodies (listings and metadata) are buffered before decoding, so a
+// server cannot make the client allocate more than this for one response.
+const DEFAULT_MAX_RESPONSE_BYTES: usize = 64 * 1024 * 1024;
+const DEFAULT_RETRY_BASE: Duration = Duration::from_millis(100);
+// Keeps a long run of failures from sleeping longer than a typical request.
+const MAX_RETRY_DELAY: Duration = Duration::from_secs(10);
 const COMPARE_BUFFER_SIZE: usize = 4 * 1024 * 1024;
+// The server sends a keep-alive comment every 15 seconds, so a change feed
+// silent for longer than this has lost its connection.
+const CHANGE_FEED_IDLE: Duration = Duration::from_secs(60);
+// Replaces the per-request timeout for the long-lived change feed, which
+// is then reconnected.
+const CHANGE_FEED_SESSION: Duration = Duration::from_secs(24 * 60 * 60);
+
+/// One notification from the server's `GET /events` change feed.
+#[derive(Debug, PartialEq, Eq)]
+pub enum ChangeEvent {
+    // The entry at this absolute path was created, modified, or removed.
+    Changed(String),
+    // Changes may have been missed, so anything cached can be stale.
+    Reset,
+}
+
+/// Failure of one HTTP API call, classified at the transport boundary so the
+/// platform adapters never need to inspect `reqwest` internals. Errors are
+/// cheap to clone so one shared request can fail every caller waiting on it.
+#[derive(Clone, Debug)]
+pub enum ApiError {
+    Http(Arc<reqwest::Error>),
+    Status(StatusCode, ErrorDetail),
+    Decode(Arc<serde_json::Error>),
+    NotFound,
+    Timeout,
+    // `--verify-checksums` rejected a read: the body did not match the
+    // server's digest, or the server sent none.
+    Checksum(&'static str),
+    // A JSON body grew past the `--max-response-mb` limit, in bytes, and was
+    // abandoned before it was fully read.
+    TooLarge(usize),
+    // Not sent: the circuit breaker is open after repeated server failures.
+    Unavailable,
+}
+
+/// What the server said about a failed request. `code` is the `error` of a
+/// JSON error body; proxies and older servers answer in plain text, which
+/// becomes the message alone.
+#[derive(Clone, Debug, Default)]
+pub struct ErrorDetail {
+    pub code: Option<String>,
+    pub message: String,
+}
+
+impl ErrorDetail {
+    fn from_body(body: &str) -> Self {
+        match serde_json::from_str::<ErrorBody>(body) {
+            Ok(body) => ErrorDetail {
+                code: Some(body.error),
+                message: body.message,
+            },
+            Err(_) => ErrorDetail {
+                code: None,
+                message: body.trim().to_string(),
+            },
+        }
+    }
+}
+
+impl ApiError {
+    pub fn status(&self) -> Option<StatusCode> {
+        match self {
+            ApiError::Http(error) => error.status(),
+            ApiError::Status(status, _) => Some(*status),
+            ApiError::Decode(_)
+            | ApiError::Timeout
+            | ApiError::Checksum(_)
+            | ApiError::TooLarge(_)
+            | ApiError::Unavailable => None,
+            ApiError::NotFound => Some(StatusCode::NOT_FOUND),
+        }
+    }
+
+    // The server lacks the endpoint, as one older than this client does: it
+    // said so with `unknown_endpoint`, rejected the method, or implements
+    // nothing there. A path the endpoint could not find is `NotFound`.
+    pub fn is_unknown_endpoint(&self) -> bool {
+        match self {
+            ApiError::Status(status, detail) => {
+                matches!(
+                    *status,
+                    StatusCode::METHOD_NOT_ALLOWED | StatusCode::NOT_IMPLEMENTED
+                ) || detail.code.as_deref() == Some(error_codes::UNKNOWN_ENDPOINT)
+            }
+            _ => false,
+        }
+    }
+}
+
+impl std::fmt::Display for ApiError {
+    fn fmt(&self, formatter: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ApiError::Http(error) => error.fmt(formatter),
+            ApiError::Status(status, detail) => {
+                status.fmt(formatter)?;
+                if !detail.message.is_empty() {
+                    write!(formatter, ": {}", detail.message)?;
+                }
+                match &detail.code {
+                    Some(code) => write!(formatter, " ({code})"),
+                    None => Ok(()),
+                }
+            }
+            ApiError::Decode(error) => write!(formatter, "invalid server response: {error}"),
+            ApiError::NotFound => formatter.write_str("remote path not found"),
+            ApiError::Timeout => formatter.write_str("server did not respond in time"),
+            ApiError::Checksum(reason) => write!(formatter, "read failed verification: {reason}"),
+            ApiError::TooLarge(limit) => {
+                write!(formatter, "server response exceeds {limit} bytes")
+            }
+            ApiError::Unavailable => {
+                formatter.write_str("server is failing; requests are paused until it recovers")
+            }
+        }
+    }
+}
+
+impl std::error::Error for ApiError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            ApiError::Http(error) => Some(error.as_ref()),
+            ApiError::Decode(error) => Some(error.as_ref()),
+            ApiError::Status(..)
+            | ApiError::NotFound
+            | ApiError::Timeout
+            | ApiError::Checksum(_)
+            | ApiError::TooLarge(_)
+            | ApiError::Unavailable => None,
+        }
+    }
+}
+
+impl From<reqwest::Error> for ApiError {
+    fn from(error: reqwest::Error) -> Self {
+        if error.is_timeout() {
+            ApiError::Timeout
+        } else {
+            ApiError::Http(Arc::new(error))
+        }
+    }
+}
+
+impl From<serde_json::Error> for ApiError {
+    fn from(error: serde_json::Error) -> Self {
+        ApiError::Decode(Arc::new(error))
+    }
+}
 
 #[derive(Debug)]
 pub enum UploadError {
     Io(io::Error),
-    Http(reqwest::Error),
+    Api(ApiError),
 }
 
 impl UploadError {
-    pub fn status(&self) -> Option<reqwest::StatusCode> {
+    pub fn status(&self) -> Option<StatusCode> {
         match self {
             UploadError::Io(_) => None,
-            UploadError::Http(error) => error.status(),
+            UploadError::Api(error) => error.status(),
         }
     }
 }
@@ -34,7 +215,7 @@ impl std::fmt::Display for UploadError {
     fn fmt(&self, formatter: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         match self {
             UploadError::Io(error) => error.fmt(formatter),
-            UploadError::Http(error) => error.fmt(formatter),
+            UploadError::Api(error) => error.fmt(formatter),
         }
     }
 }
@@ -47,36 +228,356 @@ impl From<io::Error> for UploadError {
     }
 }
 
+impl From<ApiError> for UploadError {
+    fn from(error: ApiError) -> Self {
+        UploadError::Api(error)
+    }
+}
+
 impl From<reqwest::Error> for UploadError {
     fn from(error: reqwest::Error) -> Self {
-        UploadError::Http(error)
+        UploadError::Api(ApiError::from(error))
+    }
+}
+
+/// Transport settings chosen on the command line. They are applied once,
+/// before the first request, and shared by every API call afterwards.
+#[derive(Default, Clone)]
+pub struct HttpClientOptions {
+    /// Extra PEM root certificate to trust, e.g. for a self-signed server.
+    pub ca_cert: Option<PathBuf>,
+    /// Skip TLS certificate verification entirely.
+    pub insecure: bool,
+    /// Bearer token sent with every request; `REMOTE_FS_TOKEN` is used when unset.
+    pub auth_token: Option<String>,
+    /// Longest any single request may take; 30 seconds when unset.
+    pub request_timeout: Option<Duration>,
+    /// Extra attempts for an idempotent read that hit a transient failure.
+    pub retries: Option<u32>,
+    /// Delay before the first retry; later retries back off exponentially.
+    pub retry_base: Option<Duration>,
+    /// Log each request and its outcome under `logging::REQUEST_TRACE_TARGET`.
+    pub trace_requests: bool,
+    /// Ask for a SHA-256 with every file read and reject mismatched bodies.
+    pub verify_checksums: bool,
+    /// Ask the server to leave access times alone when reading files.
+    pub no_atime: bool,
+    /// Most requests awaiting a response at once; 64 when unset.
+    pub max_concurrency: Option<usize>,
+    /// `User-Agent` for every request; `DEFAULT_USER_AGENT` when unset.
+    pub user_agent: Option<String>,
+    /// Largest JSON response body accepted, in bytes; 64 MiB when unset.
+    pub max_response_bytes: Option<usize>,
+    /// Server directory mounted as `/`; the server's root when unset.
+    pub remote_root: Option<String>,
+    /// Consecutive failed requests that pause all requests; 5 when unset,
+    /// and 0 never pauses.
+    pub breaker_threshold: Option<u32>,
+    /// How long requests stay paused before one probes the server; 10
+    /// seconds when unset.
+    pub breaker_cooldown: Option<Duration>,
+}
+
+pub const DEFAULT_USER_AGENT: &str = concat!("remote-fs-client/", env!("CARGO_PKG_VERSION"));
+
+#[derive(Clone, Copy)]
+struct RetryPolicy {
+    retries: u32,
+    base: Duration,
+}
+
+static CLIENT: OnceLock<reqwest::Client> = OnceLock::new();
+static AUTH_TOKEN: OnceLock<Option<String>> = OnceLock::new();
+static REQUEST_TIMEOUT: OnceLock<Duration> = OnceLock::new();
+static RETRY_POLICY: OnceLock<RetryPolicy> = OnceLock::new();
+static TRACE_REQUESTS: OnceLock<bool> = OnceLock::new();
+static VERIFY_CHECKSUMS: OnceLock<bool> = OnceLock::new();
+static NO_ATIME: OnceLock<bool> = OnceLock::new();
+static IN_FLIGHT: OnceLock<Semaphore> = OnceLock::new();
+static MAX_RESPONSE_BYTES: OnceLock<usize> = OnceLock::new();
+static BREAKER: OnceLock<CircuitBreaker> = OnceLock::new();
+static STAT_FLIGHTS: OnceLock<SingleFlight<String, Result<DirectoryEntry, ApiError>>> =
+    OnceLock::new();
+// `--remote-root` without surrounding slashes; empty for the server's root.
+static REMOTE_ROOT: OnceLock<String> = OnceLock::new();
+
+pub fn configure_http_client(options: &HttpClientOptions) -> io::Result<()> {
+    let client = build_http_client(options)?;
+    CLIENT
+        .set(client)
+        .map_err(|_| io::Error::other("HTTP client is already configured"))?;
+    if let Some(timeout) = options.request_timeout {
+        let _ = REQUEST_TIMEOUT.set(timeout);
     }
+    let _ = TRACE_REQUESTS.set(options.trace_requests);
+    let _ = VERIFY_CHECKSUMS.set(options.verify_checksums);
+    let _ = NO_ATIME.set(options.no_atime);
+    let _ = IN_FLIGHT.set(Semaphore::new(
+        options.max_concurrency.unwrap_or(DEFAULT_MAX_CONCURRENCY),
+    ));
+    let _ = MAX_RESPONSE_BYTES.set(
+        options
+            .max_response_bytes
+            .unwrap_or(DEFAULT_MAX_RESPONSE_BYTES),
+    );
+    let _ = RETRY_POLICY.set(RetryPolicy {
+        retries: options.retries.unwrap_or(DEFAULT_RETRIES),
+        base: options.retry_base.unwrap_or(DEFAULT_RETRY_BASE),
+    });
+    let _ = BREAKER.set(CircuitBreaker::new(
+        options
+            .breaker_threshold
+            .unwrap_or(circuit_breaker::DEFAULT_FAILURE_THRESHOLD),
+        options
+            .breaker_cooldown
+            .unwrap_or(circuit_breaker::DEFAULT_COOLDOWN),
+    ));
+    if let Some(root) = &options.remote_root {
+        let _ = REMOTE_ROOT.set(root.trim_matches('/').to_string());
+    }
+    if let Some(token) = &options.auth_token {
+        AUTH_TOKEN
+            .set(non_empty_token(token))
+            .map_err(|_| io::Error::other("auth token is already configured"))?;
+    }
+    Ok(())
+}
+
+fn build_http_client(options: &HttpClientOptions) -> io::Result<reqwest::Client> {
+    let mut default_headers = HeaderMap::new();
+    default_headers.insert(
+        PROTOCOL_VERSION_HEADER,
+        HeaderValue::from_static(PROTOCOL_VERSION),
+    );
+    let mut builder = reqwest::Client::builder()
+        .connect_timeout(CONNECT_TIMEOUT)
+        .pool_idle_timeout(POOL_IDLE_TIMEOUT)
+        .tcp_keepalive(TCP_KEEPALIVE)
+        .timeout(options.request_timeout.unwrap_or(DEFAULT_REQUEST_TIMEOUT))
+        .user_agent(options.user_agent.as_deref().unwrap_or(DEFAULT_USER_AGENT))
+        .default_headers(default_headers);
+
+    if let Some(path) = &options.ca_cert {
+        let pem = std::fs::read(path).map_err(|error| {
+            io::Error::new(
+                error.kind(),
+                format!("could not read CA certificate {}: {error}", path.display()),
+            )
+        })?;
+        let certificate = reqwest::Certificate::from_pem(&pem).map_err(|error| {
+            io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("invalid CA certificate {}: {error}", path.display()),
+            )
+        })?;
+        builder = builder.add_root_certificate(certificate);
+    }
+    if options.insecure {
+        log::warn!("TLS certificate verification is disabled.");
+        builder = builder.danger_accept_invalid_certs(true);
+    }
+
+    builder.build().map_err(io::Error::other)
 }
 
+// Every API call goes through this one client, so lookups, listings, and
+// reads share its connection pool instead of reconnecting per request.
 fn http_client() -> &'static reqwest::Client {
-    static CLIENT: OnceLock<reqwest::Client> = OnceLock::new();
     CLIENT.get_or_init(|| {
-        let mut default_headers = HeaderMap::new();
-        default_headers.insert(
-            PROTOCOL_VERSION_HEADER,
-            HeaderValue::from_static(PROTOCOL_VERSION),
-        );
-        reqwest::Client::builder()
-            .connect_timeout(CONNECT_TIMEOUT)
-            .timeout(REQUEST_TIMEOUT)
-            .default_headers(default_headers)
-            .build()
-            .expect("failed to build HTTP client")
+        build_http_client(&HttpClientOptions::default()).expect("failed to build HTTP client")
     })
 }
 
+fn upload_timeout() -> Duration {
+    REQUEST_TIMEOUT
+        .get()
+        .copied()
+        .unwrap_or(DEFAULT_REQUEST_TIMEOUT)
+        .max(UPLOAD_TIMEOUT)
+}
+
+fn non_empty_token(token: &str) -> Option<String> {
+    let token = token.trim();
+    (!token.is_empty()).then(|| token.to_string())
+}
+
 fn authenticated(request: reqwest::RequestBuilder) -> reqwest::RequestBuilder {
-    match std::env::var("REMOTE_FS_TOKEN") {
-        Ok(token) if !token.trim().is_empty() => request.bearer_auth(token.trim()),
-        _ => request,
+    let token = AUTH_TOKEN.get_or_init(|| {
+        std::env::var("REMOTE_FS_TOKEN")
+            .ok()
+            .and_then(|token| non_empty_token(&token))
+    });
+    match token {
+        Some(token) => request.bearer_auth(token),
+        None => request,
     }
 }
 
+fn circuit_breaker() -> &'static CircuitBreaker {
+    BREAKER.get_or_init(|| {
+        CircuitBreaker::new(
+            circuit_breaker::DEFAULT_FAILURE_THRESHOLD,
+            circuit_breaker::DEFAULT_COOLDOWN,
+        )
+    })
+}
+
+fn retry_policy() -> RetryPolicy {
+    *RETRY_POLICY.get_or_init(|| RetryPolicy {
+        retries: DEFAULT_RETRIES,
+        base: DEFAULT_RETRY_BASE,
+    })
+}
+
+// Every request is sent through `send_timed`, which records its latency in
+// the client metrics under a `METHOD /endpoint` label and, with
+// `--trace-requests`, logs it. Retried reads log one line per attempt.
+// Each attempt holds an in-flight permit until its response headers arrive,
+// so `--max-concurrency` bounds what the server sees at once; the wait for a
+// permit is not counted as latency. While the circuit breaker is open,
+// requests fail with `ApiError::Unavailable` without being sent.
+trait SendTimed {
+    async fn send_timed(self, endpoint: &'static str) -> Result<reqwest::Response, ApiError>;
+}
+
+impl SendTimed for reqwest::RequestBuilder {
+    async fn send_timed(self, endpoint: &'static str) -> Result<reqwest::Response, ApiError> {
+        let _permit = IN_FLIGHT
+            .get_or_init(|| Semaphore::new(DEFAULT_MAX_CONCURRENCY))
+            .acquire()
+            .await
+            .expect("the in-flight semaphore is never closed");
+        let request_id = uuid::Uuid::new_v4().to_string();
+        let (client, request) = self.header(headers::REQUEST_ID, &request_id).build_split();
+        let request = request?;
+        let (method, url) = (request.method().clone(), request.url().clone());
+        if !circuit_breaker().allow(Instant::now()) {
+            return Err(ApiError::Unavailable);
+        }
+        let started = Instant::now();
+        let result = client.execute(request).await;
+        let elapsed = started.elapsed();
+        metrics::global().record_request(endpoint, elapsed);
+        circuit_breaker().record(
+            result.as_ref().ok().map(reqwest::Response::status),
+            Instant::now(),
+        );
+
+        // The line is always available at debug level; `--trace-requests`
+        // raises it to info.
+        let level = if TRACE_REQUESTS.get().copied().unwrap_or(false) {
+            log::Level::Info
+        } else {
+            log::Level::Debug
+        };
+        match &result {
+            Ok(response) => log::log!(
+                target: logging::REQUEST_TRACE_TARGET,
+                level,
+                "{method} {url} -> {} in {elapsed:?} [{request_id}]",
+                response.status()
+            ),
+            Err(error) => log::log!(
+                target: logging::REQUEST_TRACE_TARGET,
+                level,
+                "{method} {url} -> failed in {elapsed:?} [{request_id}]: {error}"
+            ),
+        }
+        Ok(result?)
+    }
+}
+
+// Sends a request that is safe to repeat, retrying connection failures and
+// `5xx`/`429` replies with jittered exponential backoff. Any other status is
+// returned on the first attempt for `check_status` to classify. Each attempt
+// is timed separately.
+async fn send_idempotent(
+    endpoint: &'static str,
+    request: reqwest::RequestBuilder,
+) -> Result<reqwest::Response, ApiError> {
+    let policy = retry_policy();
+    let mut attempt = 0;
+    loop {
+        let Some(this_attempt) = request.try_clone() else {
+            return request.send_timed(endpoint).await;
+        };
+        let retryable = match this_attempt.send_timed(endpoint).await {
+            Ok(response) if attempt < policy.retries && is_retryable_status(response.status()) => {
+                format!("status {}", response.status())
+            }
+            Err(ApiError::Http(error)) if attempt < policy.retries && error.is_connect() => {
+                error.to_string()
+            }
+            result => return result,
+        };
+
+        let delay = retry_delay(policy.base, attempt, random_fraction());
+        log::debug!(
+            "Retrying request in {delay:?} after {retryable} (attempt {} of {})",
+            attempt + 1,
+            policy.retries
+        );
+        tokio::time::sleep(delay).await;
+        attempt += 1;
+    }
+}
+
+fn is_retryable_status(status: StatusCode) -> bool {
+    status.is_server_error() || status == StatusCode::TOO_MANY_REQUESTS
+}
+
+// Half of the exponential step is fixed and half is jitter, so clients that
+// failed together do not retry in lockstep.
+fn retry_delay(base: Duration, attempt: u32, jitter: f64) -> Duration {
+    let step = base
+        .saturating_mul(1_u32.checked_shl(attempt).unwrap_or(u32::MAX))
+        .min(MAX_RETRY_DELAY);
+    step / 2 + (step / 2).mul_f64(jitter.clamp(0.0, 1.0))
+}
+
+fn random_fraction() -> f64 {
+    use std::hash::{BuildHasher, Hasher};
+
+    let bits = std::collections::hash_map::RandomState::new()
+        .build_hasher()
+        .finish();
+    (bits >> 11) as f64 / (1_u64 << 53) as f64
+}
+
+// Every path is sent to the server below `--remote-root`, so the mount's `/`
+// is that directory and nothing above it can be named.
+fn server_path(path: &str) -> String {
+    below_root(remote_root(), path)
+}
+
+pub fn remote_root() -> &'static str {
+    REMOTE_ROOT.get().map_or("", String::as_str)
+}
+
+fn below_root(root: &str, path: &str) -> String {
+    let path = path.trim_start_matches('/');
+    match (root, path) {
+        ("", _) => path.to_string(),
+        (_, "") => root.to_string(),
+        _ => format!("{root}/{path}"),
+    }
+}
+
+// The mount path of a server path, or `None` outside `--remote-root`.
+fn mount_path(root: &str, path: &str) -> Option<String> {
+    let path = path.trim_start_matches('/');
+    let relative = if root.is_empty() {
+        path
+    } else {
+        let rest = path.strip_prefix(root)?;
+        if !rest.is_empty() && !rest.starts_with('/') {
+            return None;
+        }
+        rest.trim_start_matches('/')
+    };
+    Some(format!("/{relative}"))
+}
+
 fn encode_api_path(path: &str) -> String {
     let normalized = path.trim_matches('/');
     let mut encoded = String::with_capacity(normalized.len());
@@ -103,10 +604,60 @@ fn endpoint_url(base_url: &str, endpoint: &str, path: &str) -> String {
         "{}/{}/{}",
         base_url.trim_end_matches('/'),
         endpoint,
-        encode_api_path(path)
+        encode_api_path(&server_path(path))
     )
 }
 
+// Converts a non-success status into `ApiError`, keeping the server's
+// explanation for logs.
+async fn check_status(response: reqwest::Response) -> Result<reqwest::Response, ApiError> {
+    let status = response.status();
+    if status.is_success() {
+        return Ok(response);
+    }
+    let body = limited_body(response).await.unwrap_or_default();
+    let detail = ErrorDetail::from_body(&String::from_utf8_lossy(&body));
+    // A route the server lacks keeps its code, so callers can fall back.
+    if status == StatusCode::NOT_FOUND
+        && detail.code.as_deref() != Some(error_codes::UNKNOWN_ENDPOINT)
+    {
+        return Err(ApiError::NotFound);
+    }
+    Err(ApiError::Status(status, detail))
+}
+
+async fn json_body<T: DeserializeOwned>(response: reqwest::Response) -> Result<T, ApiError> {
+    let bytes = limited_body(check_status(response).await?).await?;
+    Ok(serde_json::from_slice(&bytes)?)
+}
+
+// Reads the body chunk by chunk so an oversized response is abandoned as soon
+// as it crosses `--max-response-mb` instead of after it has been buffered.
+async fn limited_body(mut response: reqwest::Response) -> Result<Vec<u8>, ApiError> {
+    let limit = *MAX_RESPONSE_BYTES
+        .get()
+        .unwrap_or(&DEFAULT_MAX_RESPONSE_BYTES);
+    if response
+        .content_length()
+        .is_some_and(|length| length > limit as u64)
+    {
+        return Err(ApiError::TooLarge(limit));
+    }
+    let mut bytes = Vec::new();
+    while let Some(chunk) = response.chunk().await? {
+        append_limited(&mut bytes, &chunk, limit)?;
+    }
+    Ok(bytes)
+}
+
+fn append_limited(bytes: &mut Vec<u8>, chunk: &[u8], limit: usize) -> Result<(), ApiError> {
+    if bytes.len() + chunk.len() > limit {
+        return Err(ApiError::TooLarge(limit));
+    }
+    bytes.extend_from_slice(chunk);
+    Ok(())
+}
+
 fn unix_seconds_from_system_time(time: SystemTime) -> u64 {
     time.duration_since(UNIX_EPOCH)
         .unwrap_or_else(|_| Duration::from_secs(0))
@@ -139,46 +690,286 @@ fn add_optional_metadata_headers(
     request
 }
 
-pub async fn list_directory(
-    base_url: &str,
-    path: &str,
-) -> Result<Vec<DirectoryEntry>, reqwest::Error> {
+pub async fn list_directory(base_url: &str, path: &str) -> Result<Vec<DirectoryEntry>, ApiError> {
     let request_url = endpoint_url(base_url, "list", path);
 
     log::debug!("Requesting directory list from URL: {}", request_url);
 
-    let response = authenticated(http_client().get(&request_url))
-        .send()
-        .await?;
+    let response =
+        send_idempotent("GET /list", authenticated(http_client().get(&request_url))).await?;
     log::debug!("Received response: {:?}", response.status());
 
-    let entries = response
-        .error_for_status()?
-        .json::<Vec<DirectoryEntry>>()
-        .await?;
-    Ok(entries)
+    json_body(response).await
 }
 
-pub async fn get_metadata(base_url: &str, path: &str) -> Result<RemoteMetadata, reqwest::Error> {
+/// One page of a directory listing. `next_cursor` is `None` on the last page,
+/// and always for servers that ignore pagination and return everything.
+/// `etag` validates the page for a later conditional request.
+pub struct DirectoryPage {
+    pub entries: Vec<DirectoryEntry>,
+    pub next_cursor: Option<String>,
+    pub etag: Option<String>,
+}
+
+/// Returns `None` when the server confirms the page tagged `if_none_match`
+/// is unchanged.
+pub async fn list_directory_page(
+    base_url: &str,
+    path: &str,
+    cursor: Option<&str>,
+    limit: usize,
+    if_none_match: Option<&str>,
+) -> Result<Option<DirectoryPage>, ApiError> {
+    let request_url = endpoint_url(base_url, "list", path);
+
+    log::debug!(
+        "Requesting directory page from URL: {} (cursor={:?}, limit={})",
+        request_url,
+        cursor,
+        limit
+    );
+
+    let mut request = authenticated(http_client().get(&request_url)).query(&[("limit", limit)]);
+    if let Some(cursor) = cursor {
+        request = request.query(&[("cursor", cursor)]);
+    }
+    if let Some(etag) = if_none_match {
+        request = request.header(IF_NONE_MATCH, etag);
+    }
+    let response = send_idempotent("GET /list", request).await?;
+    // The page named by `if_none_match` is still current.
+    if if_none_match.is_some() && response.status() == StatusCode::NOT_MODIFIED {
+        log::debug!("Directory page from {} is unchanged", request_url);
+        return Ok(None);
+    }
+    let response = check_status(response).await?;
+    let header = |name| {
+        response
+            .headers()
+            .get(name)
+            .and_then(|value| value.to_str().ok())
+            .map(str::to_string)
+    };
+    let next_cursor = header(headers::NEXT_CURSOR);
+    let etag = header(ETAG.as_str());
+    let bytes = limited_body(response).await?;
+
+    Ok(Some(DirectoryPage {
+        entries: serde_json::from_slice(&bytes)?,
+        next_cursor,
+        etag,
+    }))
+}
+
+/// One page of a `GET /names` listing, paged like `DirectoryPage`.
+pub struct NamesPage {
+    pub entries: Vec<NameEntry>,
+    pub next_cursor: Option<String>,
+}
+
+// Names and types only, for readdirs that report no other attributes.
+pub async fn list_names_page(
+    base_url: &str,
+    path: &str,
+    cursor: Option<&str>,
+    limit: usize,
+) -> Result<NamesPage, ApiError> {
+    let request_url = endpoint_url(base_url, "names", path);
+
+    log::debug!(
+        "Requesting names from URL: {} (cursor={:?}, limit={})",
+        request_url,
+        cursor,
+        limit
+    );
+
+    let mut request = authenticated(http_client().get(&request_url)).query(&[("limit", limit)]);
+    if let Some(cursor) = cursor {
+        request = request.query(&[("cursor", cursor)]);
+    }
+    let response = check_status(send_idempotent("GET /names", request).await?).await?;
+    let next_cursor = response
+        .headers()
+        .get(headers::NEXT_CURSOR)
+        .and_then(|value| value.to_str().ok())
+        .map(str::to_string);
+    Ok(NamesPage {
+        entries: json_body(response).await?,
+        next_cursor,
+    })
+}
+
+// Lists `depth` levels of the subtree in one request. Descendants carry their
+// path relative to `path` in `name`, e.g. `docs/a.txt`.
+pub async fn list_tree(
+    base_url: &str,
+    path: &str,
+    depth: u32,
+) -> Result<Vec<DirectoryEntry>, ApiError> {
+    let request_url = endpoint_url(base_url, "list", path);
+
+    log::debug!(
+        "Requesting directory tree from URL: {} (depth={})",
+        request_url,
+        depth
+    );
+
+    let response = send_idempotent(
+        "GET /list",
+        authenticated(http_client().get(&request_url)).query(&[("depth", depth)]),
+    )
+    .await?;
+    json_body(response).await
+}
+
+pub async fn get_metadata(base_url: &str, path: &str) -> Result<RemoteMetadata, ApiError> {
     let request_url = endpoint_url(base_url, "metadata", path);
 
     log::debug!("Requesting metadata from URL: {}", request_url);
 
-    authenticated(http_client().get(&request_url))
-        .send()
-        .await?
-        .error_for_status()?
-        .json::<RemoteMetadata>()
+    let response = send_idempotent(
+        "GET /metadata",
+        authenticated(http_client().get(&request_url)),
+    )
+    .await?;
+    json_body(response).await
+}
+
+// Concurrent stats of one path, as parallel traversals of a fresh listing
+// make, share a single request.
+pub async fn stat_entry(base_url: &str, path: &str) -> Result<DirectoryEntry, ApiError> {
+    let request_url = endpoint_url(base_url, "stat", path);
+
+    let stat = async {
+        log::debug!("Requesting entry from URL: {}", request_url);
+        let response =
+            send_idempotent("GET /stat", authenticated(http_client().get(&request_url))).await?;
+        json_body(response).await
+    };
+    STAT_FLIGHTS
+        .get_or_init(SingleFlight::new)
+        .run(request_url.clone(), stat)
         .await
 }
 
+// Pings the server's unauthenticated liveness probe.
+pub async fn check_health(base_url: &str) -> Result<(), ApiError> {
+    let request_url = format!("{}/healthz", base_url.trim_end_matches('/'));
+
+    log::debug!("Checking server health at URL: {}", request_url);
+
+    let response = send_idempotent("GET /healthz", http_client().get(&request_url)).await?;
+    check_status(response).await?;
+    Ok(())
+}
+
+// Follows the server's change feed until it ends, fails, or `on_event`
+// breaks. A `Reset` is reported first on every connection, since changes
+// made while disconnected were not seen.
+pub async fn watch_changes(
+    base_url: &str,
+    mut on_event: impl FnMut(ChangeEvent) -> ControlFlow<()>,
+) -> Result<(), ApiError> {
+    let request_url = format!("{}/events", base_url.trim_end_matches('/'));
+
+    log::debug!("Following server changes at URL: {}", request_url);
+
+    let response = authenticated(http_client().get(&request_url))
+        .timeout(CHANGE_FEED_SESSION)
+        .send_timed("GET /events")
+        .await?;
+    let mut response = check_status(response).await?;
+    if on_event(ChangeEvent::Reset).is_break() {
+        return Ok(());
+    }
+
+    let limit = *MAX_RESPONSE_BYTES
+        .get()
+        .unwrap_or(&DEFAULT_MAX_RESPONSE_BYTES);
+    let mut pending = Vec::new();
+    loop {
+        let chunk = match tokio::time::timeout(CHANGE_FEED_IDLE, response.chunk()).await {
+            Ok(chunk) => chunk?,
+            Err(_) => return Err(ApiError::Timeout),
+        };
+        let Some(chunk) = chunk else {
+            return Ok(());
+        };
+        append_limited(&mut pending, &chunk, limit)?;
+        let root = remote_root();
+        for event in drain_change_events(&mut pending) {
+            let event = match event {
+                ChangeEvent::Changed(path) => match mount_path(root, &path) {
+                    Some(path) => ChangeEvent::Changed(path),
+                    None => continue,
+                },
+                ChangeEvent::Reset => ChangeEvent::Reset,
+            };
+            if on_event(event).is_break() {
+                return Ok(());
+            }
+        }
+    }
+}
+
+// Splits the complete, blank-line-terminated Server-Sent Events off the
+// front of `pending`. Keep-alive comments and unknown event types are skipped.
+fn drain_change_events(pending: &mut Vec<u8>) -> Vec<ChangeEvent> {
+    let mut events = Vec::new();
+    while let Some(end) = pending.windows(2).position(|window| window == b"\n\n") {
+        let block: Vec<u8> = pending.drain(..end + 2).collect();
+        let block = String::from_utf8_lossy(&block);
+        let (mut kind, mut data) = ("message", Vec::new());
+        for line in block.lines() {
+            if let Some(value) = line.strip_prefix("event:") {
+                kind = value.trim_start();
+            } else if let Some(value) = line.strip_prefix("data:") {
+                data.push(value.strip_prefix(' ').unwrap_or(value));
+            }
+        }
+        match kind {
+            "changed" => events.push(ChangeEvent::Changed(data.join("\n"))),
+            "reset" => events.push(ChangeEvent::Reset),
+            _ => {}
+        }
+    }
+    events
+}
+
+pub async fn get_statfs(base_url: &str) -> Result<FilesystemStats, ApiError> {
+    let request_url = format!("{}/statfs", base_url.trim_end_matches('/'));
+
+    log::debug!("Requesting filesystem statistics from URL: {}", request_url);
+
+    let response = send_idempotent(
+        "GET /statfs",
+        authenticated(http_client().get(&request_url)),
+    )
+    .await?;
+    json_body(response).await
+}
+
 // Requests only one byte range from the server instead of downloading the whole file.
 pub async fn read_file(
     base_url: &str,
     path: &str,
     offset: u64,
     size: u32,
-) -> Result<Vec<u8>, reqwest::Error> {
+) -> Result<Vec<u8>, ApiError> {
+    read_range(base_url, path, offset, size, None)
+        .await
+        .map(|(bytes, _)| bytes)
+}
+
+// Reads one byte range and returns the file's ETag with it. With `if_match`
+// the server refuses with 412 if the file is no longer that version.
+async fn read_range(
+    base_url: &str,
+    path: &str,
+    offset: u64,
+    size: u32,
+    if_match: Option<&str>,
+) -> Result<(Vec<u8>, Option<String>), ApiError> {
     let request_url = endpoint_url(base_url, "files", path);
 
     log::debug!(
@@ -189,22 +980,148 @@ pub async fn read_file(
     );
 
     let client = http_client();
-    let response = authenticated(client.get(&request_url))
+    let verify = VERIFY_CHECKSUMS.get().copied().unwrap_or(false);
+    let mut request = authenticated(client.get(&request_url))
         .header(headers::FILE_OFFSET, offset.to_string())
-        .header(headers::FILE_SIZE, size.to_string())
-        .send()
-        .await?
-        .error_for_status()?;
-    let bytes = response.bytes().await?;
+        .header(headers::FILE_SIZE, size.to_string());
+    if verify {
+        request = request.header(headers::WANT_CONTENT_SHA256, "1");
+    }
+    if NO_ATIME.get().copied().unwrap_or(false) {
+        request = request.header(headers::NO_ATIME, "1");
+    }
+    if let Some(etag) = if_match {
+        request = request.header(IF_MATCH, etag);
+    }
+    let mut response = check_status(send_idempotent("GET /files", request).await?).await?;
+    let etag = response
+        .headers()
+        .get(ETAG)
+        .and_then(|value| value.to_str().ok())
+        .map(str::to_string);
+    let checksum = response
+        .headers()
+        .get(headers::CONTENT_SHA256)
+        .and_then(|value| value.to_str().ok())
+        .map(str::to_ascii_lowercase);
+
+    // Never hand the kernel more than it asked for, even if a server ignores
+    // the size header; short replies already mean EOF. The body is streamed
+    // and only the window is kept, so such a server cannot make the client
+    // buffer the rest of a large file. Unverified reads stop once the window
+    // is full; verified ones must hash the body to its end.
+    let window = size as usize;
+    let expected_len = response
+        .content_length()
+        .map_or(window, |len| window.min(len as usize));
+    let mut bytes = Vec::with_capacity(expected_len);
+    let mut hasher = verify.then(Sha256::new);
+    while let Some(chunk) = response.chunk().await? {
+        if let Some(hasher) = &mut hasher {
+            hasher.update(&chunk);
+        }
+        let wanted = window - bytes.len();
+        bytes.extend_from_slice(&chunk[..chunk.len().min(wanted)]);
+        if hasher.is_none() && bytes.len() == window {
+            break;
+        }
+    }
+    if let Some(hasher) = hasher {
+        verify_checksum(&hasher.finalize(), checksum.as_deref(), size).inspect_err(|error| {
+            log::error!("Read of {request_url} at offset {offset}: {error}");
+        })?;
+    }
+    Ok((bytes, etag))
+}
 
-    Ok(bytes.to_vec())
+// Times a chunked read starts over because the file changed between chunks.
+const TORN_READ_ATTEMPTS: usize = 3;
+
+// Reads `size` bytes at `offset` in server requests of at most `chunk_size`
+// bytes, so no one response is larger; a short chunk is the end of the file.
+// The buffer grows with what arrives instead of being sized for the request.
+// Later chunks must match the first chunk's ETag, so a file rewritten midway
+// is read again from the start rather than returned half old and half new.
+pub async fn read_file_chunked(
+    base_url: &str,
+    path: &str,
+    offset: u64,
+    size: u32,
+    chunk_size: u32,
+) -> Result<Vec<u8>, ApiError> {
+    if size <= chunk_size {
+        return read_file(base_url, path, offset, size).await;
+    }
+    let mut attempt = 1;
+    loop {
+        match read_chunks(base_url, path, offset, size, chunk_size).await {
+            Err(ApiError::Status(status, _))
+                if status == StatusCode::PRECONDITION_FAILED && attempt < TORN_READ_ATTEMPTS =>
+            {
+                log::debug!(
+                    "{path} changed during a chunked read at offset {offset}, reading again"
+                );
+                attempt += 1;
+            }
+            result => return result,
+        }
+    }
+}
+
+async fn read_chunks(
+    base_url: &str,
+    path: &str,
+    offset: u64,
+    size: u32,
+    chunk_size: u32,
+) -> Result<Vec<u8>, ApiError> {
+    let mut bytes = Vec::new();
+    let mut first_etag: Option<String> = None;
+    while bytes.len() < size as usize {
+        let wanted = (size - bytes.len() as u32).min(chunk_size);
+        let (chunk, etag) = read_range(
+            base_url,
+            path,
+            offset + bytes.len() as u64,
+            wanted,
+            first_etag.as_deref(),
+        )
+        .await?;
+        if bytes.is_empty() {
+            first_etag = etag;
+        }
+        let end_of_file = chunk.len() < wanted as usize;
+        bytes.extend_from_slice(&chunk);
+        if end_of_file {
+            break;
+        }
+    }
+    Ok(bytes)
+}
+
+// Compares the digest of a read body with the server's `X-Content-SHA256`.
+// The server streams reads larger than it hashes without one, so only a read
+// of `requested` bytes that could fit must carry it.
+fn verify_checksum(digest: &[u8], expected: Option<&str>, requested: u32) -> Result<(), ApiError> {
+    let Some(expected) = expected else {
+        if u64::from(requested) > MAX_CHECKSUMMED_READ {
+            return Ok(());
+        }
+        return Err(ApiError::Checksum("server sent no checksum"));
+    };
+    let actual: String = digest.iter().map(|byte| format!("{byte:02x}")).collect();
+    if actual == expected {
+        Ok(())
+    } else {
+        Err(ApiError::Checksum("checksum mismatch"))
+    }
 }
 
 pub async fn create_directory(
     base_url: &str,
     path: &str,
     mode: u32,
-) -> Result<RemoteMetadata, reqwest::Error> {
+) -> Result<RemoteMetadata, ApiError> {
     let request_url = endpoint_url(base_url, "mkdir", path);
     log::debug!("Requesting directory creation: POST {}", request_url);
 
@@ -216,10 +1133,195 @@ pub async fn create_directory(
         None,
         None,
     )
-    .send()
+    .send_timed("POST /mkdir")
     .await?;
 
-    response.error_for_status()?.json::<RemoteMetadata>().await
+    json_body(response).await
+}
+
+pub async fn read_link(base_url: &str, path: &str) -> Result<String, ApiError> {
+    let request_url = endpoint_url(base_url, "readlink", path);
+    log::debug!("Requesting symlink target: GET {}", request_url);
+
+    let response = send_idempotent(
+        "GET /readlink",
+        authenticated(http_client().get(&request_url)),
+    )
+    .await?;
+    let target = limited_body(check_status(response).await?).await?;
+    Ok(String::from_utf8_lossy(&target).into_owned())
+}
+
+pub async fn create_symlink(
+    base_url: &str,
+    path: &str,
+    target: &str,
+) -> Result<RemoteMetadata, ApiError> {
+    let request_url = format!("{}/symlink", base_url.trim_end_matches('/'));
+    log::debug!(
+        "Requesting symlink creation: POST {} ({} -> {})",
+        request_url,
+        path,
+        target
+    );
+
+    let response = authenticated(http_client().post(&request_url))
+        .json(&SymlinkRequest {
+            path: server_path(path),
+            target: target.to_string(),
+        })
+        .send_timed("POST /symlink")
+        .await?;
+    json_body(response).await
+}
+
+// Makes `to` another name for the file at `from`.
+pub async fn link_file(base_url: &str, from: &str, to: &str) -> Result<RemoteMetadata, ApiError> {
+    let request_url = format!("{}/link", base_url.trim_end_matches('/'));
+    log::debug!(
+        "Requesting hard link: POST {} ({} -> {})",
+        request_url,
+        to,
+        from
+    );
+
+    let response = authenticated(http_client().post(&request_url))
+        .json(&LinkRequest {
+            from: server_path(from),
+            to: server_path(to),
+        })
+        .send_timed("POST /link")
+        .await?;
+    json_body(response).await
+}
+
+// Copies a byte range between two files without the data leaving the
+// server. Returns the bytes copied, which is short at the source's EOF.
+pub async fn copy_range(
+    base_url: &str,
+    from: &str,
+    from_offset: u64,
+    to: &str,
+    to_offset: u64,
+    length: u64,
+) -> Result<u64, ApiError> {
+    let request_url = format!("{}/copy", base_url.trim_end_matches('/'));
+    log::debug!(
+        "Requesting server-side copy: POST {} ({}@{} -> {}@{}, {} bytes)",
+        request_url,
+        from,
+        from_offset,
+        to,
+        to_offset,
+        length
+    );
+
+    // Copying is bounded by server disk speed, like a full-file upload.
+    let response = authenticated(http_client().post(&request_url))
+        .timeout(upload_timeout())
+        .json(&CopyRequest {
+            from: server_path(from),
+            to: server_path(to),
+            from_offset,
+            to_offset,
+            length,
+        })
+        .send_timed("POST /copy")
+        .await?;
+    let result: CopyResult = json_body(response).await?;
+    Ok(result.copied)
+}
+
+// `mode` includes the `S_IFMT` type bits; the server accepts regular files
+// and FIFOs.
+pub async fn make_node(
+    base_url: &str,
+    path: &str,
+    mode: u32,
+    rdev: u64,
+) -> Result<RemoteMetadata, ApiError> {
+    let request_url = format!("{}/mknod", base_url.trim_end_matches('/'));
+    log::debug!(
+        "Requesting node creation: POST {} ({}, mode={:o})",
+        request_url,
+        path,
+        mode
+    );
+
+    let response = authenticated(http_client().post(&request_url))
+        .json(&MknodRequest {
+            path: server_path(path),
+            mode,
+            rdev,
+        })
+        .send_timed("POST /mknod")
+        .await?;
+    json_body(response).await
+}
+
+// How `set_xattr` treats an attribute that may already exist.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum XattrMode {
+    Upsert,
+    Create,
+    Replace,
+}
+
+pub async fn get_xattr(base_url: &str, path: &str, name: &str) -> Result<Vec<u8>, ApiError> {
+    let request_url = endpoint_url(base_url, "xattr", path);
+    log::debug!("Requesting attribute {} from URL: {}", name, request_url);
+
+    let response = send_idempotent(
+        "GET /xattr",
+        authenticated(http_client().get(&request_url)).query(&[("name", name)]),
+    )
+    .await?;
+    limited_body(check_status(response).await?).await
+}
+
+pub async fn list_xattrs(base_url: &str, path: &str) -> Result<Vec<String>, ApiError> {
+    let request_url = endpoint_url(base_url, "xattr", path);
+    log::debug!("Requesting attribute names from URL: {}", request_url);
+
+    let response =
+        send_idempotent("GET /xattr", authenticated(http_client().get(&request_url))).await?;
+    json_body(response).await
+}
+
+pub async fn set_xattr(
+    base_url: &str,
+    path: &str,
+    name: &str,
+    value: &[u8],
+    mode: XattrMode,
+) -> Result<(), ApiError> {
+    let request_url = endpoint_url(base_url, "xattr", path);
+    log::debug!("Setting attribute {} via PUT {}", name, request_url);
+
+    let mut request = authenticated(http_client().put(&request_url)).query(&[("name", name)]);
+    match mode {
+        XattrMode::Upsert => {}
+        XattrMode::Create => request = request.query(&[("flags", "create")]),
+        XattrMode::Replace => request = request.query(&[("flags", "replace")]),
+    }
+    let response = request
+        .body(value.to_vec())
+        .send_timed("PUT /xattr")
+        .await?;
+    check_status(response).await?;
+    Ok(())
+}
+
+pub async fn remove_xattr(base_url: &str, path: &str, name: &str) -> Result<(), ApiError> {
+    let request_url = endpoint_url(base_url, "xattr", path);
+    log::debug!("Removing attribute {} via DELETE {}", name, request_url);
+
+    let response = authenticated(http_client().delete(&request_url))
+        .query(&[("name", name)])
+        .send_timed("DELETE /xattr")
+        .await?;
+    check_status(response).await?;
+    Ok(())
 }
 
 pub async fn conditionally_create_file_from_path(
@@ -233,17 +1335,15 @@ pub async fn conditionally_create_file_from_path(
     let file = tokio::fs::File::open(data_path).await?;
     let body = reqwest::Body::wrap_stream(ReaderStream::new(file));
     let request = authenticated(http_client().put(&request_url))
+        .timeout(upload_timeout())
         .header("If-None-Match", "*")
         .body(body);
     let response =
         add_optional_metadata_headers(request, Some(mode), None, None, Some(modified_at))
-            .send()
+            .send_timed("PUT /files")
             .await?;
 
-    Ok(response
-        .error_for_status()?
-        .json::<RemoteMetadata>()
-        .await?)
+    Ok(json_body(response).await?)
 }
 
 pub async fn remote_file_matches_local(
@@ -253,7 +1353,7 @@ pub async fn remote_file_matches_local(
 ) -> Result<bool, UploadError> {
     let local_size = tokio::fs::metadata(data_path).await?.len();
     let remote = get_metadata(base_url, path).await?;
-    if remote.type_ != "file" || remote.size != local_size {
+    if remote.type_ != EntryType::File || remote.size != local_size {
         return Ok(false);
     }
 
@@ -274,13 +1374,20 @@ pub async fn remote_file_matches_local(
     Ok(true)
 }
 
+// Result of a ranged write. `written` is below the length sent when the
+// server stored only a prefix, e.g. because its disk filled up.
+pub struct WriteOutcome {
+    pub metadata: RemoteMetadata,
+    pub written: usize,
+}
+
 // Sends a chunk of bytes to the server at a specific offset
 pub async fn write_file(
     base_url: &str,
     path: &str,
     data: &[u8],
     offset: u64,
-) -> Result<RemoteMetadata, reqwest::Error> {
+) -> Result<WriteOutcome, ApiError> {
     let request_url = endpoint_url(base_url, "files", path);
 
     log::debug!(
@@ -294,10 +1401,19 @@ pub async fn write_file(
     let response = authenticated(client.put(&request_url))
         .header(headers::FILE_OFFSET, offset.to_string())
         .body(data.to_vec())
-        .send()
+        .send_timed("PUT /files")
         .await?;
 
-    response.error_for_status()?.json::<RemoteMetadata>().await
+    // Servers that predate the header store everything or fail the request.
+    let written = response
+        .headers()
+        .get(headers::BYTES_WRITTEN)
+        .and_then(|value| value.to_str().ok()?.parse::<usize>().ok())
+        .map_or(data.len(), |written| written.min(data.len()));
+    Ok(WriteOutcome {
+        metadata: json_body(response).await?,
+        written,
+    })
 }
 
 // Asks the server to resize a file without sending file contents.
@@ -305,7 +1421,7 @@ pub async fn resize_file(
     base_url: &str,
     path: &str,
     size: u64,
-) -> Result<RemoteMetadata, reqwest::Error> {
+) -> Result<RemoteMetadata, ApiError> {
     let request_url = endpoint_url(base_url, "files", path);
 
     log::debug!("API: Resizing {} to {} bytes", request_url, size);
@@ -314,17 +1430,77 @@ pub async fn resize_file(
     let response = authenticated(client.put(&request_url))
         .header(headers::FILE_TRUNCATE, size.to_string())
         .body(vec![])
-        .send()
+        .send_timed("PUT /files")
         .await?;
 
-    response.error_for_status()?.json::<RemoteMetadata>().await
+    json_body(response).await
+}
+
+// Reserves space for `length` bytes at `offset`; `keep_size` leaves the
+// reported size alone.
+pub async fn preallocate_file(
+    base_url: &str,
+    path: &str,
+    offset: u64,
+    length: u64,
+    keep_size: bool,
+) -> Result<RemoteMetadata, ApiError> {
+    let request_url = endpoint_url(base_url, "fallocate", path);
+
+    log::debug!(
+        "API: Preallocating {} bytes at offset {} of {} (keep_size={})",
+        length,
+        offset,
+        request_url,
+        keep_size
+    );
+
+    let response = authenticated(http_client().post(&request_url))
+        .json(&FallocateRequest {
+            offset,
+            length,
+            keep_size,
+        })
+        .send_timed("POST /fallocate")
+        .await?;
+
+    json_body(response).await
+}
+
+// Next data or hole offset at or after `offset` in the server's file; `None`
+// when there is no such offset, as at or past the end of the file.
+pub async fn seek_file(
+    base_url: &str,
+    path: &str,
+    offset: u64,
+    whence: SeekWhence,
+) -> Result<Option<u64>, ApiError> {
+    let request_url = endpoint_url(base_url, "seek", path);
+
+    log::debug!(
+        "API: Seeking {} from offset {} of {}",
+        whence,
+        offset,
+        request_url
+    );
+
+    let response = send_idempotent(
+        "GET /seek",
+        authenticated(http_client().get(&request_url)).query(&SeekQuery { whence, offset }),
+    )
+    .await?;
+    if response.status() == StatusCode::RANGE_NOT_SATISFIABLE {
+        return Ok(None);
+    }
+    let result: SeekResult = json_body(response).await?;
+    Ok(Some(result.offset))
 }
 
 pub async fn overwrite_file(
     base_url: &str,
     path: &str,
     mode: Option<u32>,
-) -> Result<RemoteMetadata, reqwest::Error> {
+) -> Result<RemoteMetadata, ApiError> {
     let request_url = endpoint_url(base_url, "files", path);
 
     log::debug!("API: Overwriting {}", request_url);
@@ -334,10 +1510,91 @@ pub async fn overwrite_file(
         .header(headers::FILE_TRUNCATE, "0")
         .body(Vec::new());
     let response = add_optional_metadata_headers(request, mode, None, None, None)
-        .send()
+        .send_timed("PUT /files")
         .await?;
 
-    response.error_for_status()?.json::<RemoteMetadata>().await
+    json_body(response).await
+}
+
+// Starts a staged write that replaces `path` only once it is committed.
+pub async fn begin_temp_write(base_url: &str, path: &str) -> Result<String, ApiError> {
+    let request_url = format!("{}/write-temp", base_url.trim_end_matches('/'));
+
+    log::debug!("API: Staging a replacement for {}", path);
+
+    let response = authenticated(http_client().post(&request_url))
+        .json(&TempWriteRequest {
+            path: server_path(path),
+        })
+        .send_timed("POST /write-temp")
+        .await?;
+    let staged: TempWriteHandle = json_body(response).await?;
+    Ok(staged.handle)
+}
+
+pub async fn write_temp(
+    base_url: &str,
+    handle: &str,
+    data: &[u8],
+    offset: u64,
+) -> Result<(), ApiError> {
+    let request_url = format!("{}/write-temp/{handle}", base_url.trim_end_matches('/'));
+
+    log::debug!(
+        "API: Staging {} bytes at offset {} in {}",
+        data.len(),
+        offset,
+        request_url
+    );
+
+    let response = authenticated(http_client().put(&request_url))
+        .header(headers::FILE_OFFSET, offset.to_string())
+        .body(data.to_vec())
+        .send_timed("PUT /write-temp")
+        .await?;
+    check_status(response).await?;
+    Ok(())
+}
+
+// Resizes a staged write, as `ftruncate` on its handle would the file.
+pub async fn resize_temp_write(base_url: &str, handle: &str, size: u64) -> Result<(), ApiError> {
+    let request_url = format!("{}/write-temp/{handle}", base_url.trim_end_matches('/'));
+
+    log::debug!(
+        "API: Resizing staged write {} to {} bytes",
+        request_url,
+        size
+    );
+
+    let response = authenticated(http_client().put(&request_url))
+        .header(headers::FILE_TRUNCATE, size.to_string())
+        .send_timed("PUT /write-temp")
+        .await?;
+    check_status(response).await?;
+    Ok(())
+}
+
+pub async fn commit_temp_write(base_url: &str, handle: &str) -> Result<RemoteMetadata, ApiError> {
+    let request_url = format!("{}/commit/{handle}", base_url.trim_end_matches('/'));
+
+    log::debug!("API: Committing staged write via POST {}", request_url);
+
+    let response = authenticated(http_client().post(&request_url))
+        .send_timed("POST /commit")
+        .await?;
+    json_body(response).await
+}
+
+pub async fn abort_temp_write(base_url: &str, handle: &str) -> Result<(), ApiError> {
+    let request_url = format!("{}/write-temp/{handle}", base_url.trim_end_matches('/'));
+
+    log::debug!("API: Discarding staged write {}", request_url);
+
+    let response = authenticated(http_client().delete(&request_url))
+        .send_timed("DELETE /write-temp")
+        .await?;
+    check_status(response).await?;
+    Ok(())
 }
 
 pub async fn update_metadata(
@@ -347,46 +1604,56 @@ pub async fn update_metadata(
     uid: Option<u32>,
     gid: Option<u32>,
     modified_at: Option<SystemTime>,
-) -> Result<RemoteMetadata, reqwest::Error> {
+    accessed_at: Option<SystemTime>,
+) -> Result<RemoteMetadata, ApiError> {
     let request_url = endpoint_url(base_url, "metadata", path);
 
     log::debug!("API: Updating metadata for {}", request_url);
 
     let client = http_client();
-    let response = add_optional_metadata_headers(
+    let mut request = add_optional_metadata_headers(
         authenticated(client.patch(&request_url)),
         mode,
         uid,
         gid,
         modified_at,
-    )
-    .send()
-    .await?;
+    );
+    if let Some(accessed_at) = accessed_at {
+        request = request.header(
+            headers::FILE_ATIME,
+            unix_seconds_from_system_time(accessed_at).to_string(),
+        );
+    }
+    let response = request.send_timed("PATCH /metadata").await?;
 
-    response.error_for_status()?.json::<RemoteMetadata>().await
+    json_body(response).await
 }
 
-pub async fn delete_file(base_url: &str, path: &str) -> Result<(), reqwest::Error> {
+pub async fn delete_file(base_url: &str, path: &str) -> Result<(), ApiError> {
     let request_url = endpoint_url(base_url, "files", path);
 
     log::debug!("API: Deleting {}", request_url);
 
     let client = http_client();
-    let response = authenticated(client.delete(&request_url)).send().await?;
+    let response = authenticated(client.delete(&request_url))
+        .send_timed("DELETE /files")
+        .await?;
 
-    response.error_for_status()?;
+    check_status(response).await?;
     Ok(())
 }
 
-pub async fn delete_directory(base_url: &str, path: &str) -> Result<(), reqwest::Error> {
+pub async fn delete_directory(base_url: &str, path: &str) -> Result<(), ApiError> {
     let request_url = endpoint_url(base_url, "directories", path);
 
     log::debug!("API: Deleting directory {}", request_url);
 
     let client = http_client();
-    let response = authenticated(client.delete(&request_url)).send().await?;
+    let response = authenticated(client.delete(&request_url))
+        .send_timed("DELETE /directories")
+        .await?;
 
-    response.error_for_status()?;
+    check_status(response).await?;
     Ok(())
 }
 
@@ -395,11 +1662,11 @@ pub async fn rename_file(
     from: &str,
     to: &str,
     replace_if_exists: bool,
-) -> Result<(), reqwest::Error> {
+) -> Result<(), ApiError> {
     let normalized_base = base_url.trim_end_matches('/');
     let request_url = format!("{}/rename", normalized_base);
-    let normalized_from = from.trim_start_matches('/');
-    let normalized_to = to.trim_start_matches('/');
+    let normalized_from = server_path(from);
+    let normalized_to = server_path(to);
 
     log::debug!(
         "API: Renaming {} to {} via POST {}",
@@ -411,20 +1678,28 @@ pub async fn rename_file(
     let client = http_client();
     let response = authenticated(client.post(&request_url))
         .json(&RenameRequest {
-            from: normalized_from.to_string(),
-            to: normalized_to.to_string(),
+            from: normalized_from,
+            to: normalized_to,
             replace_if_exists,
         })
-        .send()
+        .send_timed("POST /rename")
         .await?;
 
-    response.error_for_status()?;
+    check_status(response).await?;
     Ok(())
 }
 
 #[cfg(test)]
 mod tests {
-    use super::{encode_api_path, endpoint_url};
+    use super::{
+        ApiError, ChangeEvent, ErrorDetail, HttpClientOptions, MAX_CHECKSUMMED_READ,
+        MAX_RETRY_DELAY, append_limited, below_root, build_http_client, drain_change_events,
+        encode_api_path, endpoint_url, is_retryable_status, mount_path, retry_delay,
+        verify_checksum,
+    };
+    use reqwest::StatusCode;
+    use sha2::{Digest, Sha256};
+    use std::time::Duration;
 
     #[test]
     fn api_paths_encode_reserved_and_unicode_characters_per_component() {
@@ -449,4 +1724,163 @@ mod tests {
             "http://localhost:3000/list/"
         );
     }
+
+    #[test]
+    fn http_client_reports_unreadable_ca_certificate() {
+        let options = HttpClientOptions {
+            ca_cert: Some(std::env::temp_dir().join("remote-fs-missing-ca.pem")),
+            ..HttpClientOptions::default()
+        };
+        let error = build_http_client(&options).unwrap_err();
+        assert_eq!(error.kind(), std::io::ErrorKind::NotFound);
+    }
+
+    #[test]
+    fn only_server_errors_and_throttling_are_retried() {
+        assert!(is_retryable_status(StatusCode::BAD_GATEWAY));
+        assert!(is_retryable_status(StatusCode::TOO_MANY_REQUESTS));
+        assert!(!is_retryable_status(StatusCode::NOT_FOUND));
+        assert!(!is_retryable_status(StatusCode::FORBIDDEN));
+    }
+
+    #[test]
+    fn checksums_must_match_and_be_present_on_reads_the_server_hashes() {
+        let world = "486ea46224d1bb4fb680f34f7c9ad96a8f24ec88be73ea8e5a6c65260e9cb8a7";
+        let digest = |bytes: &[u8]| Sha256::digest(bytes).to_vec();
+        let limit = MAX_CHECKSUMMED_READ as u32;
+        assert!(verify_checksum(&digest(b"world"), Some(world), 5).is_ok());
+        assert!(verify_checksum(&digest(b"worle"), Some(world), 5).is_err());
+        assert!(verify_checksum(&digest(b"world"), None, 5).is_err());
+        assert!(verify_checksum(&digest(b"world"), None, limit).is_err());
+        assert!(verify_checksum(&digest(b"world"), None, limit + 1).is_ok());
+        assert!(verify_checksum(&digest(b"worle"), Some(world), limit + 1).is_err());
+    }
+
+    // A server that ignores `X-File-Size` and sends a whole 8 MiB file.
+    #[tokio::test]
+    async fn reads_keep_only_the_requested_window_of_an_oversized_body() {
+        use std::io::{Read, Write};
+
+        let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+        std::thread::spawn(move || {
+            let (mut stream, _) = listener.accept().unwrap();
+            let mut request = [0; 4096];
+            let _ = stream.read(&mut request);
+            let body = vec![b'x'; 8 * 1024 * 1024];
+            let head = format!("HTTP/1.1 200 OK\r\nContent-Length: {}\r\n\r\n", body.len());
+            let _ = stream.write_all(head.as_bytes());
+            let _ = stream.write_all(&body);
+        });
+
+        let bytes = super::read_file(&format!("http://{addr}"), "big.bin", 0, 10)
+            .await
+            .unwrap();
+        assert_eq!(bytes, b"xxxxxxxxxx");
+    }
+
+    #[tokio::test]
+    async fn listings_declaring_an_oversized_body_are_rejected_unread() {
+        use std::io::{Read, Write};
+
+        let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+        std::thread::spawn(move || {
+            let (mut stream, _) = listener.accept().unwrap();
+            let mut request = [0; 4096];
+            let _ = stream.read(&mut request);
+            let head = "HTTP/1.1 200 OK\r\nContent-Length: 1073741824\r\n\r\n[";
+            let _ = stream.write_all(head.as_bytes());
+        });
+
+        let error = super::list_directory(&format!("http://{addr}"), "")
+            .await
+            .err()
+            .unwrap();
+        assert!(matches!(error, ApiError::TooLarge(_)));
+    }
+
+    #[test]
+    fn error_bodies_keep_the_server_code_when_there_is_one() {
+        let detail = ErrorDetail::from_body(
+            r#"{"error":"insufficient_storage","message":"Disk quota exceeded"}"#,
+        );
+        assert_eq!(detail.code.as_deref(), Some("insufficient_storage"));
+        assert_eq!(
+            ApiError::Status(StatusCode::INSUFFICIENT_STORAGE, detail).to_string(),
+            "507 Insufficient Storage: Disk quota exceeded (insufficient_storage)"
+        );
+
+        let detail = ErrorDetail::from_body("Bad gateway\n");
+        assert_eq!(
+            (detail.code, detail.message.as_str()),
+            (None, "Bad gateway")
+        );
+        assert_eq!(
+            ApiError::Status(StatusCode::BAD_GATEWAY, ErrorDetail::default()).to_string(),
+            "502 Bad Gateway"
+        );
+    }
+
+    #[test]
+    fn streamed_bodies_stop_at_the_limit() {
+        let mut bytes = Vec::new();
+        append_limited(&mut bytes, b"[1,", 5).unwrap();
+        append_limited(&mut bytes, b"2]", 5).unwrap();
+        assert!(matches!(
+            append_limited(&mut bytes, b" ", 5),
+            Err(ApiError::TooLarge(5))
+        ));
+        assert_eq!(bytes, b"[1,2]");
+    }
+
+    #[test]
+    fn remote_roots_prefix_sent_paths_and_filter_changes() {
+        assert_eq!(below_root("", "/docs/a.txt"), "docs/a.txt");
+        assert_eq!(below_root("projects/foo", "/"), "projects/foo");
+        assert_eq!(below_root("projects/foo", "/a.txt"), "projects/foo/a.txt");
+
+        assert_eq!(mount_path("", "/docs").as_deref(), Some("/docs"));
+        assert_eq!(
+            mount_path("projects/foo", "/projects/foo/a.txt").as_deref(),
+            Some("/a.txt")
+        );
+        assert_eq!(
+            mount_path("projects/foo", "/projects/foo").as_deref(),
+            Some("/")
+        );
+        assert_eq!(mount_path("projects/foo", "/projects/foobar"), None);
+        assert_eq!(mount_path("projects/foo", "/projects"), None);
+    }
+
+    #[test]
+    fn change_events_are_split_on_blank_lines() {
+        let mut pending =
+            b":\n\nevent: changed\ndata: /docs/a.txt\n\nevent: reset\ndata: /\n\nevent: chan"
+                .to_vec();
+        assert_eq!(
+            drain_change_events(&mut pending),
+            [
+                ChangeEvent::Changed("/docs/a.txt".to_string()),
+                ChangeEvent::Reset
+            ]
+        );
+        assert_eq!(pending, b"event: chan");
+
+        pending.extend_from_slice(b"ged\ndata: /line\ndata: break\n\n");
+        assert_eq!(
+            drain_change_events(&mut pending),
+            [ChangeEvent::Changed("/line\nbreak".to_string())]
+        );
+        assert!(pending.is_empty());
+    }
+
+    #[test]
+    fn retry_delays_double_with_bounded_jitter() {
+        let base = Duration::from_millis(100);
+        assert_eq!(retry_delay(base, 0, 0.0), Duration::from_millis(50));
+        assert_eq!(retry_delay(base, 0, 1.0), Duration::from_millis(100));
+        assert_eq!(retry_delay(base, 2, 0.5), Duration::from_millis(300));
+        assert_eq!(retry_delay(base, 40, 1.0), MAX_RETRY_DELAY);
+    }
 }
@@ -0,0 +1,141 @@
+use reqwest::StatusCode;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+pub(super) const DEFAULT_FAILURE_THRESHOLD: u32 = 5;
+pub(super) const DEFAULT_COOLDOWN: Duration = Duration::from_secs(10);
+
+// Stops sending requests to a server that looks down. After `threshold`
+// consecutive failures the circuit opens and requests fail at once for
+// `cooldown`; then one request is let through as a probe. Its success closes
+// the circuit, and its failure, or no answer within another cooldown, lets
+// the next probe through. A zero threshold never opens it.
+pub(super) struct CircuitBreaker {
+    threshold: u32,
+    cooldown: Duration,
+    state: Mutex<BreakerState>,
+}
+
+#[derive(Default)]
+struct BreakerState {
+    failures: u32,
+    // Set while the circuit is open: no request is sent before this.
+    open_until: Option<Instant>,
+}
+
+impl CircuitBreaker {
+    pub(super) fn new(threshold: u32, cooldown: Duration) -> Self {
+        CircuitBreaker {
+            threshold,
+            cooldown,
+            state: Mutex::new(BreakerState::default()),
+        }
+    }
+
+    // Whether a request may be sent now. Once the cooldown is over, the one
+    // caller that gets through holds off the others for another cooldown.
+    pub(super) fn allow(&self, now: Instant) -> bool {
+        let mut state = self.state.lock().unwrap();
+        match state.open_until {
+            Some(until) if now < until => false,
+            Some(_) => {
+                state.open_until = Some(now + self.cooldown);
+                log::info!("Sending a request to probe the server");
+                true
+            }
+            None => true,
+        }
+    }
+
+    pub(super) fn record(&self, status: Option<StatusCode>, now: Instant) {
+        let mut state = self.state.lock().unwrap();
+        if status.is_some_and(|status| !is_unavailable_status(status)) {
+            if state.open_until.take().is_some() {
+                log::info!("Server answered again; sending requests normally");
+            }
+            state.failures = 0;
+            return;
+        }
+
+        state.failures = state.failures.saturating_add(1);
+        if state.open_until.is_some() {
+            // A failed probe, or a request sent before the circuit opened.
+            state.open_until = Some(now + self.cooldown);
+        } else if self.threshold != 0 && state.failures >= self.threshold {
+            log::warn!(
+                "Server failed {} requests in a row; failing requests for {:?}",
+                state.failures,
+                self.cooldown
+            );
+            state.open_until = Some(now + self.cooldown);
+        }
+    }
+}
+
+// Replies meaning the server, or the proxy in front of it, cannot handle
+// requests at all; other errors are about the request.
+fn is_unavailable_status(status: StatusCode) -> bool {
+    matches!(
+        status,
+        StatusCode::BAD_GATEWAY | StatusCode::SERVICE_UNAVAILABLE | StatusCode::GATEWAY_TIMEOUT
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const COOLDOWN: Duration = Duration::from_secs(10);
+
+    #[test]
+    fn consecutive_failures_open_the_circuit_until_the_cooldown_ends() {
+        let breaker = CircuitBreaker::new(3, COOLDOWN);
+        let start = Instant::now();
+        breaker.record(None, start);
+        breaker.record(Some(StatusCode::SERVICE_UNAVAILABLE), start);
+        assert!(breaker.allow(start));
+        breaker.record(None, start);
+
+        assert!(!breaker.allow(start + COOLDOWN / 2));
+        assert!(breaker.allow(start + COOLDOWN));
+        // Only one probe goes out per cooldown.
+        assert!(!breaker.allow(start + COOLDOWN));
+    }
+
+    #[test]
+    fn successes_reset_the_count_and_close_the_circuit() {
+        let breaker = CircuitBreaker::new(2, COOLDOWN);
+        let start = Instant::now();
+        breaker.record(None, start);
+        breaker.record(Some(StatusCode::NOT_FOUND), start);
+        breaker.record(None, start);
+        assert!(breaker.allow(start));
+
+        breaker.record(None, start);
+        assert!(breaker.allow(start + COOLDOWN));
+        breaker.record(Some(StatusCode::OK), start + COOLDOWN);
+        assert!(breaker.allow(start + COOLDOWN));
+    }
+
+    #[test]
+    fn failed_probes_reopen_the_circuit() {
+        let breaker = CircuitBreaker::new(1, COOLDOWN);
+        let start = Instant::now();
+        breaker.record(None, start);
+        assert!(breaker.allow(start + COOLDOWN));
+        breaker.record(None, start + COOLDOWN);
+
+        assert!(!breaker.allow(start + COOLDOWN * 3 / 2));
+        assert!(breaker.allow(start + COOLDOWN * 2));
+    }
+
+    #[test]
+    fn a_zero_threshold_never_opens_the_circuit() {
+        let breaker = CircuitBreaker::new(0, COOLDOWN);
+        let start = Instant::now();
+        for _ in 0..100 {
+            breaker.record(None, start);
+        }
+        assert!(breaker.allow(start));
+    }
+}
@@ -0,0 +1,118 @@
+use std::collections::HashMap;
+use std::hash::Hash;
+use std::sync::Mutex;
+use tokio::sync::watch;
+
+// Sends one copy of a request per key at a time. Callers asking for a key
+// while its request is in flight wait for that one and get a copy of its
+// result instead of sending their own.
+pub(super) struct SingleFlight<K, V> {
+    in_flight: Mutex<HashMap<K, watch::Receiver<Option<V>>>>,
+}
+
+impl<K, V> SingleFlight<K, V>
+where
+    K: Eq + Hash + Clone,
+    V: Clone,
+{
+    pub(super) fn new() -> Self {
+        SingleFlight {
+            in_flight: Mutex::new(HashMap::new()),
+        }
+    }
+
+    pub(super) async fn run(&self, key: K, request: impl Future<Output = V>) -> V {
+        let joined = {
+            let mut in_flight = self.in_flight.lock().unwrap();
+            match in_flight.get(&key) {
+                Some(receiver) => Err(receiver.clone()),
+                None => {
+                    let (sender, receiver) = watch::channel(None);
+                    in_flight.insert(key.clone(), receiver);
+                    Ok(sender)
+                }
+            }
+        };
+
+        let sender = match joined {
+            Ok(sender) => sender,
+            Err(mut receiver) => {
+                if let Ok(result) = receiver.wait_for(Option::is_some).await {
+                    return result.clone().expect("waited for a result");
+                }
+                // The first caller was cancelled before it got an answer.
+                return request.await;
+            }
+        };
+        let _leader = Leader { flight: self, key };
+        let result = request.await;
+        sender.send_replace(Some(result.clone()));
+        result
+    }
+}
+
+// Clears the key once its request ends, answered or cancelled, so the next
+// caller sends a new one.
+struct Leader<'a, K: Eq + Hash, V> {
+    flight: &'a SingleFlight<K, V>,
+    key: K,
+}
+
+impl<K: Eq + Hash, V> Drop for Leader<'_, K, V> {
+    fn drop(&mut self) {
+        self.flight.in_flight.lock().unwrap().remove(&self.key);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::time::Duration;
+
+    #[tokio::test]
+    async fn concurrent_callers_share_one_request() {
+        let flight = SingleFlight::new();
+        let sent = AtomicUsize::new(0);
+        let request = || async {
+            sent.fetch_add(1, Ordering::SeqCst);
+            tokio::time::sleep(Duration::from_millis(50)).await;
+            "entry".to_string()
+        };
+
+        let results = tokio::join!(
+            flight.run("/a", request()),
+            flight.run("/a", request()),
+            flight.run("/a", request()),
+        );
+        assert_eq!(results, ("entry".into(), "entry".into(), "entry".into()));
+        assert_eq!(sent.load(Ordering::SeqCst), 1);
+
+        // Later callers and other keys send their own.
+        flight.run("/a", request()).await;
+        flight.run("/b", request()).await;
+        assert_eq!(sent.load(Ordering::SeqCst), 3);
+    }
+
+    #[tokio::test]
+    async fn a_cancelled_request_leaves_waiters_to_send_their_own() {
+        let flight = SingleFlight::new();
+        let mut first = Box::pin(flight.run("/a", std::future::pending::<u32>()));
+        let mut second = Box::pin(flight.run("/a", async { 7 }));
+
+        // Starts the first request, then lets the second join it.
+        assert!(
+            tokio::time::timeout(Duration::from_millis(10), &mut first)
+                .await
+                .is_err()
+        );
+        assert!(
+            tokio::time::timeout(Duration::from_millis(10), &mut second)
+                .await
+                .is_err()
+        );
+        drop(first);
+        assert_eq!(second.await, 7);
+        assert_eq!(flight.run("/a", async { 8 }).await, 8);
+    }
+}
@@ -1,4 +1,4 @@
-use std::collections::HashMap;
+use std::collections::{BTreeMap, HashMap};
 use std::hash::Hash;
 use std::time::{Duration, Instant};
 
@@ -6,12 +6,21 @@ use std::time::{Duration, Instant};
 struct CacheEntry<V> {
     value: V,
     inserted_at: Instant,
+    // Access counter values at insertion and last use; they key the entry in
+    // the cache's ordered indexes.
+    inserted: u64,
     last_used: u64,
+    // Kept regardless of age or use until removed or replaced.
+    pinned: bool,
 }
 
 #[derive(Debug)]
 pub struct TtlLruCache<K, V> {
     entries: HashMap<K, CacheEntry<V>>,
+    // Unpinned keys oldest first, which is also the order they expire in.
+    by_insertion: BTreeMap<u64, K>,
+    // Unpinned keys least recently used first, for eviction.
+    by_use: BTreeMap<u64, K>,
     max_entries: usize,
     ttl: Duration,
     access_counter: u64,
@@ -25,6 +34,8 @@ where
     pub fn new(max_entries: usize, ttl: Duration) -> Self {
         TtlLruCache {
             entries: HashMap::new(),
+            by_insertion: BTreeMap::new(),
+            by_use: BTreeMap::new(),
             max_entries,
             ttl,
             access_counter: 0,
@@ -32,74 +43,140 @@ where
     }
 
     pub fn get(&mut self, key: &K) -> Option<V> {
-        if self
-            .entries
-            .get(key)
-            .is_some_and(|entry| entry.inserted_at.elapsed() > self.ttl)
-        {
-            self.entries.remove(key);
+        let entry = self.entries.get(key)?;
+        if !entry.pinned && entry.inserted_at.elapsed() > self.ttl {
+            self.remove(key);
             return None;
         }
 
         self.access_counter += 1;
-        self.entries.get_mut(key).map(|entry| {
-            entry.last_used = self.access_counter;
-            entry.value.clone()
-        })
+        let entry = self.entries.get_mut(key)?;
+        if !entry.pinned {
+            self.by_use.remove(&entry.last_used);
+            self.by_use.insert(self.access_counter, key.clone());
+        }
+        entry.last_used = self.access_counter;
+        Some(entry.value.clone())
     }
 
     pub fn insert(&mut self, key: K, value: V) {
+        self.insert_entry(key, value, false);
+    }
+
+    // Inserts an entry that neither expires nor is evicted; something else
+    // must keep it current.
+    pub fn insert_pinned(&mut self, key: K, value: V) {
+        self.insert_entry(key, value, true);
+    }
+
+    fn insert_entry(&mut self, key: K, value: V, pinned: bool) {
         if self.max_entries == 0 {
             return;
         }
 
         self.access_counter += 1;
-        self.entries.insert(
-            key,
+        let previous = self.entries.insert(
+            key.clone(),
             CacheEntry {
                 value,
                 inserted_at: Instant::now(),
+                inserted: self.access_counter,
                 last_used: self.access_counter,
+                pinned,
             },
         );
+        if let Some(previous) = previous {
+            self.unindex(&previous);
+        }
+        if !pinned {
+            self.by_insertion.insert(self.access_counter, key.clone());
+            self.by_use.insert(self.access_counter, key);
+        }
         self.evict_expired();
         self.evict_lru();
     }
 
     pub fn remove(&mut self, key: &K) {
-        self.entries.remove(key);
+        if let Some(entry) = self.entries.remove(key) {
+            self.unindex(&entry);
+        }
     }
 
     pub fn remove_matching<F>(&mut self, predicate: F)
     where
         F: Fn(&K) -> bool,
     {
-        self.entries.retain(|key, _| !predicate(key));
+        let matching: Vec<K> = self
+            .entries
+            .keys()
+            .filter(|key| predicate(key))
+            .cloned()
+            .collect();
+        for key in matching {
+            self.remove(&key);
+        }
     }
 
     pub fn clear(&mut self) {
         self.entries.clear();
+        self.by_insertion.clear();
+        self.by_use.clear();
+    }
+
+    fn unindex(&mut self, entry: &CacheEntry<V>) {
+        if !entry.pinned {
+            self.by_insertion.remove(&entry.inserted);
+            self.by_use.remove(&entry.last_used);
+        }
     }
 
     fn evict_expired(&mut self) {
-        let ttl = self.ttl;
-        self.entries
-            .retain(|_, entry| entry.inserted_at.elapsed() <= ttl);
+        while let Some((_, key)) = self.by_insertion.first_key_value() {
+            if self.entries[key].inserted_at.elapsed() <= self.ttl {
+                break;
+            }
+            let key = key.clone();
+            self.remove(&key);
+        }
     }
 
     fn evict_lru(&mut self) {
         while self.entries.len() > self.max_entries {
-            let key_to_remove = self
-                .entries
-                .iter()
-                .min_by_key(|(_, entry)| entry.last_used)
-                .map(|(key, _)| key.clone());
-
-            if let Some(key) = key_to_remove {
-                self.entries.remove(&key);
-            } else {
+            let Some((_, key)) = self.by_use.first_key_value() else {
                 break;
-            }
+            };
+            let key = key.clone();
+            self.remove(&key);
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn pinned_entries_outlive_their_ttl_and_are_never_evicted() {
+        let mut cache = TtlLruCache::new(2, Duration::ZERO);
+        cache.insert_pinned("root", 0);
+        cache.insert("a", 1);
+        std::thread::sleep(Duration::from_millis(2));
+        assert_eq!(cache.get(&"a"), None);
+        assert_eq!(cache.get(&"root"), Some(0));
+
+        let mut cache = TtlLruCache::new(2, Duration::from_secs(60));
+        cache.insert_pinned("root", 0);
+        cache.insert("a", 1);
+        cache.insert("b", 2);
+        assert_eq!(cache.get(&"root"), Some(0));
+        assert_eq!(cache.get(&"a"), None);
+        assert_eq!(cache.get(&"b"), Some(2));
+
+        // Replacing a pinned entry with an ordinary one unpins it.
+        cache.insert("root", 3);
+        assert_eq!(cache.get(&"b"), Some(2));
+        cache.insert("c", 4);
+        assert_eq!(cache.get(&"root"), None);
+        assert_eq!(cache.get(&"c"), Some(4));
+    }
+}
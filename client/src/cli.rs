@@ -0,0 +1,733 @@
+//! Command-line options shared by the FUSE and WinFSP front ends.
+
+use crate::api::HttpClientOptions;
+use crate::logging::LogFormat;
+use clap::{Parser, ValueEnum};
+use std::{net::SocketAddr, path::PathBuf, time::Duration};
+
+const DEFAULT_SERVER_URL: &str = "http://127.0.0.1:3000";
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, ValueEnum)]
+pub(crate) enum InodeAllocation {
+    /// Hand out numbers the kernel has forgotten again, with a new generation.
+    #[default]
+    Recycle,
+    /// Never reuse a number within a mount.
+    Sequential,
+}
+
+impl InodeAllocation {
+    pub(crate) fn as_str(self) -> &'static str {
+        match self {
+            InodeAllocation::Recycle => "recycle",
+            InodeAllocation::Sequential => "sequential",
+        }
+    }
+}
+
+#[derive(Parser)]
+#[command(
+    name = "remote-fs-client",
+    version,
+    about = "Mount a remote-fs server as a local filesystem."
+)]
+pub(crate) struct ClientArgs {
+    /// Local mount point; a free drive letter such as `R:` on Windows.
+    pub(crate) mountpoint: String,
+
+    /// Server URL, accepted positionally for compatibility with older invocations.
+    #[arg(
+        value_name = "SERVER_URL",
+        conflicts_with = "server_url",
+        value_parser = parse_server_url
+    )]
+    legacy_server_url: Option<String>,
+
+    /// Base URL of the remote-fs server [default: http://127.0.0.1:3000].
+    #[arg(long, value_name = "URL", value_parser = parse_server_url)]
+    server_url: Option<String>,
+
+    /// Server directory to mount as the root, such as `/projects/foo`
+    /// [default: the server's whole tree].
+    #[arg(long, value_name = "PATH", value_parser = parse_remote_root)]
+    remote_root: Option<String>,
+
+    /// Start the mount in a detached background process.
+    #[arg(long)]
+    pub(crate) daemon: bool,
+
+    /// Internal: the detached process started by `--daemon`.
+    #[arg(long, hide = true)]
+    #[cfg_attr(windows, allow(dead_code))]
+    pub(crate) serve_daemon: bool,
+
+    /// Report this UID for every entry instead of the server-provided owner.
+    #[arg(long)]
+    pub(crate) uid: Option<u32>,
+
+    /// Report this GID for every entry instead of the server-provided group.
+    #[arg(long)]
+    pub(crate) gid: Option<u32>,
+
+    /// Seconds the kernel may cache attributes and lookups [default: 1].
+    #[arg(long, value_name = "SECONDS")]
+    pub(crate) attr_ttl: Option<u64>,
+
+    /// Seconds the kernel may cache a found name and the attributes sent with
+    /// it [default: the --attr-ttl value].
+    #[arg(long, value_name = "SECONDS")]
+    pub(crate) entry_ttl: Option<u64>,
+
+    /// Seconds a name the server reported missing stays missing, so newly
+    /// created files from other clients appear within it; 0 disables
+    /// negative caching [default: 2].
+    #[arg(long, value_name = "SECONDS")]
+    pub(crate) negative_ttl: Option<u64>,
+
+    /// File that keeps inode numbers stable across remounts.
+    #[arg(long, value_name = "PATH")]
+    pub(crate) inode_db: Option<PathBuf>,
+
+    /// How numbers are given to inodes not kept in `--inode-db`.
+    #[arg(long, value_enum, default_value_t = InodeAllocation::Recycle)]
+    #[cfg_attr(windows, allow(dead_code))]
+    pub(crate) inode_allocation: InodeAllocation,
+
+    /// Directory levels fetched per listing; above 1 prefetches subdirectories.
+    #[arg(long, value_name = "LEVELS", default_value_t = 1)]
+    pub(crate) list_depth: u32,
+
+    /// List the whole tree in the background after mounting, so later `ls`
+    /// and `stat` calls are answered from cache.
+    #[arg(long)]
+    #[cfg_attr(windows, allow(dead_code))]
+    pub(crate) prewarm: bool,
+
+    /// Follow the server's change feed and drop cached data for paths other
+    /// clients change, instead of waiting for cache TTLs to expire.
+    #[arg(long)]
+    #[cfg_attr(windows, allow(dead_code))]
+    pub(crate) watch_changes: bool,
+
+    /// List the root directory again every SECONDS in the background, so
+    /// `ls` of the mount point is answered from a cache that stays fresh.
+    #[arg(long, value_name = "SECONDS", value_parser = clap::value_parser!(u64).range(1..))]
+    #[cfg_attr(windows, allow(dead_code))]
+    pub(crate) root_refresh_secs: Option<u64>,
+
+    /// Threads running server requests; 1 runs them all on a single thread
+    /// [default: one per CPU].
+    #[arg(
+        long,
+        value_name = "N",
+        value_parser = clap::builder::RangedU64ValueParser::<usize>::new().range(1..)
+    )]
+    #[cfg_attr(windows, allow(dead_code))]
+    pub(crate) runtime_threads: Option<usize>,
+
+    /// Block size in bytes reported to applications and used to align
+    /// transfers; a power of two from 512 to 4 MiB [default: 131072].
+    #[arg(long, value_name = "BYTES", value_parser = parse_blksize)]
+    #[cfg_attr(windows, allow(dead_code))]
+    pub(crate) blksize: Option<u32>,
+
+    /// Octal permissions for created files whose requested mode has none left
+    /// after the umask [default: 644].
+    #[arg(long, value_name = "MODE", value_parser = parse_mode)]
+    #[cfg_attr(windows, allow(dead_code))]
+    pub(crate) file_mode: Option<u32>,
+
+    /// Octal permissions for created directories whose requested mode has
+    /// none left after the umask [default: 755].
+    #[arg(long, value_name = "MODE", value_parser = parse_mode)]
+    #[cfg_attr(windows, allow(dead_code))]
+    pub(crate) dir_mode: Option<u32>,
+
+    /// Largest write in bytes the kernel sends in one request; from 4096 to
+    /// 16 MiB, with larger ones split [default: 4194304].
+    #[arg(long, value_name = "BYTES", value_parser = parse_transfer_size)]
+    #[cfg_attr(windows, allow(dead_code))]
+    pub(crate) max_write: Option<u32>,
+
+    /// Largest range in bytes fetched from the server in one request; from
+    /// 4096 to 16 MiB, with larger reads assembled from several
+    /// [default: 4194304].
+    #[arg(long, value_name = "BYTES", value_parser = parse_transfer_size)]
+    #[cfg_attr(windows, allow(dead_code))]
+    pub(crate) read_chunk_size: Option<u32>,
+
+    /// Largest file size in bytes this mount reads or writes; reads and
+    /// writes past it fail with EFBIG, though sizes are reported in full.
+    #[arg(long, value_name = "BYTES", value_parser = clap::value_parser!(u64).range(1..))]
+    #[cfg_attr(windows, allow(dead_code))]
+    pub(crate) max_file_size: Option<u64>,
+
+    /// Let the kernel cache written pages and send them in larger batches;
+    /// size and mtime changes from other clients may go unseen while cached.
+    #[arg(long)]
+    #[cfg_attr(windows, allow(dead_code))]
+    pub(crate) writeback_cache: bool,
+
+    /// Mount with `noatime` and have the server leave access times unchanged
+    /// when files are read, where it can.
+    #[arg(long)]
+    #[cfg_attr(windows, allow(dead_code))]
+    pub(crate) noatime: bool,
+
+    /// Let other users, root included, use the mount. The kernel then checks
+    /// the reported permission bits on every access.
+    #[arg(long, conflicts_with = "allow_root")]
+    #[cfg_attr(windows, allow(dead_code))]
+    pub(crate) allow_other: bool,
+
+    /// Let root use the mount besides the mounting user. The kernel then
+    /// checks the reported permission bits on every access.
+    #[arg(long)]
+    #[cfg_attr(windows, allow(dead_code))]
+    pub(crate) allow_root: bool,
+
+    /// Mount read-only; writes fail with EROFS without contacting the server.
+    #[arg(long)]
+    #[cfg_attr(windows, allow(dead_code))]
+    pub(crate) read_only: bool,
+
+    /// Mount without first checking that the server is reachable and accepts the token.
+    #[arg(long)]
+    #[cfg_attr(windows, allow(dead_code))]
+    pub(crate) no_health_check: bool,
+
+    /// Log output: `text` lines, or `json` objects with a timed span per FUSE call.
+    #[arg(long, value_enum, default_value_t = LogFormat::Text)]
+    pub(crate) log_format: LogFormat,
+
+    /// Log every server request's method, URL, status, and latency at info level.
+    #[arg(long)]
+    pub(crate) trace_requests: bool,
+
+    /// Check a server-computed SHA-256 on every file read; mismatches fail with EIO.
+    #[arg(long)]
+    pub(crate) verify_checksums: bool,
+
+    /// Serve Prometheus metrics for FUSE operations and server requests at `http://ADDR/metrics`.
+    #[arg(long, value_name = "ADDR")]
+    pub(crate) metrics_addr: Option<SocketAddr>,
+
+    /// Memory budget in MiB for cached file blocks; 0 disables the cache.
+    #[arg(long, value_name = "MIB", default_value_t = 64)]
+    pub(crate) cache_size_mb: u64,
+
+    /// Seconds to wait for each server request before failing it with ETIMEDOUT.
+    #[arg(long, value_name = "SECONDS", default_value_t = 30)]
+    pub(crate) timeout: u64,
+
+    /// Extra attempts for reads that fail with a connection error, 5xx, or 429.
+    #[arg(long, value_name = "COUNT", default_value_t = 3)]
+    pub(crate) retries: u32,
+
+    /// Consecutive failed server requests (no response, 502, 503, or 504)
+    /// after which requests fail with EIO without being sent; 0 never stops.
+    #[arg(long, value_name = "COUNT", default_value_t = 5)]
+    pub(crate) breaker_failures: u32,
+
+    /// Seconds requests stay stopped before one is sent to probe the server.
+    #[arg(long, value_name = "SECONDS", default_value_t = 10, value_parser = clap::value_parser!(u64).range(1..))]
+    pub(crate) breaker_cooldown: u64,
+
+    /// `User-Agent` sent with every server request.
+    #[arg(long, value_name = "STRING", default_value = crate::api::DEFAULT_USER_AGENT)]
+    pub(crate) user_agent: String,
+
+    /// Most server requests awaiting a response at once; others queue.
+    #[arg(long, value_name = "COUNT", default_value_t = 64, value_parser = clap::value_parser!(u32).range(1..))]
+    pub(crate) max_concurrency: u32,
+
+    /// Largest listing or metadata response accepted from the server, in MiB.
+    #[arg(long, value_name = "MB", default_value_t = 64, value_parser = clap::value_parser!(u64).range(1..))]
+    pub(crate) max_response_mb: u64,
+
+    /// Milliseconds before the first retry; each further retry doubles it.
+    #[arg(long, value_name = "MS", default_value_t = 100)]
+    pub(crate) retry_base_ms: u64,
+
+    /// Extra PEM root certificate to trust for `https://` servers.
+    #[arg(long, value_name = "PEM")]
+    ca_cert: Option<PathBuf>,
+
+    /// Skip TLS certificate verification (testing only).
+    #[arg(long)]
+    insecure: bool,
+
+    /// Bearer token for the server; defaults to `REMOTE_FS_TOKEN`.
+    #[arg(long, value_name = "TOKEN")]
+    pub(crate) auth_token: Option<String>,
+}
+
+// `st_blocks` counts 512-byte units, and readahead and upload buffers are
+// 4 MiB, so only powers of two in between divide both evenly.
+fn parse_blksize(value: &str) -> Result<u32, String> {
+    let blksize: u32 = value.parse().map_err(|error| format!("{error}"))?;
+    if blksize.is_power_of_two() && (512..=4 * 1024 * 1024).contains(&blksize) {
+        Ok(blksize)
+    } else {
+        Err("must be a power of two from 512 to 4194304".to_string())
+    }
+}
+
+fn parse_transfer_size(value: &str) -> Result<u32, String> {
+    let size: u32 = value.parse().map_err(|error| format!("{error}"))?;
+    if (4096..=16 * 1024 * 1024).contains(&size) {
+        Ok(size)
+    } else {
+        Err("must be from 4096 to 16777216".to_string())
+    }
+}
+
+// Checked here so a mistyped URL stops the client before mounting instead
+// of failing every operation; `http` and `https` URLs always have a host
+// once parsed. Request paths are appended to it, so a query
+// or fragment would end up in the wrong place.
+fn parse_server_url(value: &str) -> Result<String, String> {
+    let url = reqwest::Url::parse(value)
+        .map_err(|error| format!("{error}; expected a URL such as http://127.0.0.1:3000"))?;
+    if !matches!(url.scheme(), "http" | "https") {
+        return Err("must start with http:// or https://".to_string());
+    }
+    if url.query().is_some() || url.fragment().is_some() {
+        return Err("must not have a query or fragment".to_string());
+    }
+    Ok(value.to_string())
+}
+
+// Normalizes to slash-separated components without `.` or `..`, which the
+// server would refuse, or to `None` for `/`.
+fn parse_remote_root(value: &str) -> Result<String, String> {
+    let components: Vec<&str> = value.split('/').filter(|part| !part.is_empty()).collect();
+    if components.iter().any(|part| matches!(*part, "." | "..")) {
+        return Err("must not contain `.` or `..` components".to_string());
+    }
+    Ok(components.join("/"))
+}
+
+// Accepts `600`, `0600`, or `0o600`, including setuid/setgid/sticky bits.
+fn parse_mode(value: &str) -> Result<u32, String> {
+    let digits = value.strip_prefix("0o").unwrap_or(value);
+    match u32::from_str_radix(digits, 8) {
+        Ok(mode) if mode <= 0o7777 => Ok(mode),
+        _ => Err("must be an octal mode from 0 to 7777".to_string()),
+    }
+}
+
+impl ClientArgs {
+    pub(crate) fn server_url(&self) -> &str {
+        self.server_url
+            .as_deref()
+            .or(self.legacy_server_url.as_deref())
+            .unwrap_or(DEFAULT_SERVER_URL)
+    }
+
+    pub(crate) fn http_options(&self) -> HttpClientOptions {
+        HttpClientOptions {
+            ca_cert: self.ca_cert.clone(),
+            insecure: self.insecure,
+            auth_token: self.auth_token.clone(),
+            request_timeout: Some(Duration::from_secs(self.timeout)),
+            retries: Some(self.retries),
+            retry_base: Some(Duration::from_millis(self.retry_base_ms)),
+            trace_requests: self.trace_requests,
+            verify_checksums: self.verify_checksums,
+            no_atime: self.noatime,
+            max_concurrency: Some(self.max_concurrency as usize),
+            user_agent: Some(self.user_agent.clone()),
+            remote_root: self.remote_root.clone(),
+            breaker_threshold: Some(self.breaker_failures),
+            breaker_cooldown: Some(Duration::from_secs(self.breaker_cooldown)),
+            max_response_bytes: Some(
+                usize::try_from(self.max_response_mb.saturating_mul(1024 * 1024))
+                    .unwrap_or(usize::MAX),
+            ),
+        }
+    }
+
+    // Options the detached daemon needs to mount with the same settings. The
+    // auth token travels through the environment instead, keeping it out of
+    // the daemon's visible command line.
+    pub(crate) fn daemon_args(&self) -> Vec<String> {
+        let mut args = vec!["--serve-daemon".to_string()];
+        if let Some(uid) = self.uid {
+            args.extend(["--uid".to_string(), uid.to_string()]);
+        }
+        if let Some(gid) = self.gid {
+            args.extend(["--gid".to_string(), gid.to_string()]);
+        }
+        if let Some(attr_ttl) = self.attr_ttl {
+            args.extend(["--attr-ttl".to_string(), attr_ttl.to_string()]);
+        }
+        if let Some(entry_ttl) = self.entry_ttl {
+            args.extend(["--entry-ttl".to_string(), entry_ttl.to_string()]);
+        }
+        if let Some(negative_ttl) = self.negative_ttl {
+            args.extend(["--negative-ttl".to_string(), negative_ttl.to_string()]);
+        }
+        args.extend([
+            "--cache-size-mb".to_string(),
+            self.cache_size_mb.to_string(),
+        ]);
+        args.extend(["--list-depth".to_string(), self.list_depth.to_string()]);
+        if self.prewarm {
+            args.push("--prewarm".to_string());
+        }
+        if self.watch_changes {
+            args.push("--watch-changes".to_string());
+        }
+        if let Some(seconds) = self.root_refresh_secs {
+            args.extend(["--root-refresh-secs".to_string(), seconds.to_string()]);
+        }
+        if let Some(threads) = self.runtime_threads {
+            args.extend(["--runtime-threads".to_string(), threads.to_string()]);
+        }
+        if let Some(blksize) = self.blksize {
+            args.extend(["--blksize".to_string(), blksize.to_string()]);
+        }
+        if let Some(mode) = self.file_mode {
+            args.extend(["--file-mode".to_string(), format!("{mode:o}")]);
+        }
+        if let Some(mode) = self.dir_mode {
+            args.extend(["--dir-mode".to_string(), format!("{mode:o}")]);
+        }
+        if let Some(inode_db) = &self.inode_db {
+            args.extend(["--inode-db".to_string(), inode_db.display().to_string()]);
+        }
+        if self.inode_allocation != InodeAllocation::Recycle {
+            args.extend([
+                "--inode-allocation".to_string(),
+                self.inode_allocation.as_str().to_string(),
+            ]);
+        }
+        args.extend([
+            "--timeout".to_string(),
+            self.timeout.to_string(),
+            "--retries".to_string(),
+            self.retries.to_string(),
+            "--retry-base-ms".to_string(),
+            self.retry_base_ms.to_string(),
+            "--breaker-failures".to_string(),
+            self.breaker_failures.to_string(),
+            "--breaker-cooldown".to_string(),
+            self.breaker_cooldown.to_string(),
+            "--max-concurrency".to_string(),
+            self.max_concurrency.to_string(),
+            "--max-response-mb".to_string(),
+            self.max_response_mb.to_string(),
+            "--user-agent".to_string(),
+            self.user_agent.clone(),
+        ]);
+        if let Some(root) = &self.remote_root {
+            args.extend(["--remote-root".to_string(), format!("/{root}")]);
+        }
+        if let Some(ca_cert) = &self.ca_cert {
+            args.extend(["--ca-cert".to_string(), ca_cert.display().to_string()]);
+        }
+        if self.insecure {
+            args.push("--insecure".to_string());
+        }
+        if let Some(max_write) = self.max_write {
+            args.extend(["--max-write".to_string(), max_write.to_string()]);
+        }
+        if let Some(read_chunk_size) = self.read_chunk_size {
+            args.extend(["--read-chunk-size".to_string(), read_chunk_size.to_string()]);
+        }
+        if let Some(max_file_size) = self.max_file_size {
+            args.extend(["--max-file-size".to_string(), max_file_size.to_string()]);
+        }
+        if self.writeback_cache {
+            args.push("--writeback-cache".to_string());
+        }
+        if self.noatime {
+            args.push("--noatime".to_string());
+        }
+        if self.allow_other {
+            args.push("--allow-other".to_string());
+        }
+        if self.allow_root {
+            args.push("--allow-root".to_string());
+        }
+        if self.read_only {
+            args.push("--read-only".to_string());
+        }
+        if self.no_health_check {
+            args.push("--no-health-check".to_string());
+        }
+        if let Some(addr) = self.metrics_addr {
+            args.extend(["--metrics-addr".to_string(), addr.to_string()]);
+        }
+        if self.trace_requests {
+            args.push("--trace-requests".to_string());
+        }
+        if self.verify_checksums {
+            args.push("--verify-checksums".to_string());
+        }
+        if self.log_format != LogFormat::Text {
+            args.extend([
+                "--log-format".to_string(),
+                self.log_format.as_str().to_string(),
+            ]);
+        }
+        args.extend([
+            "--server-url".to_string(),
+            self.server_url().to_string(),
+            self.mountpoint.clone(),
+        ]);
+        args
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::ClientArgs;
+    use clap::Parser;
+
+    fn parse(args: &[&str]) -> Result<ClientArgs, clap::Error> {
+        ClientArgs::try_parse_from(std::iter::once("remote-fs-client").chain(args.iter().copied()))
+    }
+
+    #[test]
+    fn positional_and_flag_server_urls_are_both_accepted() {
+        assert_eq!(
+            parse(&["mnt", "http://server:3000"]).unwrap().server_url(),
+            "http://server:3000"
+        );
+        assert_eq!(
+            parse(&["--server-url", "https://server", "mnt"])
+                .unwrap()
+                .server_url(),
+            "https://server"
+        );
+        assert_eq!(
+            parse(&["mnt"]).unwrap().server_url(),
+            "http://127.0.0.1:3000"
+        );
+        assert!(parse(&["mnt", "http://a", "--server-url", "http://b"]).is_err());
+    }
+
+    #[test]
+    fn server_urls_need_an_http_scheme_and_a_host() {
+        assert!(parse(&["mnt", "https://[::1]:3000/base/"]).is_ok());
+        for url in [
+            "server:3000",
+            "localhost:3000",
+            "ftp://server",
+            "http://",
+            "not a url",
+            "http://server/?x=1",
+            "http://server/#top",
+        ] {
+            assert!(parse(&["--server-url", url, "mnt"]).is_err(), "{url}");
+        }
+    }
+
+    #[test]
+    fn unknown_flags_and_missing_mountpoint_are_rejected() {
+        assert!(parse(&["--bogus", "mnt"]).is_err());
+        assert!(parse(&[]).is_err());
+    }
+
+    #[test]
+    fn block_sizes_must_be_powers_of_two_in_range() {
+        assert_eq!(
+            parse(&["--blksize", "4096", "mnt"]).unwrap().blksize,
+            Some(4096)
+        );
+        assert!(parse(&["--blksize", "3000", "mnt"]).is_err());
+        assert!(parse(&["--blksize", "256", "mnt"]).is_err());
+        assert!(parse(&["--blksize", "8388608", "mnt"]).is_err());
+    }
+
+    #[test]
+    fn transfer_sizes_must_be_in_the_kernel_range() {
+        assert_eq!(
+            parse(&["--max-write", "65536", "mnt"]).unwrap().max_write,
+            Some(65536)
+        );
+        assert!(parse(&["--max-write", "1024", "mnt"]).is_err());
+        assert!(parse(&["--max-write", "33554432", "mnt"]).is_err());
+        assert!(parse(&["--read-chunk-size", "1024", "mnt"]).is_err());
+    }
+
+    #[test]
+    fn sharing_with_everyone_or_only_root_is_one_choice() {
+        let args = parse(&["--allow-root", "mnt"]).unwrap();
+        assert!(args.allow_root && !args.allow_other);
+        assert_eq!(
+            args.daemon_args()
+                .iter()
+                .filter(|arg| *arg == "--allow-root")
+                .count(),
+            1
+        );
+        assert!(parse(&["--allow-other", "--allow-root", "mnt"]).is_err());
+    }
+
+    #[test]
+    fn creation_modes_must_be_octal_permissions() {
+        assert_eq!(
+            parse(&["--file-mode", "640", "mnt"]).unwrap().file_mode,
+            Some(0o640)
+        );
+        assert!(parse(&["--file-mode", "0689", "mnt"]).is_err());
+        assert!(parse(&["--dir-mode", "17777", "mnt"]).is_err());
+    }
+
+    #[test]
+    fn remote_roots_drop_extra_slashes_and_refuse_dot_components() {
+        let root = |args: &[&str]| parse(args).unwrap().http_options().remote_root;
+        assert_eq!(root(&["mnt"]), None);
+        assert_eq!(root(&["--remote-root", "/", "mnt"]).as_deref(), Some(""));
+        assert_eq!(
+            root(&["--remote-root", "projects/foo/", "mnt"]).as_deref(),
+            Some("projects/foo")
+        );
+        assert!(parse(&["--remote-root", "/projects/../etc", "mnt"]).is_err());
+    }
+
+    #[test]
+    fn concurrency_caps_must_allow_at_least_one_request() {
+        assert_eq!(parse(&["mnt"]).unwrap().max_concurrency, 64);
+        assert!(parse(&["--max-concurrency", "0", "mnt"]).is_err());
+        assert!(parse(&["--max-response-mb", "0", "mnt"]).is_err());
+    }
+
+    #[test]
+    fn daemon_args_round_trip_through_the_parser() {
+        let args = parse(&[
+            "--daemon",
+            "--uid",
+            "1000",
+            "--attr-ttl",
+            "30",
+            "--entry-ttl",
+            "300",
+            "--negative-ttl",
+            "0",
+            "--cache-size-mb",
+            "0",
+            "--list-depth",
+            "3",
+            "--prewarm",
+            "--watch-changes",
+            "--root-refresh-secs",
+            "15",
+            "--inode-allocation",
+            "sequential",
+            "--runtime-threads",
+            "1",
+            "--blksize",
+            "65536",
+            "--inode-db",
+            "inodes.jsonl",
+            "--file-mode",
+            "0600",
+            "--dir-mode",
+            "0o700",
+            "--timeout",
+            "5",
+            "--retries",
+            "0",
+            "--breaker-failures",
+            "0",
+            "--breaker-cooldown",
+            "30",
+            "--max-concurrency",
+            "8",
+            "--max-response-mb",
+            "2",
+            "--user-agent",
+            "backup-job/2",
+            "--remote-root",
+            "/projects//foo/",
+            "--insecure",
+            "--no-health-check",
+            "--max-write",
+            "131072",
+            "--read-chunk-size",
+            "65536",
+            "--max-file-size",
+            "1048576",
+            "--writeback-cache",
+            "--noatime",
+            "--allow-other",
+            "--read-only",
+            "--log-format",
+            "json",
+            "--trace-requests",
+            "--verify-checksums",
+            "--metrics-addr",
+            "127.0.0.1:9400",
+            "--auth-token",
+            "secret",
+            "mnt",
+            "http://server:3000",
+        ])
+        .unwrap();
+        let forwarded = args.daemon_args();
+        assert!(!forwarded.iter().any(|arg| arg == "secret"));
+
+        let daemon = parse(&forwarded.iter().map(String::as_str).collect::<Vec<_>>()).unwrap();
+        assert!(daemon.serve_daemon && !daemon.daemon);
+        assert_eq!(daemon.uid, Some(1000));
+        assert_eq!(daemon.attr_ttl, Some(30));
+        assert_eq!(daemon.entry_ttl, Some(300));
+        assert_eq!(daemon.negative_ttl, Some(0));
+        assert_eq!(daemon.cache_size_mb, 0);
+        assert_eq!(daemon.list_depth, 3);
+        assert!(daemon.prewarm);
+        assert!(daemon.watch_changes);
+        assert_eq!(daemon.root_refresh_secs, Some(15));
+        assert_eq!(daemon.inode_allocation, super::InodeAllocation::Sequential);
+        assert_eq!(daemon.runtime_threads, Some(1));
+        assert_eq!(daemon.blksize, Some(65536));
+        assert_eq!(daemon.file_mode, Some(0o600));
+        assert_eq!(daemon.dir_mode, Some(0o700));
+        assert_eq!(
+            daemon.inode_db.as_deref(),
+            Some(std::path::Path::new("inodes.jsonl"))
+        );
+        assert!(daemon.http_options().insecure);
+        assert!(daemon.no_health_check);
+        assert_eq!(daemon.max_write, Some(131072));
+        assert_eq!(daemon.read_chunk_size, Some(65536));
+        assert_eq!(daemon.max_file_size, Some(1048576));
+        assert!(daemon.writeback_cache);
+        assert!(daemon.noatime && daemon.http_options().no_atime);
+        assert!(daemon.allow_other && !daemon.allow_root);
+        assert!(daemon.read_only);
+        assert_eq!(daemon.log_format, crate::logging::LogFormat::Json);
+        assert!(daemon.http_options().trace_requests);
+        assert!(daemon.http_options().verify_checksums);
+        assert_eq!(daemon.metrics_addr, Some("127.0.0.1:9400".parse().unwrap()));
+        assert_eq!(
+            daemon.http_options().request_timeout,
+            Some(std::time::Duration::from_secs(5))
+        );
+        assert_eq!(daemon.http_options().retries, Some(0));
+        assert_eq!(daemon.http_options().breaker_threshold, Some(0));
+        assert_eq!(
+            daemon.http_options().breaker_cooldown,
+            Some(std::time::Duration::from_secs(30))
+        );
+        assert_eq!(daemon.http_options().max_concurrency, Some(8));
+        assert_eq!(
+            daemon.http_options().max_response_bytes,
+            Some(2 * 1024 * 1024)
+        );
+        assert_eq!(
+            daemon.http_options().user_agent.as_deref(),
+            Some("backup-job/2")
+        );
+        assert_eq!(
+            daemon.http_options().remote_root.as_deref(),
+            Some("projects/foo")
+        );
+        assert_eq!(daemon.server_url(), "http://server:3000");
+        assert_eq!(daemon.mountpoint, "mnt");
+    }
+}
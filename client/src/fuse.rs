@@ -1,29 +1,109 @@
-use fuser::{FUSE_ROOT_ID, FileAttr, FileType};
+use fuser::{FUSE_ROOT_ID, FileAttr, FileType, ReplyEntry};
 use libc::{ENOENT, c_int};
 use log::{debug, info, warn};
+use remote_fs_protocol::names;
+use std::borrow::Cow;
 use std::collections::HashMap;
 use std::ffi::OsStr;
 use std::io;
+use std::os::unix::ffi::OsStrExt;
 use std::path::Path;
+use std::sync::atomic::{AtomicU64, Ordering};
 use std::sync::{Arc, Mutex};
 use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
 
 use crate::api;
+use crate::metrics::{self, Metrics};
 use crate::{
     cache::TtlLruCache,
-    ownership, remote_path,
+    ownership::MountOwner,
+    remote_path,
     writeback::{PendingFile, Writeback},
 };
 
 // Keep the long fuser::Filesystem callback implementation separate from
 // the state/cache helpers in this file.
+mod block_cache;
+mod change_feed;
+mod directory_pages;
+mod inode_store;
+mod inode_table;
+mod operation_span;
 mod ops;
-
-const TTL: Duration = Duration::from_secs(1); // Kernel attribute TTL.
+mod poll_waiters;
+mod prewarm;
+mod readahead;
+mod root_refresh;
+mod write_buffer;
+
+use block_cache::BlockCache;
+use directory_pages::{DirectoryPages, DirectorySnapshot, READDIR_PAGE_SIZE};
+use inode_store::InodeStore;
+use inode_table::InodeTable;
+use operation_span::OperationSpan;
+use poll_waiters::PollWaiters;
+use readahead::{ReadAhead, ReadPattern};
+use write_buffer::{WRITE_BUFFER_LIMIT, WriteBuffer};
+
+pub(crate) const DEFAULT_ATTR_TTL: Duration = Duration::from_secs(1); // Kernel attribute TTL.
 const TRANSFER_IO_SIZE: u32 = 4 * 1024 * 1024;
+// Most bytes one copy_file_range request asks the server to copy; the kernel
+// issues another request for the rest.
+const COPY_RANGE_LIMIT: u64 = 256 * 1024 * 1024;
+// Largest write request a mount accepts from the kernel; `--max-write` may
+// lower it.
+pub(crate) const DEFAULT_MAX_WRITE: u32 = TRANSFER_IO_SIZE;
+// Largest range one server request reads; `--read-chunk-size` may change it.
+pub(crate) const DEFAULT_READ_CHUNK_SIZE: u32 = TRANSFER_IO_SIZE;
+
+// Preferred I/O size reported as `st_blksize`; `--blksize` overrides it.
+pub(crate) const DEFAULT_BLKSIZE: u32 = 128 * 1024;
+// Permissions for created entries whose requested mode has none left after
+// the umask; `--file-mode` and `--dir-mode` override them.
+pub(crate) const DEFAULT_FILE_MODE: u32 = 0o644;
+pub(crate) const DEFAULT_DIR_MODE: u32 = 0o755;
 const ATTR_CACHE_TTL: Duration = Duration::from_secs(5);
 const DIRECTORY_CACHE_TTL: Duration = Duration::from_secs(5);
 const DIRECTORY_CACHE_MAX_ENTRIES: usize = 256;
+// Paths the server reported missing are answered locally for this long, so
+// probes of nonexistent names (shell PATH searches, build tools) cost one
+// round trip per name rather than one per probe. The kernel caches misses
+// for as long; `--negative-ttl` changes both.
+pub(crate) const DEFAULT_NEGATIVE_TTL: Duration = Duration::from_secs(2);
+const NEGATIVE_LOOKUP_MAX_ENTRIES: usize = 4096;
+// First readdir pages are kept with their ETag well past the listing cache's
+// TTL; once that expires, an unchanged directory costs the server a 304
+// instead of a full listing.
+const VALIDATED_PAGE_TTL: Duration = Duration::from_secs(10 * 60);
+const VALIDATED_PAGE_MAX_ENTRIES: usize = 1024;
+// Kernel flag asking rename to fail instead of replacing an existing target.
+#[cfg(target_os = "macos")]
+const RENAME_NOREPLACE: u32 = libc::RENAME_EXCL;
+#[cfg(not(target_os = "macos"))]
+const RENAME_NOREPLACE: u32 = libc::RENAME_NOREPLACE;
+// `fallocate` flag reserving space without growing the file. macOS has no
+// constant for it, so the Linux value is used there.
+#[cfg(target_os = "macos")]
+const FALLOC_FL_KEEP_SIZE: i32 = 0x01;
+#[cfg(not(target_os = "macos"))]
+const FALLOC_FL_KEEP_SIZE: i32 = libc::FALLOC_FL_KEEP_SIZE;
+
+// Reported by statfs when the server has no `/statfs` endpoint: 1 TiB of
+// free 4 KiB blocks and a million free inodes, enough for capacity checks.
+fn synthetic_filesystem_stats() -> api::FilesystemStats {
+    const BLOCK_SIZE: u32 = 4096;
+    const BLOCKS: u64 = (1 << 40) / BLOCK_SIZE as u64;
+    const FILES: u64 = 1_000_000;
+    api::FilesystemStats {
+        block_size: BLOCK_SIZE,
+        blocks: BLOCKS,
+        blocks_free: BLOCKS,
+        blocks_available: BLOCKS,
+        files: FILES,
+        files_free: FILES,
+        name_max: 255,
+    }
+}
 
 // Helper function to create FileAttr
 fn create_file_attr(
@@ -38,7 +118,7 @@ fn create_file_attr(
     FileAttr {
         ino,
         size,
-        blocks: size.div_ceil(512), // Calculate blocks assuming 512 byte block size
+        blocks: allocated_blocks(size, DEFAULT_BLKSIZE),
         atime: modified_at,
         mtime: modified_at,
         ctime: modified_at,
@@ -50,84 +130,180 @@ fn create_file_attr(
         gid,
         rdev: 0,
         flags: 0,
-        blksize: 512,
+        blksize: DEFAULT_BLKSIZE,
+    }
+}
+
+// `st_blocks` always counts 512-byte units; the size is rounded up to whole
+// `blksize` blocks so `du` agrees with the reported I/O size.
+fn allocated_blocks(size: u64, blksize: u32) -> u64 {
+    size.div_ceil(u64::from(blksize)) * u64::from(blksize / 512)
+}
+
+// The server's allocation, when it reports one, replaces the size-based
+// estimate: sparse files use less than their size and preallocated ones more.
+fn with_server_blocks(mut attr: FileAttr, blocks: Option<u64>) -> FileAttr {
+    if let Some(blocks) = blocks {
+        attr.blocks = blocks;
     }
+    attr
+}
+
+// `SEEK_DATA`/`SEEK_HOLE` answer for a file with no holes: every byte is
+// data and the end is the only hole. `None` at or past the end.
+fn seek_without_holes(size: u64, offset: u64, whence: api::SeekWhence) -> Option<u64> {
+    (offset < size).then_some(match whence {
+        api::SeekWhence::Data => offset,
+        api::SeekWhence::Hole => size,
+    })
+}
+
+fn with_blksize(mut attr: FileAttr, blksize: u32) -> FileAttr {
+    attr.blksize = blksize;
+    attr.blocks = allocated_blocks(attr.size, blksize);
+    attr
 }
 
 fn default_perm(kind: FileType) -> u16 {
-    if kind == FileType::Directory {
-        0o755
-    } else {
-        0o644
+    match kind {
+        FileType::Directory => 0o755,
+        FileType::Symlink => 0o777,
+        _ => 0o644,
     }
 }
 
+// Server timestamps are Unix seconds, optionally with a fractional part. A
+// malformed value is reported and replaced by the current time rather than
+// showing every such entry as dated 1970.
 fn system_time_from_unix_seconds(value: &str) -> SystemTime {
-    let seconds = value.parse::<u64>().unwrap_or(0);
-    UNIX_EPOCH + Duration::from_secs(seconds)
+    parse_unix_seconds(value).unwrap_or_else(|| {
+        warn!("Malformed timestamp {value:?} from server; using the current time");
+        SystemTime::now()
+    })
 }
 
-fn kind_from_type(type_: &str) -> FileType {
-    if type_ == "directory" {
-        FileType::Directory
+fn parse_unix_seconds(value: &str) -> Option<SystemTime> {
+    let (seconds, fraction) = value.trim().split_once('.').unwrap_or((value.trim(), ""));
+    let seconds = seconds.parse::<u64>().ok()?;
+    let nanos = if fraction.is_empty() {
+        0
+    } else if fraction.len() <= 9 && fraction.bytes().all(|byte| byte.is_ascii_digit()) {
+        format!("{fraction:0<9}").parse::<u32>().ok()?
     } else {
-        FileType::RegularFile
+        return None;
+    };
+    UNIX_EPOCH.checked_add(Duration::new(seconds, nanos))
+}
+
+// Kernel names that are not valid UTF-8 live in paths in the protocol's
+// escaped form, so inode paths stay `String`s and the server can still
+// address them.
+fn wire_name(name: &OsStr) -> Cow<'_, str> {
+    names::escape(name.as_bytes())
+}
+
+// The bytes handed back to the kernel for a listed or cached name.
+fn kernel_name(name: &str) -> Cow<'_, OsStr> {
+    match names::unescape(name) {
+        Some(Cow::Owned(bytes)) => Cow::Owned(OsStr::from_bytes(&bytes).to_os_string()),
+        _ => Cow::Borrowed(OsStr::new(name)),
     }
 }
 
-fn attr_from_directory_entry(ino: u64, entry: &api::DirectoryEntry) -> FileAttr {
-    let kind = kind_from_type(&entry.type_);
+fn kind_from_type(type_: api::EntryType) -> FileType {
+    match type_ {
+        api::EntryType::File => FileType::RegularFile,
+        api::EntryType::Directory => FileType::Directory,
+        api::EntryType::Symlink => FileType::Symlink,
+        api::EntryType::Fifo => FileType::NamedPipe,
+    }
+}
+
+fn attr_from_directory_entry(
+    ino: u64,
+    entry: &api::DirectoryEntry,
+    owner: MountOwner,
+    blksize: u32,
+) -> FileAttr {
+    let kind = kind_from_type(entry.type_);
     let perm = entry
         .mode
         .map(|mode| (mode & 0o7777) as u16)
         .unwrap_or_else(|| default_perm(kind));
     let modified_at = system_time_from_unix_seconds(&entry.modified_at);
-    let mounting_uid = unsafe { libc::getuid() as u32 };
-    let mounting_gid = unsafe { libc::getgid() as u32 };
 
-    create_file_attr(
+    let mut attr = create_file_attr(
         ino,
         kind,
         entry.size,
         perm,
-        ownership::remote_or_mounting_user(entry.uid, mounting_uid),
-        ownership::remote_or_mounting_user(entry.gid, mounting_gid),
+        owner.uid(entry.uid),
+        owner.gid(entry.gid),
         modified_at,
-    )
+    );
+    if let Some(accessed_at) = &entry.accessed_at {
+        attr.atime = system_time_from_unix_seconds(accessed_at);
+    }
+    if let Some(changed_at) = &entry.changed_at {
+        attr.ctime = system_time_from_unix_seconds(changed_at);
+    }
+    if let Some(nlink) = entry.nlink {
+        attr.nlink = u32::try_from(nlink).unwrap_or(u32::MAX);
+    }
+    with_server_blocks(with_blksize(attr, blksize), entry.blocks)
 }
 
-fn attr_from_remote_metadata(ino: u64, metadata: &api::RemoteMetadata) -> FileAttr {
-    let kind = kind_from_type(&metadata.type_);
+fn attr_from_remote_metadata(
+    ino: u64,
+    metadata: &api::RemoteMetadata,
+    owner: MountOwner,
+    blksize: u32,
+) -> FileAttr {
+    let kind = kind_from_type(metadata.type_);
     let perm = metadata
         .mode
         .map(|mode| (mode & 0o7777) as u16)
         .unwrap_or_else(|| default_perm(kind));
     let modified_at = system_time_from_unix_seconds(&metadata.modified_at);
-    let mounting_uid = unsafe { libc::getuid() as u32 };
-    let mounting_gid = unsafe { libc::getgid() as u32 };
 
-    create_file_attr(
+    let mut attr = create_file_attr(
         ino,
         kind,
         metadata.size,
         perm,
-        ownership::remote_or_mounting_user(metadata.uid, mounting_uid),
-        ownership::remote_or_mounting_user(metadata.gid, mounting_gid),
+        owner.uid(metadata.uid),
+        owner.gid(metadata.gid),
         modified_at,
-    )
+    );
+    if let Some(accessed_at) = &metadata.accessed_at {
+        attr.atime = system_time_from_unix_seconds(accessed_at);
+    }
+    if let Some(changed_at) = &metadata.changed_at {
+        attr.ctime = system_time_from_unix_seconds(changed_at);
+    }
+    if let Some(nlink) = metadata.nlink {
+        attr.nlink = u32::try_from(nlink).unwrap_or(u32::MAX);
+    }
+    with_server_blocks(with_blksize(attr, blksize), metadata.blocks)
 }
 
-fn attr_from_pending(ino: u64, pending: &PendingFile) -> io::Result<FileAttr> {
+fn attr_from_pending(
+    ino: u64,
+    pending: &PendingFile,
+    owner: MountOwner,
+    blksize: u32,
+) -> io::Result<FileAttr> {
     let metadata = pending.metadata()?;
-    Ok(create_file_attr(
+    let attr = create_file_attr(
         ino,
         FileType::RegularFile,
         metadata.size,
         (metadata.mode & 0o7777) as u16,
-        unsafe { libc::getuid() as u32 },
-        unsafe { libc::getgid() as u32 },
+        owner.uid(None),
+        owner.gid(None),
         system_time_from_unix_seconds(&metadata.modified_at),
-    ))
+    );
+    Ok(with_blksize(attr, blksize))
 }
 
 fn directory_entry_from_pending(pending: &PendingFile) -> io::Result<api::DirectoryEntry> {
@@ -139,19 +315,28 @@ fn directory_entry_from_pending(pending: &PendingFile) -> io::Result<api::Direct
             .next()
             .unwrap_or_default()
             .to_string(),
-        type_: "file".to_string(),
+        type_: api::EntryType::File,
         size: metadata.size,
         modified_at: metadata.modified_at,
         mode: Some(metadata.mode),
         uid: None,
         gid: None,
+        target: None,
+        accessed_at: None,
+        changed_at: None,
+        nlink: None,
+        blocks: None,
+        content_type: None,
+        name_encoding: None,
     })
 }
 
-fn apply_umask(mode: u32, umask: u32, fallback: u16) -> u32 {
+// The requested mode minus the caller's umask, or `default_mode` when the
+// request carries no permission bits at all.
+fn apply_umask(mode: u32, umask: u32, default_mode: u32) -> u32 {
     let effective_mode = (mode & !umask) & 0o7777;
     if effective_mode == 0 {
-        fallback as u32
+        default_mode
     } else {
         effective_mode
     }
@@ -164,23 +349,92 @@ fn time_or_now(time: fuser::TimeOrNow) -> SystemTime {
     }
 }
 
-fn errno_from_api_error(error: &reqwest::Error) -> c_int {
+// The one place a server failure becomes an errno; the per-operation helpers
+// below only reinterpret statuses whose meaning depends on the request.
+// Transport failures, undecodable replies, and `5xx` all surface as EIO.
+fn errno_for(error: &api::ApiError) -> c_int {
+    if matches!(error, api::ApiError::Timeout) {
+        return libc::ETIMEDOUT;
+    }
     match error.status().map(|status| status.as_u16()) {
         Some(400) => libc::EINVAL,
         Some(401 | 403) => libc::EACCES,
         Some(404) => ENOENT,
         Some(409) => libc::EEXIST,
+        Some(413) => libc::EFBIG,
+        Some(507) => libc::ENOSPC,
         _ => libc::EIO,
     }
 }
 
-fn errno_from_rmdir_error(error: &reqwest::Error) -> c_int {
+fn errno_from_rmdir_error(error: &api::ApiError) -> c_int {
     match error.status().map(|status| status.as_u16()) {
+        Some(400) => libc::ENOTDIR,
         Some(409) => libc::ENOTEMPTY,
-        _ => errno_from_api_error(error),
+        _ => errno_for(error),
+    }
+}
+
+fn errno_from_unlink_error(error: &api::ApiError) -> c_int {
+    match error.status().map(|status| status.as_u16()) {
+        Some(409) => libc::EISDIR,
+        _ => errno_for(error),
     }
 }
 
+// Answered with the server's guessed MIME type for files that do not store
+// an attribute of this name themselves, the name file managers look for.
+const MIME_TYPE_XATTR: &str = "user.mime_type";
+
+// Linux reports a missing attribute as ENODATA; macOS spells it ENOATTR.
+#[cfg(target_os = "macos")]
+const ENOATTR: c_int = libc::ENOATTR;
+#[cfg(not(target_os = "macos"))]
+const ENOATTR: c_int = libc::ENODATA;
+
+fn errno_from_xattr_error(error: &api::ApiError) -> c_int {
+    match error.status().map(|status| status.as_u16()) {
+        Some(400) => libc::ENOTSUP,
+        Some(404) => ENOATTR,
+        _ => errno_for(error),
+    }
+}
+
+// Answers the getxattr/listxattr size convention: a zero-sized request asks
+// only for the length, and a too-small buffer is ERANGE.
+fn reply_xattr_bytes(reply: fuser::ReplyXattr, bytes: &[u8], size: u32) {
+    if size == 0 {
+        reply.size(bytes.len() as u32);
+    } else if bytes.len() > size as usize {
+        reply.error(libc::ERANGE);
+    } else {
+        reply.data(bytes);
+    }
+}
+
+// Replies that can carry an errno, so one guard can refuse any callback.
+trait ErrorReply {
+    fn error(self, errno: c_int);
+}
+
+macro_rules! error_reply {
+    ($($reply:ty),*) => {
+        $(impl ErrorReply for $reply {
+            fn error(self, errno: c_int) {
+                <$reply>::error(self, errno);
+            }
+        })*
+    };
+}
+
+error_reply!(
+    fuser::ReplyEntry,
+    fuser::ReplyEmpty,
+    fuser::ReplyCreate,
+    fuser::ReplyWrite,
+    fuser::ReplyAttr
+);
+
 fn errno_from_io_error(error: &io::Error) -> c_int {
     match error.kind() {
         io::ErrorKind::NotFound => ENOENT,
@@ -209,6 +463,22 @@ impl CachedAttr {
     fn is_fresh(&self) -> bool {
         self.attr.ino == FUSE_ROOT_ID || self.refreshed_at.elapsed() <= ATTR_CACHE_TTL
     }
+
+    // Makes the next use refetch the attributes, as if the TTL had run out.
+    fn expire(&mut self) {
+        if let Some(expired) = Instant::now().checked_sub(ATTR_CACHE_TTL + Duration::from_secs(1)) {
+            self.refreshed_at = expired;
+        }
+    }
+}
+
+// A first listing page and the ETag the server sent with it. Entries are
+// kept without journaled files merged in, since those are merged per pass.
+#[derive(Clone)]
+struct ValidatedPage {
+    etag: String,
+    entries: Vec<api::DirectoryEntry>,
+    next_cursor: Option<String>,
 }
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
@@ -222,46 +492,183 @@ struct OpenHandle {
     ino: u64,
     path: String,
     kind: HandleKind,
+    // Flags passed to open/create; only the access mode is consulted.
+    flags: i32,
     dirty: bool,
+    read_pattern: ReadPattern,
+    // Server handle of the staged write a write-only `O_TRUNC` open sends its
+    // bytes to; flush renames it over the file.
+    staged_write: Option<String>,
+}
+
+impl OpenHandle {
+    fn allows_reads(&self) -> bool {
+        self.flags & libc::O_ACCMODE != libc::O_WRONLY
+    }
+
+    fn allows_writes(&self) -> bool {
+        self.flags & libc::O_ACCMODE != libc::O_RDONLY
+    }
 }
 
 // Client-side state that gives stateless HTTP paths stable FUSE inodes,
-// open file handles, and short-lived directory/attribute caches.
+// open file handles, and short-lived directory/attribute caches. Clones
+// share all of that state.
+#[derive(Clone)]
 pub struct RemoteFs {
     server_addr: String,
+    owner: MountOwner,
+    attr_ttl: Duration,
+    // Lifetime of a positive lookup in the kernel, and of the attributes
+    // sent with it; `--entry-ttl` sets it apart from `attr_ttl`.
+    entry_ttl: Duration,
+    // Lifetime of a lookup miss, both in `negative_lookups` and as a kernel
+    // negative entry. Zero leaves misses uncached.
+    negative_ttl: Duration,
+    // Reported as `st_blksize`; readahead windows and buffered uploads are
+    // aligned to it.
+    blksize: u32,
+    // Set by `--read-only`: every mutating operation fails with EROFS
+    // before reaching the server.
+    read_only: bool,
+    // Largest write the kernel may send; `--max-write` lowers it.
+    max_write: u32,
+    // Reads the kernel asks for are fetched in server requests of at most
+    // this many bytes; `--read-chunk-size` sets it.
+    read_chunk_size: u32,
+    // Set by `--max-file-size`: writes past this many bytes, and reads past
+    // it in larger files, fail with EFBIG; reported sizes stay true.
+    max_file_size: Option<u64>,
+    // Set by `--writeback-cache`: the kernel holds written pages and sends
+    // them later, reading through any open handle to fill partial pages.
+    writeback_cache: bool,
+    // Set in `init` once the kernel agrees to readdirplus. Without it every
+    // readdir is plain, so listings can skip attributes.
+    readdirplus: bool,
+    // Levels fetched per listing; anything above 1 prefetches subdirectories.
+    list_depth: u32,
+    // Set by `--root-refresh-secs`: the root listing is fetched again on
+    // this timer, and its cached copy does not expire in between.
+    root_refresh: Option<Duration>,
+    // Bumped whenever a change here invalidates the root listing, so a root
+    // refresh can tell its listing may predate a create or rename into `/`.
+    root_generation: Arc<AtomicU64>,
+    // Modes for created files and directories when the request has none.
+    file_mode: u32,
+    dir_mode: u32,
     runtime: Arc<tokio::runtime::Runtime>,
-    inode_map: Arc<Mutex<HashMap<u64, CachedAttr>>>,
-    path_to_inode: Arc<Mutex<HashMap<String, u64>>>,
-    next_inode: Arc<Mutex<u64>>,
+    inodes: Arc<Mutex<InodeTable>>,
     directory_cache: Arc<Mutex<TtlLruCache<String, Vec<api::DirectoryEntry>>>>,
+    // Full paths known to be absent; dropped whenever their parent changes.
+    negative_lookups: Arc<Mutex<TtlLruCache<String, ()>>>,
+    // Last first-page listing per directory inode, as the server sent it.
+    validated_pages: Arc<Mutex<TtlLruCache<u64, ValidatedPage>>>,
     open_handles: Arc<Mutex<HashMap<u64, OpenHandle>>>,
+    directory_snapshots: Arc<Mutex<HashMap<u64, DirectorySnapshot>>>,
+    // Writes to existing files held per handle until flush, fsync, release,
+    // or a write that cannot be merged.
+    write_buffers: Arc<Mutex<HashMap<u64, WriteBuffer>>>,
     next_handle: Arc<Mutex<u64>>,
+    readahead: ReadAhead,
+    poll_waiters: PollWaiters,
+    block_cache: Arc<Mutex<BlockCache>>,
     writeback: Writeback,
+    metrics: Arc<Metrics>,
 }
 
-impl RemoteFs {
-    pub fn new(server_addr: &str) -> io::Result<Self> {
-        let rt = Arc::new(tokio::runtime::Runtime::new().expect("Failed to create Tokio runtime"));
-        let writeback = Writeback::new(server_addr, rt.handle().clone())?;
-        writeback.start_recovery();
-        let mut inode_map_val = HashMap::new();
-        let mut path_to_inode_val = HashMap::new();
+// `--runtime-threads 1` gives one `current_thread` runtime. Readahead and
+// uploads are spawned tasks, which only such a runtime's `block_on` drives,
+// so a thread of its own blocks on it for the life of the process and they
+// progress while no FUSE call is waiting on a request. That thread keeps the
+// runtime, which is never dropped.
+fn build_runtime(threads: Option<usize>) -> io::Result<Arc<tokio::runtime::Runtime>> {
+    let runtime = Arc::new(match threads {
+        Some(1) => tokio::runtime::Builder::new_current_thread()
+            .enable_all()
+            .build()?,
+        Some(threads) => tokio::runtime::Builder::new_multi_thread()
+            .worker_threads(threads)
+            .enable_all()
+            .build()?,
+        None => tokio::runtime::Runtime::new()?,
+    });
+    if threads == Some(1) {
+        let driver = runtime.clone();
+        std::thread::Builder::new()
+            .name("runtime".to_string())
+            .spawn(move || driver.block_on(std::future::pending::<()>()))?;
+    }
+    Ok(runtime)
+}
 
-        let uid = unsafe { libc::getuid() as u32 };
-        let gid = unsafe { libc::getgid() as u32 };
+// Mount-wide settings taken from the command line.
+pub struct MountSettings<'a> {
+    pub owner: MountOwner,
+    pub attr_ttl: Duration,
+    pub entry_ttl: Duration,
+    pub negative_ttl: Duration,
+    pub block_cache_bytes: u64,
+    pub inode_db: Option<&'a Path>,
+    // Where pending uploads are journaled instead of the default location.
+    pub journal_dir: Option<&'a Path>,
+    pub recycle_inodes: bool,
+    pub list_depth: u32,
+    pub root_refresh: Option<Duration>,
+    pub runtime_threads: Option<usize>,
+    pub blksize: u32,
+    pub read_only: bool,
+    pub max_write: u32,
+    pub read_chunk_size: u32,
+    pub max_file_size: Option<u64>,
+    pub writeback_cache: bool,
+    pub file_mode: u32,
+    pub dir_mode: u32,
+}
 
+impl RemoteFs {
+    pub fn new(server_addr: &str, settings: MountSettings<'_>) -> io::Result<Self> {
+        let MountSettings {
+            owner,
+            attr_ttl,
+            entry_ttl,
+            negative_ttl,
+            block_cache_bytes,
+            inode_db,
+            journal_dir,
+            recycle_inodes,
+            list_depth,
+            root_refresh,
+            runtime_threads,
+            blksize,
+            read_only,
+            max_write,
+            read_chunk_size,
+            max_file_size,
+            writeback_cache,
+            file_mode,
+            dir_mode,
+        } = settings;
+        let inode_store = inode_db.map(InodeStore::open).transpose()?;
+        let rt = build_runtime(runtime_threads)?;
+        let writeback = Writeback::new(server_addr, journal_dir, rt.handle().clone())?;
+        // A read-only mount sends no writes at all; journaled uploads wait
+        // for the next writable mount.
+        if !read_only {
+            writeback.start_recovery();
+        }
         // Add root directory
-        let root_attr = create_file_attr(
-            FUSE_ROOT_ID,
-            FileType::Directory,
-            0,
-            0o755,
-            uid,
-            gid,
-            SystemTime::now(),
+        let root_attr = with_blksize(
+            create_file_attr(
+                FUSE_ROOT_ID,
+                FileType::Directory,
+                0,
+                0o755,
+                owner.uid(None),
+                owner.gid(None),
+                SystemTime::now(),
+            ),
+            blksize,
         );
-        inode_map_val.insert(FUSE_ROOT_ID, CachedAttr::new(root_attr));
-        path_to_inode_val.insert("/".to_string(), FUSE_ROOT_ID);
 
         info!(
             "RemoteFs initialized. Root inode: {}, Path: /",
@@ -270,38 +677,127 @@ impl RemoteFs {
 
         Ok(RemoteFs {
             server_addr: server_addr.to_string(),
+            owner,
+            attr_ttl,
+            entry_ttl,
+            negative_ttl,
+            blksize,
+            read_only,
+            max_write,
+            read_chunk_size,
+            max_file_size,
+            writeback_cache,
+            readdirplus: false,
+            list_depth: list_depth.max(1),
+            root_refresh,
+            root_generation: Arc::new(AtomicU64::new(0)),
+            file_mode,
+            dir_mode,
             runtime: rt,
-            inode_map: Arc::new(Mutex::new(inode_map_val)),
-            path_to_inode: Arc::new(Mutex::new(path_to_inode_val)),
-            next_inode: Arc::new(Mutex::new(FUSE_ROOT_ID + 1)),
+            inodes: Arc::new(Mutex::new(InodeTable::new(
+                root_attr,
+                inode_store,
+                recycle_inodes,
+            ))),
             directory_cache: Arc::new(Mutex::new(TtlLruCache::new(
                 DIRECTORY_CACHE_MAX_ENTRIES,
                 DIRECTORY_CACHE_TTL,
             ))),
+            negative_lookups: Arc::new(Mutex::new(TtlLruCache::new(
+                NEGATIVE_LOOKUP_MAX_ENTRIES,
+                negative_ttl,
+            ))),
+            validated_pages: Arc::new(Mutex::new(TtlLruCache::new(
+                VALIDATED_PAGE_MAX_ENTRIES,
+                VALIDATED_PAGE_TTL,
+            ))),
             open_handles: Arc::new(Mutex::new(HashMap::new())),
+            directory_snapshots: Arc::new(Mutex::new(HashMap::new())),
+            write_buffers: Arc::new(Mutex::new(HashMap::new())),
             next_handle: Arc::new(Mutex::new(1)),
+            readahead: ReadAhead::default(),
+            poll_waiters: PollWaiters::default(),
+            block_cache: Arc::new(Mutex::new(BlockCache::new(block_cache_bytes))),
             writeback,
+            metrics: metrics::global().clone(),
         })
     }
 
+    // Checks the server before mounting so a dead server, a rejected token,
+    // or a missing storage root fails the mount instead of every later
+    // operation. Servers predating `/healthz` answer 404, which still proves
+    // they are reachable; the one-entry root listing covers authentication.
+    pub fn check_server(&self) -> Result<(), api::ApiError> {
+        match self.runtime.block_on(api::check_health(&self.server_addr)) {
+            Ok(()) | Err(api::ApiError::NotFound) => {}
+            Err(error) => return Err(error),
+        }
+        self.runtime
+            .block_on(api::list_directory_page(
+                &self.server_addr,
+                "",
+                None,
+                1,
+                None,
+            ))
+            .map(|_| ())
+    }
+
+    // Resolves the server's host and opens a pooled connection in the
+    // background while the mount is set up, so the first operation does not
+    // pay for DNS and the TCP and TLS handshakes. `check_server` leaves its
+    // connection pooled the same way, so this is for `--no-health-check`.
+    pub fn warm_up_connection(&self) {
+        let server_addr = self.server_addr.clone();
+        self.runtime.spawn(async move {
+            let started = Instant::now();
+            match api::check_health(&server_addr).await {
+                Ok(()) | Err(api::ApiError::NotFound) => {
+                    debug!("Connected to the server in {:?}", started.elapsed());
+                }
+                Err(error) => debug!("Could not connect to the server ahead of time: {error}"),
+            }
+        });
+    }
+
     // Finds the cached path that belongs to an inode.
     fn path_for_inode(&self, ino: u64) -> Option<String> {
-        let path_map = self.path_to_inode.lock().unwrap();
-        path_map.iter().find_map(|(path, &inode)| {
-            if inode == ino {
-                Some(path.clone())
-            } else {
-                None
-            }
-        })
+        self.inodes
+            .lock()
+            .unwrap()
+            .path_for(ino)
+            .map(str::to_string)
     }
 
-    // Builds a full child path from a parent inode and a file name.
-    fn child_path(&self, parent: u64, name: &OsStr) -> Result<String, c_int> {
-        let name_str = name.to_str().ok_or(libc::EINVAL)?;
-        let parent_path = self.path_for_inode(parent).ok_or(ENOENT)?;
+    fn inode_for_path(&self, path: &str) -> Option<u64> {
+        self.inodes.lock().unwrap().ino_for(path)
+    }
+
+    // Must be called before every reply that hands the kernel an inode.
+    fn remember_lookup(&self, ino: u64) {
+        self.inodes.lock().unwrap().add_lookup(ino);
+    }
 
-        Ok(remote_path::child(&parent_path, name_str))
+    fn generation(&self, ino: u64) -> u64 {
+        self.inodes.lock().unwrap().generation(ino)
+    }
+
+    // Answers EROFS on a read-only mount; otherwise hands the reply back for
+    // the callback to use.
+    fn writable<R: ErrorReply>(&self, reply: R) -> Option<R> {
+        if self.read_only {
+            reply.error(libc::EROFS);
+            return None;
+        }
+        Some(reply)
+    }
+
+    // Builds the full path of `name` inside a parent directory inode.
+    // `None` means the parent is no longer known, which callers report as
+    // ENOENT rather than guessing a location.
+    fn full_path(&self, parent: u64, name: &OsStr) -> Option<String> {
+        let parent_path = self.path_for_inode(parent)?;
+        Some(remote_path::child(&parent_path, &wire_name(name)))
     }
 
     fn directory_cache_key(path: &str) -> String {
@@ -312,14 +808,16 @@ impl RemoteFs {
         }
     }
 
-    fn allocate_inode(&self) -> u64 {
-        let mut next_inode = self.next_inode.lock().unwrap();
-        let ino = *next_inode;
-        *next_inode += 1;
-        ino
+    fn allocate_inode(&self, path: &str) -> u64 {
+        self.inodes.lock().unwrap().allocate_for(path)
     }
 
-    fn allocate_handle(&self, ino: u64, path: String, kind: HandleKind, _flags: i32) -> u64 {
+    fn allocate_handle(&self, ino: u64, path: String, kind: HandleKind, mut flags: i32) -> u64 {
+        // The kernel reads pages it only partly overwrites through whichever
+        // handle flushes them, including write-only ones.
+        if self.writeback_cache && flags & libc::O_ACCMODE == libc::O_WRONLY {
+            flags = flags & !libc::O_ACCMODE | libc::O_RDWR;
+        }
         let mut next_handle = self.next_handle.lock().unwrap();
         let handle = *next_handle;
         *next_handle += 1;
@@ -330,13 +828,22 @@ impl RemoteFs {
                 ino,
                 path,
                 kind,
+                flags,
                 dirty: false,
+                read_pattern: ReadPattern::default(),
+                staged_write: None,
             },
         );
 
         handle
     }
 
+    fn stage_handle_writes(&self, fh: u64, staged_write: Option<String>) {
+        if let Some(handle) = self.open_handles.lock().unwrap().get_mut(&fh) {
+            handle.staged_write = staged_write;
+        }
+    }
+
     fn handle_for(&self, fh: u64, ino: u64, kind: HandleKind) -> Result<OpenHandle, c_int> {
         if fh == 0 {
             return Err(libc::EBADF);
@@ -350,6 +857,87 @@ impl RemoteFs {
         }
     }
 
+    // EFBIG when a write or resize reaching `end` would pass `--max-file-size`.
+    fn check_file_size(&self, end: u64) -> Result<(), c_int> {
+        match self.max_file_size {
+            Some(limit) if end > limit => Err(libc::EFBIG),
+            _ => Ok(()),
+        }
+    }
+
+    // EFBIG when a copy_file_range would take the destination past
+    // `--max-file-size`. The kernel asks for far more than it means to copy,
+    // so only what one request copies counts: at most COPY_RANGE_LIMIT, and
+    // no more than the source holds past `offset_in` as far as its cached
+    // size says. The server still refuses a copy that its own count puts
+    // over the limit.
+    fn check_copy_size(
+        &self,
+        ino_in: u64,
+        offset_in: u64,
+        offset_out: u64,
+        len: u64,
+    ) -> Result<(), c_int> {
+        let mut copied = len.min(COPY_RANGE_LIMIT);
+        if let Some(attr) = self.attr_for_inode(ino_in) {
+            copied = copied.min(attr.size.saturating_sub(offset_in));
+        }
+        self.check_file_size(offset_out.saturating_add(copied))
+    }
+
+    // EFBIG for a read reaching past `--max-file-size` in a file larger
+    // than that. Reads are never cut short instead: the kernel would take a
+    // short read for the end of the file and shrink its cached size. An
+    // inode with no attr here is one the read itself will fail to find.
+    fn check_read_size(&self, ino: u64, offset: u64, size: u32) -> Result<(), c_int> {
+        let Some(limit) = self.max_file_size else {
+            return Ok(());
+        };
+        if offset.saturating_add(u64::from(size)) <= limit {
+            return Ok(());
+        }
+        let known_size = self
+            .inodes
+            .lock()
+            .unwrap()
+            .attr(ino)
+            .map(|cached| cached.attr.size);
+        match known_size {
+            Some(file_size) if file_size > limit => Err(libc::EFBIG),
+            _ => Ok(()),
+        }
+    }
+
+    // Notes a server read on `fh` and prefetches what follows once the handle
+    // reads sequentially. Short reads mean EOF, so nothing is prefetched then.
+    fn record_handle_read(
+        &self,
+        fh: u64,
+        ino: u64,
+        path: &str,
+        offset: u64,
+        size: u32,
+        read: usize,
+    ) {
+        let sequential = match self.open_handles.lock().unwrap().get_mut(&fh) {
+            Some(handle) => handle.read_pattern.record(offset, read as u64),
+            None => false,
+        };
+        if sequential && read == size as usize {
+            // Starting on a block boundary keeps every window aligned once the
+            // reader is past the first one.
+            let next = offset + read as u64;
+            self.readahead.start(
+                &self.runtime,
+                ino,
+                &self.server_addr,
+                remote_path::api(path),
+                next - next % u64::from(self.blksize),
+                self.read_chunk_size,
+            );
+        }
+    }
+
     fn mark_handle_dirty(&self, fh: u64) {
         if let Some(handle) = self.open_handles.lock().unwrap().get_mut(&fh) {
             handle.dirty = true;
@@ -386,34 +974,459 @@ impl RemoteFs {
         self.writeback.flush(&pending).map(Some)
     }
 
-    fn cache_attr(&self, path: String, attr: FileAttr) {
-        self.inode_map
+    // Uploads a journaled file before flush/fsync replies so a failed save is
+    // reported to the application. `sync_metadata` also adopts the server's
+    // attributes for the inode; `fdatasync` skips that step.
+    fn sync_pending(&self, ino: u64, path: &str, sync_metadata: bool) -> Result<(), c_int> {
+        match self.materialize_pending(path) {
+            Ok(Some(metadata)) => {
+                if sync_metadata {
+                    self.update_cached_attr(
+                        ino,
+                        attr_from_remote_metadata(ino, &metadata, self.owner, self.blksize),
+                    );
+                }
+                Ok(())
+            }
+            Ok(None) => Ok(()),
+            // Uploads are paused; the journal already holds the data durably.
+            Err(error) if error.kind() == io::ErrorKind::WouldBlock => {
+                warn!("Upload of {path} is paused; keeping it in the local journal");
+                Ok(())
+            }
+            Err(error) => {
+                log::error!("Failed to upload {path}: {error}");
+                Err(libc::EIO)
+            }
+        }
+    }
+
+    fn staged_write_for(&self, fh: u64) -> Option<String> {
+        self.open_handles
             .lock()
             .unwrap()
-            .insert(attr.ino, CachedAttr::new(attr));
-        self.path_to_inode.lock().unwrap().insert(path, attr.ino);
+            .get(&fh)
+            .and_then(|handle| handle.staged_write.clone())
+    }
+
+    // Write-only `O_TRUNC` opens of server files stage their bytes on the
+    // server until flush, so readers never see the file half written. Other
+    // truncating opens empty the file in place, as do all of them with
+    // `--writeback-cache`, whose kernel reads back through the handle.
+    fn open_truncated(
+        &self,
+        ino: u64,
+        path: &str,
+        flags: i32,
+    ) -> Result<(FileAttr, Option<String>), c_int> {
+        let journaled = self
+            .writeback
+            .get(path)
+            .is_some_and(|pending| !pending.is_committed());
+        if flags & libc::O_ACCMODE != libc::O_WRONLY || journaled || self.writeback_cache {
+            return self
+                .truncate_existing_file(ino, path)
+                .map(|attr| (attr, None));
+        }
+
+        let mut attr = self.attr_for_inode(ino).ok_or(ENOENT)?;
+        let staged_write = self
+            .runtime
+            .block_on(api::begin_temp_write(
+                &self.server_addr,
+                remote_path::api(path),
+            ))
+            .map_err(|error| errno_for(&error))?;
+        attr.size = 0;
+        attr.blocks = 0;
+        self.invalidate_file_data(ino);
+        self.update_cached_attr(ino, attr);
+        Ok((attr, Some(staged_write)))
+    }
+
+    // Opens a file that a create found already on the server, as a stale
+    // negative lookup allows: O_EXCL fails with EEXIST, O_TRUNC empties it,
+    // and otherwise it opens as it is. One `GET /stat` checks, rather than
+    // listing the whole parent. `None` when nothing is there.
+    fn open_existing_for_create(
+        &self,
+        path: &str,
+        flags: i32,
+    ) -> Result<Option<(FileAttr, Option<String>)>, c_int> {
+        let Some(existing) = self.stat_path(path)? else {
+            return Ok(None);
+        };
+        if existing.kind == FileType::Directory {
+            return Err(libc::EISDIR);
+        }
+        if flags & libc::O_EXCL != 0 {
+            return Err(libc::EEXIST);
+        }
+        if flags & libc::O_TRUNC == 0 {
+            return Ok(Some((existing, None)));
+        }
+        let opened = self.open_truncated(existing.ino, path, flags)?;
+        self.invalidate_directory_cache_for_path(path);
+        Ok(Some(opened))
+    }
+
+    // Renames a handle's staged write over its file once its buffered bytes
+    // are on the server. Later writes through the handle go to the file.
+    fn commit_staged_write(&self, fh: u64) -> Result<(), c_int> {
+        let Some((ino, path, staged_write)) = self
+            .open_handles
+            .lock()
+            .unwrap()
+            .get_mut(&fh)
+            .and_then(|handle| {
+                let staged_write = handle.staged_write.take()?;
+                Some((handle.ino, handle.path.clone(), staged_write))
+            })
+        else {
+            return Ok(());
+        };
+        match self
+            .runtime
+            .block_on(api::commit_temp_write(&self.server_addr, &staged_write))
+        {
+            Ok(metadata) => {
+                self.invalidate_file_data(ino);
+                let attr = attr_from_remote_metadata(ino, &metadata, self.owner, self.blksize);
+                self.update_cached_attr(ino, attr);
+                self.invalidate_directory_cache_for_path(&path);
+                Ok(())
+            }
+            Err(error) => {
+                log::error!("Failed to replace {path} with its staged write: {error}");
+                Err(libc::EIO)
+            }
+        }
+    }
+
+    // Drops a staged write whose bytes can no longer all reach the server.
+    fn abort_staged_write(&self, fh: u64) {
+        let staged_write = self
+            .open_handles
+            .lock()
+            .unwrap()
+            .get_mut(&fh)
+            .and_then(|handle| handle.staged_write.take());
+        if let Some(staged_write) = staged_write
+            && let Err(error) = self
+                .runtime
+                .block_on(api::abort_temp_write(&self.server_addr, &staged_write))
+        {
+            warn!("Could not discard staged write {staged_write}: {error}");
+        }
+    }
+
+    // Empties an existing file for `O_TRUNC` opens, keeping journaled files local.
+    fn truncate_existing_file(&self, ino: u64, path: &str) -> Result<FileAttr, c_int> {
+        self.invalidate_file_data(ino);
+        if let Some(pending) = self.writeback.get(path)
+            && !pending.is_committed()
+        {
+            match pending.resize(0) {
+                Ok(()) => {
+                    let attr = attr_from_pending(ino, &pending, self.owner, self.blksize)
+                        .map_err(|error| errno_from_io_error(&error))?;
+                    self.update_cached_attr(ino, attr);
+                    return Ok(attr);
+                }
+                Err(error)
+                    if matches!(
+                        error.kind(),
+                        io::ErrorKind::WouldBlock | io::ErrorKind::AlreadyExists
+                    ) =>
+                {
+                    self.materialize_pending(path)
+                        .map_err(|error| errno_from_io_error(&error))?;
+                }
+                Err(error) => return Err(errno_from_io_error(&error)),
+            }
+        }
+
+        let metadata = self
+            .runtime
+            .block_on(api::overwrite_file(
+                &self.server_addr,
+                remote_path::api(path),
+                None,
+            ))
+            .map_err(|error| errno_for(&error))?;
+        let attr = attr_from_remote_metadata(ino, &metadata, self.owner, self.blksize);
+        self.update_cached_attr(ino, attr);
+        Ok(attr)
+    }
+
+    fn cache_attr(&self, path: String, attr: FileAttr) {
+        let attr = self.with_buffered_size(attr);
+        let mut inodes = self.inodes.lock().unwrap();
+        self.invalidate_file_data_if_changed(
+            inodes.attr(attr.ino).map(|cached| cached.attr),
+            &attr,
+        );
+        inodes.insert(path, attr);
     }
 
     fn update_cached_attr(&self, ino: u64, attr: FileAttr) {
-        self.inode_map
+        let attr = self.with_buffered_size(attr);
+        let mut inodes = self.inodes.lock().unwrap();
+        self.invalidate_file_data_if_changed(inodes.attr(ino).map(|cached| cached.attr), &attr);
+        inodes.set_attr(ino, attr);
+    }
+
+    // Server metadata lags behind writes still held in a handle's buffer, so
+    // the size reported to the kernel covers the buffered range too.
+    fn with_buffered_size(&self, mut attr: FileAttr) -> FileAttr {
+        let buffered_end = self
+            .write_buffers
+            .lock()
+            .unwrap()
+            .values()
+            .filter(|buffer| buffer.ino == attr.ino)
+            .map(WriteBuffer::end)
+            .max();
+        if let Some(end) = buffered_end
+            && end > attr.size
+        {
+            attr.size = end;
+            attr.blocks = allocated_blocks(end, attr.blksize);
+        }
+        attr
+    }
+
+    // Merges a write into the handle's buffer. A write that leaves a gap or
+    // would overflow the buffer sends the buffered bytes first; writes of a
+    // whole buffer or more go straight to the server. Returns how many bytes
+    // of `data` were accepted, which is short only when the server stored
+    // part of a direct write.
+    fn buffer_write(
+        &self,
+        fh: u64,
+        ino: u64,
+        path: &str,
+        offset: u64,
+        data: &[u8],
+    ) -> Result<usize, c_int> {
+        let previous = {
+            let mut buffers = self.write_buffers.lock().unwrap();
+            if let Some(buffer) = buffers.get_mut(&fh)
+                && buffer.accepts(offset, data.len())
+            {
+                buffer.write(offset, data);
+                drop(buffers);
+                self.note_buffered_write(ino);
+                return Ok(data.len());
+            }
+            buffers.remove(&fh)
+        };
+        if let Some(mut previous) = previous
+            && let Err(err) = self.upload_write_buffer(fh, path, &mut previous)
+        {
+            self.write_buffers.lock().unwrap().insert(fh, previous);
+            return Err(err);
+        }
+
+        if data.len() >= WRITE_BUFFER_LIMIT {
+            let mut direct = WriteBuffer::new(ino, offset, data, self.blksize);
+            return match self.upload_write_buffer(fh, path, &mut direct) {
+                Ok(()) => Ok(data.len()),
+                // The stored prefix is reported as a short write; the caller
+                // retries the rest and sees the error if space is still short.
+                Err(libc::ENOSPC) if direct.offset > offset => {
+                    Ok((direct.offset - offset) as usize)
+                }
+                Err(err) => Err(err),
+            };
+        }
+        self.write_buffers
             .lock()
             .unwrap()
-            .insert(ino, CachedAttr::new(attr));
+            .insert(fh, WriteBuffer::new(ino, offset, data, self.blksize));
+        self.note_buffered_write(ino);
+        Ok(data.len())
+    }
+
+    fn note_buffered_write(&self, ino: u64) {
+        if let Some(mut attr) = self.attr_for_inode(ino) {
+            let now = SystemTime::now();
+            attr.mtime = now;
+            attr.ctime = now;
+            let attr = self.with_buffered_size(attr);
+            self.inodes.lock().unwrap().set_attr(ino, attr);
+        }
+    }
+
+    // Sends a buffer to the server. When the server stores only a prefix, that
+    // prefix is consumed from `buffer` and ENOSPC is returned, so a caller
+    // that keeps the buffer retries just the bytes that are still missing.
+    fn upload_write_buffer(
+        &self,
+        fh: u64,
+        path: &str,
+        buffer: &mut WriteBuffer,
+    ) -> Result<(), c_int> {
+        let api_path = path.trim_start_matches('/');
+        if let Some(staged_write) = self.staged_write_for(fh) {
+            return self
+                .runtime
+                .block_on(api::write_temp(
+                    &self.server_addr,
+                    &staged_write,
+                    &buffer.data,
+                    buffer.offset,
+                ))
+                .map_err(|error| {
+                    warn!("Failed to stage a write to {api_path} on server: {error:?}");
+                    errno_for(&error)
+                });
+        }
+        let outcome = self
+            .runtime
+            .block_on(api::write_file(
+                &self.server_addr,
+                api_path,
+                &buffer.data,
+                buffer.offset,
+            ))
+            .map_err(|error| {
+                warn!("Failed to write to file {api_path} on server: {error:?}");
+                errno_for(&error)
+            })?;
+        let attr =
+            attr_from_remote_metadata(buffer.ino, &outcome.metadata, self.owner, self.blksize);
+        self.update_cached_attr(buffer.ino, attr);
+        self.invalidate_directory_cache_for_path(path);
+        if outcome.written < buffer.data.len() {
+            warn!(
+                "Server stored {} of {} bytes written to {api_path}",
+                outcome.written,
+                buffer.data.len()
+            );
+            buffer.consume(outcome.written);
+            return Err(libc::ENOSPC);
+        }
+        Ok(())
+    }
+
+    // Uploads every handle's buffered writes and every journaled file. Runs in
+    // `destroy`, and from the main thread on SIGINT/SIGTERM before unmounting,
+    // so data is not lost when a busy mount point cannot be unmounted.
+    pub fn flush_pending_writes(&self) {
+        let handles: Vec<u64> = self.write_buffers.lock().unwrap().keys().copied().collect();
+        for fh in handles {
+            if let Err(err) = self.flush_handle_writes(fh) {
+                warn!("Buffered writes for handle {fh} were lost at unmount: errno {err}");
+            }
+        }
+        if let Err(error) = self.writeback.flush_all() {
+            warn!("Some journaled files were not uploaded before unmount: {error}");
+        }
+    }
+
+    // Sends a handle's buffered writes. A failed upload keeps the buffer so a
+    // later flush or release can retry it.
+    fn flush_handle_writes(&self, fh: u64) -> Result<(), c_int> {
+        let Some(mut buffer) = self.write_buffers.lock().unwrap().remove(&fh) else {
+            return Ok(());
+        };
+        let path = self
+            .open_handles
+            .lock()
+            .unwrap()
+            .get(&fh)
+            .map(|handle| handle.path.clone())
+            .or_else(|| self.path_for_inode(buffer.ino))
+            .ok_or(ENOENT)?;
+        if let Err(err) = self.upload_write_buffer(fh, &path, &mut buffer) {
+            self.write_buffers.lock().unwrap().insert(fh, buffer);
+            return Err(err);
+        }
+        Ok(())
+    }
+
+    // Sends every handle's buffered writes for an inode before an operation
+    // that must see the server's copy of the file.
+    fn flush_inode_writes(&self, ino: u64) -> Result<(), c_int> {
+        let handles: Vec<u64> = self
+            .write_buffers
+            .lock()
+            .unwrap()
+            .iter()
+            .filter(|(_, buffer)| buffer.ino == ino)
+            .map(|(fh, _)| *fh)
+            .collect();
+        for fh in handles {
+            self.flush_handle_writes(fh)?;
+        }
+        Ok(())
+    }
+
+    // Puts every byte written to `path` so far on the server, from the write
+    // buffers of inode `ino` and from the writeback journal, before an
+    // operation that works on the server's copy.
+    fn push_writes_to_server(&self, ino: Option<u64>, path: &str) -> Result<(), c_int> {
+        if let Some(ino) = ino {
+            self.flush_inode_writes(ino)?;
+        }
+        if self
+            .writeback
+            .get(path)
+            .is_some_and(|pending| !pending.is_committed())
+        {
+            self.materialize_pending(path)
+                .map_err(|error| errno_from_io_error(&error))?;
+        }
+        Ok(())
+    }
+
+    fn discard_inode_writes(&self, ino: u64) {
+        self.write_buffers
+            .lock()
+            .unwrap()
+            .retain(|_, buffer| buffer.ino != ino);
+    }
+
+    // Drops cached and prefetched bytes for a file whose contents changed.
+    fn invalidate_file_data(&self, ino: u64) {
+        self.readahead.invalidate(ino);
+        self.block_cache.lock().unwrap().invalidate(ino);
+    }
+
+    // A new size or mtime in refreshed metadata means the remote contents
+    // may differ from the cached blocks.
+    fn invalidate_file_data_if_changed(&self, previous: Option<FileAttr>, attr: &FileAttr) {
+        if previous
+            .is_some_and(|previous| previous.size != attr.size || previous.mtime != attr.mtime)
+        {
+            self.invalidate_file_data(attr.ino);
+        }
+    }
+
+    // Keeps a cached directory's link count in step with child directories
+    // created or removed through this mount. Directories never drop below 2.
+    fn adjust_directory_nlink(&self, ino: u64, delta: i32) {
+        if let Some(cached) = self.inodes.lock().unwrap().attr_mut(ino)
+            && cached.attr.kind == FileType::Directory
+        {
+            cached.attr.nlink = cached.attr.nlink.saturating_add_signed(delta).max(2);
+        }
     }
 
     fn attr_for_inode(&self, ino: u64) -> Option<FileAttr> {
-        self.inode_map
+        self.inodes
             .lock()
             .unwrap()
-            .get(&ino)
+            .attr(ino)
             .map(|cached| cached.attr)
     }
 
     fn fresh_attr_for_inode(&self, ino: u64) -> Option<FileAttr> {
-        self.inode_map
+        self.inodes
             .lock()
             .unwrap()
-            .get(&ino)
+            .attr(ino)
             .filter(|cached| cached.is_fresh())
             .map(|cached| cached.attr)
     }
@@ -432,11 +1445,272 @@ impl RemoteFs {
 
         debug!("DIRECTORY CACHE MISS: {}", cache_key);
         let api_path = remote_path::api(&cache_key);
-        let mut entries = self
+        if self.list_depth > 1 {
+            let tree = self
+                .runtime
+                .block_on(api::list_tree(&self.server_addr, api_path, self.list_depth))
+                .map_err(|error| errno_for(&error))?;
+            return self.cache_listed_tree(&cache_key, tree);
+        }
+
+        let entries = self
             .runtime
             .block_on(api::list_directory(&self.server_addr, api_path))
-            .map_err(|error| errno_from_api_error(&error))?;
+            .map_err(|error| errno_for(&error))?;
+        self.cache_listing(cache_key, entries)
+    }
+
+    // Feeds `add` the entries of a readdir/readdirplus reply from `offset`
+    // on, stopping once it reports the buffer full. `.` and `..` take offsets
+    // 1 and 2; later entries come from the handle's snapshot. `names_only`
+    // replies use only each entry's inode and type, so a snapshot taken for
+    // them lists just names.
+    fn fill_directory(
+        &self,
+        ino: u64,
+        fh: u64,
+        offset: i64,
+        names_only: bool,
+        mut add: impl FnMut(u64, i64, &FileAttr, &str) -> bool,
+    ) -> Result<(), c_int> {
+        if offset < 0 {
+            return Err(libc::EINVAL);
+        }
+        let current_path = if fh != 0 {
+            self.handle_for(fh, ino, HandleKind::Directory)?.path
+        } else {
+            self.path_for_inode(ino).ok_or_else(|| {
+                log::error!("readdir: Could not find path for ino {}.", ino);
+                ENOENT
+            })?
+        };
+
+        let directory_attr = self.attr_for_inode(ino).ok_or(ENOENT)?;
+        let parent_attr = if current_path == "/" {
+            directory_attr
+        } else {
+            self.inode_for_path(remote_path::parent(&current_path))
+                .and_then(|parent| self.attr_for_inode(parent))
+                .unwrap_or(directory_attr)
+        };
+        for (index, (attr, name)) in [(directory_attr, "."), (parent_attr, "..")]
+            .iter()
+            .enumerate()
+            .skip(offset as usize)
+        {
+            if add(attr.ino, index as i64 + 1, attr, name) {
+                return Ok(());
+            }
+        }
+
+        // A rewound handle takes a fresh snapshot, as `rewinddir` should
+        // show changes made since `opendir`.
+        let saved = if fh != 0 {
+            self.directory_snapshots.lock().unwrap().remove(&fh)
+        } else {
+            None
+        };
+        let mut snapshot = match saved {
+            Some(snapshot)
+                if (offset > 0 || !snapshot.was_served())
+                    && (names_only || !snapshot.is_names_only()) =>
+            {
+                snapshot
+            }
+            _ => self.snapshot_directory(ino, &current_path, names_only)?,
+        };
+
+        let listed_names = snapshot.is_names_only();
+        let mut offset = offset;
+        let mut added_any = false;
+        loop {
+            let mut full = false;
+            for (entry_offset, api_entry) in snapshot.entries_after(offset) {
+                let entry_path = remote_path::child(&current_path, &api_entry.name);
+                let attr = if listed_names {
+                    self.attr_for_listed_name(entry_path, api_entry)
+                } else {
+                    let attr = self.attr_from_entry_for_path(&entry_path, api_entry);
+                    self.cache_attr(entry_path, attr);
+                    attr
+                };
+                if add(attr.ino, entry_offset, &attr, &api_entry.name) {
+                    debug!("Reply buffer full after adding ino {}.", attr.ino);
+                    full = true;
+                    break;
+                }
+                offset = entry_offset;
+                added_any = true;
+            }
+            if full || !snapshot.needs_page() {
+                break;
+            }
+            // A failed page after some entries still replies with those; the
+            // kernel asks again from the last one and the page is retried.
+            match self.fetch_next_page(ino, &current_path, &mut snapshot) {
+                Ok(()) => {}
+                Err(error) if added_any => {
+                    debug!("Could not fetch more of {current_path}: errno {error}");
+                    break;
+                }
+                Err(error) => return Err(error),
+            }
+        }
+
+        if fh != 0 {
+            self.directory_snapshots
+                .lock()
+                .unwrap()
+                .insert(fh, snapshot);
+        }
+        Ok(())
+    }
+
+    // Starts a directory handle's listing with its first page; readdir
+    // fetches the rest as it reaches them. With `names_only`, an uncached
+    // directory is listed from `GET /names` instead, or in full from servers
+    // without it.
+    fn snapshot_directory(
+        &self,
+        ino: u64,
+        directory_path: &str,
+        names_only: bool,
+    ) -> Result<DirectorySnapshot, c_int> {
+        let mut pages = self.open_directory_pages(directory_path)?;
+        if names_only && pages.needs_page() {
+            match self.fetch_names_page(directory_path, &mut pages) {
+                Ok(()) => return Ok(pages.into_snapshot()),
+                Err(error) if error.is_unknown_endpoint() => {
+                    debug!("Names of {directory_path} unavailable ({error}); listing in full");
+                    pages = self.open_directory_pages(directory_path)?;
+                }
+                Err(error) => return Err(errno_for(&error)),
+            }
+        }
+        if pages.needs_page() {
+            self.fetch_directory_page(ino, directory_path, &mut pages)?;
+        }
+        Ok(pages.into_snapshot())
+    }
+
+    // Fetches the page after the last one a handle's snapshot has, in the
+    // form its first page came in.
+    fn fetch_next_page(
+        &self,
+        ino: u64,
+        directory_path: &str,
+        snapshot: &mut DirectorySnapshot,
+    ) -> Result<(), c_int> {
+        if snapshot.is_names_only() {
+            self.fetch_names_page(directory_path, snapshot.pages_mut())
+                .map_err(|error| errno_for(&error))
+        } else {
+            self.fetch_directory_page(ino, directory_path, snapshot.pages_mut())
+        }
+    }
+
+    fn fetch_names_page(
+        &self,
+        directory_path: &str,
+        pages: &mut DirectoryPages,
+    ) -> Result<(), api::ApiError> {
+        let cache_key = Self::directory_cache_key(directory_path);
+        let page = self.runtime.block_on(api::list_names_page(
+            &self.server_addr,
+            remote_path::api(&cache_key),
+            pages.cursor(),
+            READDIR_PAGE_SIZE,
+        ))?;
+        pages.add_names_page(page.entries, page.next_cursor);
+        Ok(())
+    }
+
+    // Cached or prefetched listings are used whole; otherwise the server is
+    // paged and journaled files are merged as pages arrive.
+    fn open_directory_pages(&self, directory_path: &str) -> Result<DirectoryPages, c_int> {
+        let cache_key = Self::directory_cache_key(directory_path);
+        if let Some(entries) = self.directory_cache.lock().unwrap().get(&cache_key) {
+            return Ok(DirectoryPages::complete(entries));
+        }
+        if self.list_depth > 1 {
+            return self
+                .list_directory_cached(directory_path)
+                .map(DirectoryPages::complete);
+        }
+
+        let pending = self
+            .writeback
+            .entries_in_directory(&cache_key)
+            .into_iter()
+            .filter(|pending| !pending.is_committed())
+            .map(|pending| directory_entry_from_pending(&pending))
+            .collect::<io::Result<Vec<_>>>()
+            .map_err(|error| errno_from_io_error(&error))?;
+        Ok(DirectoryPages::new(pending))
+    }
+
+    // The first page is requested with the ETag of the last one seen for
+    // this directory, and a 304 reuses those entries.
+    fn fetch_directory_page(
+        &self,
+        ino: u64,
+        directory_path: &str,
+        pages: &mut DirectoryPages,
+    ) -> Result<(), c_int> {
+        let cache_key = Self::directory_cache_key(directory_path);
+        let validated = if pages.is_first_page() {
+            self.validated_pages.lock().unwrap().get(&ino)
+        } else {
+            None
+        };
+        let fetched = self
+            .runtime
+            .block_on(api::list_directory_page(
+                &self.server_addr,
+                remote_path::api(&cache_key),
+                pages.cursor(),
+                READDIR_PAGE_SIZE,
+                validated.as_ref().map(|validated| validated.etag.as_str()),
+            ))
+            .map_err(|error| errno_for(&error))?;
+        let page = match (fetched, validated) {
+            (Some(page), _) => {
+                if pages.is_first_page()
+                    && let Some(etag) = &page.etag
+                {
+                    self.validated_pages.lock().unwrap().insert(
+                        ino,
+                        ValidatedPage {
+                            etag: etag.clone(),
+                            entries: page.entries.clone(),
+                            next_cursor: page.next_cursor.clone(),
+                        },
+                    );
+                }
+                page
+            }
+            (None, Some(validated)) => api::DirectoryPage {
+                entries: validated.entries,
+                next_cursor: validated.next_cursor,
+                etag: Some(validated.etag),
+            },
+            (None, None) => unreachable!("304 is only returned for a conditional request"),
+        };
+
+        // A directory that fits in one page is also a complete listing for lookups.
+        if pages.is_first_page() && page.next_cursor.is_none() {
+            self.cache_listing(cache_key, page.entries.clone())?;
+        }
+        pages.add_page(page.entries, page.next_cursor);
+        Ok(())
+    }
 
+    // Merges journaled files into a fresh listing and caches the result.
+    fn cache_listing(
+        &self,
+        cache_key: String,
+        mut entries: Vec<api::DirectoryEntry>,
+    ) -> Result<Vec<api::DirectoryEntry>, c_int> {
         for pending in self.writeback.entries_in_directory(&cache_key) {
             if pending.is_committed() {
                 continue;
@@ -448,14 +1722,53 @@ impl RemoteFs {
         }
         entries.sort_by(|left, right| left.name.cmp(&right.name));
 
-        self.directory_cache
-            .lock()
-            .unwrap()
-            .insert(cache_key, entries.clone());
+        let mut directory_cache = self.directory_cache.lock().unwrap();
+        if self.root_refresh.is_some() && cache_key == "/" {
+            directory_cache.insert_pinned(cache_key, entries.clone());
+        } else {
+            directory_cache.insert(cache_key, entries.clone());
+        }
 
         Ok(entries)
     }
 
+    // Splits a `list_tree` response into one cached listing per directory
+    // whose children were all included, and registers every entry's inode so
+    // later lookups and getattrs below `cache_key` need no round trip.
+    fn cache_listed_tree(
+        &self,
+        cache_key: &str,
+        tree: Vec<api::DirectoryEntry>,
+    ) -> Result<Vec<api::DirectoryEntry>, c_int> {
+        let mut listings: HashMap<String, Vec<api::DirectoryEntry>> = HashMap::new();
+        listings.insert(cache_key.to_string(), Vec::new());
+        for mut entry in tree {
+            let path = remote_path::child(cache_key, &entry.name);
+            let levels = entry.name.split('/').count() as u32;
+            if entry.type_ == api::EntryType::Directory && levels < self.list_depth {
+                listings.entry(path.clone()).or_default();
+            }
+            let attr = self.attr_from_entry_for_path(&path, &entry);
+            self.cache_attr(path.clone(), attr);
+
+            let (directory, name) = match entry.name.rsplit_once('/') {
+                Some((relative_parent, name)) => (
+                    remote_path::child(cache_key, relative_parent),
+                    name.to_string(),
+                ),
+                None => (cache_key.to_string(), entry.name.clone()),
+            };
+            entry.name = name;
+            listings.entry(directory).or_default().push(entry);
+        }
+
+        let top = listings.remove(cache_key).unwrap_or_default();
+        for (directory, entries) in listings {
+            self.cache_listing(directory, entries)?;
+        }
+        self.cache_listing(cache_key.to_string(), top)
+    }
+
     fn invalidate_directory_cache_for_path(&self, path: &str) {
         let directory_path = if path == "/" {
             "/".to_string()
@@ -463,61 +1776,235 @@ impl RemoteFs {
             remote_path::parent(path).to_string()
         };
         let directory_key = Self::directory_cache_key(&directory_path);
+        if directory_key == "/" {
+            self.root_generation.fetch_add(1, Ordering::AcqRel);
+        }
 
         self.directory_cache.lock().unwrap().remove(&directory_key);
+        self.negative_lookups
+            .lock()
+            .unwrap()
+            .remove_matching(|missing| remote_path::parent(missing) == directory_path);
     }
 
     fn invalidate_directory_cache_tree(&self, path: &str) {
         let path_key = Self::directory_cache_key(path);
         let parent_key = Self::directory_cache_key(remote_path::parent(path));
+        if path_key == "/" || parent_key == "/" {
+            self.root_generation.fetch_add(1, Ordering::AcqRel);
+        }
         self.directory_cache.lock().unwrap().remove_matching(|key| {
             key == &path_key
                 || key == &parent_key
                 || key.starts_with(&format!("{}/", path_key.trim_end_matches('/')))
         });
+        let parent = remote_path::parent(path);
+        let tree_prefix = format!("{}/", path.trim_end_matches('/'));
+        self.negative_lookups
+            .lock()
+            .unwrap()
+            .remove_matching(|missing| {
+                remote_path::parent(missing) == parent || missing.starts_with(&tree_prefix)
+            });
     }
 
-    fn attr_from_entry_for_path(&self, path: &str, entry: &api::DirectoryEntry) -> FileAttr {
-        let ino = self
-            .path_to_inode
+    fn is_known_missing(&self, path: &str) -> bool {
+        self.negative_lookups
             .lock()
             .unwrap()
-            .get(path)
-            .copied()
-            .unwrap_or_else(|| self.allocate_inode());
-        attr_from_directory_entry(ino, entry)
+            .get(&path.to_string())
+            .is_some()
+    }
+
+    fn remember_missing(&self, path: &str) {
+        if self.negative_ttl.is_zero() {
+            return;
+        }
+        self.negative_lookups
+            .lock()
+            .unwrap()
+            .insert(path.to_string(), ());
+    }
+
+    // Answers a lookup of a missing name. An entry with inode 0 lets the
+    // kernel cache the miss for `negative_ttl`; ENOENT is never cached.
+    fn reply_missing(&self, reply: ReplyEntry, span: &OperationSpan) {
+        let errno = span.fail(ENOENT);
+        if self.negative_ttl.is_zero() {
+            reply.error(errno);
+            return;
+        }
+        let missing = create_file_attr(0, FileType::RegularFile, 0, 0, 0, 0, UNIX_EPOCH);
+        reply.entry(&self.negative_ttl, &missing, 0);
+    }
+
+    // A path listed by name alone keeps whatever is cached for it. A new one
+    // is bound with attributes already expired, so the lookup the kernel
+    // makes before using it fetches the real ones.
+    fn attr_for_listed_name(&self, path: String, entry: &api::DirectoryEntry) -> FileAttr {
+        let mut inodes = self.inodes.lock().unwrap();
+        if let Some(cached) = inodes.ino_for(&path).and_then(|ino| inodes.attr(ino)) {
+            return cached.attr;
+        }
+        let ino = inodes.allocate_for(&path);
+        let attr = attr_from_directory_entry(ino, entry, self.owner, self.blksize);
+        inodes.insert(path, attr);
+        if let Some(cached) = inodes.attr_mut(ino) {
+            cached.expire();
+        }
+        attr
+    }
+
+    fn attr_from_entry_for_path(&self, path: &str, entry: &api::DirectoryEntry) -> FileAttr {
+        let ino = self
+            .inode_for_path(path)
+            .unwrap_or_else(|| self.allocate_inode(path));
+        attr_from_directory_entry(ino, entry, self.owner, self.blksize)
+    }
+
+    fn attr_from_parent_listing(&self, path: &str) -> Result<Option<FileAttr>, c_int> {
+        let Some(name) = Path::new(path).file_name().and_then(|name| name.to_str()) else {
+            return Ok(None);
+        };
+
+        Ok(self
+            .list_directory_cached(remote_path::parent(path))?
+            .into_iter()
+            .find(|entry| entry.name == name)
+            .map(|entry| {
+                let attr = self.attr_from_entry_for_path(path, &entry);
+                self.cache_attr(path.to_string(), attr);
+                attr
+            }))
     }
 
-    fn refresh_path_from_parent(&self, path: &str) -> Option<FileAttr> {
+    // Fetches fresh attributes for one path, allocating an inode if it is new.
+    // A cached parent listing answers without a round trip; otherwise a single
+    // `GET /stat` does. Journaled files only exist locally, so they go through
+    // the listing that merges them in.
+    fn stat_path(&self, path: &str) -> Result<Option<FileAttr>, c_int> {
         if path == "/" {
-            return self.attr_for_inode(FUSE_ROOT_ID);
+            return Ok(self.attr_for_inode(FUSE_ROOT_ID));
         }
 
-        let parent_path = remote_path::parent(path);
-        let name = Path::new(path)
-            .file_name()
-            .and_then(|name| name.to_str())
-            .map(|name| name.to_string())?;
+        let parent_key = Self::directory_cache_key(remote_path::parent(path));
+        let listing_cached = self
+            .directory_cache
+            .lock()
+            .unwrap()
+            .get(&parent_key)
+            .is_some();
+        let journaled = self
+            .writeback
+            .get(path)
+            .is_some_and(|pending| !pending.is_committed());
+        if listing_cached || journaled {
+            return self.attr_from_parent_listing(path);
+        }
 
-        match self.list_directory_cached(parent_path) {
-            Ok(entries) => entries
+        match self
+            .runtime
+            .block_on(api::stat_entry(&self.server_addr, remote_path::api(path)))
+        {
+            Ok(entry) => {
+                let attr = self.attr_from_entry_for_path(path, &entry);
+                self.cache_attr(path.to_string(), attr);
+                Ok(Some(attr))
+            }
+            Err(api::ApiError::NotFound) => Ok(None),
+            Err(error) => Err(errno_for(&error)),
+        }
+    }
+
+    // The server's guess at a file's MIME type. A cached parent listing
+    // answers without a round trip; journaled files have none yet.
+    fn content_type_for_path(&self, path: &str) -> Result<Option<String>, c_int> {
+        let Some(name) = Path::new(path).file_name().and_then(|name| name.to_str()) else {
+            return Ok(None);
+        };
+        let parent_key = Self::directory_cache_key(remote_path::parent(path));
+        if let Some(entries) = self.directory_cache.lock().unwrap().get(&parent_key) {
+            return Ok(entries
                 .into_iter()
                 .find(|entry| entry.name == name)
-                .map(|entry| {
-                    let attr = self.attr_from_entry_for_path(path, &entry);
-                    self.cache_attr(path.to_string(), attr);
-                    attr
-                }),
-            Err(err) => {
-                warn!("Failed to refresh metadata for {}: {:?}", path, err);
-                None
-            }
+                .and_then(|entry| entry.content_type));
+        }
+
+        match self
+            .runtime
+            .block_on(api::stat_entry(&self.server_addr, remote_path::api(path)))
+        {
+            Ok(entry) => Ok(entry.content_type),
+            Err(api::ApiError::NotFound) => Ok(None),
+            Err(error) => Err(errno_for(&error)),
+        }
+    }
+
+    // An attribute's value, falling back to the guessed MIME type for
+    // `MIME_TYPE_XATTR` when the file stores none of its own.
+    fn xattr_value(&self, path: &str, name: &str) -> Result<Vec<u8>, c_int> {
+        let error = match self
+            .runtime
+            .block_on(api::get_xattr(&self.server_addr, path, name))
+        {
+            Ok(value) => return Ok(value),
+            Err(error) => errno_from_xattr_error(&error),
+        };
+        if error != ENOATTR || name != MIME_TYPE_XATTR {
+            return Err(error);
+        }
+        match self.content_type_for_path(path)? {
+            Some(content_type) => Ok(content_type.into_bytes()),
+            None => Err(error),
         }
     }
 
-    fn refresh_inode_from_parent(&self, ino: u64) -> Option<FileAttr> {
+    // A journaled file has not reached the server, so it has no attributes yet.
+    fn xattr_names(&self, path: &str) -> Result<Vec<String>, c_int> {
+        if self
+            .writeback
+            .get(path)
+            .is_some_and(|pending| !pending.is_committed())
+        {
+            return Ok(Vec::new());
+        }
+        let mut names = self
+            .runtime
+            .block_on(api::list_xattrs(&self.server_addr, path))
+            .map_err(|error| errno_from_xattr_error(&error))?;
+        if !names.iter().any(|name| name == MIME_TYPE_XATTR)
+            && let Ok(Some(_)) = self.content_type_for_path(path)
+        {
+            names.push(MIME_TYPE_XATTR.to_string());
+        }
+        Ok(names)
+    }
+
+    // The guessed MIME type is listed but not stored, so removing it is
+    // refused rather than reported missing.
+    fn remove_xattr(&self, path: &str, name: &str) -> Result<(), c_int> {
+        let error = match self
+            .runtime
+            .block_on(api::remove_xattr(&self.server_addr, path, name))
+        {
+            Ok(()) => return Ok(()),
+            Err(error) => errno_from_xattr_error(&error),
+        };
+        if error == ENOATTR
+            && name == MIME_TYPE_XATTR
+            && self.content_type_for_path(path)?.is_some()
+        {
+            return Err(libc::EPERM);
+        }
+        Err(error)
+    }
+
+    fn refresh_inode(&self, ino: u64) -> Option<FileAttr> {
         let path = self.path_for_inode(ino)?;
-        self.refresh_path_from_parent(&path)
+        self.stat_path(&path).unwrap_or_else(|err| {
+            warn!("Failed to refresh metadata for {}: {:?}", path, err);
+            None
+        })
     }
 
     // Resolves rename sources, including macOS cases where macFUSE reports a truncated name.
@@ -527,17 +2014,14 @@ impl RemoteFs {
         name: &OsStr,
         newname: &OsStr,
     ) -> Result<String, c_int> {
-        let full_path = self.child_path(parent, name)?;
+        let full_path = self.full_path(parent, name).ok_or(ENOENT)?;
 
-        {
-            let path_to_inode = self.path_to_inode.lock().unwrap();
-            if path_to_inode.contains_key(&full_path) {
-                return Ok(full_path);
-            }
+        if self.inode_for_path(&full_path).is_some() {
+            return Ok(full_path);
         }
 
-        let name_str = name.to_str().ok_or(libc::EINVAL)?;
-        let newname_str = newname.to_str().ok_or(libc::EINVAL)?;
+        let name_str = wire_name(name);
+        let newname_str = wire_name(newname);
         let wants_appledouble = newname_str.starts_with("._");
         let parent_path = self.path_for_inode(parent).ok_or(ENOENT)?;
         let parent_prefix = if parent_path == "/" {
@@ -546,15 +2030,17 @@ impl RemoteFs {
             format!("{}/", parent_path.trim_end_matches('/'))
         };
 
-        let path_to_inode = self.path_to_inode.lock().unwrap();
-        let mut candidates: Vec<String> = path_to_inode
-            .keys()
+        let mut candidates: Vec<String> = self
+            .inodes
+            .lock()
+            .unwrap()
+            .paths()
             .filter_map(|path| {
                 let child_name = path.strip_prefix(&parent_prefix)?;
-                if child_name.contains('/') || !child_name.ends_with(name_str) {
+                if child_name.contains('/') || !child_name.ends_with(name_str.as_ref()) {
                     return None;
                 }
-                Some(path.clone())
+                Some(path.to_string())
             })
             .collect();
 
@@ -573,69 +2059,44 @@ impl RemoteFs {
 
     // Removes a cached path and all cached children below it.
     fn remove_cached_path(&self, path: &str) {
-        let mut inode_map = self.inode_map.lock().unwrap();
-        let mut path_to_inode = self.path_to_inode.lock().unwrap();
-        let prefix = format!("{}/", path.trim_end_matches('/'));
-        let removed_paths: Vec<String> = path_to_inode
-            .keys()
-            .filter(|cached_path| *cached_path == path || cached_path.starts_with(&prefix))
-            .cloned()
-            .collect();
-
-        for removed_path in removed_paths {
-            if let Some(ino) = path_to_inode.remove(&removed_path) {
-                inode_map.remove(&ino);
+        let mut inodes = self.inodes.lock().unwrap();
+        inodes.remove_tree(path);
+        // Handles opened through a removed hard link continue under the
+        // inode's remaining name.
+        for handle in self.open_handles.lock().unwrap().values_mut() {
+            if handle.path == path
+                && let Some(remaining) = inodes.path_for(handle.ino)
+            {
+                handle.path = remaining.to_string();
             }
         }
-
-        drop(path_to_inode);
-        drop(inode_map);
+        drop(inodes);
         self.invalidate_directory_cache_tree(path);
     }
 
-    // Moves cached paths from one prefix to another after a successful server rename.
-    fn rename_cached_path(&self, from: &str, to: &str) {
-        let mut inode_map = self.inode_map.lock().unwrap();
-        let mut path_to_inode = self.path_to_inode.lock().unwrap();
-        let prefix = format!("{}/", from.trim_end_matches('/'));
-        let moved_paths: Vec<(String, u64)> = path_to_inode
-            .iter()
-            .filter(|(cached_path, _)| *cached_path == from || cached_path.starts_with(&prefix))
-            .map(|(cached_path, &ino)| (cached_path.clone(), ino))
-            .collect();
-
-        let destination_prefix = format!("{}/", to.trim_end_matches('/'));
-        let overwritten_paths: Vec<String> = path_to_inode
-            .keys()
-            .filter(|cached_path| {
-                *cached_path == to || cached_path.starts_with(&destination_prefix)
-            })
-            .cloned()
-            .collect();
+    // Binds another name to an existing inode after a server-side hard link.
+    fn link_cached_path(&self, path: &str, attr: FileAttr) {
+        self.inodes.lock().unwrap().link(path.to_string(), attr.ino);
+        self.update_cached_attr(attr.ino, attr);
+        self.invalidate_directory_cache_for_path(path);
+    }
 
-        for overwritten_path in overwritten_paths {
-            if let Some(ino) = path_to_inode.remove(&overwritten_path) {
-                inode_map.remove(&ino);
-            }
-        }
+    fn name_count(&self, ino: u64) -> usize {
+        self.inodes.lock().unwrap().name_count(ino)
+    }
 
-        for (old_path, _) in &moved_paths {
-            path_to_inode.remove(old_path);
-        }
-
-        for (old_path, ino) in moved_paths {
-            let new_path = if old_path == from {
-                to.to_string()
-            } else {
-                let suffix = old_path.strip_prefix(&prefix).unwrap_or("");
-                format!("{}/{}", to.trim_end_matches('/'), suffix)
-            };
-            path_to_inode.insert(new_path, ino);
+    // Counts the removal of one hard link from an inode that keeps other names.
+    fn drop_cached_link(&self, ino: u64) {
+        if let Some(cached) = self.inodes.lock().unwrap().attr_mut(ino) {
+            cached.attr.nlink = cached.attr.nlink.saturating_sub(1).max(1);
         }
+    }
 
-        drop(path_to_inode);
-        drop(inode_map);
+    // Moves cached paths from one prefix to another after a successful server rename.
+    fn rename_cached_path(&self, from: &str, to: &str) {
+        self.inodes.lock().unwrap().rename_tree(from, to);
 
+        let prefix = format!("{}/", from.trim_end_matches('/'));
         let mut open_handles = self.open_handles.lock().unwrap();
         for handle in open_handles.values_mut() {
             if handle.path == from || handle.path.starts_with(&prefix) {
@@ -653,3 +2114,227 @@ impl RemoteFs {
         self.invalidate_directory_cache_tree(to);
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn server_block_counts_replace_the_size_estimate() {
+        let metadata = |size, blocks| api::RemoteMetadata {
+            type_: api::EntryType::File,
+            size,
+            modified_at: "0".to_string(),
+            mode: None,
+            uid: None,
+            gid: None,
+            accessed_at: None,
+            changed_at: None,
+            nlink: None,
+            blocks,
+            content_type: None,
+        };
+        let blocks = |metadata: &api::RemoteMetadata| {
+            attr_from_remote_metadata(2, metadata, MountOwner::new(None, None), 4096).blocks
+        };
+
+        assert_eq!(blocks(&metadata(1 << 30, Some(16))), 16);
+        assert_eq!(blocks(&metadata(5000, None)), 16);
+        assert_eq!(blocks(&metadata(0, None)), 0);
+    }
+
+    #[test]
+    fn files_without_holes_are_data_up_to_their_end() {
+        use api::SeekWhence::{Data, Hole};
+
+        assert_eq!(seek_without_holes(100, 0, Data), Some(0));
+        assert_eq!(seek_without_holes(100, 40, Data), Some(40));
+        assert_eq!(seek_without_holes(100, 40, Hole), Some(100));
+        assert_eq!(seek_without_holes(100, 100, Data), None);
+        assert_eq!(seek_without_holes(100, 100, Hole), None);
+        assert_eq!(seek_without_holes(0, 0, Hole), None);
+    }
+
+    #[test]
+    fn server_access_and_change_times_are_kept_apart_from_mtime() {
+        let mut metadata = api::RemoteMetadata {
+            type_: api::EntryType::File,
+            size: 0,
+            modified_at: "1000".to_string(),
+            mode: None,
+            uid: None,
+            gid: None,
+            accessed_at: Some("3000".to_string()),
+            changed_at: Some("2000".to_string()),
+            nlink: None,
+            blocks: None,
+            content_type: None,
+        };
+        let at = |seconds| UNIX_EPOCH + Duration::from_secs(seconds);
+        let owner = MountOwner::new(None, None);
+
+        let attr = attr_from_remote_metadata(2, &metadata, owner, 4096);
+        assert_eq!(
+            (attr.atime, attr.mtime, attr.ctime),
+            (at(3000), at(1000), at(2000))
+        );
+
+        metadata.accessed_at = None;
+        metadata.changed_at = None;
+        let attr = attr_from_remote_metadata(2, &metadata, owner, 4096);
+        assert_eq!((attr.atime, attr.ctime), (at(1000), at(1000)));
+    }
+
+    #[test]
+    fn unix_second_timestamps_parse_with_optional_fractions() {
+        assert_eq!(
+            parse_unix_seconds("1710000000"),
+            Some(UNIX_EPOCH + Duration::from_secs(1_710_000_000))
+        );
+        assert_eq!(
+            parse_unix_seconds("12.5"),
+            Some(UNIX_EPOCH + Duration::from_millis(12_500))
+        );
+        assert_eq!(parse_unix_seconds("2024-03-09T16:00:00Z"), None);
+        assert_eq!(parse_unix_seconds("12.x"), None);
+        assert_eq!(parse_unix_seconds(""), None);
+    }
+
+    #[test]
+    fn created_modes_apply_the_umask_or_the_configured_default() {
+        assert_eq!(apply_umask(0o666, 0o022, DEFAULT_FILE_MODE), 0o644);
+        assert_eq!(apply_umask(0o777, 0o077, DEFAULT_DIR_MODE), 0o700);
+        assert_eq!(apply_umask(0, 0o022, 0o600), 0o600);
+        assert_eq!(apply_umask(0o600, 0o777, 0o640), 0o640);
+    }
+
+    #[test]
+    fn the_size_limit_counts_what_writes_reads_and_copies_would_reach() {
+        let server = crate::integration_tests::TestServer::start("size-limit");
+        let mut fs = server.remote_fs();
+        fs.max_file_size = Some(100);
+        let cache = |path: &str, size| {
+            let ino = fs.allocate_inode(path);
+            let attr = create_file_attr(ino, FileType::RegularFile, size, 0o644, 0, 0, UNIX_EPOCH);
+            fs.cache_attr(path.to_string(), attr);
+            ino
+        };
+        let small = cache("/small", 50);
+        let large = cache("/large", 200);
+
+        assert_eq!(fs.check_file_size(100), Ok(()));
+        assert_eq!(fs.check_file_size(101), Err(libc::EFBIG));
+
+        assert_eq!(fs.check_read_size(large, 0, 100), Ok(()));
+        assert_eq!(fs.check_read_size(small, 90, 20), Ok(()));
+        assert_eq!(fs.check_read_size(large, 90, 20), Err(libc::EFBIG));
+        assert_eq!(fs.check_read_size(large + 1, 90, 20), Ok(()));
+
+        // Copies count what they move, not the 1 GiB the kernel asks for.
+        let asked = 1 << 30;
+        assert_eq!(fs.check_copy_size(small, 0, 0, asked), Ok(()));
+        assert_eq!(fs.check_copy_size(small, 0, 50, asked), Ok(()));
+        assert_eq!(fs.check_copy_size(small, 0, 51, asked), Err(libc::EFBIG));
+        assert_eq!(fs.check_copy_size(large, 150, 0, asked), Ok(()));
+        assert_eq!(fs.check_copy_size(large, 0, 0, asked), Err(libc::EFBIG));
+    }
+
+    #[test]
+    fn the_guessed_mime_type_reads_and_lists_as_an_attribute_but_cannot_be_removed() {
+        let server = crate::integration_tests::TestServer::start("mime-xattr");
+        std::fs::write(server.root.join("notes.txt"), b"hello").unwrap();
+        std::fs::write(server.root.join("notes"), b"hello").unwrap();
+        let fs = server.remote_fs();
+
+        assert_eq!(
+            fs.content_type_for_path("/notes.txt"),
+            Ok(Some("text/plain".to_string()))
+        );
+        assert_eq!(fs.content_type_for_path("/notes"), Ok(None));
+        assert_eq!(fs.content_type_for_path("/missing.txt"), Ok(None));
+
+        assert_eq!(
+            fs.xattr_value("/notes.txt", MIME_TYPE_XATTR),
+            Ok(b"text/plain".to_vec())
+        );
+        assert_eq!(fs.xattr_value("/notes", MIME_TYPE_XATTR), Err(ENOATTR));
+        assert_eq!(
+            fs.xattr_names("/notes.txt"),
+            Ok(vec![MIME_TYPE_XATTR.to_string()])
+        );
+        assert_eq!(fs.xattr_names("/notes"), Ok(Vec::new()));
+        assert_eq!(
+            fs.remove_xattr("/notes.txt", MIME_TYPE_XATTR),
+            Err(libc::EPERM)
+        );
+        assert_eq!(fs.remove_xattr("/notes", MIME_TYPE_XATTR), Err(ENOATTR));
+    }
+
+    #[test]
+    fn names_of_a_removed_directory_are_not_listed_again_in_full() {
+        let server = crate::integration_tests::TestServer::start("names-removed");
+        std::fs::create_dir(server.root.join("gone")).unwrap();
+        let fs = server.remote_fs();
+        let ino = fs.allocate_inode("/gone");
+        std::fs::remove_dir(server.root.join("gone")).unwrap();
+
+        let mut pages = fs.open_directory_pages("/gone").unwrap();
+        let error = fs.fetch_names_page("/gone", &mut pages).unwrap_err();
+        assert!(!error.is_unknown_endpoint(), "{error}");
+        assert_eq!(
+            fs.snapshot_directory(ino, "/gone", true).err(),
+            Some(ENOENT)
+        );
+    }
+
+    #[test]
+    fn creating_an_existing_file_honors_o_excl_and_o_trunc() {
+        let server = crate::integration_tests::TestServer::start("create-existing");
+        std::fs::write(server.root.join("a.txt"), b"old contents").unwrap();
+        std::fs::create_dir(server.root.join("dir")).unwrap();
+        let fs = server.remote_fs();
+        let create = |path: &str, flags: i32| {
+            fs.open_existing_for_create(path, libc::O_CREAT | flags)
+                .map(|opened| opened.map(|(attr, staged)| (attr.size, staged.is_some())))
+        };
+
+        assert_eq!(create("/new.txt", libc::O_RDWR), Ok(None));
+        assert_eq!(create("/dir", libc::O_RDWR), Err(libc::EISDIR));
+        assert_eq!(
+            create("/a.txt", libc::O_RDWR | libc::O_EXCL),
+            Err(libc::EEXIST)
+        );
+        assert_eq!(create("/a.txt", libc::O_RDWR), Ok(Some((12, false))));
+        assert_eq!(
+            std::fs::read(server.root.join("a.txt")).unwrap(),
+            b"old contents"
+        );
+
+        assert_eq!(
+            create("/a.txt", libc::O_RDWR | libc::O_TRUNC),
+            Ok(Some((0, false)))
+        );
+        assert!(std::fs::read(server.root.join("a.txt")).unwrap().is_empty());
+    }
+
+    #[test]
+    fn server_statuses_map_to_distinct_errnos() {
+        let status = |code: u16| {
+            api::ApiError::Status(
+                reqwest::StatusCode::from_u16(code).unwrap(),
+                api::ErrorDetail::default(),
+            )
+        };
+        assert_eq!(errno_for(&status(403)), libc::EACCES);
+        assert_eq!(errno_for(&status(404)), ENOENT);
+        assert_eq!(errno_for(&api::ApiError::NotFound), ENOENT);
+        assert_eq!(errno_for(&status(409)), libc::EEXIST);
+        assert_eq!(errno_for(&status(413)), libc::EFBIG);
+        assert_eq!(errno_for(&status(507)), libc::ENOSPC);
+        assert_eq!(errno_for(&status(503)), libc::EIO);
+        assert_eq!(errno_for(&api::ApiError::Timeout), libc::ETIMEDOUT);
+        assert_eq!(errno_for(&api::ApiError::TooLarge(64)), libc::EIO);
+        assert_eq!(errno_from_rmdir_error(&status(409)), libc::ENOTEMPTY);
+        assert_eq!(errno_from_unlink_error(&status(409)), libc::EISDIR);
+    }
+}
@@ -0,0 +1,243 @@
+use crate::cache::TtlLruCache;
+use std::sync::Arc;
+use std::time::Duration;
+
+// Cached reads are stored as aligned fixed-size blocks so overlapping kernel
+// reads share data. A block shorter than `BLOCK_SIZE` marks end of file.
+pub(super) const BLOCK_SIZE: u64 = 64 * 1024;
+// Blocks are also dropped whenever a refreshed attr shows a new size or
+// mtime; the TTL only bounds how long an unobserved remote change can hide.
+const BLOCK_CACHE_TTL: Duration = Duration::from_secs(60);
+
+type Block = Arc<[u8]>;
+
+// Result of consulting the cache for one kernel read.
+pub(super) enum CachedRead {
+    Hit(Vec<u8>),
+    Miss(BlockMiss),
+}
+
+// The contiguous run of blocks that must be fetched, plus the blocks around
+// it that were already cached when the read was looked up.
+pub(super) struct BlockMiss {
+    first_block: u64,
+    blocks: Vec<Option<Block>>,
+    first_missing: u64,
+    // The cache's generation at lookup; see `BlockCache::generation`.
+    generation: u64,
+    pub(super) fetch_offset: u64,
+    pub(super) fetch_len: u32,
+}
+
+pub(super) struct BlockCache {
+    blocks: TtlLruCache<(u64, u64), Block>,
+    // Bumped by every invalidation. Fetches run without the lock, so one
+    // that started before an invalidation may hold the old contents and is
+    // served to its read but not cached.
+    generation: u64,
+}
+
+impl BlockCache {
+    pub(super) fn new(budget_bytes: u64) -> Self {
+        let max_blocks = usize::try_from(budget_bytes / BLOCK_SIZE).unwrap_or(usize::MAX);
+        BlockCache {
+            blocks: TtlLruCache::new(max_blocks, BLOCK_CACHE_TTL),
+            generation: 0,
+        }
+    }
+
+    pub(super) fn lookup(&mut self, ino: u64, offset: u64, size: u32) -> CachedRead {
+        if size == 0 {
+            return CachedRead::Hit(Vec::new());
+        }
+
+        let first_block = offset / BLOCK_SIZE;
+        let last_block = (offset + u64::from(size) - 1) / BLOCK_SIZE;
+        let mut blocks = Vec::new();
+        for index in first_block..=last_block {
+            let block = self.blocks.get(&(ino, index));
+            let at_eof = block
+                .as_ref()
+                .is_some_and(|block| (block.len() as u64) < BLOCK_SIZE);
+            blocks.push(block);
+            if at_eof {
+                break;
+            }
+        }
+
+        let missing: Vec<u64> = blocks
+            .iter()
+            .enumerate()
+            .filter(|(_, block)| block.is_none())
+            .map(|(position, _)| first_block + position as u64)
+            .collect();
+        let (Some(&first_missing), Some(&last_missing)) = (missing.first(), missing.last()) else {
+            return CachedRead::Hit(assemble(first_block, &blocks, offset, size));
+        };
+
+        CachedRead::Miss(BlockMiss {
+            first_block,
+            blocks,
+            first_missing,
+            generation: self.generation,
+            fetch_offset: first_missing * BLOCK_SIZE,
+            fetch_len: ((last_missing - first_missing + 1) * BLOCK_SIZE) as u32,
+        })
+    }
+
+    // Caches the blocks fetched for `miss`, unless the cache was invalidated
+    // since the lookup, and returns the bytes for the read.
+    pub(super) fn complete(
+        &mut self,
+        ino: u64,
+        offset: u64,
+        size: u32,
+        mut miss: BlockMiss,
+        fetched: &[u8],
+    ) -> Vec<u8> {
+        let current = miss.generation == self.generation;
+        let fetched_blocks = u64::from(miss.fetch_len) / BLOCK_SIZE;
+        for fetched_index in 0..fetched_blocks {
+            let start = ((fetched_index * BLOCK_SIZE) as usize).min(fetched.len());
+            let end = (start + BLOCK_SIZE as usize).min(fetched.len());
+            let block: Block = Arc::from(&fetched[start..end]);
+            let index = miss.first_missing + fetched_index;
+            let short = (block.len() as u64) < BLOCK_SIZE;
+
+            if current {
+                self.blocks.insert((ino, index), block.clone());
+            }
+            let position = (index - miss.first_block) as usize;
+            if position < miss.blocks.len() {
+                miss.blocks[position] = Some(block);
+            }
+            if short {
+                miss.blocks.truncate(position + 1);
+                break;
+            }
+        }
+
+        assemble(miss.first_block, &miss.blocks, offset, size)
+    }
+
+    pub(super) fn invalidate(&mut self, ino: u64) {
+        self.generation += 1;
+        self.blocks
+            .remove_matching(|(cached_ino, _)| *cached_ino == ino);
+    }
+}
+
+fn assemble(first_block: u64, blocks: &[Option<Block>], offset: u64, size: u32) -> Vec<u8> {
+    let mut bytes = Vec::with_capacity(blocks.len() * BLOCK_SIZE as usize);
+    for block in blocks.iter().flatten() {
+        bytes.extend_from_slice(block);
+        if (block.len() as u64) < BLOCK_SIZE {
+            break;
+        }
+    }
+
+    let start = ((offset - first_block * BLOCK_SIZE) as usize).min(bytes.len());
+    let end = (start + size as usize).min(bytes.len());
+    bytes[start..end].to_vec()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const BLOCK: usize = BLOCK_SIZE as usize;
+
+    fn file(len: usize) -> Vec<u8> {
+        (0..len).map(|index| (index % 251) as u8).collect()
+    }
+
+    fn fetch(contents: &[u8], miss: &BlockMiss) -> Vec<u8> {
+        let start = (miss.fetch_offset as usize).min(contents.len());
+        let end = (start + miss.fetch_len as usize).min(contents.len());
+        contents[start..end].to_vec()
+    }
+
+    #[test]
+    fn reads_fetch_only_the_missing_blocks() {
+        let contents = file(3 * BLOCK);
+        let mut cache = BlockCache::new(16 * BLOCK_SIZE);
+
+        let CachedRead::Miss(miss) = cache.lookup(1, 10, 100) else {
+            panic!("empty cache should miss");
+        };
+        assert_eq!((miss.fetch_offset, miss.fetch_len), (0, BLOCK as u32));
+        let fetched = fetch(&contents, &miss);
+        assert_eq!(
+            cache.complete(1, 10, 100, miss, &fetched),
+            &contents[10..110]
+        );
+
+        // A read spanning blocks 0 and 1 only downloads block 1.
+        let offset = BLOCK as u64 - 50;
+        let CachedRead::Miss(miss) = cache.lookup(1, offset, 100) else {
+            panic!("block 1 is not cached yet");
+        };
+        assert_eq!(miss.fetch_offset, BLOCK_SIZE);
+        let fetched = fetch(&contents, &miss);
+        assert_eq!(
+            cache.complete(1, offset, 100, miss, &fetched),
+            &contents[BLOCK - 50..BLOCK + 50]
+        );
+
+        let CachedRead::Hit(bytes) = cache.lookup(1, offset, 100) else {
+            panic!("both blocks are cached now");
+        };
+        assert_eq!(bytes, &contents[BLOCK - 50..BLOCK + 50]);
+    }
+
+    #[test]
+    fn short_blocks_mark_end_of_file() {
+        let contents = file(BLOCK + 10);
+        let mut cache = BlockCache::new(16 * BLOCK_SIZE);
+
+        let CachedRead::Miss(miss) = cache.lookup(1, 0, 4 * BLOCK as u32) else {
+            panic!("empty cache should miss");
+        };
+        let fetched = fetch(&contents, &miss);
+        assert_eq!(
+            cache.complete(1, 0, 4 * BLOCK as u32, miss, &fetched),
+            contents
+        );
+
+        let CachedRead::Hit(bytes) = cache.lookup(1, BLOCK as u64, 4096) else {
+            panic!("the short final block answers reads past EOF");
+        };
+        assert_eq!(bytes, &contents[BLOCK..]);
+    }
+
+    #[test]
+    fn budget_evicts_and_invalidate_drops_an_inode() {
+        let contents = file(2 * BLOCK);
+        let mut cache = BlockCache::new(BLOCK_SIZE);
+        for ino in [1, 2] {
+            if let CachedRead::Miss(miss) = cache.lookup(ino, 0, 10) {
+                let fetched = fetch(&contents, &miss);
+                cache.complete(ino, 0, 10, miss, &fetched);
+            }
+        }
+        assert!(matches!(cache.lookup(1, 0, 10), CachedRead::Miss(_)));
+        assert!(matches!(cache.lookup(2, 0, 10), CachedRead::Hit(_)));
+
+        cache.invalidate(2);
+        assert!(matches!(cache.lookup(2, 0, 10), CachedRead::Miss(_)));
+    }
+
+    #[test]
+    fn fetches_that_raced_an_invalidation_are_not_cached() {
+        let contents = file(BLOCK);
+        let mut cache = BlockCache::new(16 * BLOCK_SIZE);
+
+        let CachedRead::Miss(miss) = cache.lookup(1, 0, 10) else {
+            panic!("empty cache should miss");
+        };
+        let fetched = fetch(&contents, &miss);
+        cache.invalidate(1);
+        assert_eq!(cache.complete(1, 0, 10, miss, &fetched), &contents[..10]);
+        assert!(matches!(cache.lookup(1, 0, 10), CachedRead::Miss(_)));
+    }
+}
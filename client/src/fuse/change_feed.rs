@@ -0,0 +1,115 @@
+use super::{RemoteFs, kernel_name, remote_path};
+use crate::api::{self, ApiError, ChangeEvent};
+use fuser::Notifier;
+use std::ops::ControlFlow;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::time::Duration;
+
+// Delay before reconnecting a dropped change feed; it doubles after each
+// failed attempt up to the maximum.
+const RECONNECT_DELAY: Duration = Duration::from_secs(1);
+const MAX_RECONNECT_DELAY: Duration = Duration::from_secs(30);
+
+impl RemoteFs {
+    // Starts `--watch-changes` on its own thread. Changes reported by the
+    // server drop this mount's cached attributes, listings, and blocks for
+    // the affected paths, ask the kernel to drop its copies too, and wake
+    // handles polling the changed files. Setting
+    // the returned flag stops it at the next event or keep-alive.
+    pub fn spawn_change_watcher(&self, notifier: Notifier) -> Arc<AtomicBool> {
+        let stop = Arc::new(AtomicBool::new(false));
+        let fs = self.clone();
+        let stopped = stop.clone();
+        if let Err(error) = std::thread::Builder::new()
+            .name("change-feed".to_string())
+            .spawn(move || fs.follow_changes(&notifier, &stopped))
+        {
+            log::warn!("Could not start the change feed: {error}");
+        }
+        stop
+    }
+
+    fn follow_changes(&self, notifier: &Notifier, stop: &AtomicBool) {
+        let mut delay = RECONNECT_DELAY;
+        while !stop.load(Ordering::Relaxed) {
+            let mut connected = false;
+            let result = self
+                .runtime
+                .block_on(api::watch_changes(&self.server_addr, |event| {
+                    if stop.load(Ordering::Relaxed) {
+                        return ControlFlow::Break(());
+                    }
+                    connected = true;
+                    match event {
+                        ChangeEvent::Changed(path) => self.forget_remote_change(notifier, &path),
+                        ChangeEvent::Reset => self.forget_remote_state(notifier),
+                    }
+                    ControlFlow::Continue(())
+                }));
+            if stop.load(Ordering::Relaxed) {
+                break;
+            }
+            if connected {
+                delay = RECONNECT_DELAY;
+            }
+            match result {
+                Err(ApiError::NotFound) => {
+                    log::warn!("The server has no change feed; --watch-changes is disabled");
+                    return;
+                }
+                Ok(()) => log::info!("Change feed ended; reconnecting in {delay:?}"),
+                Err(error) => log::warn!("Change feed failed: {error}; reconnecting in {delay:?}"),
+            }
+            std::thread::sleep(delay);
+            delay = (delay * 2).min(MAX_RECONNECT_DELAY);
+        }
+        log::info!("Change feed stopped");
+    }
+
+    // Drops what is cached about `path` and the listing of its parent, which
+    // gained, lost, or updated an entry.
+    fn forget_remote_change(&self, notifier: &Notifier, path: &str) {
+        self.invalidate_directory_cache_for_path(path);
+        if let Some(ino) = self.inode_for_path(path) {
+            self.forget_inode(notifier, ino);
+        }
+        if path == "/" {
+            return;
+        }
+        if let Some(parent) = self.inode_for_path(remote_path::parent(path)) {
+            self.forget_inode(notifier, parent);
+            let name = path.rsplit('/').next().unwrap_or_default();
+            if let Err(error) = notifier.inval_entry(parent, &kernel_name(name)) {
+                log::debug!("Kernel kept the entry for {path}: {error}");
+            }
+        }
+    }
+
+    // Every cache may be stale after missed events, so all of them go.
+    fn forget_remote_state(&self, notifier: &Notifier) {
+        self.directory_cache.lock().unwrap().clear();
+        self.negative_lookups.lock().unwrap().clear();
+        let inodes = self.inodes.lock().unwrap().expire_attrs();
+        for ino in inodes {
+            self.forget_inode(notifier, ino);
+        }
+    }
+
+    fn forget_inode(&self, notifier: &Notifier, ino: u64) {
+        if let Some(cached) = self.inodes.lock().unwrap().attr_mut(ino) {
+            cached.expire();
+        }
+        self.validated_pages.lock().unwrap().remove(&ino);
+        self.invalidate_file_data(ino);
+        for waiter in self.poll_waiters.changed(ino) {
+            if let Err(error) = waiter.notify() {
+                log::debug!("Could not wake a poller of inode {ino}: {error}");
+            }
+        }
+        // The kernel reports inodes it never cached as missing.
+        if let Err(error) = notifier.inval_inode(ino, 0, 0) {
+            log::debug!("Kernel kept the cache for inode {ino}: {error}");
+        }
+    }
+}
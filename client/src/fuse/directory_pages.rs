@@ -0,0 +1,307 @@
+use crate::api::{DirectoryEntry, NameEntry};
+use std::collections::VecDeque;
+
+// Entries requested per `GET /list` page while a directory handle is read.
+pub(super) const READDIR_PAGE_SIZE: usize = 1024;
+// `.` and `..` take the first two readdir offsets.
+const FIRST_ENTRY_OFFSET: i64 = 3;
+
+// Collects a directory's listing page by page. Journaled files are merged
+// in name order as the pages that would contain them arrive.
+pub(super) struct DirectoryPages {
+    entries: Vec<DirectoryEntry>,
+    next_cursor: Option<String>,
+    fetched_any: bool,
+    pending: VecDeque<DirectoryEntry>,
+    names_only: bool,
+}
+
+impl DirectoryPages {
+    pub(super) fn new(mut pending: Vec<DirectoryEntry>) -> Self {
+        pending.sort_by(|left, right| left.name.cmp(&right.name));
+        DirectoryPages {
+            entries: Vec::new(),
+            next_cursor: None,
+            fetched_any: false,
+            pending: pending.into(),
+            names_only: false,
+        }
+    }
+
+    // A listing that is already complete, such as one from the directory cache.
+    pub(super) fn complete(entries: Vec<DirectoryEntry>) -> Self {
+        let mut pages = DirectoryPages::new(Vec::new());
+        pages.add_page(entries, None);
+        pages
+    }
+
+    pub(super) fn needs_page(&self) -> bool {
+        !self.fetched_any || self.next_cursor.is_some()
+    }
+
+    pub(super) fn is_first_page(&self) -> bool {
+        !self.fetched_any
+    }
+
+    pub(super) fn cursor(&self) -> Option<&str> {
+        self.next_cursor.as_deref()
+    }
+
+    pub(super) fn add_page(&mut self, entries: Vec<DirectoryEntry>, next_cursor: Option<String>) {
+        let last_name = entries.last().map(|entry| entry.name.clone());
+        for entry in entries {
+            self.take_pending_before(&entry.name);
+            if self
+                .pending
+                .front()
+                .is_some_and(|pending| pending.name == entry.name)
+            {
+                self.entries.extend(self.pending.pop_front());
+            } else {
+                self.entries.push(entry);
+            }
+        }
+        match (&next_cursor, last_name) {
+            // Journaled names past this page belong to a later one.
+            (Some(_), Some(last_name)) => self.take_pending_before(&last_name),
+            _ => self.entries.extend(self.pending.drain(..)),
+        }
+
+        self.next_cursor = next_cursor;
+        self.fetched_any = true;
+    }
+
+    // A `GET /names` page. Its entries carry only a name and a type, so the
+    // snapshot they end up in says its attributes are not to be trusted.
+    pub(super) fn add_names_page(&mut self, names: Vec<NameEntry>, next_cursor: Option<String>) {
+        let entries = names
+            .into_iter()
+            .map(|name| DirectoryEntry {
+                name: name.name,
+                type_: name.type_,
+                size: 0,
+                modified_at: "0".to_string(),
+                mode: None,
+                uid: None,
+                gid: None,
+                target: None,
+                accessed_at: None,
+                changed_at: None,
+                nlink: None,
+                blocks: None,
+                content_type: None,
+                name_encoding: name.name_encoding,
+            })
+            .collect();
+        self.add_page(entries, next_cursor);
+        self.names_only = true;
+    }
+
+    fn take_pending_before(&mut self, name: &str) {
+        while self
+            .pending
+            .front()
+            .is_some_and(|pending| *pending.name < *name)
+        {
+            self.entries.extend(self.pending.pop_front());
+        }
+    }
+
+    // Later pages are fetched into the snapshot as readdir reaches them.
+    pub(super) fn into_snapshot(self) -> DirectorySnapshot {
+        DirectorySnapshot {
+            pages: self,
+            served: false,
+        }
+    }
+}
+
+// The listing an open directory handle reads, a page at a time. Entry `i`
+// has readdir offset `FIRST_ENTRY_OFFSET + i` for the life of the handle, and
+// pages are only ever appended, so changes to the directory while it is read
+// never skip or repeat an entry.
+pub(super) struct DirectorySnapshot {
+    pages: DirectoryPages,
+    served: bool,
+}
+
+impl DirectorySnapshot {
+    // Whether the server has entries past the ones fetched so far.
+    pub(super) fn needs_page(&self) -> bool {
+        self.pages.needs_page()
+    }
+
+    pub(super) fn pages_mut(&mut self) -> &mut DirectoryPages {
+        &mut self.pages
+    }
+
+    // Entries after the one the kernel consumed at `offset`, with their offsets.
+    pub(super) fn entries_after(
+        &mut self,
+        offset: i64,
+    ) -> impl Iterator<Item = (i64, &DirectoryEntry)> {
+        self.served = true;
+        let skip = (offset + 1 - FIRST_ENTRY_OFFSET).max(0) as usize;
+        self.pages
+            .entries
+            .iter()
+            .enumerate()
+            .skip(skip)
+            .map(|(index, entry)| (FIRST_ENTRY_OFFSET + index as i64, entry))
+    }
+
+    // A readdir at offset 0 after entries were served is a `rewinddir`.
+    pub(super) fn was_served(&self) -> bool {
+        self.served
+    }
+
+    // Whether entries came from `GET /names`, with only names and types.
+    pub(super) fn is_names_only(&self) -> bool {
+        self.pages.names_only
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn entry(name: &str) -> DirectoryEntry {
+        DirectoryEntry {
+            name: name.to_string(),
+            type_: crate::api::EntryType::File,
+            size: 0,
+            modified_at: "0".to_string(),
+            mode: None,
+            uid: None,
+            gid: None,
+            target: None,
+            accessed_at: None,
+            changed_at: None,
+            nlink: None,
+            blocks: None,
+            content_type: None,
+            name_encoding: None,
+        }
+    }
+
+    fn names(snapshot: &mut DirectorySnapshot, offset: i64) -> Vec<(i64, String)> {
+        snapshot
+            .entries_after(offset)
+            .map(|(offset, entry)| (offset, entry.name.clone()))
+            .collect()
+    }
+
+    #[test]
+    fn snapshot_offsets_are_stable_across_pages() {
+        let mut pages = DirectoryPages::new(Vec::new());
+        assert!(pages.needs_page());
+        pages.add_page(vec![entry("a"), entry("b")], Some("62".to_string()));
+        assert!(pages.needs_page());
+        assert_eq!(pages.cursor(), Some("62"));
+        pages.add_page(vec![entry("c")], None);
+        assert!(!pages.needs_page());
+
+        let mut snapshot = pages.into_snapshot();
+        assert!(!snapshot.was_served());
+        assert_eq!(names(&mut snapshot, 0).len(), 3);
+        assert_eq!(
+            names(&mut snapshot, 3),
+            [(4, "b".to_string()), (5, "c".to_string())]
+        );
+        assert_eq!(names(&mut snapshot, 2), names(&mut snapshot, 0));
+        assert!(names(&mut snapshot, 5).is_empty());
+        assert!(snapshot.was_served());
+    }
+
+    #[test]
+    fn pages_fetched_after_reads_extend_the_snapshot() {
+        let mut pages = DirectoryPages::new(Vec::new());
+        pages.add_page(vec![entry("a"), entry("b")], Some("62".to_string()));
+        let mut snapshot = pages.into_snapshot();
+        assert_eq!(
+            names(&mut snapshot, 0),
+            [(3, "a".to_string()), (4, "b".to_string())]
+        );
+        assert!(snapshot.needs_page());
+
+        snapshot.pages_mut().add_page(vec![entry("c")], None);
+        assert!(!snapshot.needs_page());
+        assert_eq!(names(&mut snapshot, 4), [(5, "c".to_string())]);
+    }
+
+    #[test]
+    fn full_replies_resume_after_the_last_entry_sent() {
+        let listed: Vec<String> = (0..2500).map(|index| format!("file-{index:04}")).collect();
+        let mut pages = DirectoryPages::new(Vec::new());
+        for page in listed.chunks(READDIR_PAGE_SIZE) {
+            let entries = page.iter().map(|name| entry(name)).collect();
+            pages.add_page(entries, Some("next".to_string()));
+        }
+        pages.add_page(Vec::new(), None);
+        let mut snapshot = pages.into_snapshot();
+
+        // Each reply fits 100 entries; the kernel then asks again from the
+        // offset of the last one it got, starting past `.` and `..`.
+        let mut served = Vec::new();
+        let mut offset = 2;
+        loop {
+            let reply: Vec<(i64, String)> =
+                names(&mut snapshot, offset).into_iter().take(100).collect();
+            let Some((last, _)) = reply.last() else {
+                break;
+            };
+            offset = *last;
+            served.extend(reply.into_iter().map(|(_, name)| name));
+        }
+        assert_eq!(served, listed);
+    }
+
+    #[test]
+    fn name_pages_keep_types_and_mark_the_snapshot() {
+        let name = |name: &str, type_| NameEntry {
+            name: name.to_string(),
+            type_,
+            name_encoding: None,
+        };
+        let mut pages = DirectoryPages::new(vec![entry("b")]);
+        pages.add_names_page(
+            vec![
+                name("a", crate::api::EntryType::Directory),
+                name("c", crate::api::EntryType::Symlink),
+            ],
+            None,
+        );
+        let mut snapshot = pages.into_snapshot();
+
+        assert!(snapshot.is_names_only());
+        let listed: Vec<_> = snapshot
+            .entries_after(0)
+            .map(|(_, entry)| (entry.name.as_str(), entry.type_))
+            .collect();
+        assert_eq!(
+            listed,
+            [
+                ("a", crate::api::EntryType::Directory),
+                ("b", crate::api::EntryType::File),
+                ("c", crate::api::EntryType::Symlink)
+            ]
+        );
+        assert!(
+            !DirectoryPages::complete(Vec::new())
+                .into_snapshot()
+                .is_names_only()
+        );
+    }
+
+    #[test]
+    fn journaled_entries_merge_into_the_page_that_covers_them() {
+        let mut pages = DirectoryPages::new(vec![entry("z"), entry("b"), entry("c")]);
+        pages.add_page(vec![entry("a"), entry("c")], Some("63".to_string()));
+        pages.add_page(vec![entry("d")], None);
+        let merged: Vec<String> = names(&mut pages.into_snapshot(), 0)
+            .into_iter()
+            .map(|(_, name)| name)
+            .collect();
+        assert_eq!(merged, ["a", "b", "c", "d", "z"]);
+    }
+}
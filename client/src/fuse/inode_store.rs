@@ -0,0 +1,124 @@
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, BufRead, BufReader, BufWriter, Write};
+use std::path::{Path, PathBuf};
+
+// One line of the inode database. `ino: None` retires a path that was renamed
+// away so a new file created there does not reuse the moved inode.
+#[derive(Serialize, Deserialize)]
+struct Record {
+    path: String,
+    ino: Option<u64>,
+}
+
+// Persistent `path -> inode` map behind `--inode-db`. Records are appended as
+// JSON lines while mounted and compacted to live entries on the next load.
+pub(super) struct InodeStore {
+    inodes: HashMap<String, u64>,
+    log: BufWriter<File>,
+}
+
+impl InodeStore {
+    pub(super) fn open(path: &Path) -> io::Result<Self> {
+        let mut inodes = HashMap::new();
+        match File::open(path) {
+            Ok(file) => {
+                for line in BufReader::new(file).lines() {
+                    // A torn final line from a crash only loses that record.
+                    let Ok(record) = serde_json::from_str::<Record>(&line?) else {
+                        continue;
+                    };
+                    match record.ino {
+                        Some(ino) => inodes.insert(record.path, ino),
+                        None => inodes.remove(&record.path),
+                    };
+                }
+            }
+            Err(error) if error.kind() == io::ErrorKind::NotFound => {}
+            Err(error) => return Err(error),
+        }
+
+        let compacted = compacted_path(path);
+        let mut writer = BufWriter::new(File::create(&compacted)?);
+        for (path, ino) in &inodes {
+            write_record(&mut writer, path, Some(*ino))?;
+        }
+        writer.into_inner()?.sync_all()?;
+        fs::rename(&compacted, path)?;
+
+        let log = BufWriter::new(OpenOptions::new().append(true).open(path)?);
+        Ok(InodeStore { inodes, log })
+    }
+
+    pub(super) fn get(&self, path: &str) -> Option<u64> {
+        self.inodes.get(path).copied()
+    }
+
+    pub(super) fn max_inode(&self) -> Option<u64> {
+        self.inodes.values().copied().max()
+    }
+
+    pub(super) fn record(&mut self, path: &str, ino: u64) {
+        if self.inodes.insert(path.to_string(), ino) != Some(ino) {
+            self.append(path, Some(ino));
+        }
+    }
+
+    pub(super) fn retire(&mut self, path: &str) {
+        if self.inodes.remove(path).is_some() {
+            self.append(path, None);
+        }
+    }
+
+    // The map stays authoritative in memory, so a failed write only costs
+    // stability after the next remount.
+    fn append(&mut self, path: &str, ino: Option<u64>) {
+        if let Err(error) = write_record(&mut self.log, path, ino).and_then(|()| self.log.flush()) {
+            log::warn!("Could not update inode database: {error}");
+        }
+    }
+}
+
+fn write_record(writer: &mut impl Write, path: &str, ino: Option<u64>) -> io::Result<()> {
+    let record = Record {
+        path: path.to_string(),
+        ino,
+    };
+    serde_json::to_writer(&mut *writer, &record)?;
+    writer.write_all(b"\n")
+}
+
+fn compacted_path(path: &Path) -> PathBuf {
+    let mut name = path.file_name().unwrap_or_default().to_os_string();
+    name.push(".compact");
+    path.with_file_name(name)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn records_and_retirements_survive_a_reload() {
+        let path = std::env::temp_dir().join(format!("remote-fs-inodes-{}", std::process::id()));
+        let _ = fs::remove_file(&path);
+
+        let mut store = InodeStore::open(&path).unwrap();
+        store.record("/docs", 2);
+        store.record("/docs/a.txt", 3);
+        store.record("/moved", 3);
+        store.retire("/docs/a.txt");
+        drop(store);
+
+        let store = InodeStore::open(&path).unwrap();
+        assert_eq!(store.get("/docs"), Some(2));
+        assert_eq!(store.get("/moved"), Some(3));
+        assert_eq!(store.get("/docs/a.txt"), None);
+        assert_eq!(store.max_inode(), Some(3));
+        drop(store);
+        assert_eq!(fs::read_to_string(&path).unwrap().lines().count(), 2);
+
+        fs::remove_file(path).unwrap();
+    }
+}
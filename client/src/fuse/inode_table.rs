@@ -0,0 +1,560 @@
+use super::CachedAttr;
+use super::inode_store::InodeStore;
+use fuser::{FUSE_ROOT_ID, FileAttr};
+use std::collections::{BTreeSet, HashMap};
+
+// Inode bookkeeping for the FUSE adapter. Paths and inodes are kept in two
+// maps that mirror each other so lookups in either direction are O(1); a
+// hard-linked inode has one primary path there and its other names in
+// `links`. Each inode also carries the kernel's lookup count so `forget` can
+// release it. With a store, paths keep the inode they were given on earlier
+// mounts, so a released number can come back for a different file; its
+// generation then changes so the kernel and NFS file handles can tell the two
+// apart. Without a store, numbers the kernel has forgotten are handed out
+// again, lowest first and with the next generation, before new ones are
+// taken; with recycling off they are never reused and stay at generation 0.
+pub(super) struct InodeTable {
+    attrs: HashMap<u64, CachedAttr>,
+    path_to_inode: HashMap<String, u64>,
+    inode_to_path: HashMap<u64, String>,
+    links: HashMap<u64, Vec<String>>,
+    lookups: HashMap<u64, u64>,
+    generations: HashMap<u64, u64>,
+    next_inode: u64,
+    // Forgotten numbers waiting to be reused; `None` when they are not.
+    free: Option<BTreeSet<u64>>,
+    store: Option<InodeStore>,
+}
+
+impl InodeTable {
+    pub(super) fn new(root_attr: FileAttr, store: Option<InodeStore>, recycle: bool) -> Self {
+        let next_inode = store
+            .as_ref()
+            .and_then(InodeStore::max_inode)
+            .map_or(FUSE_ROOT_ID, |max| max.max(FUSE_ROOT_ID))
+            + 1;
+        let mut table = InodeTable {
+            attrs: HashMap::new(),
+            path_to_inode: HashMap::new(),
+            inode_to_path: HashMap::new(),
+            links: HashMap::new(),
+            lookups: HashMap::new(),
+            generations: HashMap::new(),
+            next_inode,
+            free: (recycle && store.is_none()).then(BTreeSet::new),
+            store,
+        };
+        table.insert("/".to_string(), root_attr);
+        table
+    }
+
+    pub(super) fn allocate(&mut self) -> u64 {
+        if let Some(ino) = self.free.as_mut().and_then(BTreeSet::pop_first) {
+            *self.generations.entry(ino).or_default() += 1;
+            return ino;
+        }
+        let ino = self.next_inode;
+        self.next_inode += 1;
+        ino
+    }
+
+    // Reuses the stored inode for `path` unless another live path already
+    // holds it, in which case `path` gets (and records) a fresh one. A hard
+    // link recorded on an earlier mount is the exception: both names keep
+    // the one inode.
+    pub(super) fn allocate_for(&mut self, path: &str) -> u64 {
+        if let Some(ino) = self.store.as_ref().and_then(|store| store.get(path))
+            && ino != FUSE_ROOT_ID
+            && (self
+                .inode_to_path
+                .get(&ino)
+                .is_none_or(|bound| bound == path)
+                || self.is_stored_link(path, ino))
+        {
+            return ino;
+        }
+
+        let ino = self.allocate();
+        if let Some(store) = &mut self.store {
+            store.record(path, ino);
+        }
+        ino
+    }
+
+    // Caches `attr` and binds `path` to its inode, dropping any stale binding
+    // either side previously had.
+    pub(super) fn insert(&mut self, path: String, attr: FileAttr) {
+        self.attrs.insert(attr.ino, CachedAttr::new(attr));
+        if self.is_stored_link(&path, attr.ino) {
+            self.link(path, attr.ino);
+        } else {
+            self.bind(path, attr.ino);
+        }
+    }
+
+    // Binds `path` as another name for `ino`, keeping its existing path, and
+    // records it so the names still share the inode after a remount.
+    pub(super) fn link(&mut self, path: String, ino: u64) {
+        if let Some(store) = &mut self.store {
+            store.record(&path, ino);
+        }
+        if let Some(previous_ino) = self.path_to_inode.insert(path.clone(), ino)
+            && previous_ino != ino
+            && !self.drop_link(previous_ino, &path)
+        {
+            self.inode_to_path.remove(&previous_ino);
+        }
+        if self.inode_to_path.get(&ino) == Some(&path) {
+            return;
+        }
+        let links = self.links.entry(ino).or_default();
+        if !links.contains(&path) {
+            links.push(path);
+        }
+    }
+
+    // Number of names this table knows for `ino`.
+    pub(super) fn name_count(&self, ino: u64) -> usize {
+        usize::from(self.inode_to_path.contains_key(&ino))
+            + self.links.get(&ino).map_or(0, Vec::len)
+    }
+
+    pub(super) fn set_attr(&mut self, ino: u64, attr: FileAttr) {
+        self.attrs.insert(ino, CachedAttr::new(attr));
+    }
+
+    pub(super) fn attr(&self, ino: u64) -> Option<&CachedAttr> {
+        self.attrs.get(&ino)
+    }
+
+    pub(super) fn attr_mut(&mut self, ino: u64) -> Option<&mut CachedAttr> {
+        self.attrs.get_mut(&ino)
+    }
+
+    // Marks every cached attr stale and returns the inodes they belong to.
+    pub(super) fn expire_attrs(&mut self) -> Vec<u64> {
+        self.attrs
+            .iter_mut()
+            .map(|(ino, cached)| {
+                cached.expire();
+                *ino
+            })
+            .collect()
+    }
+
+    pub(super) fn path_for(&self, ino: u64) -> Option<&str> {
+        self.inode_to_path.get(&ino).map(String::as_str)
+    }
+
+    pub(super) fn ino_for(&self, path: &str) -> Option<u64> {
+        self.path_to_inode.get(path).copied()
+    }
+
+    // Generation to report with `ino` in entry and create replies.
+    pub(super) fn generation(&self, ino: u64) -> u64 {
+        self.generations.get(&ino).copied().unwrap_or(0)
+    }
+
+    // Counts one more kernel reference, made by every entry or create reply.
+    pub(super) fn add_lookup(&mut self, ino: u64) {
+        *self.lookups.entry(ino).or_default() += 1;
+    }
+
+    // Drops `nlookup` kernel references and forgets the inode once none are
+    // left, returning whether it did; anything else cached under the number
+    // must go with it. The root is pinned for the lifetime of the mount.
+    pub(super) fn forget(&mut self, ino: u64, nlookup: u64) -> bool {
+        if ino == FUSE_ROOT_ID {
+            return false;
+        }
+        let remaining = self
+            .lookups
+            .get(&ino)
+            .map_or(0, |count| count.saturating_sub(nlookup));
+        if remaining > 0 {
+            self.lookups.insert(ino, remaining);
+            return false;
+        }
+
+        self.lookups.remove(&ino);
+        self.release(ino);
+        if let Some(path) = self.inode_to_path.remove(&ino) {
+            self.path_to_inode.remove(&path);
+        }
+        for path in self.links.remove(&ino).unwrap_or_default() {
+            self.path_to_inode.remove(&path);
+        }
+        if let Some(free) = &mut self.free {
+            free.insert(ino);
+        }
+        true
+    }
+
+    pub(super) fn paths(&self) -> impl Iterator<Item = &str> {
+        self.path_to_inode.keys().map(String::as_str)
+    }
+
+    // Forgets `path` and every cached path below it.
+    pub(super) fn remove_tree(&mut self, path: &str) {
+        for removed_path in self.paths_in_tree(path) {
+            if let Some(ino) = self.path_to_inode.remove(&removed_path) {
+                if self.drop_link(ino, &removed_path) {
+                    // The inode lives on under its other names, so a file
+                    // later created here must not be handed it.
+                    if let Some(store) = &mut self.store {
+                        store.retire(&removed_path);
+                    }
+                    continue;
+                }
+                self.inode_to_path.remove(&ino);
+                self.release(ino);
+                self.lookups.remove(&ino);
+            }
+        }
+    }
+
+    // Rebinds `from` and its descendants under `to`, keeping their inodes and
+    // discarding whatever was cached at the destination.
+    pub(super) fn rename_tree(&mut self, from: &str, to: &str) {
+        let moved_paths: Vec<(String, u64)> = self
+            .paths_in_tree(from)
+            .into_iter()
+            .filter_map(|path| self.path_to_inode.remove(&path).map(|ino| (path, ino)))
+            .collect();
+        for (path, ino) in &moved_paths {
+            if self.inode_to_path.get(ino) == Some(path) {
+                self.inode_to_path.remove(ino);
+            }
+        }
+
+        self.remove_tree(to);
+
+        let prefix = tree_prefix(from);
+        for (old_path, ino) in moved_paths {
+            let new_path = if old_path == from {
+                to.to_string()
+            } else {
+                let suffix = old_path.strip_prefix(&prefix).unwrap_or("");
+                format!("{}/{}", to.trim_end_matches('/'), suffix)
+            };
+            if let Some(store) = &mut self.store {
+                store.retire(&old_path);
+                store.record(&new_path, ino);
+            }
+            // A moved secondary name stays secondary.
+            if let Some(link) = self
+                .links
+                .get_mut(&ino)
+                .and_then(|links| links.iter_mut().find(|link| **link == old_path))
+            {
+                *link = new_path.clone();
+                self.path_to_inode.insert(new_path, ino);
+                continue;
+            }
+            self.bind(new_path, ino);
+        }
+    }
+
+    // Whether the store gives `path` the inode another live path is bound
+    // to, which only a recorded hard link does: renames retire the old path.
+    fn is_stored_link(&self, path: &str, ino: u64) -> bool {
+        let Some(store) = &self.store else {
+            return false;
+        };
+        store.get(path) == Some(ino)
+            && self
+                .inode_to_path
+                .get(&ino)
+                .is_some_and(|primary| primary != path && store.get(primary) == Some(ino))
+    }
+
+    // Drops the cached attributes of an inode that no longer has a name here.
+    // A stored inode can be handed out again later, so the next holder gets
+    // a new generation.
+    fn release(&mut self, ino: u64) {
+        if self.attrs.remove(&ino).is_some() && self.store.is_some() {
+            *self.generations.entry(ino).or_default() += 1;
+        }
+    }
+
+    // Forgets one name of a hard-linked inode, promoting another name when
+    // the primary path goes. Returns false when `path` was its only name.
+    fn drop_link(&mut self, ino: u64, path: &str) -> bool {
+        let Some(links) = self.links.get_mut(&ino) else {
+            return false;
+        };
+        links.retain(|link| link != path);
+        if self
+            .inode_to_path
+            .get(&ino)
+            .is_some_and(|primary| primary == path)
+        {
+            match links.pop() {
+                Some(next) => {
+                    self.inode_to_path.insert(ino, next);
+                }
+                None => {
+                    self.links.remove(&ino);
+                    return false;
+                }
+            }
+        }
+        if self.links.get(&ino).is_some_and(Vec::is_empty) {
+            self.links.remove(&ino);
+        }
+        true
+    }
+
+    fn bind(&mut self, path: String, ino: u64) {
+        if let Some(previous_ino) = self.path_to_inode.insert(path.clone(), ino)
+            && previous_ino != ino
+            && !self.drop_link(previous_ino, &path)
+        {
+            self.inode_to_path.remove(&previous_ino);
+        }
+        // Refreshing a secondary name must not demote the primary path.
+        if self
+            .links
+            .get(&ino)
+            .is_some_and(|links| links.contains(&path))
+        {
+            return;
+        }
+        if let Some(previous_path) = self.inode_to_path.insert(ino, path.clone())
+            && previous_path != path
+        {
+            self.path_to_inode.remove(&previous_path);
+        }
+    }
+
+    fn paths_in_tree(&self, path: &str) -> Vec<String> {
+        let prefix = tree_prefix(path);
+        self.path_to_inode
+            .keys()
+            .filter(|cached_path| *cached_path == path || cached_path.starts_with(&prefix))
+            .cloned()
+            .collect()
+    }
+}
+
+fn tree_prefix(path: &str) -> String {
+    format!("{}/", path.trim_end_matches('/'))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fuse::create_file_attr;
+    use fuser::FileType;
+    use std::time::SystemTime;
+
+    fn attr(ino: u64, kind: FileType) -> FileAttr {
+        create_file_attr(ino, kind, 0, 0o755, 0, 0, SystemTime::UNIX_EPOCH)
+    }
+
+    fn table_with(paths: &[&str]) -> InodeTable {
+        let mut table = InodeTable::new(attr(FUSE_ROOT_ID, FileType::Directory), None, false);
+        for path in paths {
+            let ino = table.allocate();
+            table.insert(path.to_string(), attr(ino, FileType::RegularFile));
+        }
+        table
+    }
+
+    #[test]
+    fn resolves_paths_and_inodes_in_both_directions() {
+        let table = table_with(&["/docs"]);
+        let ino = table.ino_for("/docs").unwrap();
+
+        assert_eq!(table.path_for(ino), Some("/docs"));
+        assert_eq!(table.path_for(FUSE_ROOT_ID), Some("/"));
+        assert_eq!(table.ino_for("/missing"), None);
+    }
+
+    #[test]
+    fn rebinding_a_path_drops_the_stale_reverse_entry() {
+        let mut table = table_with(&["/file.txt"]);
+        let old_ino = table.ino_for("/file.txt").unwrap();
+        let new_ino = table.allocate();
+        table.insert(
+            "/file.txt".to_string(),
+            attr(new_ino, FileType::RegularFile),
+        );
+
+        assert_eq!(table.ino_for("/file.txt"), Some(new_ino));
+        assert_eq!(table.path_for(old_ino), None);
+    }
+
+    #[test]
+    fn rename_tree_moves_descendants_and_replaces_destination() {
+        let mut table = table_with(&["/docs", "/docs/a.txt", "/docs-old", "/archive"]);
+        let docs = table.ino_for("/docs").unwrap();
+        let child = table.ino_for("/docs/a.txt").unwrap();
+        let replaced = table.ino_for("/archive").unwrap();
+
+        table.rename_tree("/docs", "/archive");
+
+        assert_eq!(table.ino_for("/archive"), Some(docs));
+        assert_eq!(table.path_for(child), Some("/archive/a.txt"));
+        assert_eq!(table.ino_for("/docs"), None);
+        assert!(table.ino_for("/docs-old").is_some());
+        assert!(table.attr(replaced).is_none());
+    }
+
+    #[test]
+    fn forget_releases_an_inode_only_after_its_last_lookup() {
+        let mut table = table_with(&["/docs"]);
+        let ino = table.ino_for("/docs").unwrap();
+        table.add_lookup(ino);
+        table.add_lookup(ino);
+
+        table.forget(ino, 1);
+        assert_eq!(table.path_for(ino), Some("/docs"));
+
+        table.forget(ino, 1);
+        assert_eq!(table.ino_for("/docs"), None);
+        assert_eq!(table.path_for(ino), None);
+        assert!(table.attr(ino).is_none());
+
+        table.forget(FUSE_ROOT_ID, u64::MAX);
+        assert_eq!(table.path_for(FUSE_ROOT_ID), Some("/"));
+    }
+
+    #[test]
+    fn hard_links_share_an_inode_until_the_last_name_goes() {
+        let mut table = table_with(&["/a.txt"]);
+        let ino = table.ino_for("/a.txt").unwrap();
+        table.link("/b.txt".to_string(), ino);
+        // Refreshing the second name keeps both bindings.
+        table.insert("/b.txt".to_string(), attr(ino, FileType::RegularFile));
+
+        assert_eq!(table.ino_for("/b.txt"), Some(ino));
+        assert_eq!(table.path_for(ino), Some("/a.txt"));
+        assert_eq!(table.name_count(ino), 2);
+
+        table.rename_tree("/b.txt", "/c.txt");
+        assert_eq!(table.ino_for("/c.txt"), Some(ino));
+        assert_eq!(table.path_for(ino), Some("/a.txt"));
+
+        table.remove_tree("/a.txt");
+        assert_eq!(table.path_for(ino), Some("/c.txt"));
+        assert!(table.attr(ino).is_some());
+        assert_eq!(table.name_count(ino), 1);
+
+        table.remove_tree("/c.txt");
+        assert_eq!(table.ino_for("/c.txt"), None);
+        assert!(table.attr(ino).is_none());
+    }
+
+    #[test]
+    fn stored_hard_links_share_their_inode_after_a_remount() {
+        let path = std::env::temp_dir().join(format!("remote-fs-links-{}", std::process::id()));
+        let _ = std::fs::remove_file(&path);
+        let remount = || {
+            let store = InodeStore::open(&path).unwrap();
+            InodeTable::new(attr(FUSE_ROOT_ID, FileType::Directory), Some(store), true)
+        };
+
+        let mut table = remount();
+        let ino = table.allocate_for("/a.txt");
+        table.insert("/a.txt".to_string(), attr(ino, FileType::RegularFile));
+        table.link("/b.txt".to_string(), ino);
+        drop(table);
+
+        let mut table = remount();
+        for name in ["/a.txt", "/b.txt"] {
+            assert_eq!(table.allocate_for(name), ino);
+            table.insert(name.to_string(), attr(ino, FileType::RegularFile));
+        }
+        assert_eq!(table.ino_for("/a.txt"), Some(ino));
+        assert_eq!(table.ino_for("/b.txt"), Some(ino));
+        assert_eq!(table.name_count(ino), 2);
+
+        // Unlinking one name frees it for an unrelated file.
+        table.remove_tree("/b.txt");
+        drop(table);
+        let mut table = remount();
+        assert_eq!(table.allocate_for("/a.txt"), ino);
+        table.insert("/a.txt".to_string(), attr(ino, FileType::RegularFile));
+        assert_ne!(table.allocate_for("/b.txt"), ino);
+
+        std::fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn forget_drops_every_name_of_a_linked_inode() {
+        let mut table = table_with(&["/a.txt"]);
+        let ino = table.ino_for("/a.txt").unwrap();
+        table.link("/b.txt".to_string(), ino);
+
+        table.forget(ino, 1);
+        assert_eq!(table.ino_for("/a.txt"), None);
+        assert_eq!(table.ino_for("/b.txt"), None);
+    }
+
+    #[test]
+    fn reused_stored_inodes_get_a_new_generation() {
+        let path =
+            std::env::temp_dir().join(format!("remote-fs-generations-{}", std::process::id()));
+        let _ = std::fs::remove_file(&path);
+        let store = InodeStore::open(&path).unwrap();
+        let mut table = InodeTable::new(attr(FUSE_ROOT_ID, FileType::Directory), Some(store), true);
+
+        let ino = table.allocate_for("/a.txt");
+        table.insert("/a.txt".to_string(), attr(ino, FileType::RegularFile));
+        table.add_lookup(ino);
+        assert_eq!(table.generation(ino), 0);
+
+        // Deleting and recreating the path hands back the same number.
+        table.remove_tree("/a.txt");
+        assert_eq!(table.allocate_for("/a.txt"), ino);
+        table.insert("/a.txt".to_string(), attr(ino, FileType::RegularFile));
+        assert_eq!(table.generation(ino), 1);
+
+        table.forget(ino, 1);
+        assert_eq!(table.generation(ino), 2);
+        // Numbers are not recycled alongside a store, so new ones start at
+        // generation 0.
+        let fresh = table.allocate();
+        assert_eq!(table.generation(fresh), 0);
+
+        std::fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn forgotten_inodes_are_reused_with_a_new_generation() {
+        let mut table = InodeTable::new(attr(FUSE_ROOT_ID, FileType::Directory), None, true);
+        let first = table.allocate();
+        table.insert("/a.txt".to_string(), attr(first, FileType::RegularFile));
+        table.add_lookup(first);
+        let second = table.allocate();
+        table.insert("/b.txt".to_string(), attr(second, FileType::RegularFile));
+        table.add_lookup(second);
+
+        assert!(table.forget(second, 1));
+        assert!(table.forget(first, 1));
+        assert_eq!(table.allocate(), first);
+        assert_eq!(table.generation(first), 1);
+        assert_eq!(table.allocate(), second);
+        assert_eq!(table.allocate(), second + 1);
+
+        // Without recycling, forgotten numbers stay retired.
+        let mut table = table_with(&["/a.txt"]);
+        let ino = table.ino_for("/a.txt").unwrap();
+        table.forget(ino, 1);
+        assert_eq!(table.allocate(), ino + 1);
+    }
+
+    #[test]
+    fn remove_tree_forgets_children_only_under_the_prefix() {
+        let mut table = table_with(&["/docs", "/docs/a.txt", "/docs-old"]);
+        let child = table.ino_for("/docs/a.txt").unwrap();
+
+        table.remove_tree("/docs");
+
+        assert_eq!(table.ino_for("/docs"), None);
+        assert_eq!(table.path_for(child), None);
+        assert!(table.attr(child).is_none());
+        assert!(table.ino_for("/docs-old").is_some());
+    }
+}
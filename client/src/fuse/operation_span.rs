@@ -0,0 +1,51 @@
+use crate::metrics::Metrics;
+use libc::c_int;
+use std::sync::Arc;
+use std::time::Instant;
+use tracing::{field, span::EnteredSpan};
+
+// One FUSE call. Under `--log-format json` the span's close event carries its
+// fields and duration (`time.busy`); an `errno` field marks a failed call.
+// Without a tracing subscriber the span is disabled and costs nothing. The
+// call's latency is added to the metrics when the span is dropped.
+pub(super) struct OperationSpan {
+    span: EnteredSpan,
+    operation: &'static str,
+    started: Instant,
+    metrics: Arc<Metrics>,
+}
+
+impl OperationSpan {
+    pub(super) fn enter(metrics: &Arc<Metrics>, operation: &'static str, ino: u64) -> Self {
+        OperationSpan {
+            span: tracing::info_span!(
+                "fuse",
+                operation,
+                ino,
+                path = field::Empty,
+                errno = field::Empty
+            )
+            .entered(),
+            operation,
+            started: Instant::now(),
+            metrics: metrics.clone(),
+        }
+    }
+
+    pub(super) fn record_path(&self, path: &str) {
+        self.span.record("path", path);
+    }
+
+    // Records the failure and hands the errno on to the reply.
+    pub(super) fn fail(&self, errno: c_int) -> c_int {
+        self.span.record("errno", errno);
+        errno
+    }
+}
+
+impl Drop for OperationSpan {
+    fn drop(&mut self) {
+        self.metrics
+            .record_operation(self.operation, self.started.elapsed());
+    }
+}
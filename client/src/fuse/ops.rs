@@ -1,11 +1,16 @@
+use super::block_cache::CachedRead;
+use super::operation_span::OperationSpan;
 use super::{
-    HandleKind, RemoteFs, TRANSFER_IO_SIZE, TTL, api, apply_umask, attr_from_pending,
-    attr_from_remote_metadata, errno_from_api_error, errno_from_io_error, errno_from_rmdir_error,
-    time_or_now,
+    COPY_RANGE_LIMIT, FALLOC_FL_KEEP_SIZE, HandleKind, RENAME_NOREPLACE, RemoteFs,
+    TRANSFER_IO_SIZE, allocated_blocks, api, apply_umask, attr_from_pending,
+    attr_from_remote_metadata, errno_for, errno_from_io_error, errno_from_rmdir_error,
+    errno_from_unlink_error, errno_from_xattr_error, kernel_name, reply_xattr_bytes,
+    seek_without_holes, synthetic_filesystem_stats, time_or_now, wire_name,
 };
+use crate::ownership::EntryAccess;
 use fuser::{
-    FUSE_ROOT_ID, FileType, Filesystem, ReplyAttr, ReplyData, ReplyDirectory, ReplyEmpty,
-    ReplyEntry, Request,
+    FileType, Filesystem, PollHandle, ReplyAttr, ReplyBmap, ReplyData, ReplyDirectory,
+    ReplyDirectoryPlus, ReplyEmpty, ReplyEntry, ReplyLseek, ReplyPoll, ReplyStatfs, Request,
 };
 use libc::{ENOENT, c_int};
 use log::{debug, error, info, warn};
@@ -17,16 +22,37 @@ use std::time::SystemTime;
 // RemoteFs' local inode/path caches coherent after each successful mutation.
 impl Filesystem for RemoteFs {
     fn init(&mut self, _req: &Request<'_>, config: &mut fuser::KernelConfig) -> Result<(), c_int> {
-        if let Err(limit) = config.set_max_write(TRANSFER_IO_SIZE) {
+        // fuser always asks for `FUSE_BIG_WRITES`, so this is the only cap.
+        if let Err(limit) = config.set_max_write(self.max_write) {
             let _ = config.set_max_write(limit);
         }
         if let Err(limit) = config.set_max_readahead(TRANSFER_IO_SIZE) {
             let _ = config.set_max_readahead(limit);
         }
+        // Let the kernel list directories with attributes when it judges that
+        // cheaper than separate lookups; macFUSE has no readdirplus.
+        #[cfg(not(target_os = "macos"))]
+        match config.add_capabilities(
+            fuser::consts::FUSE_DO_READDIRPLUS | fuser::consts::FUSE_READDIRPLUS_AUTO,
+        ) {
+            Ok(()) => self.readdirplus = true,
+            Err(missing) => debug!("Kernel lacks readdirplus capabilities {missing:#x}"),
+        }
+        // Truncating opens reach `open` with `O_TRUNC` instead of as a
+        // separate setattr, so write-only ones can be staged.
+        if let Err(missing) = config.add_capabilities(fuser::consts::FUSE_ATOMIC_O_TRUNC) {
+            debug!("Kernel lacks atomic O_TRUNC {missing:#x}");
+        }
+
+        if self.writeback_cache
+            && let Err(missing) = config.add_capabilities(fuser::consts::FUSE_WRITEBACK_CACHE)
+        {
+            warn!("Kernel lacks write-back caching {missing:#x}; writes go through uncached");
+        }
 
         info!(
-            "Filesystem init method called. Requested max I/O size: {} bytes.",
-            TRANSFER_IO_SIZE
+            "Filesystem init method called. Requested max write: {} bytes, max readahead: {} bytes.",
+            self.max_write, TRANSFER_IO_SIZE
         );
         Ok(())
     }
@@ -40,28 +66,23 @@ impl Filesystem for RemoteFs {
         _umask: u32,
         reply: ReplyEntry,
     ) {
-        let name_str = match name.to_str() {
-            Some(s) => s.to_string(),
-            None => {
-                reply.error(libc::EINVAL); // Invalid argument
-                return;
-            }
+        let Some(reply) = self.writable(reply) else {
+            return;
         };
+
+        let name_str = wire_name(name);
         debug!(
             "mkdir(parent={}, name='{}', mode={:o})",
             parent, name_str, mode
         );
 
-        let full_path = match self.child_path(parent, name) {
-            Ok(path) => path,
-            Err(err) => {
-                reply.error(err);
-                return;
-            }
+        let Some(full_path) = self.full_path(parent, name) else {
+            reply.error(ENOENT);
+            return;
         };
 
         let api_path = full_path.trim_start_matches('/');
-        let effective_mode = apply_umask(mode, _umask, 0o755);
+        let effective_mode = apply_umask(mode, _umask, self.dir_mode);
 
         // Tell the remote server to create it and return real metadata.
         match self.runtime.block_on(api::create_directory(
@@ -70,9 +91,10 @@ impl Filesystem for RemoteFs {
             effective_mode,
         )) {
             Ok(metadata) => {
-                let new_ino = self.allocate_inode();
-                let attr = attr_from_remote_metadata(new_ino, &metadata);
+                let new_ino = self.allocate_inode(&full_path);
+                let attr = attr_from_remote_metadata(new_ino, &metadata, self.owner, self.blksize);
                 self.cache_attr(full_path.clone(), attr);
+                self.adjust_directory_nlink(parent, 1);
                 self.invalidate_directory_cache_for_path(&full_path);
 
                 debug!(
@@ -80,14 +102,152 @@ impl Filesystem for RemoteFs {
                     new_ino, full_path
                 );
 
-                reply.entry(&TTL, &attr, 0);
+                self.remember_lookup(attr.ino);
+
+                reply.entry(&self.entry_ttl, &attr, self.generation(attr.ino));
             }
             Err(err) => {
                 error!(
                     "Failed to create directory {} on server: {:?}",
                     api_path, err
                 );
-                reply.error(errno_from_api_error(&err));
+                reply.error(errno_for(&err));
+            }
+        }
+    }
+
+    fn symlink(
+        &mut self,
+        _req: &Request<'_>,
+        parent: u64,
+        link_name: &OsStr,
+        target: &Path,
+        reply: ReplyEntry,
+    ) {
+        debug!(
+            "symlink(parent={}, name={:?}, target={:?})",
+            parent, link_name, target
+        );
+
+        let Some(reply) = self.writable(reply) else {
+            return;
+        };
+
+        let Some(full_path) = self.full_path(parent, link_name) else {
+            reply.error(ENOENT);
+            return;
+        };
+        let Some(target) = target.to_str() else {
+            reply.error(libc::EINVAL);
+            return;
+        };
+
+        match self
+            .runtime
+            .block_on(api::create_symlink(&self.server_addr, &full_path, target))
+        {
+            Ok(metadata) => {
+                let attr = attr_from_remote_metadata(
+                    self.allocate_inode(&full_path),
+                    &metadata,
+                    self.owner,
+                    self.blksize,
+                );
+                self.cache_attr(full_path.clone(), attr);
+                self.invalidate_directory_cache_for_path(&full_path);
+                self.remember_lookup(attr.ino);
+                reply.entry(&self.entry_ttl, &attr, self.generation(attr.ino));
+            }
+            Err(err) => {
+                error!(
+                    "Failed to create symlink {} on server: {:?}",
+                    full_path, err
+                );
+                reply.error(errno_for(&err));
+            }
+        }
+    }
+
+    fn mknod(
+        &mut self,
+        _req: &Request<'_>,
+        parent: u64,
+        name: &OsStr,
+        mode: u32,
+        umask: u32,
+        rdev: u32,
+        reply: ReplyEntry,
+    ) {
+        debug!(
+            "mknod(parent={}, name={:?}, mode={:o}, rdev={})",
+            parent, name, mode, rdev
+        );
+
+        let Some(reply) = self.writable(reply) else {
+            return;
+        };
+
+        // Device nodes and sockets cannot be represented on the server.
+        // `mode_t` is narrower than `u32` on macOS.
+        #[allow(clippy::unnecessary_cast)]
+        let (type_mask, regular, fifo) = (
+            libc::S_IFMT as u32,
+            libc::S_IFREG as u32,
+            libc::S_IFIFO as u32,
+        );
+        let node_type = mode & type_mask;
+        if node_type != regular && node_type != fifo {
+            reply.error(libc::EPERM);
+            return;
+        }
+
+        let Some(full_path) = self.full_path(parent, name) else {
+            reply.error(ENOENT);
+            return;
+        };
+        let effective_mode = node_type | apply_umask(mode, umask, self.file_mode);
+
+        match self.runtime.block_on(api::make_node(
+            &self.server_addr,
+            &full_path,
+            effective_mode,
+            u64::from(rdev),
+        )) {
+            Ok(metadata) => {
+                let attr = attr_from_remote_metadata(
+                    self.allocate_inode(&full_path),
+                    &metadata,
+                    self.owner,
+                    self.blksize,
+                );
+                self.cache_attr(full_path.clone(), attr);
+                self.invalidate_directory_cache_for_path(&full_path);
+                self.remember_lookup(attr.ino);
+                reply.entry(&self.entry_ttl, &attr, self.generation(attr.ino));
+            }
+            Err(err) => {
+                error!("Failed to create node {} on server: {:?}", full_path, err);
+                reply.error(errno_for(&err));
+            }
+        }
+    }
+
+    fn readlink(&mut self, _req: &Request<'_>, ino: u64, reply: ReplyData) {
+        debug!("readlink(ino={})", ino);
+
+        let Some(path) = self.path_for_inode(ino) else {
+            reply.error(ENOENT);
+            return;
+        };
+
+        match self
+            .runtime
+            .block_on(api::read_link(&self.server_addr, &path))
+        {
+            Ok(target) => reply.data(target.as_bytes()),
+            Err(err) => {
+                error!("Failed to read symlink {} from server: {:?}", path, err);
+                reply.error(errno_for(&err));
             }
         }
     }
@@ -95,15 +255,33 @@ impl Filesystem for RemoteFs {
     fn open(&mut self, _req: &Request, ino: u64, _flags: i32, reply: fuser::ReplyOpen) {
         debug!("open(ino={}, flags={})", ino, _flags);
 
+        if self.read_only
+            && (_flags & libc::O_ACCMODE != libc::O_RDONLY || _flags & libc::O_TRUNC != 0)
+        {
+            reply.error(libc::EROFS);
+            return;
+        }
+
         if let Some(attr) = self.attr_for_inode(ino) {
             if attr.kind == FileType::RegularFile {
-                match self.path_for_inode(ino) {
-                    Some(path) => {
-                        let fh = self.allocate_handle(ino, path, HandleKind::File, _flags);
-                        reply.opened(fh, 0);
+                let Some(path) = self.path_for_inode(ino) else {
+                    reply.error(ENOENT);
+                    return;
+                };
+                let staged_write = if _flags & libc::O_TRUNC != 0 {
+                    match self.open_truncated(ino, &path, _flags) {
+                        Ok((_, staged_write)) => staged_write,
+                        Err(err) => {
+                            reply.error(err);
+                            return;
+                        }
                     }
-                    None => reply.error(ENOENT),
-                }
+                } else {
+                    None
+                };
+                let fh = self.allocate_handle(ino, path, HandleKind::File, _flags);
+                self.stage_handle_writes(fh, staged_write);
+                reply.opened(fh, 0);
             } else {
                 reply.error(libc::EISDIR);
             }
@@ -117,12 +295,26 @@ impl Filesystem for RemoteFs {
 
         if let Some(attr) = self.attr_for_inode(ino) {
             if attr.kind == FileType::Directory {
-                match self.path_for_inode(ino) {
-                    Some(path) => {
+                let Some(path) = self.path_for_inode(ino) else {
+                    reply.error(ENOENT);
+                    return;
+                };
+                // Plain readdirs take their names-only snapshot on the first call.
+                if !self.readdirplus {
+                    let fh = self.allocate_handle(ino, path, HandleKind::Directory, _flags);
+                    reply.opened(fh, 0);
+                    return;
+                }
+                match self.snapshot_directory(ino, &path, false) {
+                    Ok(snapshot) => {
                         let fh = self.allocate_handle(ino, path, HandleKind::Directory, _flags);
+                        self.directory_snapshots
+                            .lock()
+                            .unwrap()
+                            .insert(fh, snapshot);
                         reply.opened(fh, 0);
                     }
-                    None => reply.error(ENOENT),
+                    Err(err) => reply.error(err),
                 }
             } else {
                 reply.error(libc::ENOTDIR);
@@ -142,23 +334,49 @@ impl Filesystem for RemoteFs {
     ) {
         debug!("flush(ino={}, fh={})", ino, fh);
 
-        match self.handle_for(fh, ino, HandleKind::File) {
-            Ok(handle) => {
-                debug!(
-                    "flush accepted for path='{}', dirty={}",
-                    handle.path, handle.dirty
-                );
-                reply.ok();
+        let handle = match self.handle_for(fh, ino, HandleKind::File) {
+            Ok(handle) => handle,
+            Err(err) => {
+                reply.error(err);
+                return;
             }
+        };
+        debug!("flushing path='{}', dirty={}", handle.path, handle.dirty);
+
+        if let Err(err) = self
+            .flush_handle_writes(fh)
+            .and_then(|()| self.commit_staged_write(fh))
+        {
+            reply.error(err);
+            return;
+        }
+        match self.sync_pending(ino, &handle.path, true) {
+            Ok(()) => reply.ok(),
             Err(err) => reply.error(err),
         }
     }
 
-    fn fsync(&mut self, _req: &Request<'_>, ino: u64, fh: u64, _datasync: bool, reply: ReplyEmpty) {
-        debug!("fsync(ino={}, fh={})", ino, fh);
+    fn fsync(&mut self, _req: &Request<'_>, ino: u64, fh: u64, datasync: bool, reply: ReplyEmpty) {
+        debug!("fsync(ino={}, fh={}, datasync={})", ino, fh, datasync);
+
+        let handle = match self.handle_for(fh, ino, HandleKind::File) {
+            Ok(handle) => handle,
+            Err(err) => {
+                reply.error(err);
+                return;
+            }
+        };
 
-        match self.handle_for(fh, ino, HandleKind::File) {
-            Ok(_) => reply.ok(),
+        // A staged write is only durable at its path once it is committed.
+        if let Err(err) = self
+            .flush_handle_writes(fh)
+            .and_then(|()| self.commit_staged_write(fh))
+        {
+            reply.error(err);
+            return;
+        }
+        match self.sync_pending(ino, &handle.path, !datasync) {
+            Ok(()) => reply.ok(),
             Err(err) => reply.error(err),
         }
     }
@@ -175,12 +393,27 @@ impl Filesystem for RemoteFs {
     ) {
         debug!("release(ino={}, fh={}, flags={})", ino, fh, _flags);
 
+        // Buffered bytes that still cannot be sent are dropped with the handle,
+        // along with a staged write they were meant to complete.
+        let flushed = self
+            .flush_handle_writes(fh)
+            .and_then(|()| self.commit_staged_write(fh));
+        if let Err(err) = flushed {
+            error!("Discarding buffered writes for handle {fh}: errno {err}");
+            self.write_buffers.lock().unwrap().remove(&fh);
+            self.abort_staged_write(fh);
+        }
+
         match self.release_handle(fh, HandleKind::File) {
             Ok(handle) if handle.ino == ino => {
+                self.poll_waiters.release(fh);
                 debug!(
                     "Released file handle {} for path='{}', dirty={}",
                     fh, handle.path, handle.dirty
                 );
+                if !self.has_open_file_handle(&handle.path) {
+                    self.readahead.invalidate(ino);
+                }
                 if !self.has_open_file_handle(&handle.path)
                     && let Some(pending) = self.writeback.get(&handle.path)
                     && !pending.is_committed()
@@ -191,7 +424,10 @@ impl Filesystem for RemoteFs {
                         pending.path()
                     );
                 }
-                reply.ok();
+                match flushed {
+                    Ok(()) => reply.ok(),
+                    Err(err) => reply.error(err),
+                }
             }
             Ok(handle) => {
                 self.open_handles.lock().unwrap().insert(fh, handle);
@@ -201,6 +437,37 @@ impl Filesystem for RemoteFs {
         }
     }
 
+    // Regular files are always readable and writable. A change the server
+    // reports for the file, through `--watch-changes`, also raises POLLPRI
+    // once, as sysfs does for changed attributes, and wakes a poller that
+    // asked to be notified.
+    fn poll(
+        &mut self,
+        _req: &Request<'_>,
+        ino: u64,
+        fh: u64,
+        ph: PollHandle,
+        events: u32,
+        flags: u32,
+        reply: ReplyPoll,
+    ) {
+        debug!(
+            "poll(ino={}, fh={}, events={:#x}, flags={:#x})",
+            ino, fh, events, flags
+        );
+
+        if let Err(err) = self.handle_for(fh, ino, HandleKind::File) {
+            reply.error(err);
+            return;
+        }
+        let wake = (flags & fuser::consts::FUSE_POLL_SCHEDULE_NOTIFY != 0).then_some(ph);
+        let mut revents = libc::POLLIN | libc::POLLRDNORM | libc::POLLOUT | libc::POLLWRNORM;
+        if self.poll_waiters.poll(fh, ino, wake) {
+            revents |= libc::POLLPRI;
+        }
+        reply.poll(revents as u32);
+    }
+
     fn releasedir(
         &mut self,
         _req: &Request<'_>,
@@ -211,6 +478,7 @@ impl Filesystem for RemoteFs {
     ) {
         debug!("releasedir(ino={}, fh={}, flags={})", ino, fh, _flags);
 
+        self.directory_snapshots.lock().unwrap().remove(&fh);
         match self.release_handle(fh, HandleKind::Directory) {
             Ok(handle) if handle.ino == ino => reply.ok(),
             Ok(handle) => {
@@ -222,193 +490,379 @@ impl Filesystem for RemoteFs {
     }
 
     fn destroy(&mut self) {
+        self.flush_pending_writes();
+        let handles: Vec<u64> = self.open_handles.lock().unwrap().keys().copied().collect();
+        for fh in handles {
+            self.abort_staged_write(fh);
+        }
+        self.write_buffers.lock().unwrap().clear();
         self.open_handles.lock().unwrap().clear();
+        self.directory_snapshots.lock().unwrap().clear();
         self.directory_cache.lock().unwrap().clear();
         info!("Filesystem destroyed.");
     }
 
     fn lookup(&mut self, _req: &Request, parent_ino: u64, name: &OsStr, reply: ReplyEntry) {
-        let name_str = match name.to_str() {
-            Some(s) => s.to_string(),
-            None => {
-                reply.error(ENOENT);
-                return;
-            }
-        };
+        let span = OperationSpan::enter(&self.metrics, "lookup", parent_ino);
+        let name_str = wire_name(name);
         debug!("lookup(parent_ino={}, name='{}')", parent_ino, name_str);
 
-        // 1. Determine the parent path
-        let parent_path_str = {
-            let path_to_inode_map = self.path_to_inode.lock().unwrap();
-            path_to_inode_map
-                .iter()
-                .find_map(|(path, &inode)| {
-                    if inode == parent_ino {
-                        Some(path.clone())
-                    } else {
-                        None
-                    }
-                })
-                .unwrap_or_else(|| {
-                    warn!(
-                        "Parent path for ino {} not found, defaulting to root.",
-                        parent_ino
-                    );
-                    "/".to_string()
-                })
-        };
-
-        let full_path = if parent_path_str == "/" {
-            format!("/{}", name_str)
-        } else {
-            format!("{}/{}", parent_path_str.trim_end_matches('/'), name_str)
+        // 1. Resolve the full path through the parent inode
+        let Some(full_path) = self.full_path(parent_ino, name) else {
+            warn!("Parent path for ino {} not found.", parent_ino);
+            reply.error(span.fail(ENOENT));
+            return;
         };
 
         debug!("Looking up full path: {}", full_path);
+        span.record_path(&full_path);
 
         // 2. Check the local inode cache first, but only while metadata is fresh.
+        if let Some(ino) = self.inode_for_path(&full_path)
+            && let Some(attr) = self.fresh_attr_for_inode(ino)
         {
-            let path_to_inode_map = self.path_to_inode.lock().unwrap();
-            if let Some(&ino) = path_to_inode_map.get(&full_path)
-                && let Some(attr) = self.fresh_attr_for_inode(ino)
-            {
-                debug!("CACHE HIT: Found attr for path {}: {:?}", full_path, attr);
-                reply.entry(&TTL, &attr, 0);
-                return;
-            }
+            debug!("CACHE HIT: Found attr for path {}: {:?}", full_path, attr);
+            self.remember_lookup(attr.ino);
+            reply.entry(&self.entry_ttl, &attr, self.generation(attr.ino));
+            return;
         }
 
-        // 3. Cache miss or expired metadata: ask the server about the parent directory.
-        debug!(
-            "CACHE MISS: Fetching parent directory '{}' from server...",
-            parent_path_str
-        );
+        // 3. A recent miss for this path stands until the TTL runs out or the
+        // parent directory is modified.
+        if self.is_known_missing(&full_path) {
+            debug!("NEGATIVE CACHE HIT: {} is known to be absent", full_path);
+            self.reply_missing(reply, &span);
+            return;
+        }
 
-        match self.list_directory_cached(&parent_path_str) {
-            Ok(api_entries) => {
-                if let Some(api_entry) = api_entries.into_iter().find(|e| e.name == name_str) {
-                    let attr = self.attr_from_entry_for_path(&full_path, &api_entry);
-                    self.cache_attr(full_path.clone(), attr);
+        // 4. Cache miss or expired metadata: ask the server about this one path,
+        // so lookups never depend on the parent having been listed first.
+        debug!("CACHE MISS: Fetching {} from server...", full_path);
 
-                    debug!(
-                        "Dynamically added new entry to cache: ino={}, path='{}'",
-                        attr.ino, full_path
-                    );
-                    reply.entry(&TTL, &attr, 0);
-                } else {
-                    debug!("Path {} genuinely does not exist on the server.", full_path);
-                    reply.error(ENOENT);
-                }
+        match self.stat_path(&full_path) {
+            Ok(Some(attr)) => {
+                debug!(
+                    "Dynamically added new entry to cache: ino={}, path='{}'",
+                    attr.ino, full_path
+                );
+                self.remember_lookup(attr.ino);
+                reply.entry(&self.entry_ttl, &attr, self.generation(attr.ino));
+            }
+            Ok(None) => {
+                debug!("Path {} genuinely does not exist on the server.", full_path);
+                self.remember_missing(&full_path);
+                self.reply_missing(reply, &span);
             }
             Err(err) => {
                 error!("Failed to lookup {} on server: {:?}", full_path, err);
-                reply.error(err);
+                reply.error(span.fail(err));
             }
         }
     }
 
-    fn getattr(&mut self, _req: &Request, ino: u64, _fh: Option<u64>, reply: ReplyAttr) {
-        debug!("getattr(ino={})", ino);
-
-        if let Some(attr) = self.fresh_attr_for_inode(ino) {
-            reply.attr(&TTL, &attr);
-            return;
+    // On Linux fuser's default `batch_forget` forwards each node here; macOS
+    // builds predate batched forgets, so the kernel always sends these singly.
+    fn forget(&mut self, _req: &Request<'_>, ino: u64, nlookup: u64) {
+        debug!("forget(ino={}, nlookup={})", ino, nlookup);
+        if self.inodes.lock().unwrap().forget(ino, nlookup) {
+            // The number may be handed out again for another file.
+            self.validated_pages.lock().unwrap().remove(&ino);
+            self.invalidate_file_data(ino);
         }
+    }
 
-        match self
-            .refresh_inode_from_parent(ino)
-            .or_else(|| self.attr_for_inode(ino))
-        {
-            Some(attr) => reply.attr(&TTL, &attr),
-            None => {
-                warn!("getattr: Inode {} not found in map.", ino);
-                reply.error(ENOENT);
+    fn statfs(&mut self, _req: &Request<'_>, _ino: u64, reply: ReplyStatfs) {
+        let stats = match self.runtime.block_on(api::get_statfs(&self.server_addr)) {
+            Ok(stats) => stats,
+            Err(err) => {
+                if err.status() == Some(reqwest::StatusCode::NOT_FOUND) {
+                    debug!("Server has no filesystem statistics; reporting synthetic values");
+                } else {
+                    warn!("Failed to fetch filesystem statistics: {err}");
+                }
+                synthetic_filesystem_stats()
             }
-        }
+        };
+
+        reply.statfs(
+            stats.blocks,
+            stats.blocks_free,
+            stats.blocks_available,
+            stats.files,
+            stats.files_free,
+            stats.block_size,
+            stats.name_max,
+            stats.block_size,
+        );
     }
 
-    fn create(
+    fn getxattr(
         &mut self,
         _req: &Request<'_>,
-        parent: u64,
+        ino: u64,
         name: &OsStr,
-        mode: u32,
-        _umask: u32,
-        _flags: i32,
-        reply: fuser::ReplyCreate,
+        size: u32,
+        reply: fuser::ReplyXattr,
     ) {
-        let name_str = match name.to_str() {
-            Some(s) => s.to_string(),
-            None => {
-                reply.error(libc::EINVAL);
-                return;
-            }
-        };
-        debug!(
-            "create(parent={}, name='{}', mode={:o})",
-            parent, name_str, mode
-        );
+        debug!("getxattr(ino={}, name={:?}, size={})", ino, name, size);
 
-        let full_path = match self.child_path(parent, name) {
-            Ok(path) => path,
-            Err(err) => {
-                reply.error(err);
-                return;
-            }
+        let (Some(path), Some(name)) = (self.path_for_inode(ino), name.to_str()) else {
+            reply.error(ENOENT);
+            return;
         };
-        let effective_mode = apply_umask(mode, _umask, 0o644);
+        match self.xattr_value(&path, name) {
+            Ok(value) => reply_xattr_bytes(reply, &value, size),
+            Err(err) => reply.error(err),
+        }
+    }
 
-        // Acknowledge only after an empty durable local journal entry exists.
-        match self.writeback.stage_new(&full_path, effective_mode) {
-            Ok(pending) => match attr_from_pending(self.allocate_inode(), &pending) {
-                Ok(attr) => {
-                    self.cache_attr(full_path.clone(), attr);
-                    self.invalidate_directory_cache_for_path(&full_path);
-                    let fh =
-                        self.allocate_handle(attr.ino, full_path.clone(), HandleKind::File, _flags);
-
-                    debug!(
-                        "Successfully created file: ino={}, path='{}'",
-                        attr.ino, full_path
-                    );
+    fn listxattr(&mut self, _req: &Request<'_>, ino: u64, size: u32, reply: fuser::ReplyXattr) {
+        debug!("listxattr(ino={}, size={})", ino, size);
 
-                    reply.created(&TTL, &attr, 0, fh, 0);
-                }
-                Err(error) => {
-                    let _ = self.writeback.discard(&pending);
-                    error!("Failed to read new-file journal metadata: {error}");
-                    reply.error(errno_from_io_error(&error));
+        let Some(path) = self.path_for_inode(ino) else {
+            reply.error(ENOENT);
+            return;
+        };
+        match self.xattr_names(&path) {
+            Ok(names) => {
+                let mut bytes = Vec::new();
+                for name in names {
+                    bytes.extend_from_slice(name.as_bytes());
+                    bytes.push(0);
                 }
-            },
-            Err(error) => {
-                error!("Failed to create durable journal for {full_path}: {error}");
-                reply.error(errno_from_io_error(&error));
+                reply_xattr_bytes(reply, &bytes, size);
             }
+            Err(err) => reply.error(err),
         }
     }
 
-    fn write(
+    fn setxattr(
         &mut self,
         _req: &Request<'_>,
         ino: u64,
-        _fh: u64,
-        offset: i64,
-        data: &[u8],
-        _write_flags: u32,
-        _flags: i32,
-        _lock_owner: Option<u64>,
-        reply: fuser::ReplyWrite,
+        name: &OsStr,
+        value: &[u8],
+        flags: i32,
+        position: u32,
+        reply: ReplyEmpty,
     ) {
-        debug!("write(ino={}, offset={}, size={})", ino, offset, data.len());
+        debug!("setxattr(ino={}, name={:?}, flags={})", ino, name, flags);
 
-        if offset < 0 {
-            reply.error(libc::EINVAL);
+        let Some(reply) = self.writable(reply) else {
             return;
-        }
+        };
+
+        // Only macOS resource forks use a non-zero position.
+        if position != 0 {
+            reply.error(libc::ENOTSUP);
+            return;
+        }
+        let mode = match flags {
+            0 => api::XattrMode::Upsert,
+            libc::XATTR_CREATE => api::XattrMode::Create,
+            libc::XATTR_REPLACE => api::XattrMode::Replace,
+            _ => {
+                reply.error(libc::EINVAL);
+                return;
+            }
+        };
+        let (Some(path), Some(name)) = (self.path_for_inode(ino), name.to_str()) else {
+            reply.error(ENOENT);
+            return;
+        };
+        if let Err(error) = self.materialize_pending(&path) {
+            reply.error(errno_from_io_error(&error));
+            return;
+        }
+
+        match self
+            .runtime
+            .block_on(api::set_xattr(&self.server_addr, &path, name, value, mode))
+        {
+            Ok(()) => reply.ok(),
+            Err(err) => reply.error(errno_from_xattr_error(&err)),
+        }
+    }
+
+    fn removexattr(&mut self, _req: &Request<'_>, ino: u64, name: &OsStr, reply: ReplyEmpty) {
+        debug!("removexattr(ino={}, name={:?})", ino, name);
+
+        let Some(reply) = self.writable(reply) else {
+            return;
+        };
+
+        let (Some(path), Some(name)) = (self.path_for_inode(ino), name.to_str()) else {
+            reply.error(ENOENT);
+            return;
+        };
+        match self.remove_xattr(&path, name) {
+            Ok(()) => reply.ok(),
+            Err(err) => reply.error(err),
+        }
+    }
+
+    fn access(&mut self, req: &Request<'_>, ino: u64, mask: i32, reply: ReplyEmpty) {
+        debug!("access(ino={}, mask={:o})", ino, mask);
+
+        let Some(attr) = self.attr_for_inode(ino) else {
+            reply.error(ENOENT);
+            return;
+        };
+        if self.read_only && mask & libc::W_OK != 0 {
+            reply.error(libc::EROFS);
+            return;
+        }
+        // Cached attrs already carry any --uid/--gid override.
+        let entry = EntryAccess {
+            perm: attr.perm,
+            uid: attr.uid,
+            gid: attr.gid,
+            is_directory: attr.kind == FileType::Directory,
+        };
+        if entry.permits(req.uid(), req.gid(), mask) {
+            reply.ok();
+        } else {
+            reply.error(libc::EACCES);
+        }
+    }
+
+    fn getattr(&mut self, _req: &Request, ino: u64, _fh: Option<u64>, reply: ReplyAttr) {
+        debug!("getattr(ino={})", ino);
+        let span = OperationSpan::enter(&self.metrics, "getattr", ino);
+
+        if let Some(attr) = self.fresh_attr_for_inode(ino) {
+            reply.attr(&self.attr_ttl, &attr);
+            return;
+        }
+
+        match self.refresh_inode(ino).or_else(|| self.attr_for_inode(ino)) {
+            Some(attr) => reply.attr(&self.attr_ttl, &attr),
+            None => {
+                warn!("getattr: Inode {} not found in map.", ino);
+                reply.error(span.fail(ENOENT));
+            }
+        }
+    }
+
+    fn create(
+        &mut self,
+        _req: &Request<'_>,
+        parent: u64,
+        name: &OsStr,
+        mode: u32,
+        _umask: u32,
+        _flags: i32,
+        reply: fuser::ReplyCreate,
+    ) {
+        let Some(reply) = self.writable(reply) else {
+            return;
+        };
+
+        let name_str = wire_name(name);
+        debug!(
+            "create(parent={}, name='{}', mode={:o})",
+            parent, name_str, mode
+        );
+
+        let Some(full_path) = self.full_path(parent, name) else {
+            reply.error(ENOENT);
+            return;
+        };
+        let effective_mode = apply_umask(mode, _umask, self.file_mode);
+
+        // A stale negative lookup can route an existing name here, so honor
+        // O_EXCL and O_TRUNC rather than replacing the file with a new journal.
+        match self.open_existing_for_create(&full_path, _flags) {
+            Ok(None) => {}
+            Ok(Some((attr, staged_write))) => {
+                let fh = self.allocate_handle(attr.ino, full_path, HandleKind::File, _flags);
+                self.stage_handle_writes(fh, staged_write);
+                self.remember_lookup(attr.ino);
+                reply.created(&self.entry_ttl, &attr, self.generation(attr.ino), fh, 0);
+                return;
+            }
+            Err(err) => {
+                reply.error(err);
+                return;
+            }
+        }
+
+        // Acknowledge only after an empty durable local journal entry exists.
+        match self.writeback.stage_new(&full_path, effective_mode) {
+            Ok(pending) => {
+                match attr_from_pending(
+                    self.allocate_inode(&full_path),
+                    &pending,
+                    self.owner,
+                    self.blksize,
+                ) {
+                    Ok(attr) => {
+                        self.cache_attr(full_path.clone(), attr);
+                        self.invalidate_directory_cache_for_path(&full_path);
+                        let fh = self.allocate_handle(
+                            attr.ino,
+                            full_path.clone(),
+                            HandleKind::File,
+                            _flags,
+                        );
+
+                        debug!(
+                            "Successfully created file: ino={}, path='{}'",
+                            attr.ino, full_path
+                        );
+
+                        self.remember_lookup(attr.ino);
+
+                        reply.created(&self.entry_ttl, &attr, self.generation(attr.ino), fh, 0);
+                    }
+                    Err(error) => {
+                        let _ = self.writeback.discard(&pending);
+                        error!("Failed to read new-file journal metadata: {error}");
+                        reply.error(errno_from_io_error(&error));
+                    }
+                }
+            }
+            Err(error) => {
+                error!("Failed to create durable journal for {full_path}: {error}");
+                reply.error(errno_from_io_error(&error));
+            }
+        }
+    }
+
+    fn write(
+        &mut self,
+        _req: &Request<'_>,
+        ino: u64,
+        _fh: u64,
+        offset: i64,
+        data: &[u8],
+        _write_flags: u32,
+        _flags: i32,
+        _lock_owner: Option<u64>,
+        reply: fuser::ReplyWrite,
+    ) {
+        debug!("write(ino={}, offset={}, size={})", ino, offset, data.len());
+
+        let Some(reply) = self.writable(reply) else {
+            return;
+        };
+
+        if offset < 0 {
+            reply.error(libc::EINVAL);
+            return;
+        }
+        if let Err(err) = self.check_file_size(offset as u64 + data.len() as u64) {
+            reply.error(err);
+            return;
+        }
 
         let file_path = if _fh != 0 {
             match self.handle_for(_fh, ino, HandleKind::File) {
-                Ok(handle) => handle.path,
+                Ok(handle) if handle.allows_writes() => handle.path,
+                Ok(_) => {
+                    reply.error(libc::EBADF);
+                    return;
+                }
                 Err(err) => {
                     reply.error(err);
                     return;
@@ -425,12 +879,13 @@ impl Filesystem for RemoteFs {
             }
         };
         let api_path = file_path.trim_start_matches('/');
+        self.invalidate_file_data(ino);
 
         if let Some(pending) = self.writeback.get(&file_path)
             && !pending.is_committed()
         {
             match pending.write_at(data, offset as u64) {
-                Ok(()) => match attr_from_pending(ino, &pending) {
+                Ok(()) => match attr_from_pending(ino, &pending, self.owner, self.blksize) {
                     Ok(attr) => {
                         self.update_cached_attr(ino, attr);
                         self.invalidate_directory_cache_for_path(&file_path);
@@ -463,26 +918,39 @@ impl Filesystem for RemoteFs {
             }
         }
 
-        // Existing files retain the synchronous server write path.
+        // Existing files written through a handle are coalesced in memory and
+        // sent on flush, fsync, release, or when the buffer cannot take more.
+        if _fh != 0 {
+            match self.buffer_write(_fh, ino, &file_path, offset as u64, data) {
+                Ok(written) => {
+                    self.mark_handle_dirty(_fh);
+                    reply.written(written as u32);
+                }
+                Err(err) => reply.error(err),
+            }
+            return;
+        }
+
         match self.runtime.block_on(api::write_file(
             &self.server_addr,
             api_path,
             data,
             offset as u64,
         )) {
-            Ok(metadata) => {
-                let attr = attr_from_remote_metadata(ino, &metadata);
+            Ok(outcome) => {
+                let attr =
+                    attr_from_remote_metadata(ino, &outcome.metadata, self.owner, self.blksize);
                 self.update_cached_attr(ino, attr);
                 self.invalidate_directory_cache_for_path(&file_path);
                 if _fh != 0 {
                     self.mark_handle_dirty(_fh);
                 }
 
-                reply.written(data.len() as u32);
+                reply.written(outcome.written as u32);
             }
             Err(err) => {
                 error!("Failed to write to file {} on server: {:?}", api_path, err);
-                reply.error(errno_from_api_error(&err));
+                reply.error(errno_for(&err));
             }
         }
     }
@@ -507,6 +975,16 @@ impl Filesystem for RemoteFs {
     ) {
         debug!("setattr(ino={}, size={:?})", ino, size);
 
+        let Some(reply) = self.writable(reply) else {
+            return;
+        };
+        if let Some(size) = size
+            && let Err(err) = self.check_file_size(size)
+        {
+            reply.error(err);
+            return;
+        }
+
         let path = match self.path_for_inode(ino) {
             Some(path) => path,
             None => {
@@ -514,12 +992,36 @@ impl Filesystem for RemoteFs {
                 return;
             }
         };
+        if let Err(err) = self.flush_inode_writes(ino) {
+            reply.error(err);
+            return;
+        }
         let api_path = path.trim_start_matches('/');
         let mut latest_metadata = None;
+        if size.is_some() {
+            self.invalidate_file_data(ino);
+        }
         let mut pending_changed = false;
+        let mut staged_size = None;
+
+        // `ftruncate` on a handle staging a replacement resizes what it stages;
+        // the file itself is replaced on commit anyway.
+        if let Some(s) = size
+            && let Some(staged_write) = _fh.and_then(|fh| self.staged_write_for(fh))
+        {
+            if let Err(err) =
+                self.runtime
+                    .block_on(api::resize_temp_write(&self.server_addr, &staged_write, s))
+            {
+                error!("Failed to resize the staged write for {api_path}: {err:?}");
+                reply.error(errno_for(&err));
+                return;
+            }
+            staged_size = Some(s);
+        }
 
         // Resize a new file in its durable journal; existing files remain synchronous.
-        if let Some(s) = size {
+        if let Some(s) = size.filter(|_| staged_size.is_none()) {
             let mut resize_remotely = true;
             if let Some(pending) = self.writeback.get(&path)
                 && !pending.is_committed()
@@ -554,7 +1056,7 @@ impl Filesystem for RemoteFs {
                     Ok(metadata) => latest_metadata = Some(metadata),
                     Err(err) => {
                         error!("Failed to resize file {} on server: {:?}", api_path, err);
-                        reply.error(errno_from_api_error(&err));
+                        reply.error(errno_for(&err));
                         return;
                     }
                 }
@@ -562,7 +1064,13 @@ impl Filesystem for RemoteFs {
         }
 
         let requested_mtime = _mtime.map(time_or_now);
-        if mode.is_some() || uid.is_some() || gid.is_some() || requested_mtime.is_some() {
+        let requested_atime = _atime.map(time_or_now);
+        if mode.is_some()
+            || uid.is_some()
+            || gid.is_some()
+            || requested_mtime.is_some()
+            || requested_atime.is_some()
+        {
             if self
                 .writeback
                 .get(&path)
@@ -579,36 +1087,48 @@ impl Filesystem for RemoteFs {
                 uid,
                 gid,
                 requested_mtime,
+                requested_atime,
             )) {
                 Ok(metadata) => latest_metadata = Some(metadata),
                 Err(err) => {
                     error!("Failed to update metadata for {}: {:?}", api_path, err);
-                    reply.error(errno_from_api_error(&err));
+                    reply.error(errno_for(&err));
                     return;
                 }
             }
         }
 
         if let Some(metadata) = latest_metadata {
-            let attr = attr_from_remote_metadata(ino, &metadata);
+            let mut attr = attr_from_remote_metadata(ino, &metadata, self.owner, self.blksize);
+            // Listings carry no access time, so report the one just applied.
+            if let Some(atime) = requested_atime {
+                attr.atime = atime;
+            }
             self.update_cached_attr(ino, attr);
             self.invalidate_directory_cache_for_path(&path);
-            reply.attr(&TTL, &attr);
+            reply.attr(&self.attr_ttl, &attr);
         } else if pending_changed {
             let Some(pending) = self.writeback.get(&path) else {
                 reply.error(libc::EIO);
                 return;
             };
-            match attr_from_pending(ino, &pending) {
+            match attr_from_pending(ino, &pending, self.owner, self.blksize) {
                 Ok(attr) => {
                     self.update_cached_attr(ino, attr);
                     self.invalidate_directory_cache_for_path(&path);
-                    reply.attr(&TTL, &attr);
+                    reply.attr(&self.attr_ttl, &attr);
                 }
                 Err(error) => reply.error(errno_from_io_error(&error)),
             }
+        } else if let Some(size) = staged_size
+            && let Some(mut attr) = self.attr_for_inode(ino)
+        {
+            attr.size = size;
+            attr.blocks = allocated_blocks(size, attr.blksize);
+            self.update_cached_attr(ino, attr);
+            reply.attr(&self.attr_ttl, &attr);
         } else if let Some(attr) = self.attr_for_inode(ino) {
-            reply.attr(&TTL, &attr);
+            reply.attr(&self.attr_ttl, &attr);
         } else {
             reply.error(ENOENT);
         }
@@ -626,17 +1146,26 @@ impl Filesystem for RemoteFs {
         reply: ReplyData,
     ) {
         debug!("read(ino={}, offset={}, size={})", ino, offset, size);
+        let span = OperationSpan::enter(&self.metrics, "read", ino);
 
         if offset < 0 {
-            reply.error(libc::EINVAL);
+            reply.error(span.fail(libc::EINVAL));
+            return;
+        }
+        if let Err(err) = self.check_read_size(ino, offset as u64, size) {
+            reply.error(span.fail(err));
             return;
         }
 
         let file_path = if _fh != 0 {
             match self.handle_for(_fh, ino, HandleKind::File) {
-                Ok(handle) => handle.path,
+                Ok(handle) if handle.allows_reads() => handle.path,
+                Ok(_) => {
+                    reply.error(span.fail(libc::EBADF));
+                    return;
+                }
                 Err(err) => {
-                    reply.error(err);
+                    reply.error(span.fail(err));
                     return;
                 }
             }
@@ -645,11 +1174,16 @@ impl Filesystem for RemoteFs {
                 Some(path) => path,
                 None => {
                     error!("read: Could not find path for ino {}", ino);
-                    reply.error(ENOENT);
+                    reply.error(span.fail(ENOENT));
                     return;
                 }
             }
         };
+        span.record_path(&file_path);
+        if let Err(err) = self.flush_inode_writes(ino) {
+            reply.error(span.fail(err));
+            return;
+        }
         let api_path = file_path.trim_start_matches('/');
 
         if let Some(pending) = self.writeback.get(&file_path)
@@ -668,45 +1202,268 @@ impl Filesystem for RemoteFs {
                         std::io::ErrorKind::NotFound | std::io::ErrorKind::AlreadyExists
                     ) => {}
                 Err(error) => {
-                    reply.error(errno_from_io_error(&error));
+                    reply.error(span.fail(errno_from_io_error(&error)));
                     return;
                 }
             }
         }
 
-        // Fetch only the byte range requested by the kernel.
-        match self.runtime.block_on(api::read_file(
+        // Cached blocks first, then readahead, then the server. Only the latter
+        // two count towards sequential-read detection, so a fully cached file
+        // never starts prefetching.
+        let miss = match self
+            .block_cache
+            .lock()
+            .unwrap()
+            .lookup(ino, offset as u64, size)
+        {
+            CachedRead::Hit(bytes) => {
+                debug!("BLOCK CACHE HIT: ino={}, offset={}", ino, offset);
+                reply.data(&bytes);
+                return;
+            }
+            CachedRead::Miss(miss) => miss,
+        };
+
+        if _fh != 0
+            && let Some(bytes) = self.readahead.take(&self.runtime, ino, offset as u64, size)
+        {
+            debug!("READAHEAD HIT: ino={}, offset={}", ino, offset);
+            self.record_handle_read(_fh, ino, &file_path, offset as u64, size, bytes.len());
+            reply.data(&bytes);
+            return;
+        }
+
+        // Fetch the uncached blocks covering the range requested by the kernel.
+        match self.runtime.block_on(api::read_file_chunked(
             &self.server_addr,
             api_path,
-            offset as u64,
-            size,
+            miss.fetch_offset,
+            miss.fetch_len,
+            self.read_chunk_size,
         )) {
-            Ok(bytes) => {
+            Ok(fetched) => {
+                let bytes = self.block_cache.lock().unwrap().complete(
+                    ino,
+                    offset as u64,
+                    size,
+                    miss,
+                    &fetched,
+                );
+                if _fh != 0 {
+                    self.record_handle_read(_fh, ino, &file_path, offset as u64, size, bytes.len());
+                }
                 reply.data(&bytes);
             }
             Err(err) => {
                 error!("Failed to read file {} from server: {:?}", api_path, err);
-                reply.error(errno_from_api_error(&err));
+                reply.error(span.fail(errno_for(&err)));
             }
         }
     }
 
-    fn unlink(&mut self, _req: &Request<'_>, parent: u64, name: &OsStr, reply: ReplyEmpty) {
-        // Resolve the file path, ask the server to delete it, then clear the local cache.
-        let full_path = match self.child_path(parent, name) {
-            Ok(path) => path,
+    // Reserves space on the server. Only plain and `FALLOC_FL_KEEP_SIZE`
+    // allocation are supported; hole punching and range collapsing are not.
+    fn fallocate(
+        &mut self,
+        _req: &Request<'_>,
+        ino: u64,
+        fh: u64,
+        offset: i64,
+        length: i64,
+        mode: i32,
+        reply: ReplyEmpty,
+    ) {
+        debug!(
+            "fallocate(ino={}, offset={}, length={}, mode={:#x})",
+            ino, offset, length, mode
+        );
+
+        let Some(reply) = self.writable(reply) else {
+            return;
+        };
+        if mode & !FALLOC_FL_KEEP_SIZE != 0 {
+            reply.error(libc::EOPNOTSUPP);
+            return;
+        }
+        if offset < 0 || length <= 0 {
+            reply.error(libc::EINVAL);
+            return;
+        }
+        if mode & FALLOC_FL_KEEP_SIZE == 0
+            && let Err(err) = self.check_file_size(offset as u64 + length as u64)
+        {
+            reply.error(err);
+            return;
+        }
+
+        let path = match self.handle_for(fh, ino, HandleKind::File) {
+            // The server file is not the one this handle will publish.
+            Ok(handle) if handle.staged_write.is_some() => {
+                reply.error(libc::EOPNOTSUPP);
+                return;
+            }
+            Ok(handle) if handle.allows_writes() => handle.path,
+            Ok(_) => {
+                reply.error(libc::EBADF);
+                return;
+            }
             Err(err) => {
                 reply.error(err);
                 return;
             }
         };
+        if let Err(err) = self.push_writes_to_server(Some(ino), &path) {
+            reply.error(err);
+            return;
+        }
+
+        match self.runtime.block_on(api::preallocate_file(
+            &self.server_addr,
+            &path,
+            offset as u64,
+            length as u64,
+            mode & FALLOC_FL_KEEP_SIZE != 0,
+        )) {
+            Ok(metadata) => {
+                let attr = attr_from_remote_metadata(ino, &metadata, self.owner, self.blksize);
+                self.invalidate_file_data(ino);
+                self.update_cached_attr(ino, attr);
+                self.invalidate_directory_cache_for_path(&path);
+                self.mark_handle_dirty(fh);
+                reply.ok();
+            }
+            Err(err) => {
+                error!("Failed to preallocate {} on server: {:?}", path, err);
+                reply.error(errno_for(&err));
+            }
+        }
+    }
+
+    // Copies between two files on the mount run on the server. Destinations
+    // staged by an `O_TRUNC` open, and servers without `POST /copy`, answer
+    // EOPNOTSUPP so the kernel falls back to its own read and write loop.
+    fn copy_file_range(
+        &mut self,
+        _req: &Request<'_>,
+        ino_in: u64,
+        fh_in: u64,
+        offset_in: i64,
+        ino_out: u64,
+        fh_out: u64,
+        offset_out: i64,
+        len: u64,
+        _flags: u32,
+        reply: fuser::ReplyWrite,
+    ) {
+        debug!(
+            "copy_file_range(ino_in={}, offset_in={}, ino_out={}, offset_out={}, len={})",
+            ino_in, offset_in, ino_out, offset_out, len
+        );
+
+        let Some(reply) = self.writable(reply) else {
+            return;
+        };
+        if offset_in < 0 || offset_out < 0 {
+            reply.error(libc::EINVAL);
+            return;
+        }
+
+        let from_path = match self.handle_for(fh_in, ino_in, HandleKind::File) {
+            Ok(handle) if handle.allows_reads() => handle.path,
+            Ok(_) => {
+                reply.error(libc::EBADF);
+                return;
+            }
+            Err(err) => {
+                reply.error(err);
+                return;
+            }
+        };
+        let to_path = match self.handle_for(fh_out, ino_out, HandleKind::File) {
+            Ok(handle) if handle.staged_write.is_some() => {
+                reply.error(libc::EOPNOTSUPP);
+                return;
+            }
+            Ok(handle) if handle.allows_writes() => handle.path,
+            Ok(_) => {
+                reply.error(libc::EBADF);
+                return;
+            }
+            Err(err) => {
+                reply.error(err);
+                return;
+            }
+        };
+
+        // The server must hold every byte written to either file so far.
+        for (ino, path) in [(ino_in, &from_path), (ino_out, &to_path)] {
+            if let Err(err) = self.push_writes_to_server(Some(ino), path) {
+                reply.error(err);
+                return;
+            }
+        }
+        if let Err(err) = self.check_copy_size(ino_in, offset_in as u64, offset_out as u64, len) {
+            reply.error(err);
+            return;
+        }
+
+        self.invalidate_file_data(ino_out);
+        match self.runtime.block_on(api::copy_range(
+            &self.server_addr,
+            &from_path,
+            offset_in as u64,
+            &to_path,
+            offset_out as u64,
+            len.min(COPY_RANGE_LIMIT),
+        )) {
+            Ok(copied) => {
+                self.mark_handle_dirty(fh_out);
+                match self
+                    .runtime
+                    .block_on(api::get_metadata(&self.server_addr, &to_path))
+                {
+                    Ok(metadata) => {
+                        let attr =
+                            attr_from_remote_metadata(ino_out, &metadata, self.owner, self.blksize);
+                        self.update_cached_attr(ino_out, attr);
+                    }
+                    Err(err) => warn!("Failed to refresh {} after a copy: {:?}", to_path, err),
+                }
+                self.invalidate_directory_cache_for_path(&to_path);
+                reply.written(copied as u32);
+            }
+            Err(err) if err.is_unknown_endpoint() => {
+                debug!("Server-side copy unavailable for {}: {:?}", to_path, err);
+                reply.error(libc::EOPNOTSUPP);
+            }
+            Err(err) => {
+                error!(
+                    "Failed to copy {} to {} on server: {:?}",
+                    from_path, to_path, err
+                );
+                reply.error(errno_for(&err));
+            }
+        }
+    }
+
+    fn unlink(&mut self, _req: &Request<'_>, parent: u64, name: &OsStr, reply: ReplyEmpty) {
+        let Some(reply) = self.writable(reply) else {
+            return;
+        };
+
+        // Resolve the file path, ask the server to delete it, then clear the local cache.
+        let Some(full_path) = self.full_path(parent, name) else {
+            reply.error(ENOENT);
+            return;
+        };
 
         debug!("unlink(parent={}, path='{}')", parent, full_path);
 
         // `unlink` must reject directories; those should go through `rmdir`.
-        if let Some(ino) = self.path_to_inode.lock().unwrap().get(&full_path).copied()
-            && let Some(attr) = self.inode_map.lock().unwrap().get(&ino)
-            && attr.attr.kind == FileType::Directory
+        if let Some(ino) = self.inode_for_path(&full_path)
+            && let Some(attr) = self.attr_for_inode(ino)
+            && attr.kind == FileType::Directory
         {
             reply.error(libc::EISDIR);
             return;
@@ -734,6 +1491,22 @@ impl Filesystem for RemoteFs {
             }
         }
 
+        // Bytes still buffered for the file would only recreate it later,
+        // unless another hard link keeps its contents reachable.
+        let linked_ino = match self.inode_for_path(&full_path) {
+            Some(ino) if self.name_count(ino) > 1 => {
+                if let Err(err) = self.flush_inode_writes(ino) {
+                    reply.error(err);
+                    return;
+                }
+                Some(ino)
+            }
+            Some(ino) => {
+                self.discard_inode_writes(ino);
+                None
+            }
+            None => None,
+        };
         let api_path = full_path.trim_start_matches('/');
         match self
             .runtime
@@ -741,31 +1514,96 @@ impl Filesystem for RemoteFs {
         {
             Ok(_) => {
                 self.remove_cached_path(&full_path);
+                if let Some(ino) = linked_ino {
+                    self.drop_cached_link(ino);
+                }
                 reply.ok();
             }
             Err(err) => {
                 error!("Failed to delete file {} on server: {:?}", api_path, err);
-                reply.error(errno_from_api_error(&err));
+                reply.error(errno_from_unlink_error(&err));
             }
         }
     }
 
-    fn rmdir(&mut self, _req: &Request<'_>, parent: u64, name: &OsStr, reply: ReplyEmpty) {
-        // Resolve the directory path, ask the server to delete it, then clear cached children.
-        let full_path = match self.child_path(parent, name) {
-            Ok(path) => path,
+    fn link(
+        &mut self,
+        _req: &Request<'_>,
+        ino: u64,
+        newparent: u64,
+        newname: &OsStr,
+        reply: ReplyEntry,
+    ) {
+        let Some(reply) = self.writable(reply) else {
+            return;
+        };
+
+        let Some(from_path) = self.path_for_inode(ino) else {
+            reply.error(ENOENT);
+            return;
+        };
+        let Some(to_path) = self.full_path(newparent, newname) else {
+            reply.error(ENOENT);
+            return;
+        };
+
+        debug!("link(ino={}, from='{}', to='{}')", ino, from_path, to_path);
+
+        if let Some(attr) = self.attr_for_inode(ino)
+            && attr.kind == FileType::Directory
+        {
+            reply.error(libc::EPERM);
+            return;
+        }
+        // The new name must see every byte written so far.
+        if let Err(err) = self.push_writes_to_server(Some(ino), &from_path) {
+            reply.error(err);
+            return;
+        }
+
+        match self
+            .runtime
+            .block_on(api::link_file(&self.server_addr, &from_path, &to_path))
+        {
+            Ok(metadata) => {
+                let mut attr = attr_from_remote_metadata(ino, &metadata, self.owner, self.blksize);
+                // Servers that cannot count links still gained one.
+                if metadata.nlink.is_none() {
+                    attr.nlink = self
+                        .attr_for_inode(ino)
+                        .map_or(2, |previous| previous.nlink.saturating_add(1));
+                }
+                self.link_cached_path(&to_path, attr);
+                self.remember_lookup(ino);
+                reply.entry(&self.entry_ttl, &attr, self.generation(attr.ino));
+            }
             Err(err) => {
-                reply.error(err);
-                return;
+                error!(
+                    "Failed to link {} to {} on server: {:?}",
+                    to_path, from_path, err
+                );
+                reply.error(errno_for(&err));
             }
+        }
+    }
+
+    fn rmdir(&mut self, _req: &Request<'_>, parent: u64, name: &OsStr, reply: ReplyEmpty) {
+        let Some(reply) = self.writable(reply) else {
+            return;
+        };
+
+        // Resolve the directory path, ask the server to delete it, then clear cached children.
+        let Some(full_path) = self.full_path(parent, name) else {
+            reply.error(ENOENT);
+            return;
         };
 
         debug!("rmdir(parent={}, path='{}')", parent, full_path);
 
         // `rmdir` must reject regular files; those should go through `unlink`.
-        if let Some(ino) = self.path_to_inode.lock().unwrap().get(&full_path).copied()
-            && let Some(attr) = self.inode_map.lock().unwrap().get(&ino)
-            && attr.attr.kind != FileType::Directory
+        if let Some(ino) = self.inode_for_path(&full_path)
+            && let Some(attr) = self.attr_for_inode(ino)
+            && attr.kind != FileType::Directory
         {
             reply.error(libc::ENOTDIR);
             return;
@@ -782,6 +1620,7 @@ impl Filesystem for RemoteFs {
         {
             Ok(_) => {
                 self.remove_cached_path(&full_path);
+                self.adjust_directory_nlink(parent, -1);
                 reply.ok();
             }
             Err(err) => {
@@ -804,11 +1643,16 @@ impl Filesystem for RemoteFs {
         flags: u32,
         reply: ReplyEmpty,
     ) {
-        // This implementation only handles normal rename semantics.
-        if flags != 0 {
+        let Some(reply) = self.writable(reply) else {
+            return;
+        };
+
+        // Plain and no-replace renames are supported; exchange and whiteout are not.
+        if flags & !RENAME_NOREPLACE != 0 {
             reply.error(libc::EINVAL);
             return;
         }
+        let replace_if_exists = flags & RENAME_NOREPLACE == 0;
 
         // Resolve both paths, send the move to the server, then update cached paths locally.
         let from_path = match self.resolve_rename_source_path(parent, name, newname) {
@@ -818,12 +1662,9 @@ impl Filesystem for RemoteFs {
                 return;
             }
         };
-        let to_path = match self.child_path(newparent, newname) {
-            Ok(path) => path,
-            Err(err) => {
-                reply.error(err);
-                return;
-            }
+        let Some(to_path) = self.full_path(newparent, newname) else {
+            reply.error(ENOENT);
+            return;
         };
 
         debug!(
@@ -831,23 +1672,20 @@ impl Filesystem for RemoteFs {
             parent, name, from_path, newparent, newname, to_path, flags
         );
 
-        if self
-            .writeback
-            .get(&from_path)
-            .is_some_and(|pending| !pending.is_committed())
-            && let Err(error) = self.materialize_pending(&from_path)
-        {
-            reply.error(errno_from_io_error(&error));
+        if let Err(err) = self.push_writes_to_server(self.inode_for_path(&from_path), &from_path) {
+            reply.error(err);
             return;
         }
 
         let api_from = from_path.trim_start_matches('/');
         let api_to = to_path.trim_start_matches('/');
 
-        match self
-            .runtime
-            .block_on(api::rename_file(&self.server_addr, api_from, api_to, true))
-        {
+        match self.runtime.block_on(api::rename_file(
+            &self.server_addr,
+            api_from,
+            api_to,
+            replace_if_exists,
+        )) {
             Ok(_) => {
                 self.rename_cached_path(&from_path, &to_path);
                 reply.ok();
@@ -857,7 +1695,7 @@ impl Filesystem for RemoteFs {
                     "Failed to rename {} to {} on server: {:?}",
                     api_from, api_to, err
                 );
-                reply.error(errno_from_api_error(&err));
+                reply.error(errno_for(&err));
             }
         }
     }
@@ -866,106 +1704,145 @@ impl Filesystem for RemoteFs {
         &mut self,
         _req: &Request,
         ino: u64,
-        _fh: u64,
+        fh: u64,
         offset: i64,
         mut reply: ReplyDirectory,
     ) {
         debug!("readdir(ino={}, offset={})", ino, offset);
-
-        if offset < 0 {
-            reply.error(libc::EINVAL);
-            return;
+        let span = OperationSpan::enter(&self.metrics, "readdir", ino);
+
+        match self.fill_directory(
+            ino,
+            fh,
+            offset,
+            true,
+            |entry_ino, entry_offset, attr, name| {
+                reply.add(entry_ino, entry_offset, attr.kind, kernel_name(name))
+            },
+        ) {
+            Ok(()) => reply.ok(),
+            Err(err) => reply.error(span.fail(err)),
         }
+    }
 
-        let current_path = if _fh != 0 {
-            match self.handle_for(_fh, ino, HandleKind::Directory) {
-                Ok(handle) => handle.path,
-                Err(err) => {
-                    reply.error(err);
-                    return;
+    // Same listing as readdir, but each entry carries its attributes so
+    // `ls -l` needs no lookup per file. The kernel counts every entry except
+    // `.` and `..` as looked up.
+    fn readdirplus(
+        &mut self,
+        _req: &Request<'_>,
+        ino: u64,
+        fh: u64,
+        offset: i64,
+        mut reply: ReplyDirectoryPlus,
+    ) {
+        debug!("readdirplus(ino={}, offset={})", ino, offset);
+        let span = OperationSpan::enter(&self.metrics, "readdirplus", ino);
+
+        match self.fill_directory(
+            ino,
+            fh,
+            offset,
+            false,
+            |entry_ino, entry_offset, attr, name| {
+                let full = reply.add(
+                    entry_ino,
+                    entry_offset,
+                    kernel_name(name),
+                    &self.entry_ttl,
+                    attr,
+                    self.generation(entry_ino),
+                );
+                if !full && name != "." && name != ".." {
+                    self.remember_lookup(entry_ino);
                 }
+                full
+            },
+        ) {
+            Ok(()) => reply.ok(),
+            Err(err) => reply.error(span.fail(err)),
+        }
+    }
+
+    // Server files have no device blocks to map. The kernel only asks
+    // block-device mounts, and stops asking once told ENOSYS.
+    fn bmap(&mut self, _req: &Request<'_>, ino: u64, blocksize: u32, idx: u64, reply: ReplyBmap) {
+        debug!("bmap(ino={}, blocksize={}, idx={})", ino, blocksize, idx);
+        reply.error(libc::ENOSYS);
+    }
+
+    // `SEEK_DATA` and `SEEK_HOLE`, which `cp --sparse` and `tar` use to skip
+    // holes; the kernel answers the other whences itself. The server looks
+    // at its file's sparse map. Servers without `GET /seek`, and files whose
+    // bytes are not on the server yet, count as data from start to end.
+    fn lseek(
+        &mut self,
+        _req: &Request<'_>,
+        ino: u64,
+        fh: u64,
+        offset: i64,
+        whence: i32,
+        reply: ReplyLseek,
+    ) {
+        debug!(
+            "lseek(ino={}, fh={}, offset={}, whence={})",
+            ino, fh, offset, whence
+        );
+        let whence = match whence {
+            libc::SEEK_DATA => api::SeekWhence::Data,
+            libc::SEEK_HOLE => api::SeekWhence::Hole,
+            _ => {
+                reply.error(libc::EINVAL);
+                return;
             }
-        } else {
-            match self.path_for_inode(ino) {
-                Some(path) => path,
-                None => {
-                    error!(
-                        "readdir: Could not find path for ino {}. Replying ENOENT.",
-                        ino
-                    );
-                    reply.error(ENOENT);
-                    return;
-                }
+        };
+        let Ok(offset) = u64::try_from(offset) else {
+            reply.error(libc::ENXIO);
+            return;
+        };
+        let (path, staged) = match self.handle_for(fh, ino, HandleKind::File) {
+            Ok(handle) => (handle.path, handle.staged_write.is_some()),
+            Err(err) => {
+                reply.error(err);
+                return;
             }
         };
+        if let Err(err) = self.flush_inode_writes(ino) {
+            reply.error(err);
+            return;
+        }
+        let Some(size) = self.attr_for_inode(ino).map(|attr| attr.size) else {
+            reply.error(ENOENT);
+            return;
+        };
 
-        let mut entries_for_reply: Vec<(u64, FileType, String)> = Vec::new();
-
-        // Standard entries: . and ..
-        entries_for_reply.push((ino, FileType::Directory, ".".to_string()));
-        // Determine parent inode for ".."
-        let parent_ino_for_dotdot = if current_path == "/" {
-            FUSE_ROOT_ID // Parent of root is root
+        let unsent = staged
+            || self
+                .writeback
+                .get(&path)
+                .is_some_and(|pending| !pending.is_committed());
+        let found = if unsent {
+            seek_without_holes(size, offset, whence)
         } else {
-            // Find parent path, then its inode.
-            let p = Path::new(&current_path);
-            let parent_os_str = p.parent().unwrap_or_else(|| Path::new("/")).as_os_str();
-            let parent_path_str = parent_os_str.to_str().unwrap_or("/").to_string();
-            let path_map = self.path_to_inode.lock().unwrap();
-            *path_map.get(&parent_path_str).unwrap_or(&FUSE_ROOT_ID)
-        };
-        entries_for_reply.push((parent_ino_for_dotdot, FileType::Directory, "..".to_string()));
-
-        if offset < entries_for_reply.len() as i64 {
-            match self.list_directory_cached(&current_path) {
-                Ok(api_entries) => {
-                    debug!(
-                        "Successfully listed '{}' from server. Found {} entries.",
-                        current_path,
-                        api_entries.len()
-                    );
-
-                    for api_entry in api_entries {
-                        let file_name = api_entry.name.clone();
-                        let full_entry_path = if current_path == "/" {
-                            format!("/{}", file_name)
-                        } else {
-                            format!("{}/{}", current_path.trim_end_matches('/'), file_name)
-                        };
-
-                        let attr = self.attr_from_entry_for_path(&full_entry_path, &api_entry);
-                        self.cache_attr(full_entry_path.clone(), attr);
-                        debug!(
-                            "Added or refreshed entry: ino={}, path='{}', type={:?}",
-                            attr.ino, full_entry_path, attr.kind
-                        );
-                        entries_for_reply.push((attr.ino, attr.kind, file_name));
-                    }
+            match self
+                .runtime
+                .block_on(api::seek_file(&self.server_addr, &path, offset, whence))
+            {
+                Ok(found) => found,
+                Err(err) if err.is_unknown_endpoint() => {
+                    debug!("Server-side seek unavailable for {}: {:?}", path, err);
+                    seek_without_holes(size, offset, whence)
                 }
                 Err(err) => {
-                    error!(
-                        "Failed to list directory {} from server: {:?}",
-                        current_path, err
-                    );
-                    reply.error(err);
+                    error!("Failed to seek {} in {} on server: {:?}", whence, path, err);
+                    reply.error(errno_for(&err));
                     return;
                 }
             }
+        };
+        match found {
+            Some(found) => reply.offset(found as i64),
+            None => reply.error(libc::ENXIO),
         }
-
-        for (i, entry) in entries_for_reply.iter().enumerate().skip(offset as usize) {
-            let (entry_ino, entry_ftype, ref entry_name) = *entry;
-            let entry_offset = (i + 1) as i64;
-            debug!(
-                "Adding to reply: ino={}, offset={}, type={:?}, name='{}'",
-                entry_ino, entry_offset, entry_ftype, entry_name
-            );
-            if reply.add(entry_ino, entry_offset, entry_ftype, entry_name) {
-                debug!("Reply buffer full after adding ino {}.", entry_ino);
-                break;
-            }
-        }
-        reply.ok();
-        debug!("readdir for ino {} ({}) completed.", ino, current_path);
     }
 }
@@ -0,0 +1,83 @@
+use fuser::PollHandle;
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+
+// Open file handles that poll(2) has seen, keyed by handle. A change the
+// server reports for a handle's inode is kept until the handle's next poll
+// reports it, and the kernel handle of a poller that asked to be woken is
+// notified once per change. Generic over the kernel handle so tests can
+// stand in for `PollHandle`, which only the kernel session can create.
+#[derive(Clone)]
+pub(super) struct PollWaiters<H = PollHandle> {
+    waiters: Arc<Mutex<HashMap<u64, Waiter<H>>>>,
+}
+
+struct Waiter<H> {
+    ino: u64,
+    changed: bool,
+    wake: Option<H>,
+}
+
+impl<H> Default for PollWaiters<H> {
+    fn default() -> Self {
+        PollWaiters {
+            waiters: Arc::new(Mutex::new(HashMap::new())),
+        }
+    }
+}
+
+impl<H> PollWaiters<H> {
+    // Records a poll of `fh` and reports whether its inode changed since the
+    // last poll that said so. `wake` replaces any earlier kernel handle.
+    pub(super) fn poll(&self, fh: u64, ino: u64, wake: Option<H>) -> bool {
+        let mut waiters = self.waiters.lock().unwrap();
+        let waiter = waiters.entry(fh).or_insert(Waiter {
+            ino,
+            changed: false,
+            wake: None,
+        });
+        if wake.is_some() {
+            waiter.wake = wake;
+        }
+        std::mem::take(&mut waiter.changed)
+    }
+
+    // Marks every polled handle of `ino` changed and hands back the kernel
+    // handles to notify; each is used once, until the kernel polls again.
+    pub(super) fn changed(&self, ino: u64) -> Vec<H> {
+        let mut waiters = self.waiters.lock().unwrap();
+        waiters
+            .values_mut()
+            .filter(|waiter| waiter.ino == ino)
+            .filter_map(|waiter| {
+                waiter.changed = true;
+                waiter.wake.take()
+            })
+            .collect()
+    }
+
+    pub(super) fn release(&self, fh: u64) {
+        self.waiters.lock().unwrap().remove(&fh);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn changes_are_reported_once_and_wake_each_poller_once() {
+        let waiters = PollWaiters::<&str>::default();
+        assert!(!waiters.poll(1, 10, Some("first")));
+        assert!(!waiters.poll(2, 11, Some("other")));
+
+        assert_eq!(waiters.changed(10), ["first"]);
+        assert!(waiters.changed(10).is_empty());
+        assert!(waiters.poll(1, 10, None));
+        assert!(!waiters.poll(1, 10, None));
+        assert!(!waiters.poll(2, 11, None));
+
+        waiters.release(1);
+        assert!(waiters.changed(10).is_empty());
+    }
+}
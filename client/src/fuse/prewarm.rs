@@ -0,0 +1,122 @@
+use super::{DIRECTORY_CACHE_MAX_ENTRIES, RemoteFs, remote_path};
+use crate::api;
+use std::collections::VecDeque;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::time::Instant;
+use tokio::task::JoinSet;
+
+// Listings a prewarm keeps in flight. Each request still waits for a
+// `--max-concurrency` slot, so this only bounds the prewarm's share of them.
+const PREWARM_LISTINGS: usize = 8;
+
+impl RemoteFs {
+    // Starts `--prewarm` on its own thread so the mount is usable at once.
+    // Setting the returned flag stops it; listings already sent are dropped.
+    pub fn spawn_prewarm(&self) -> Arc<AtomicBool> {
+        let stop = Arc::new(AtomicBool::new(false));
+        let fs = self.clone();
+        let stopped = stop.clone();
+        if let Err(error) = std::thread::Builder::new()
+            .name("prewarm".to_string())
+            .spawn(move || fs.prewarm(&stopped))
+        {
+            log::warn!("Could not start the directory prewarm: {error}");
+        }
+        stop
+    }
+
+    // Lists the tree breadth-first from the root, caching each listing and
+    // registering every entry's inode as lookups would. Symbolic links to
+    // directories are not followed. It stops after as many directories as
+    // the directory cache holds, since later listings would only evict
+    // earlier ones while their entries' inodes stayed allocated.
+    fn prewarm(&self, stop: &AtomicBool) {
+        let started = Instant::now();
+        let (mut directories, mut entries) = (0usize, 0usize);
+        let mut queued = VecDeque::from(["/".to_string()]);
+        let mut listings = JoinSet::new();
+
+        self.runtime.block_on(async {
+            loop {
+                while listings.len() < PREWARM_LISTINGS
+                    && directories + listings.len() < DIRECTORY_CACHE_MAX_ENTRIES
+                    && !stop.load(Ordering::Relaxed)
+                    && let Some(directory) = queued.pop_front()
+                {
+                    let server_addr = self.server_addr.clone();
+                    listings.spawn(async move {
+                        let listed =
+                            api::list_directory(&server_addr, remote_path::api(&directory)).await;
+                        (directory, listed)
+                    });
+                }
+                let Some(joined) = listings.join_next().await else {
+                    break;
+                };
+                if stop.load(Ordering::Relaxed) {
+                    break;
+                }
+                let Ok((directory, listed)) = joined else {
+                    continue;
+                };
+                let listed = match listed {
+                    Ok(listed) => listed,
+                    Err(error) => {
+                        log::warn!("Prewarm could not list {directory}: {error}");
+                        continue;
+                    }
+                };
+
+                for entry in &listed {
+                    let path = remote_path::child(&directory, &entry.name);
+                    let attr = self.attr_from_entry_for_path(&path, entry);
+                    self.cache_attr(path.clone(), attr);
+                    if entry.type_ == api::EntryType::Directory {
+                        queued.push_back(path);
+                    }
+                }
+                directories += 1;
+                entries += listed.len();
+                let _ = self.cache_listing(directory, listed);
+            }
+        });
+
+        if stop.load(Ordering::Relaxed) {
+            log::info!("Prewarm stopped after {directories} directories");
+        } else if !queued.is_empty() {
+            log::info!(
+                "Prewarmed the first {directories} directories and {entries} entries in {:?}; \
+                 the directory cache holds no more",
+                started.elapsed()
+            );
+        } else {
+            log::info!(
+                "Prewarmed {directories} directories and {entries} entries in {:?}",
+                started.elapsed()
+            );
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::integration_tests::TestServer;
+
+    #[test]
+    fn prewarm_lists_no_more_directories_than_the_cache_holds() {
+        let server = TestServer::start("prewarm-cap");
+        for index in 0..DIRECTORY_CACHE_MAX_ENTRIES + 40 {
+            let directory = server.root.join(format!("d{index:03}"));
+            std::fs::create_dir(&directory).unwrap();
+            std::fs::write(directory.join("f"), b"f").unwrap();
+        }
+        let fs = server.remote_fs();
+
+        fs.prewarm(&AtomicBool::new(false));
+        let inodes = fs.inodes.lock().unwrap();
+        let listed_children = inodes.paths().filter(|path| path.ends_with("/f")).count();
+        assert_eq!(listed_children, DIRECTORY_CACHE_MAX_ENTRIES - 1);
+    }
+}
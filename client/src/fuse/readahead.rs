@@ -0,0 +1,248 @@
+use crate::api::{self, ApiError};
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use tokio::runtime::Runtime;
+use tokio::task::JoinHandle;
+
+// Bytes fetched ahead of a sequential reader; one kernel-sized transfer.
+const READAHEAD_SIZE: u32 = super::TRANSFER_IO_SIZE;
+// Contiguous reads a handle must make before prefetching starts, so random
+// access never pays for data it will not use.
+const SEQUENTIAL_READS_BEFORE_PREFETCH: u32 = 2;
+
+// Per-handle access history used to recognise sequential readers.
+#[derive(Debug, Clone, Default)]
+pub(super) struct ReadPattern {
+    next_offset: u64,
+    sequential_reads: u32,
+}
+
+impl ReadPattern {
+    // Records a completed read and reports whether the handle looks sequential.
+    pub(super) fn record(&mut self, offset: u64, len: u64) -> bool {
+        if offset == self.next_offset {
+            self.sequential_reads = self.sequential_reads.saturating_add(1);
+        } else {
+            self.sequential_reads = 0;
+        }
+        self.next_offset = offset + len;
+        self.sequential_reads >= SEQUENTIAL_READS_BEFORE_PREFETCH
+    }
+}
+
+enum PrefetchData {
+    Pending(JoinHandle<Result<Vec<u8>, ApiError>>),
+    // A read is waiting on the fetch outside the lock.
+    Awaited,
+    Ready(Vec<u8>),
+}
+
+struct Prefetch {
+    // Unique per started fetch; a waiting read keeps the result only if the
+    // buffer it took the fetch from is still this one.
+    generation: u64,
+    offset: u64,
+    requested: u32,
+    data: PrefetchData,
+}
+
+impl Prefetch {
+    fn covers(&self, offset: u64, size: u32) -> bool {
+        offset >= self.offset && offset + u64::from(size) <= self.offset + u64::from(self.requested)
+    }
+
+    fn slice(&self, bytes: &[u8], offset: u64, size: u32) -> Vec<u8> {
+        let start = ((offset - self.offset) as usize).min(bytes.len());
+        let end = (start + size as usize).min(bytes.len());
+        bytes[start..end].to_vec()
+    }
+}
+
+// One background prefetch per inode. A buffer is consumed by the reads that
+// fall inside it and replaced once the reader moves past it.
+#[derive(Clone, Default)]
+pub(super) struct ReadAhead {
+    buffers: Arc<Mutex<HashMap<u64, Prefetch>>>,
+    next_generation: Arc<AtomicU64>,
+}
+
+impl ReadAhead {
+    // Serves `size` bytes at `offset` from a prefetched buffer, waiting for an
+    // in-flight fetch that covers the range. `None` means fall back to the
+    // server, as does a fetch invalidated or replaced while it was awaited.
+    pub(super) fn take(
+        &self,
+        runtime: &Runtime,
+        ino: u64,
+        offset: u64,
+        size: u32,
+    ) -> Option<Vec<u8>> {
+        let (generation, task) = {
+            let mut buffers = self.buffers.lock().unwrap();
+            let prefetch = buffers.get_mut(&ino)?;
+            if !prefetch.covers(offset, size) {
+                return None;
+            }
+            match std::mem::replace(&mut prefetch.data, PrefetchData::Awaited) {
+                PrefetchData::Pending(task) => (prefetch.generation, task),
+                PrefetchData::Ready(bytes) => {
+                    let slice = prefetch.slice(&bytes, offset, size);
+                    prefetch.data = PrefetchData::Ready(bytes);
+                    return Some(slice);
+                }
+                PrefetchData::Awaited => return None,
+            }
+        };
+
+        let fetched = runtime.block_on(task);
+        let mut buffers = self.buffers.lock().unwrap();
+        let current = buffers
+            .get_mut(&ino)
+            .filter(|prefetch| prefetch.generation == generation);
+        match (current, fetched) {
+            (Some(prefetch), Ok(Ok(bytes))) => {
+                let slice = prefetch.slice(&bytes, offset, size);
+                prefetch.data = PrefetchData::Ready(bytes);
+                Some(slice)
+            }
+            (Some(_), Ok(Err(error))) => {
+                log::debug!("Discarding failed readahead for ino {ino}: {error}");
+                buffers.remove(&ino);
+                None
+            }
+            (Some(_), Err(_)) => {
+                buffers.remove(&ino);
+                None
+            }
+            (None, _) => {
+                log::debug!("Discarding readahead for ino {ino} invalidated while in flight");
+                None
+            }
+        }
+    }
+
+    // Starts fetching `READAHEAD_SIZE` bytes at `offset`, in requests of at
+    // most `chunk_size` bytes, unless that range is already buffered or in
+    // flight.
+    pub(super) fn start(
+        &self,
+        runtime: &Runtime,
+        ino: u64,
+        server_addr: &str,
+        api_path: &str,
+        offset: u64,
+        chunk_size: u32,
+    ) {
+        let mut buffers = self.buffers.lock().unwrap();
+        if buffers
+            .get(&ino)
+            .is_some_and(|prefetch| prefetch.covers(offset, 1))
+        {
+            return;
+        }
+
+        let server_addr = server_addr.to_string();
+        let api_path = api_path.to_string();
+        let task = runtime.spawn(async move {
+            api::read_file_chunked(&server_addr, &api_path, offset, READAHEAD_SIZE, chunk_size)
+                .await
+        });
+        if let Some(previous) = buffers.insert(
+            ino,
+            Prefetch {
+                generation: self.next_generation.fetch_add(1, Ordering::Relaxed),
+                offset,
+                requested: READAHEAD_SIZE,
+                data: PrefetchData::Pending(task),
+            },
+        ) {
+            abort(previous);
+        }
+    }
+
+    // Drops buffered data after the file changes or its last handle closes.
+    pub(super) fn invalidate(&self, ino: u64) {
+        if let Some(previous) = self.buffers.lock().unwrap().remove(&ino) {
+            abort(previous);
+        }
+    }
+}
+
+fn abort(prefetch: Prefetch) {
+    if let PrefetchData::Pending(task) = prefetch.data {
+        task.abort();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn prefetch_starts_only_after_consecutive_contiguous_reads() {
+        let mut pattern = ReadPattern::default();
+        assert!(!pattern.record(0, 4096));
+        assert!(pattern.record(4096, 4096));
+        assert!(pattern.record(8192, 4096));
+
+        assert!(!pattern.record(65536, 4096));
+        assert!(!pattern.record(69632, 4096));
+        assert!(pattern.record(73728, 4096));
+    }
+
+    #[test]
+    fn buffered_ranges_are_sliced_and_kept_for_later_reads() {
+        let runtime = Runtime::new().unwrap();
+        let readahead = ReadAhead::default();
+        readahead.buffers.lock().unwrap().insert(
+            7,
+            Prefetch {
+                generation: 0,
+                offset: 100,
+                requested: 10,
+                data: PrefetchData::Ready(b"0123456".to_vec()),
+            },
+        );
+
+        assert_eq!(readahead.take(&runtime, 7, 102, 3).unwrap(), b"234");
+        // Short data means the server hit EOF inside the requested window.
+        assert_eq!(readahead.take(&runtime, 7, 105, 5).unwrap(), b"56");
+        assert!(readahead.take(&runtime, 7, 105, 6).is_none());
+        assert!(readahead.take(&runtime, 7, 99, 1).is_none());
+
+        readahead.invalidate(7);
+        assert!(readahead.take(&runtime, 7, 102, 3).is_none());
+    }
+
+    #[test]
+    fn fetches_invalidated_while_awaited_are_discarded() {
+        let runtime = Runtime::new().unwrap();
+        let readahead = ReadAhead::default();
+        let (send, receive) = tokio::sync::oneshot::channel::<Vec<u8>>();
+        let task = runtime.spawn(async move { Ok(receive.await.unwrap()) });
+        readahead.buffers.lock().unwrap().insert(
+            7,
+            Prefetch {
+                generation: 0,
+                offset: 0,
+                requested: 10,
+                data: PrefetchData::Pending(task),
+            },
+        );
+
+        std::thread::scope(|scope| {
+            let reader = scope.spawn(|| readahead.take(&runtime, 7, 0, 3));
+            while !matches!(
+                readahead.buffers.lock().unwrap().get(&7).unwrap().data,
+                PrefetchData::Awaited
+            ) {
+                std::thread::yield_now();
+            }
+            readahead.invalidate(7);
+            send.send(b"stale".to_vec()).unwrap();
+            assert!(reader.join().unwrap().is_none());
+        });
+        assert!(readahead.buffers.lock().unwrap().get(&7).is_none());
+    }
+}
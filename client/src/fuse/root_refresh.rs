@@ -0,0 +1,146 @@
+use super::{RemoteFs, remote_path};
+use crate::api::{self, ApiError};
+use std::collections::HashSet;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, Ordering};
+
+impl RemoteFs {
+    // Starts `--root-refresh-secs` on its own thread when it is set. Setting
+    // the returned flag stops it at the next tick.
+    pub fn spawn_root_refresh(&self) -> Option<Arc<AtomicBool>> {
+        let interval = self.root_refresh?;
+        let stop = Arc::new(AtomicBool::new(false));
+        let fs = self.clone();
+        let stopped = stop.clone();
+        if let Err(error) = std::thread::Builder::new()
+            .name("root-refresh".to_string())
+            .spawn(move || {
+                loop {
+                    std::thread::sleep(interval);
+                    if stopped.load(Ordering::Relaxed) {
+                        break;
+                    }
+                    if let Err(error) = fs.refresh_root() {
+                        log::warn!("Could not refresh the root listing: {error}");
+                    }
+                }
+            })
+        {
+            log::warn!("Could not start the root refresh: {error}");
+        }
+        Some(stop)
+    }
+
+    // Lists the root again and brings the inode map in line with it: new
+    // entries are registered, changed ones updated, and ones the server no
+    // longer has are forgotten with everything cached below them. Files that
+    // are journaled or open here are kept; the server may not have them yet.
+    fn refresh_root(&self) -> Result<(), ApiError> {
+        let generation = self.root_generation.load(Ordering::Acquire);
+        let listed = self
+            .runtime
+            .block_on(api::list_directory(&self.server_addr, "/"))?;
+        self.apply_root_listing(generation, listed);
+        Ok(())
+    }
+
+    // A create or rename into `/` here after `generation` was read may be
+    // missing from `listed`, so a changed generation drops the listing and
+    // leaves the root to the next tick rather than forget the new name.
+    fn apply_root_listing(&self, generation: u64, listed: Vec<api::DirectoryEntry>) {
+        let unchanged = || self.root_generation.load(Ordering::Acquire) == generation;
+        if !unchanged() {
+            log::debug!("Root refresh: the root changed mid-listing; skipping this tick");
+            return;
+        }
+
+        let names: HashSet<&str> = listed.iter().map(|entry| entry.name.as_str()).collect();
+        let removed: Vec<String> = self
+            .inodes
+            .lock()
+            .unwrap()
+            .paths()
+            .filter(|path| {
+                path.strip_prefix('/').is_some_and(|name| {
+                    !name.is_empty() && !name.contains('/') && !names.contains(name)
+                })
+            })
+            .map(str::to_string)
+            .collect();
+        for path in removed {
+            let journaled = self
+                .writeback
+                .get(&path)
+                .is_some_and(|pending| !pending.is_committed());
+            let open = self
+                .open_handles
+                .lock()
+                .unwrap()
+                .values()
+                .any(|handle| handle.path == path);
+            if !journaled && !open && unchanged() {
+                log::debug!("Root refresh: {path} is gone from the server");
+                self.remove_cached_path(&path);
+            }
+        }
+
+        for entry in &listed {
+            let path = remote_path::child("/", &entry.name);
+            let attr = self.attr_from_entry_for_path(&path, entry);
+            self.cache_attr(path, attr);
+        }
+        self.negative_lookups
+            .lock()
+            .unwrap()
+            .remove_matching(|missing| remote_path::parent(missing) == "/");
+        // Only fails merging journaled files, which keep the old listing.
+        if unchanged() {
+            let _ = self.cache_listing("/".to_string(), listed);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::api;
+    use crate::integration_tests::TestServer;
+    use std::sync::atomic::Ordering;
+
+    #[test]
+    fn refreshes_forget_removed_names_but_not_ones_created_mid_listing() {
+        let server = TestServer::start("root-refresh");
+        std::fs::write(server.root.join("kept.txt"), b"k").unwrap();
+        std::fs::write(server.root.join("gone.txt"), b"g").unwrap();
+        let fs = server.remote_fs();
+        let known = |path: &str| fs.inodes.lock().unwrap().paths().any(|known| known == path);
+
+        fs.refresh_root().unwrap();
+        assert!(known("/kept.txt") && known("/gone.txt"));
+
+        std::fs::remove_file(server.root.join("gone.txt")).unwrap();
+        fs.refresh_root().unwrap();
+        assert!(known("/kept.txt"));
+        assert!(!known("/gone.txt"));
+
+        // A file created here after the listing was taken is not in it.
+        let generation = fs.root_generation.load(Ordering::Acquire);
+        let listed = server
+            .block_on(api::list_directory(&server.url, "/"))
+            .unwrap();
+        std::fs::write(server.root.join("new.txt"), b"n").unwrap();
+        let entry = server
+            .block_on(api::list_directory(&server.url, "/"))
+            .unwrap()
+            .into_iter()
+            .find(|entry| entry.name == "new.txt")
+            .unwrap();
+        let attr = fs.attr_from_entry_for_path("/new.txt", &entry);
+        fs.cache_attr("/new.txt".to_string(), attr);
+        fs.invalidate_directory_cache_for_path("/new.txt");
+
+        fs.apply_root_listing(generation, listed);
+        assert!(known("/new.txt"));
+        fs.refresh_root().unwrap();
+        assert!(known("/new.txt"));
+    }
+}
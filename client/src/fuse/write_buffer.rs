@@ -0,0 +1,106 @@
+// Bytes a handle may hold before its buffered writes are sent; one
+// kernel-sized transfer, matching the readahead window.
+pub(super) const WRITE_BUFFER_LIMIT: usize = super::TRANSFER_IO_SIZE as usize;
+
+// Dirty bytes written through one handle that have not reached the server.
+// The buffer always covers a single contiguous range starting at `offset`.
+#[derive(Debug)]
+pub(super) struct WriteBuffer {
+    pub(super) ino: u64,
+    pub(super) offset: u64,
+    pub(super) data: Vec<u8>,
+    // The buffer stops growing here: `WRITE_BUFFER_LIMIT` past the `blksize`
+    // boundary at or before `offset`, so a sequential writer's uploads after
+    // the first one start and end on block boundaries.
+    limit_end: u64,
+}
+
+impl WriteBuffer {
+    pub(super) fn new(ino: u64, offset: u64, data: &[u8], blksize: u32) -> Self {
+        WriteBuffer {
+            ino,
+            offset,
+            data: data.to_vec(),
+            limit_end: offset - offset % u64::from(blksize) + WRITE_BUFFER_LIMIT as u64,
+        }
+    }
+
+    pub(super) fn end(&self) -> u64 {
+        self.offset + self.data.len() as u64
+    }
+
+    // A write can be merged when it starts inside or right after the buffered
+    // range and the merged range still ends within the limit.
+    pub(super) fn accepts(&self, offset: u64, len: usize) -> bool {
+        offset >= self.offset && offset <= self.end() && offset + len as u64 <= self.limit_end
+    }
+
+    // Callers must check `accepts` first.
+    pub(super) fn write(&mut self, offset: u64, data: &[u8]) {
+        let start = (offset - self.offset) as usize;
+        let end = start + data.len();
+        if end > self.data.len() {
+            self.data.resize(end, 0);
+        }
+        self.data[start..end].copy_from_slice(data);
+    }
+
+    // Drops the first `len` bytes once the server has stored them, leaving
+    // the rest of the range buffered.
+    pub(super) fn consume(&mut self, len: usize) {
+        let len = len.min(self.data.len());
+        self.data.drain(..len);
+        self.offset += len as u64;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn contiguous_and_overlapping_writes_merge() {
+        let mut buffer = WriteBuffer::new(1, 100, b"hello", 512);
+        assert!(buffer.accepts(105, 6));
+        buffer.write(105, b" world");
+        assert!(buffer.accepts(100, 1));
+        buffer.write(100, b"J");
+
+        assert_eq!(buffer.data, b"Jello world");
+        assert_eq!(buffer.end(), 111);
+    }
+
+    #[test]
+    fn gaps_and_oversized_ranges_are_rejected() {
+        let buffer = WriteBuffer::new(1, 100, b"hello", 512);
+        assert!(!buffer.accepts(106, 1));
+        assert!(!buffer.accepts(99, 1));
+        assert!(!buffer.accepts(105, WRITE_BUFFER_LIMIT));
+        assert!(buffer.accepts(100, WRITE_BUFFER_LIMIT - 100));
+    }
+
+    #[test]
+    fn consumed_prefixes_advance_the_range() {
+        let mut buffer = WriteBuffer::new(1, 100, b"hello world", 512);
+        buffer.consume(6);
+        assert_eq!(
+            (buffer.offset, buffer.data.as_slice()),
+            (106, &b"world"[..])
+        );
+        assert_eq!(buffer.end(), 111);
+        assert!(buffer.accepts(111, 1));
+
+        buffer.consume(100);
+        assert_eq!((buffer.offset, buffer.data.len()), (111, 0));
+    }
+
+    #[test]
+    fn unaligned_buffers_stop_at_a_block_boundary() {
+        let blksize = 128 * 1024;
+        let buffer = WriteBuffer::new(1, 200_000, b"x", blksize);
+        let limit_end = u64::from(blksize) + WRITE_BUFFER_LIMIT as u64;
+
+        assert!(buffer.accepts(200_000, (limit_end - 200_000) as usize));
+        assert!(!buffer.accepts(200_000, (limit_end - 200_000) as usize + 1));
+    }
+}
@@ -0,0 +1,305 @@
+//! End-to-end tests: a real server on an ephemeral port, with a temporary
+//! storage root, driven through the client's API functions and `RemoteFs`.
+
+use crate::{api, fuse, ownership::MountOwner};
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::time::Duration;
+
+static ROOT_COUNTER: AtomicUsize = AtomicUsize::new(0);
+
+pub(crate) struct TestServer {
+    runtime: tokio::runtime::Runtime,
+    pub(crate) url: String,
+    pub(crate) root: PathBuf,
+    // `RemoteFs` journals pending uploads under here rather than in the
+    // user's state directory.
+    journal_dir: PathBuf,
+}
+
+impl TestServer {
+    pub(crate) fn start(name: &str) -> Self {
+        let root = std::env::temp_dir().join(format!(
+            "remote-fs-client-test-{name}-{}-{}",
+            std::process::id(),
+            ROOT_COUNTER.fetch_add(1, Ordering::SeqCst)
+        ));
+        let _ = std::fs::remove_dir_all(&root);
+        std::fs::create_dir_all(&root).unwrap();
+        let journal_dir = root.with_extension("journal");
+        let _ = std::fs::remove_dir_all(&journal_dir);
+
+        let runtime = tokio::runtime::Runtime::new().unwrap();
+        let listener = runtime
+            .block_on(tokio::net::TcpListener::bind("127.0.0.1:0"))
+            .unwrap();
+        let listen_addr = listener.local_addr().unwrap();
+        let config = server::ServerConfig {
+            storage_root: root.clone(),
+            listen_addr,
+            auth_token: None,
+            shutdown_timeout: Duration::from_secs(1),
+            max_file_size: None,
+        };
+        runtime.spawn(async move {
+            if let Err(error) = server::serve(listener, config).await {
+                panic!("test server failed: {error}");
+            }
+        });
+
+        TestServer {
+            runtime,
+            url: format!("http://{listen_addr}"),
+            root,
+            journal_dir,
+        }
+    }
+
+    pub(crate) fn block_on<F: Future>(&self, future: F) -> F::Output {
+        self.runtime.block_on(future)
+    }
+
+    pub(crate) fn remote_fs(&self) -> fuse::RemoteFs {
+        fuse::RemoteFs::new(
+            &self.url,
+            fuse::MountSettings {
+                owner: MountOwner::new(None, None),
+                attr_ttl: fuse::DEFAULT_ATTR_TTL,
+                entry_ttl: fuse::DEFAULT_ATTR_TTL,
+                negative_ttl: fuse::DEFAULT_NEGATIVE_TTL,
+                block_cache_bytes: 0,
+                inode_db: None,
+                journal_dir: Some(&self.journal_dir),
+                recycle_inodes: true,
+                list_depth: 1,
+                root_refresh: None,
+                runtime_threads: None,
+                blksize: fuse::DEFAULT_BLKSIZE,
+                read_only: false,
+                max_write: fuse::DEFAULT_MAX_WRITE,
+                read_chunk_size: fuse::DEFAULT_READ_CHUNK_SIZE,
+                max_file_size: None,
+                writeback_cache: false,
+                file_mode: fuse::DEFAULT_FILE_MODE,
+                dir_mode: fuse::DEFAULT_DIR_MODE,
+            },
+        )
+        .unwrap()
+    }
+}
+
+impl Drop for TestServer {
+    fn drop(&mut self) {
+        let _ = std::fs::remove_dir_all(&self.root);
+        let _ = std::fs::remove_dir_all(&self.journal_dir);
+    }
+}
+
+#[test]
+fn listings_parse_into_directory_entries() {
+    let server = TestServer::start("listing");
+    std::fs::create_dir(server.root.join("docs")).unwrap();
+    std::fs::write(server.root.join("docs/a.txt"), b"hello").unwrap();
+    std::fs::write(server.root.join("b.bin"), [0u8; 300]).unwrap();
+
+    let mut entries = server
+        .block_on(api::list_directory(&server.url, "/"))
+        .unwrap();
+    entries.sort_by(|a, b| a.name.cmp(&b.name));
+    let summary: Vec<_> = entries
+        .iter()
+        .map(|entry| (entry.name.as_str(), entry.type_))
+        .collect();
+    assert_eq!(
+        summary,
+        [
+            ("b.bin", api::EntryType::File),
+            ("docs", api::EntryType::Directory)
+        ]
+    );
+    assert_eq!(entries[0].size, 300);
+
+    let nested = server
+        .block_on(api::list_directory(&server.url, "/docs"))
+        .unwrap();
+    assert_eq!(nested.len(), 1);
+    assert_eq!(
+        (nested[0].name.as_str(), nested[0].type_, nested[0].size),
+        ("a.txt", api::EntryType::File, 5)
+    );
+    assert!(matches!(
+        server.block_on(api::list_directory(&server.url, "/missing")),
+        Err(api::ApiError::NotFound)
+    ));
+}
+
+#[test]
+fn names_pages_list_every_entry_once() {
+    let server = TestServer::start("names");
+    std::fs::create_dir(server.root.join("docs")).unwrap();
+    for name in ["a.txt", "b.txt", "c.txt"] {
+        std::fs::write(server.root.join(name), b"x").unwrap();
+    }
+
+    let mut listed = Vec::new();
+    let mut cursor = None;
+    loop {
+        let page = server
+            .block_on(api::list_names_page(&server.url, "/", cursor.as_deref(), 3))
+            .unwrap();
+        listed.extend(
+            page.entries
+                .into_iter()
+                .map(|entry| (entry.name, entry.type_)),
+        );
+        cursor = page.next_cursor;
+        if cursor.is_none() {
+            break;
+        }
+    }
+    assert_eq!(
+        listed,
+        [
+            ("a.txt".to_string(), api::EntryType::File),
+            ("b.txt".to_string(), api::EntryType::File),
+            ("c.txt".to_string(), api::EntryType::File),
+            ("docs".to_string(), api::EntryType::Directory),
+        ]
+    );
+}
+
+#[test]
+fn written_ranges_read_back() {
+    let server = TestServer::start("read-write");
+    server
+        .block_on(api::create_directory(&server.url, "/docs", 0o755))
+        .unwrap();
+    let outcome = server
+        .block_on(api::write_file(
+            &server.url,
+            "/docs/a.txt",
+            b"hello world",
+            0,
+        ))
+        .unwrap();
+    assert_eq!((outcome.written, outcome.metadata.size), (11, 11));
+    server
+        .block_on(api::write_file(&server.url, "/docs/a.txt", b"W", 6))
+        .unwrap();
+
+    let bytes = server
+        .block_on(api::read_file(&server.url, "/docs/a.txt", 4, 100))
+        .unwrap();
+    assert_eq!(bytes, b"o World");
+    assert_eq!(
+        std::fs::read(server.root.join("docs/a.txt")).unwrap(),
+        b"hello World"
+    );
+}
+
+#[test]
+fn chunked_reads_assemble_the_range_and_stop_at_the_end_of_the_file() {
+    let server = TestServer::start("chunked-read");
+    std::fs::write(server.root.join("a.txt"), b"0123456789").unwrap();
+
+    let bytes = server
+        .block_on(api::read_file_chunked(&server.url, "/a.txt", 1, 6, 4))
+        .unwrap();
+    assert_eq!(bytes, b"123456");
+    let bytes = server
+        .block_on(api::read_file_chunked(&server.url, "/a.txt", 2, 100, 4))
+        .unwrap();
+    assert_eq!(bytes, b"23456789");
+}
+
+#[test]
+fn missing_endpoints_are_told_apart_from_missing_paths() {
+    let server = TestServer::start("unknown-endpoint");
+    std::fs::write(server.root.join("a.txt"), b"a").unwrap();
+
+    let error = server
+        .block_on(api::copy_range(
+            &server.url,
+            "/missing.txt",
+            0,
+            "/a.txt",
+            0,
+            1,
+        ))
+        .unwrap_err();
+    assert!(matches!(error, api::ApiError::NotFound));
+    assert!(!error.is_unknown_endpoint());
+
+    let older_server = format!("{}/no-such-prefix", server.url);
+    let error = server
+        .block_on(api::copy_range(&older_server, "/a.txt", 0, "/b.txt", 0, 1))
+        .unwrap_err();
+    assert!(error.is_unknown_endpoint(), "{error}");
+    assert_eq!(error.status(), Some(reqwest::StatusCode::NOT_FOUND));
+}
+
+#[test]
+fn seeking_in_a_deleted_file_is_not_mistaken_for_an_older_server() {
+    let server = TestServer::start("seek-deleted");
+    std::fs::write(server.root.join("a.txt"), b"data").unwrap();
+    assert_eq!(
+        server
+            .block_on(api::seek_file(
+                &server.url,
+                "/a.txt",
+                0,
+                api::SeekWhence::Data
+            ))
+            .unwrap(),
+        Some(0)
+    );
+
+    std::fs::remove_file(server.root.join("a.txt")).unwrap();
+    let error = server
+        .block_on(api::seek_file(
+            &server.url,
+            "/a.txt",
+            0,
+            api::SeekWhence::Data,
+        ))
+        .unwrap_err();
+    assert!(!error.is_unknown_endpoint(), "{error}");
+    assert!(matches!(error, api::ApiError::NotFound));
+}
+
+#[test]
+fn reserved_url_characters_in_names_reach_the_right_file() {
+    let server = TestServer::start("encoded-names");
+    server
+        .block_on(api::create_directory(&server.url, "/dir #1", 0o755))
+        .unwrap();
+    server
+        .block_on(api::write_file(
+            &server.url,
+            "/dir #1/what?100%.txt",
+            b"escaped",
+            0,
+        ))
+        .unwrap();
+    assert_eq!(
+        std::fs::read(server.root.join("dir #1/what?100%.txt")).unwrap(),
+        b"escaped"
+    );
+
+    let entries = server
+        .block_on(api::list_directory(&server.url, "/dir #1"))
+        .unwrap();
+    assert_eq!(entries.len(), 1);
+    assert_eq!(entries[0].name, "what?100%.txt");
+    let bytes = server
+        .block_on(api::read_file(&server.url, "/dir #1/what?100%.txt", 0, 100))
+        .unwrap();
+    assert_eq!(bytes, b"escaped");
+}
+
+#[test]
+fn remote_fs_passes_the_pre_mount_check() {
+    let server = TestServer::start("pre-mount");
+    std::fs::write(server.root.join("a.txt"), b"x").unwrap();
+    server.remote_fs().check_server().unwrap();
+}
@@ -0,0 +1,85 @@
+//! Log output selected by `--log-format`.
+
+use clap::ValueEnum;
+use tracing_subscriber::{EnvFilter, fmt::format::FmtSpan};
+
+/// Log target of the per-request lines written with `--trace-requests`.
+pub(crate) const REQUEST_TRACE_TARGET: &str = "remote_fs::requests";
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, ValueEnum)]
+pub(crate) enum LogFormat {
+    /// Human-readable lines from `env_logger`.
+    #[default]
+    Text,
+    /// One JSON object per line, plus a closing event per FUSE operation span
+    /// that carries its inode, path, errno, and timing.
+    Json,
+}
+
+impl LogFormat {
+    pub(crate) fn as_str(self) -> &'static str {
+        match self {
+            LogFormat::Text => "text",
+            LogFormat::Json => "json",
+        }
+    }
+}
+
+// Both formats honour `RUST_LOG`. Under JSON the existing `log` macros are
+// forwarded into tracing, so every message lands in the same stream.
+// `trace_requests` enables the request trace target at info level whatever
+// `RUST_LOG` says about the rest of the client.
+pub(crate) fn init(format: LogFormat, trace_requests: bool) {
+    match format {
+        LogFormat::Text => {
+            text_logger(trace_requests, std::env::var_os("RUST_LOG").is_some()).init()
+        }
+        LogFormat::Json => {
+            let mut filter = EnvFilter::from_default_env();
+            if trace_requests {
+                filter = filter.add_directive(
+                    format!("{REQUEST_TRACE_TARGET}=info")
+                        .parse()
+                        .expect("the trace target is a valid directive"),
+                );
+            }
+            tracing_subscriber::fmt()
+                .json()
+                .with_env_filter(filter)
+                .with_span_events(FmtSpan::CLOSE)
+                .with_current_span(true)
+                .with_writer(std::io::stderr)
+                .init()
+        }
+    }
+}
+
+// `env_logger` only falls back to logging errors when it has no directives at
+// all, so adding the trace target alone would silence everything else.
+fn text_logger(trace_requests: bool, rust_log_set: bool) -> env_logger::Builder {
+    let mut builder = env_logger::Builder::from_default_env();
+    if !rust_log_set {
+        builder.filter_level(log::LevelFilter::Error);
+    }
+    if trace_requests {
+        builder.filter_module(REQUEST_TRACE_TARGET, log::LevelFilter::Info);
+    }
+    builder
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use log::{Level, Log, Metadata};
+
+    #[test]
+    fn tracing_requests_keeps_the_default_error_level() {
+        let logger = text_logger(true, false).build();
+        let enabled = |target, level| {
+            logger.enabled(&Metadata::builder().target(target).level(level).build())
+        };
+        assert!(enabled(REQUEST_TRACE_TARGET, Level::Info));
+        assert!(enabled("client::fuse", Level::Error));
+        assert!(!enabled("client::fuse", Level::Warn));
+    }
+}
@@ -1,11 +1,18 @@
 mod api;
 #[cfg(not(windows))]
 mod cache;
+mod cli;
 #[cfg(not(windows))]
 mod fuse;
+#[cfg(all(test, not(windows)))]
+mod integration_tests;
+mod logging;
+mod metrics;
 #[cfg(not(windows))]
 mod ownership;
 mod remote_path;
+#[cfg(not(windows))]
+mod supervisor;
 #[cfg(windows)]
 mod windows;
 mod writeback;
@@ -21,52 +28,44 @@ use std::{
 #[cfg(unix)]
 use std::os::unix::process::CommandExt;
 
-struct ClientArgs {
-    mountpoint: String,
-    server_url: String,
-    spawn_daemon_mode: bool,
-    #[cfg(not(windows))]
-    serve_daemon_mode: bool,
-}
-
-fn parse_args() -> ClientArgs {
-    let args: Vec<String> = std::env::args().collect();
-    let program_name = args.first().map_or("remote-fs-client", |s| s.as_str());
-    let spawn_daemon_mode = args.get(1).is_some_and(|arg| arg == "--daemon");
-    let serve_daemon_mode = args.get(1).is_some_and(|arg| arg == "--serve-daemon");
-    let mountpoint_index = if spawn_daemon_mode || serve_daemon_mode {
-        2
-    } else {
-        1
-    };
+use clap::Parser;
+use cli::ClientArgs;
 
-    if args.len() <= mountpoint_index {
-        eprintln!(
-            "Usage: {} [--daemon] <MOUNTPOINT> [SERVER_URL]",
-            program_name
-        );
+fn serve_metrics(args: &ClientArgs) {
+    if let Some(addr) = args.metrics_addr
+        && let Err(error) = metrics::serve(addr, metrics::global().clone())
+    {
+        log::error!("Failed to serve metrics on {addr}: {error}");
         std::process::exit(1);
     }
+}
 
-    ClientArgs {
-        mountpoint: args[mountpoint_index].clone(),
-        server_url: args
-            .get(mountpoint_index + 1)
-            .cloned()
-            .unwrap_or_else(|| "http://127.0.0.1:3000".to_string()),
-        spawn_daemon_mode,
-        #[cfg(not(windows))]
-        serve_daemon_mode,
+// `fusermount` refuses `allow_other` and `allow_root` from users other than
+// root unless `/etc/fuse.conf` enables them, with an error that does not say
+// which flag caused it.
+#[cfg(target_os = "linux")]
+fn warn_unless_fuse_conf_allows_other() {
+    if unsafe { libc::geteuid() } == 0 {
+        return;
+    }
+    let allowed = std::fs::read_to_string("/etc/fuse.conf")
+        .is_ok_and(|contents| ownership::fuse_conf_allows_other(&contents));
+    if !allowed {
+        log::warn!(
+            "--allow-other and --allow-root need `user_allow_other` in /etc/fuse.conf \
+             when mounting as a user other than root; the mount may be refused"
+        );
     }
 }
 
 #[cfg(unix)]
-fn spawn_daemon(mountpoint: &str, server_url: &str) -> io::Result<()> {
+fn spawn_daemon(args: &ClientArgs) -> io::Result<()> {
     let mut command = Command::new(std::env::current_exe()?);
+    command.args(args.daemon_args());
+    if let Some(token) = &args.auth_token {
+        command.env("REMOTE_FS_TOKEN", token);
+    }
     command
-        .arg("--serve-daemon")
-        .arg(mountpoint)
-        .arg(server_url)
         .stdin(Stdio::null())
         .stdout(Stdio::null())
         .stderr(Stdio::null());
@@ -86,50 +85,26 @@ fn spawn_daemon(mountpoint: &str, server_url: &str) -> io::Result<()> {
 }
 
 #[cfg(all(not(unix), not(windows)))]
-fn spawn_daemon(_mountpoint: &str, _server_url: &str) -> io::Result<()> {
+fn spawn_daemon(_args: &ClientArgs) -> io::Result<()> {
     Err(io::Error::new(
         io::ErrorKind::Unsupported,
         "daemon mode is only supported on Unix platforms",
     ))
 }
 
-#[cfg(not(windows))]
-async fn wait_for_shutdown_signal() {
-    let ctrl_c = async {
-        tokio::signal::ctrl_c()
-            .await
-            .expect("Failed to install Ctrl-C handler");
-    };
-
-    #[cfg(unix)]
-    let terminate = async {
-        tokio::signal::unix::signal(tokio::signal::unix::SignalKind::terminate())
-            .expect("Failed to install SIGTERM handler")
-            .recv()
-            .await;
-    };
-
-    #[cfg(not(unix))]
-    let terminate = std::future::pending::<()>();
-
-    tokio::select! {
-        _ = ctrl_c => log::info!("Shutdown requested with Ctrl-C."),
-        _ = terminate => log::info!("Shutdown requested with SIGTERM."),
-    }
-}
-
 #[cfg(windows)]
 fn main() {
     use std::process::Command;
 
-    let args = parse_args();
-    if args.spawn_daemon_mode {
-        let result =
-            Command::new(std::env::current_exe().expect("failed to locate current executable"))
-                .arg("--serve-daemon")
-                .arg(&args.mountpoint)
-                .arg(&args.server_url)
-                .spawn();
+    let args = ClientArgs::parse();
+    if args.daemon {
+        let mut command =
+            Command::new(std::env::current_exe().expect("failed to locate current executable"));
+        command.args(args.daemon_args());
+        if let Some(token) = &args.auth_token {
+            command.env("REMOTE_FS_TOKEN", token);
+        }
+        let result = command.spawn();
         if let Err(error) = result {
             eprintln!("Failed to start daemon: {error}");
             std::process::exit(1);
@@ -137,9 +112,16 @@ fn main() {
         return;
     }
 
-    env_logger::init();
+    logging::init(args.log_format, args.trace_requests);
 
-    if let Err(error) = windows::run(&args.mountpoint, &args.server_url) {
+    if let Err(error) = api::configure_http_client(&args.http_options()) {
+        log::error!("Failed to configure HTTP client: {error}");
+        std::process::exit(1);
+    }
+
+    serve_metrics(&args);
+
+    if let Err(error) = windows::run(&args.mountpoint, args.server_url()) {
         log::error!("Failed to mount Windows filesystem: {error}");
         std::process::exit(1);
     }
@@ -147,31 +129,87 @@ fn main() {
 
 #[cfg(not(windows))]
 fn main() {
-    let args = parse_args();
-    if args.spawn_daemon_mode {
-        if let Err(error) = spawn_daemon(&args.mountpoint, &args.server_url) {
+    let args = ClientArgs::parse();
+    if args.daemon {
+        if let Err(error) = spawn_daemon(&args) {
             eprintln!("Failed to start daemon: {}", error);
             std::process::exit(1);
         }
         return;
     }
 
-    env_logger::init();
+    logging::init(args.log_format, args.trace_requests);
+
+    if let Err(error) = api::configure_http_client(&args.http_options()) {
+        log::error!("Failed to configure HTTP client: {error}");
+        std::process::exit(1);
+    }
 
     log::info!(
         "Mounting to {}, server URL: {}",
         args.mountpoint,
-        args.server_url
+        args.server_url()
     );
 
-    let options = vec![
+    let mut options = vec![
         MountOption::FSName("remoteFS".to_string()),
         #[cfg(not(target_os = "linux"))]
         MountOption::AutoUnmount,
     ];
+    if args.read_only {
+        options.push(MountOption::RO);
+    }
+    if args.noatime {
+        options.push(MountOption::NoAtime);
+    }
+    if args.allow_other || args.allow_root {
+        options.push(if args.allow_other {
+            MountOption::AllowOther
+        } else {
+            MountOption::AllowRoot
+        });
+        // Only `access` checks permissions itself; the kernel has to stop
+        // other users at the reported modes.
+        options.push(MountOption::DefaultPermissions);
+        #[cfg(target_os = "linux")]
+        warn_unless_fuse_conf_allows_other();
+    }
+
+    let attr_ttl = args
+        .attr_ttl
+        .map_or(fuse::DEFAULT_ATTR_TTL, std::time::Duration::from_secs);
 
     // Create the FUSE filesystem instance
-    let fs = match fuse::RemoteFs::new(&args.server_url) {
+    let fs = match fuse::RemoteFs::new(
+        args.server_url(),
+        fuse::MountSettings {
+            owner: ownership::MountOwner::new(args.uid, args.gid),
+            attr_ttl,
+            entry_ttl: args
+                .entry_ttl
+                .map_or(attr_ttl, std::time::Duration::from_secs),
+            negative_ttl: args
+                .negative_ttl
+                .map_or(fuse::DEFAULT_NEGATIVE_TTL, std::time::Duration::from_secs),
+            block_cache_bytes: args.cache_size_mb.saturating_mul(1024 * 1024),
+            inode_db: args.inode_db.as_deref(),
+            journal_dir: None,
+            recycle_inodes: args.inode_allocation == cli::InodeAllocation::Recycle,
+            list_depth: args.list_depth,
+            root_refresh: args.root_refresh_secs.map(std::time::Duration::from_secs),
+            runtime_threads: args.runtime_threads,
+            blksize: args.blksize.unwrap_or(fuse::DEFAULT_BLKSIZE),
+            read_only: args.read_only,
+            max_write: args.max_write.unwrap_or(fuse::DEFAULT_MAX_WRITE),
+            read_chunk_size: args
+                .read_chunk_size
+                .unwrap_or(fuse::DEFAULT_READ_CHUNK_SIZE),
+            max_file_size: args.max_file_size,
+            writeback_cache: args.writeback_cache,
+            file_mode: args.file_mode.unwrap_or(fuse::DEFAULT_FILE_MODE),
+            dir_mode: args.dir_mode.unwrap_or(fuse::DEFAULT_DIR_MODE),
+        },
+    ) {
         Ok(fs) => fs,
         Err(error) => {
             log::error!("Failed to initialize filesystem: {error}");
@@ -179,29 +217,26 @@ fn main() {
         }
     };
 
-    if args.serve_daemon_mode {
-        if let Err(e) = fuser::mount2(fs, &args.mountpoint, &options) {
-            log::error!("Failed to mount filesystem: {}", e);
-            std::process::exit(1);
-        }
-        return;
+    // `init` cannot abort a mount cleanly: the kernel refuses the session but
+    // the daemon keeps waiting on it. Checking first exits before mounting.
+    if args.no_health_check {
+        fs.warm_up_connection();
+    } else if let Err(error) = fs.check_server() {
+        log::error!(
+            "Server {} failed the pre-mount check (use --no-health-check to skip it): {error}",
+            args.server_url()
+        );
+        std::process::exit(1);
     }
 
-    let session = match fuser::spawn_mount2(fs, &args.mountpoint, &options) {
-        Ok(session) => session,
-        Err(e) => {
-            log::error!("Failed to mount filesystem: {}", e);
-            std::process::exit(1);
-        }
-    };
-
-    log::info!("Filesystem mounted successfully on {}.", args.mountpoint);
+    serve_metrics(&args);
 
-    // Wait for shutdown signal in a separate runtime to avoid blocking the FUSE session
-    let shutdown_signal_runtime =
-        tokio::runtime::Runtime::new().expect("Failed to create shutdown runtime");
-    shutdown_signal_runtime.block_on(wait_for_shutdown_signal());
-
-    log::info!("Unmounting filesystem from {}", args.mountpoint);
-    session.join();
+    supervisor::run(
+        fs,
+        &args.mountpoint,
+        &options,
+        args.server_url(),
+        args.prewarm,
+        args.watch_changes,
+    );
 }
@@ -0,0 +1,192 @@
+//! Operation counts and latency histograms, served in the Prometheus text
+//! format by `--metrics-addr`.
+
+use std::collections::BTreeMap;
+use std::fmt::Write as _;
+use std::io::{self, BufRead, BufReader, Write};
+use std::net::{SocketAddr, TcpListener, TcpStream};
+use std::sync::{Arc, Mutex, OnceLock};
+use std::time::Duration;
+
+// Upper bounds in seconds, from a block-cache hit up to a stalled upload.
+const BUCKETS: [f64; 10] = [0.0001, 0.0005, 0.001, 0.005, 0.01, 0.05, 0.1, 0.5, 1.0, 5.0];
+
+static METRICS: OnceLock<Arc<Metrics>> = OnceLock::new();
+
+// The HTTP layer has no handle on any filesystem state, so every recorder
+// shares one process-wide registry.
+pub(crate) fn global() -> &'static Arc<Metrics> {
+    METRICS.get_or_init(Arc::default)
+}
+
+#[derive(Default)]
+pub(crate) struct Metrics {
+    operations: Mutex<BTreeMap<&'static str, Histogram>>,
+    requests: Mutex<BTreeMap<&'static str, Histogram>>,
+}
+
+#[derive(Default)]
+struct Histogram {
+    // Per-bucket counts, not yet cumulative.
+    buckets: [u64; BUCKETS.len()],
+    count: u64,
+    sum: f64,
+}
+
+impl Histogram {
+    fn observe(&mut self, elapsed: Duration) {
+        let seconds = elapsed.as_secs_f64();
+        if let Some(bucket) = BUCKETS.iter().position(|bound| seconds <= *bound) {
+            self.buckets[bucket] += 1;
+        }
+        self.count += 1;
+        self.sum += seconds;
+    }
+}
+
+impl Metrics {
+    // One FUSE callback, from entry to reply.
+    pub(crate) fn record_operation(&self, operation: &'static str, elapsed: Duration) {
+        observe(&self.operations, operation, elapsed);
+    }
+
+    // One attempt of one HTTP request, from send to response headers.
+    pub(crate) fn record_request(&self, endpoint: &'static str, elapsed: Duration) {
+        observe(&self.requests, endpoint, elapsed);
+    }
+
+    pub(crate) fn render(&self) -> String {
+        let mut text = String::new();
+        render_family(
+            &mut text,
+            "remote_fs_fuse_operation_seconds",
+            "Time spent handling FUSE operations.",
+            "operation",
+            &self.operations,
+        );
+        render_family(
+            &mut text,
+            "remote_fs_server_request_seconds",
+            "Time until the server answered each request attempt.",
+            "request",
+            &self.requests,
+        );
+        text
+    }
+}
+
+fn observe(
+    histograms: &Mutex<BTreeMap<&'static str, Histogram>>,
+    label: &'static str,
+    elapsed: Duration,
+) {
+    histograms
+        .lock()
+        .unwrap()
+        .entry(label)
+        .or_default()
+        .observe(elapsed);
+}
+
+fn render_family(
+    text: &mut String,
+    name: &str,
+    help: &str,
+    label: &str,
+    histograms: &Mutex<BTreeMap<&'static str, Histogram>>,
+) {
+    let _ = writeln!(text, "# HELP {name} {help}");
+    let _ = writeln!(text, "# TYPE {name} histogram");
+    for (value, histogram) in histograms.lock().unwrap().iter() {
+        let mut cumulative = 0;
+        for (bound, count) in BUCKETS.iter().zip(histogram.buckets) {
+            cumulative += count;
+            let _ = writeln!(
+                text,
+                "{name}_bucket{{{label}=\"{value}\",le=\"{bound}\"}} {cumulative}"
+            );
+        }
+        let _ = writeln!(
+            text,
+            "{name}_bucket{{{label}=\"{value}\",le=\"+Inf\"}} {}",
+            histogram.count
+        );
+        let _ = writeln!(text, "{name}_sum{{{label}=\"{value}\"}} {}", histogram.sum);
+        let _ = writeln!(
+            text,
+            "{name}_count{{{label}=\"{value}\"}} {}",
+            histogram.count
+        );
+    }
+}
+
+// Binds before returning so a bad address fails the mount, then answers
+// `GET /metrics` on a background thread, one connection at a time.
+pub(crate) fn serve(addr: SocketAddr, metrics: Arc<Metrics>) -> io::Result<()> {
+    let listener = TcpListener::bind(addr)?;
+    log::info!("Serving metrics on http://{addr}/metrics");
+    std::thread::Builder::new()
+        .name("metrics".to_string())
+        .spawn(move || {
+            for stream in listener.incoming().flatten() {
+                if let Err(error) = answer(stream, &metrics) {
+                    log::debug!("Metrics request failed: {error}");
+                }
+            }
+        })?;
+    Ok(())
+}
+
+fn answer(stream: TcpStream, metrics: &Metrics) -> io::Result<()> {
+    stream.set_read_timeout(Some(Duration::from_secs(5)))?;
+    let mut reader = BufReader::new(stream);
+    let mut request_line = String::new();
+    reader.read_line(&mut request_line)?;
+    // Drain the headers; the request carries no body.
+    let mut header = String::new();
+    while reader.read_line(&mut header)? > 2 {
+        header.clear();
+    }
+
+    let mut parts = request_line.split_whitespace();
+    let (status, body) = match (parts.next(), parts.next()) {
+        (Some("GET"), Some("/metrics")) => ("200 OK", metrics.render()),
+        _ => ("404 Not Found", "Not found\n".to_string()),
+    };
+    write!(
+        reader.get_mut(),
+        "HTTP/1.1 {status}\r\nContent-Type: text/plain; version=0.0.4\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{body}",
+        body.len()
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn histograms_render_cumulative_buckets_per_label() {
+        let metrics = Metrics::default();
+        metrics.record_operation("read", Duration::from_micros(300));
+        metrics.record_operation("read", Duration::from_millis(20));
+        metrics.record_request("GET /files", Duration::from_secs(10));
+
+        let text = metrics.render();
+        assert!(text.contains("# TYPE remote_fs_fuse_operation_seconds histogram"));
+        assert!(text.contains(
+            "remote_fs_fuse_operation_seconds_bucket{operation=\"read\",le=\"0.0005\"} 1"
+        ));
+        assert!(
+            text.contains(
+                "remote_fs_fuse_operation_seconds_bucket{operation=\"read\",le=\"0.05\"} 2"
+            )
+        );
+        assert!(text.contains("remote_fs_fuse_operation_seconds_count{operation=\"read\"} 2"));
+        assert!(text.contains(
+            "remote_fs_server_request_seconds_bucket{request=\"GET /files\",le=\"5\"} 0"
+        ));
+        assert!(text.contains(
+            "remote_fs_server_request_seconds_bucket{request=\"GET /files\",le=\"+Inf\"} 1"
+        ));
+    }
+}
@@ -6,13 +6,132 @@ pub(crate) fn remote_or_mounting_user(remote_id: Option<u32>, mounting_id: u32)
     remote_id.unwrap_or(mounting_id)
 }
 
+/// The owner reported for mounted entries. An explicit `--uid`/`--gid`
+/// replaces remote ownership; otherwise the mounting user is only a fallback.
+#[derive(Clone, Copy, Debug)]
+pub(crate) struct MountOwner {
+    uid: u32,
+    gid: u32,
+    uid_override: bool,
+    gid_override: bool,
+}
+
+impl MountOwner {
+    pub(crate) fn new(uid: Option<u32>, gid: Option<u32>) -> Self {
+        MountOwner {
+            uid: uid.unwrap_or_else(|| unsafe { libc::getuid() as u32 }),
+            gid: gid.unwrap_or_else(|| unsafe { libc::getgid() as u32 }),
+            uid_override: uid.is_some(),
+            gid_override: gid.is_some(),
+        }
+    }
+
+    pub(crate) fn uid(&self, remote_uid: Option<u32>) -> u32 {
+        if self.uid_override {
+            self.uid
+        } else {
+            remote_or_mounting_user(remote_uid, self.uid)
+        }
+    }
+
+    pub(crate) fn gid(&self, remote_gid: Option<u32>) -> u32 {
+        if self.gid_override {
+            self.gid
+        } else {
+            remote_or_mounting_user(remote_gid, self.gid)
+        }
+    }
+}
+
+/// Whether `/etc/fuse.conf` lets users other than root mount with
+/// `allow_other` or `allow_root`: it must have a `user_allow_other` line.
+#[cfg_attr(not(target_os = "linux"), allow(dead_code))]
+pub(crate) fn fuse_conf_allows_other(contents: &str) -> bool {
+    contents
+        .lines()
+        .map(|line| line.split('#').next().unwrap_or_default().trim())
+        .any(|line| line == "user_allow_other")
+}
+
+/// Ownership and permission bits of one entry, as reported to the kernel.
+#[derive(Clone, Copy, Debug)]
+pub(crate) struct EntryAccess {
+    pub(crate) perm: u16,
+    pub(crate) uid: u32,
+    pub(crate) gid: u32,
+    pub(crate) is_directory: bool,
+}
+
+impl EntryAccess {
+    /// Applies `access(2)` rules for a caller's `R_OK`/`W_OK`/`X_OK` mask.
+    /// Only the caller's primary group is known, so supplementary groups are
+    /// not consulted. Root may read and write anything and may execute
+    /// directories or files with at least one execute bit.
+    pub(crate) fn permits(&self, uid: u32, gid: u32, mask: i32) -> bool {
+        let wants_execute = mask & libc::X_OK != 0;
+        if uid == 0 {
+            return !wants_execute || self.is_directory || self.perm & 0o111 != 0;
+        }
+
+        let granted = if uid == self.uid {
+            (self.perm >> 6) & 0o7
+        } else if gid == self.gid {
+            (self.perm >> 3) & 0o7
+        } else {
+            self.perm & 0o7
+        };
+        let requested = [(libc::R_OK, 0o4), (libc::W_OK, 0o2), (libc::X_OK, 0o1)]
+            .into_iter()
+            .filter(|(flag, _)| mask & flag != 0)
+            .fold(0, |bits, (_, bit)| bits | bit);
+        granted & requested == requested
+    }
+}
+
 #[cfg(test)]
 mod tests {
-    use super::remote_or_mounting_user;
+    use super::{EntryAccess, MountOwner, fuse_conf_allows_other, remote_or_mounting_user};
 
     #[test]
     fn remote_ownership_wins_and_missing_ownership_uses_mounting_user() {
         assert_eq!(remote_or_mounting_user(Some(1001), 501), 1001);
         assert_eq!(remote_or_mounting_user(None, 501), 501);
     }
+
+    #[test]
+    fn explicit_mount_owner_replaces_remote_ownership() {
+        let owner = MountOwner::new(Some(2000), None);
+        assert_eq!(owner.uid(Some(1001)), 2000);
+        assert_eq!(owner.uid(None), 2000);
+        assert_eq!(owner.gid(Some(1002)), 1002);
+    }
+
+    #[test]
+    fn access_checks_the_owner_group_and_other_bits_in_turn() {
+        let entry = EntryAccess {
+            perm: 0o640,
+            uid: 1000,
+            gid: 100,
+            is_directory: false,
+        };
+        assert!(entry.permits(1000, 1, libc::R_OK | libc::W_OK));
+        assert!(!entry.permits(1000, 1, libc::X_OK));
+        assert!(entry.permits(2000, 100, libc::R_OK));
+        assert!(!entry.permits(2000, 100, libc::W_OK));
+        assert!(!entry.permits(2000, 1, libc::R_OK));
+        assert!(entry.permits(2000, 1, libc::F_OK));
+
+        assert!(entry.permits(0, 0, libc::R_OK | libc::W_OK));
+        assert!(!entry.permits(0, 0, libc::X_OK));
+    }
+
+    #[test]
+    fn fuse_conf_must_enable_user_allow_other_outside_comments() {
+        assert!(fuse_conf_allows_other(
+            "mount_max = 1000\n  user_allow_other  \n"
+        ));
+        assert!(fuse_conf_allows_other("user_allow_other # shared mounts\n"));
+        assert!(!fuse_conf_allows_other("#user_allow_other\n"));
+        assert!(!fuse_conf_allows_other("mount_max = 1000\n"));
+    }
 }
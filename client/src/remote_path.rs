@@ -35,5 +35,7 @@ mod tests {
         assert_eq!(parent("/file.txt"), "/");
         assert_eq!(child("/directory", "caffè.txt"), "/directory/caffè.txt");
         assert_eq!(child("/", "file.txt"), "/file.txt");
+        assert_eq!(child("", "file.txt"), "/file.txt");
+        assert_eq!(child("/directory/", "file.txt"), "/directory/file.txt");
     }
 }
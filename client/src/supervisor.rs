@@ -0,0 +1,148 @@
+//! Runs a mounted session until it should stop.
+//!
+//! The FUSE session is served on a background thread so the main thread can
+//! watch for SIGINT/SIGTERM, the mount being removed externally, and the
+//! server's health, whether the client runs in the foreground or as a daemon.
+
+use crate::{api, fuse::RemoteFs};
+use fuser::MountOption;
+use std::{io, thread::JoinHandle, time::Duration};
+
+// How often the main thread checks whether the kernel ended the session.
+const SESSION_POLL_INTERVAL: Duration = Duration::from_millis(200);
+// How often the server's liveness probe is called while mounted.
+const HEALTH_PROBE_INTERVAL: Duration = Duration::from_secs(30);
+// How long a signalled shutdown waits for the unmount and final `destroy`.
+const UNMOUNT_TIMEOUT: Duration = Duration::from_secs(10);
+
+enum Stop {
+    Signal,
+    Unmounted,
+}
+
+// On a signal, pending writes are uploaded first; joining the session then
+// unmounts and runs `destroy`, which flushes anything written in between. A
+// mount that stays busy past `UNMOUNT_TIMEOUT` is left for the kernel to abort.
+pub(crate) fn run(
+    fs: RemoteFs,
+    mountpoint: &str,
+    options: &[MountOption],
+    server_url: &str,
+    prewarm: bool,
+    watch_changes: bool,
+) {
+    let flusher = fs.clone();
+    let session = match fuser::spawn_mount2(fs, mountpoint, options) {
+        Ok(session) => session,
+        Err(e) => {
+            log::error!("Failed to mount filesystem: {}", e);
+            std::process::exit(1);
+        }
+    };
+
+    log::info!("Filesystem mounted successfully on {}.", mountpoint);
+    let prewarm = prewarm.then(|| flusher.spawn_prewarm());
+    let change_feed = watch_changes.then(|| flusher.spawn_change_watcher(session.notifier()));
+    let root_refresh = flusher.spawn_root_refresh();
+
+    // Wait in a separate runtime to avoid blocking the FUSE session. This
+    // thread drives it alone, so it needs no workers of its own.
+    let supervisor_runtime = tokio::runtime::Builder::new_current_thread()
+        .enable_all()
+        .build()
+        .expect("Failed to create supervisor runtime");
+    let stop = supervisor_runtime.block_on(supervise(&session.guard, server_url));
+    for background in prewarm.iter().chain(&change_feed).chain(&root_refresh) {
+        background.store(true, std::sync::atomic::Ordering::Relaxed);
+    }
+    match stop {
+        Stop::Signal => {
+            flusher.flush_pending_writes();
+            log::info!("Unmounting filesystem from {}", mountpoint);
+        }
+        Stop::Unmounted => log::info!("Filesystem at {} was unmounted.", mountpoint),
+    }
+
+    let (joined, session_ended) = std::sync::mpsc::channel();
+    std::thread::spawn(move || {
+        session.join();
+        let _ = joined.send(());
+    });
+    if session_ended.recv_timeout(UNMOUNT_TIMEOUT).is_err() {
+        log::error!(
+            "{} is still busy; exiting without unmounting. Run `umount {}` once it is free.",
+            mountpoint,
+            mountpoint
+        );
+        std::process::exit(1);
+    }
+    log::info!("Shutdown complete.");
+}
+
+// Probes the server meanwhile so an outage and its recovery show up in the
+// log even while nothing touches the mount.
+async fn supervise(guard: &JoinHandle<io::Result<()>>, server_url: &str) -> Stop {
+    let signal = wait_for_shutdown_signal();
+    let ended = wait_for_session_end(guard);
+    tokio::pin!(signal, ended);
+
+    let mut probe = tokio::time::interval(HEALTH_PROBE_INTERVAL);
+    probe.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Delay);
+    // The first tick is immediate, and the mount was just checked.
+    probe.tick().await;
+    let mut reachable = true;
+    loop {
+        tokio::select! {
+            _ = &mut signal => return Stop::Signal,
+            _ = &mut ended => return Stop::Unmounted,
+            _ = probe.tick() => {
+                // Servers predating `/healthz` answer 404, which still proves
+                // they are reachable.
+                match api::check_health(server_url).await {
+                    Ok(()) | Err(api::ApiError::NotFound) => {
+                        if !reachable {
+                            log::info!("Server {} is reachable again.", server_url);
+                        }
+                        reachable = true;
+                    }
+                    Err(error) => {
+                        if reachable {
+                            log::warn!("Server {} failed a health check: {error}", server_url);
+                        }
+                        reachable = false;
+                    }
+                }
+            }
+        }
+    }
+}
+
+async fn wait_for_session_end(guard: &JoinHandle<io::Result<()>>) {
+    while !guard.is_finished() {
+        tokio::time::sleep(SESSION_POLL_INTERVAL).await;
+    }
+}
+
+async fn wait_for_shutdown_signal() {
+    let ctrl_c = async {
+        tokio::signal::ctrl_c()
+            .await
+            .expect("Failed to install Ctrl-C handler");
+    };
+
+    #[cfg(unix)]
+    let terminate = async {
+        tokio::signal::unix::signal(tokio::signal::unix::SignalKind::terminate())
+            .expect("Failed to install SIGTERM handler")
+            .recv()
+            .await;
+    };
+
+    #[cfg(not(unix))]
+    let terminate = std::future::pending::<()>();
+
+    tokio::select! {
+        _ = ctrl_c => log::info!("Shutdown requested with Ctrl-C."),
+        _ = terminate => log::info!("Shutdown requested with SIGTERM."),
+    }
+}
@@ -31,6 +31,7 @@ const FILE_DIRECTORY_FILE: u32 = 0x0000_0001;
 const SECTOR_SIZE: u64 = 4096;
 const UNIX_EPOCH_AS_FILETIME_SECS: u64 = 11_644_473_600;
 const STATUS_UNEXPECTED_IO_ERROR: i32 = 0xC000_00E9u32 as i32;
+const STATUS_DISK_FULL: i32 = 0xC000_007Fu32 as i32;
 const ERROR_DIR_NOT_EMPTY: u32 = 145;
 
 #[derive(Clone, Copy, Debug, Eq, PartialEq)]
@@ -69,7 +70,7 @@ pub fn run(
 ) -> std::result::Result<(), Box<dyn std::error::Error>> {
     let _winfsp = winfsp::winfsp_init()?;
     let runtime = tokio::runtime::Runtime::new()?;
-    let writeback = Writeback::new(server_url, runtime.handle().clone())?;
+    let writeback = Writeback::new(server_url, None, runtime.handle().clone())?;
     writeback.start_recovery();
     let fs = RemoteWinFs {
         server_addr: server_url.to_string(),
@@ -120,7 +121,7 @@ impl RemoteEntry {
 
     fn from_directory_entry(entry: &DirectoryEntry) -> Self {
         RemoteEntry {
-            kind: kind_from_type(&entry.type_),
+            kind: kind_from_type(entry.type_),
             size: entry.size,
             modified_at: entry.modified_at.clone(),
             mode: entry.mode,
@@ -129,7 +130,7 @@ impl RemoteEntry {
 
     fn from_metadata(metadata: &RemoteMetadata) -> Self {
         RemoteEntry {
-            kind: kind_from_type(&metadata.type_),
+            kind: kind_from_type(metadata.type_),
             size: metadata.size,
             modified_at: metadata.modified_at.clone(),
             mode: metadata.mode,
@@ -192,6 +193,9 @@ impl RemoteWinFs {
                 remote_path::api(path),
             ))
             .map_err(|error| fsp_error_from_api(&error))?;
+        // Windows names are UTF-16, so escaped non-UTF-8 names from a Unix
+        // server have no spelling here.
+        entries.retain(|entry| entry.name_encoding.is_none());
         for pending in self.writeback.entries_in_directory(path) {
             if pending.is_committed() {
                 continue;
@@ -304,12 +308,16 @@ impl RemoteWinFs {
     }
 }
 
-fn fsp_error_from_api(error: &reqwest::Error) -> FspError {
+fn fsp_error_from_api(error: &api::ApiError) -> FspError {
+    if matches!(error, api::ApiError::Timeout) {
+        return FspError::IO(ErrorKind::TimedOut);
+    }
     match error.status().map(|status| status.as_u16()) {
         Some(400) => FspError::IO(ErrorKind::InvalidInput),
         Some(401 | 403) => FspError::IO(ErrorKind::PermissionDenied),
         Some(404) => FspError::IO(ErrorKind::NotFound),
         Some(409) => FspError::IO(ErrorKind::AlreadyExists),
+        Some(507) => FspError::NTSTATUS(STATUS_DISK_FULL),
         _ => FspError::NTSTATUS(STATUS_UNEXPECTED_IO_ERROR),
     }
 }
@@ -340,11 +348,12 @@ fn fsp_error_from_io(error: std::io::Error) -> FspError {
     }
 }
 
-fn kind_from_type(type_: &str) -> EntryKind {
-    if type_ == "directory" {
-        EntryKind::Directory
-    } else {
-        EntryKind::File
+// WinFSP exposes only files and directories; links and FIFOs are served
+// as the files the server reports for them.
+fn kind_from_type(type_: api::EntryType) -> EntryKind {
+    match type_ {
+        api::EntryType::Directory => EntryKind::Directory,
+        api::EntryType::File | api::EntryType::Symlink | api::EntryType::Fifo => EntryKind::File,
     }
 }
 
@@ -365,15 +374,21 @@ fn directory_entry_from_remote(name: &str, entry: &RemoteEntry) -> DirectoryEntr
     DirectoryEntry {
         name: name.to_string(),
         type_: match entry.kind {
-            EntryKind::File => "file",
-            EntryKind::Directory => "directory",
-        }
-        .to_string(),
+            EntryKind::File => api::EntryType::File,
+            EntryKind::Directory => api::EntryType::Directory,
+        },
         size: entry.size,
         modified_at: entry.modified_at.clone(),
         mode: entry.mode,
         uid: None,
         gid: None,
+        target: None,
+        accessed_at: None,
+        changed_at: None,
+        nlink: None,
+        blocks: None,
+        content_type: None,
+        name_encoding: None,
     }
 }
 
@@ -265,6 +265,7 @@ impl FileSystemContext for RemoteWinFs {
                     None,
                     None,
                     mtime,
+                    None,
                 ))
                 .map_err(|error| fsp_error_from_api(&error))?;
             let entry = RemoteEntry::from_metadata(&metadata);
@@ -430,7 +431,7 @@ impl FileSystemContext for RemoteWinFs {
         } else {
             offset
         };
-        let metadata = self
+        let outcome = self
             .block_on(api::write_file(
                 &self.server_addr,
                 remote_path::api(&path),
@@ -438,12 +439,12 @@ impl FileSystemContext for RemoteWinFs {
                 offset,
             ))
             .map_err(|error| fsp_error_from_api(&error))?;
-        let entry = RemoteEntry::from_metadata(&metadata);
+        let entry = RemoteEntry::from_metadata(&outcome.metadata);
         context.update_entry(entry.clone());
         self.cache.insert_metadata(&path, &entry);
         self.cache.update_directory_for_path(&path, &entry);
         fill_file_info(file_info, &path, &entry);
-        Ok(buffer.len() as u32)
+        Ok(outcome.written as u32)
     }
 
     fn get_volume_info(&self, out_volume_info: &mut VolumeInfo) -> Result<()> {
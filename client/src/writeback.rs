@@ -15,7 +15,7 @@ use std::{
     collections::HashMap,
     fs::{self, File, OpenOptions},
     io,
-    path::PathBuf,
+    path::{Path, PathBuf},
     sync::{Arc, Mutex},
 };
 use tokio::runtime::Handle;
@@ -39,8 +39,10 @@ pub struct Writeback {
 }
 
 impl Writeback {
-    pub fn new(server_addr: &str, runtime: Handle) -> io::Result<Self> {
-        let root = journal_root(server_addr);
+    // `journal_dir` overrides `REMOTE_FS_JOURNAL_DIR` and the per-user
+    // default as the directory journals are kept under.
+    pub fn new(server_addr: &str, journal_dir: Option<&Path>, runtime: Handle) -> io::Result<Self> {
+        let root = journal_root(server_addr, journal_dir);
         fs::create_dir_all(&root)?;
         let lock_path = root.join(LOCK_FILE_NAME);
 
@@ -268,14 +270,23 @@ impl Writeback {
     }
 }
 
-fn journal_root(server_addr: &str) -> PathBuf {
-    let base = std::env::var_os("REMOTE_FS_JOURNAL_DIR")
-        .map(PathBuf::from)
+// Journaled paths are relative to the mount, so each `--remote-root` of a
+// server keeps its own journal and replays into the directory it came from.
+fn journal_root(server_addr: &str, journal_dir: Option<&Path>) -> PathBuf {
+    let base = journal_dir
+        .map(Path::to_path_buf)
+        .or_else(|| std::env::var_os("REMOTE_FS_JOURNAL_DIR").map(PathBuf::from))
         .or_else(default_journal_base)
         .unwrap_or_else(|| std::env::current_dir().unwrap_or_else(|_| PathBuf::from(".")));
     base.join("remote-fs")
         .join("journal")
-        .join(format!("server-{:016x}", stable_hash(server_addr)))
+        .join(match api::remote_root() {
+            "" => format!("server-{:016x}", stable_hash(server_addr)),
+            root => format!(
+                "server-{:016x}",
+                stable_hash(&format!("{server_addr}/{root}"))
+            ),
+        })
 }
 
 fn default_journal_base() -> Option<PathBuf> {
@@ -8,6 +8,9 @@ use serde::{Deserialize, Serialize};
 
 pub const PROTOCOL_VERSION: &str = "1";
 pub const PROTOCOL_VERSION_HEADER: &str = "X-Remote-Fs-Protocol-Version";
+/// Largest read body the server hashes for `headers::CONTENT_SHA256`; larger
+/// ones are streamed without it.
+pub const MAX_CHECKSUMMED_READ: u64 = 16 * 1024 * 1024;
 
 pub mod headers {
     pub const FILE_OFFSET: &str = "X-File-Offset";
@@ -16,30 +19,305 @@ pub mod headers {
     pub const FILE_MODE: &str = "X-File-Mode";
     pub const FILE_UID: &str = "X-File-Uid";
     pub const FILE_GID: &str = "X-File-Gid";
+    pub const FILE_ATIME: &str = "X-File-Atime";
     pub const FILE_MTIME: &str = "X-File-Mtime";
+    /// Opaque cursor for the next page of a paginated listing.
+    pub const NEXT_CURSOR: &str = "X-Next-Cursor";
+    /// Sent with a file read to ask for `CONTENT_SHA256` on the response.
+    pub const WANT_CONTENT_SHA256: &str = "X-Want-Content-SHA256";
+    /// Lowercase hex SHA-256 of exactly the bytes in the response body.
+    pub const CONTENT_SHA256: &str = "X-Content-SHA256";
+    /// Bytes a `PUT /files` write stored; fewer than sent means a short write.
+    pub const BYTES_WRITTEN: &str = "X-Bytes-Written";
+    /// Client-chosen ID for one request, logged by both ends and echoed back.
+    pub const REQUEST_ID: &str = "X-Request-Id";
+    /// Sent with a file read to ask that it leave the file's access time as
+    /// it was, where the server's platform allows.
+    pub const NO_ATIME: &str = "X-No-Atime";
+}
+
+/// File names that are not valid UTF-8 cannot travel in JSON strings or
+/// `String` paths as they are. Such a path component is sent as U+0000
+/// followed by the lowercase hex of its bytes. NUL never occurs in a real
+/// file name, so the escaped form cannot collide with one, and it is used the
+/// same way in URLs, request bodies, and listed names.
+pub mod names {
+    use std::borrow::Cow;
+
+    /// Value of `DirectoryEntry::name_encoding` when `name` has an escaped component.
+    pub const ESCAPED: &str = "nul-hex";
+    const ESCAPE_PREFIX: char = '\0';
+
+    /// Escapes one path component given as raw bytes.
+    pub fn escape(bytes: &[u8]) -> Cow<'_, str> {
+        match std::str::from_utf8(bytes) {
+            Ok(name) => Cow::Borrowed(name),
+            Err(_) => {
+                let mut escaped = String::with_capacity(1 + bytes.len() * 2);
+                escaped.push(ESCAPE_PREFIX);
+                for byte in bytes {
+                    escaped.push(char::from_digit(u32::from(byte >> 4), 16).unwrap());
+                    escaped.push(char::from_digit(u32::from(byte & 0xf), 16).unwrap());
+                }
+                Cow::Owned(escaped)
+            }
+        }
+    }
+
+    pub fn is_escaped(component: &str) -> bool {
+        component.starts_with(ESCAPE_PREFIX)
+    }
+
+    /// Raw bytes of one path component; `None` for a malformed escape.
+    pub fn unescape(component: &str) -> Option<Cow<'_, [u8]>> {
+        let Some(hex) = component.strip_prefix(ESCAPE_PREFIX) else {
+            return Some(Cow::Borrowed(component.as_bytes()));
+        };
+        if hex.is_empty() || hex.len() % 2 != 0 {
+            return None;
+        }
+        (0..hex.len())
+            .step_by(2)
+            .map(|index| u8::from_str_radix(hex.get(index..index + 2)?, 16).ok())
+            .collect::<Option<Vec<u8>>>()
+            .map(Cow::Owned)
+    }
+}
+
+/// Kind of a listed or stat'ed entry. Types this version does not define
+/// fail to decode rather than passing for regular files.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum EntryType {
+    File,
+    Directory,
+    Symlink,
+    Fifo,
+}
+
+impl EntryType {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            EntryType::File => "file",
+            EntryType::Directory => "directory",
+            EntryType::Symlink => "symlink",
+            EntryType::Fifo => "fifo",
+        }
+    }
+}
+
+impl std::fmt::Display for EntryType {
+    fn fmt(&self, formatter: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        formatter.write_str(self.as_str())
+    }
 }
 
 #[derive(Clone, Debug, Deserialize, Serialize)]
 pub struct DirectoryEntry {
     pub name: String,
     #[serde(rename = "type")]
-    pub type_: String,
+    pub type_: EntryType,
     pub size: u64,
     pub modified_at: String,
     pub mode: Option<u32>,
     pub uid: Option<u32>,
     pub gid: Option<u32>,
+    /// Link target for `symlink` entries; absent for every other type.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub target: Option<String>,
+    /// Last access and last status change, in the same Unix-seconds form as
+    /// `modified_at`; absent when the server cannot report them.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub accessed_at: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub changed_at: Option<String>,
+    /// Hard link count; absent when the server cannot report it.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub nlink: Option<u64>,
+    /// Allocated 512-byte blocks, as `st_blocks` reports them, so sparse
+    /// files show their real usage; absent when the server cannot report it.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub blocks: Option<u64>,
+    /// MIME type the server guesses for a regular file, such as
+    /// `image/png`; absent when it has no guess.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub content_type: Option<String>,
+    /// `names::ESCAPED` when `name` contains a component escaped because it
+    /// is not valid UTF-8; absent for plain names.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub name_encoding: Option<String>,
+}
+
+/// Values of `ErrorBody::error`. The few the server picks for itself are
+/// listed here; other rejections use their status's reason phrase in snake
+/// case.
+pub mod error_codes {
+    /// The request named no route this server has, as opposed to a path the
+    /// route could not find. Clients take it to mean an older server.
+    pub const UNKNOWN_ENDPOINT: &str = "unknown_endpoint";
+    pub const BAD_REQUEST: &str = "bad_request";
+    /// A request body that could not be read to its end.
+    pub const INVALID_BODY: &str = "invalid_body";
+    pub const NOT_FOUND: &str = "not_found";
+    pub const FORBIDDEN: &str = "forbidden";
+    pub const CONFLICT: &str = "conflict";
+    pub const PRECONDITION_FAILED: &str = "precondition_failed";
+    pub const RANGE_NOT_SATISFIABLE: &str = "range_not_satisfiable";
+    /// A write, resize, or copy past the server's file size limit.
+    pub const FILE_TOO_LARGE: &str = "file_too_large";
+    pub const INSUFFICIENT_STORAGE: &str = "insufficient_storage";
+    /// An unexpected failure of the storage filesystem.
+    pub const STORAGE_ERROR: &str = "storage_error";
+}
+
+/// Body of every error response. `error` is one of `error_codes`, or for
+/// other rejections the status's reason phrase in snake case, and `message`
+/// says what went wrong in words for logs.
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct ErrorBody {
+    pub error: String,
+    pub message: String,
+}
+
+/// Entry of a `GET /names` listing: a `DirectoryEntry` without the fields
+/// that need a `stat`.
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct NameEntry {
+    pub name: String,
+    #[serde(rename = "type")]
+    pub type_: EntryType,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub name_encoding: Option<String>,
 }
 
 #[derive(Clone, Debug, Deserialize, Serialize)]
 pub struct RemoteMetadata {
     #[serde(rename = "type")]
-    pub type_: String,
+    pub type_: EntryType,
     pub size: u64,
     pub modified_at: String,
     pub mode: Option<u32>,
     pub uid: Option<u32>,
     pub gid: Option<u32>,
+    /// Same as the `DirectoryEntry` fields of these names.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub accessed_at: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub changed_at: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub nlink: Option<u64>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub blocks: Option<u64>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub content_type: Option<String>,
+}
+
+/// Capacity of the filesystem holding the storage root, as reported by
+/// `statvfs`. Block counts are in units of `block_size` bytes.
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct FilesystemStats {
+    pub block_size: u32,
+    pub blocks: u64,
+    pub blocks_free: u64,
+    pub blocks_available: u64,
+    pub files: u64,
+    pub files_free: u64,
+    pub name_max: u32,
+}
+
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct SymlinkRequest {
+    pub path: String,
+    pub target: String,
+}
+
+/// Body of `POST /link`: `to` becomes another name for the file at `from`.
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct LinkRequest {
+    pub from: String,
+    pub to: String,
+}
+
+/// Body of `POST /copy`: copies up to `length` bytes of `from`, starting at
+/// `from_offset`, over `to` at `to_offset`. Both files must already exist.
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct CopyRequest {
+    pub from: String,
+    pub to: String,
+    pub from_offset: u64,
+    pub to_offset: u64,
+    pub length: u64,
+}
+
+/// Reply to `POST /copy`; `copied` is short when the source ended first.
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct CopyResult {
+    pub copied: u64,
+}
+
+/// Body of `POST /fallocate/{path}`: reserves `length` bytes at `offset`.
+/// With `keep_size` the space is reserved without growing the file.
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct FallocateRequest {
+    pub offset: u64,
+    pub length: u64,
+    #[serde(default)]
+    pub keep_size: bool,
+}
+
+/// What `GET /seek/{path}` looks for: the next byte that holds data, or the
+/// start of the next hole. The end of a file counts as a hole.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum SeekWhence {
+    Data,
+    Hole,
+}
+
+impl std::fmt::Display for SeekWhence {
+    fn fmt(&self, formatter: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        formatter.write_str(match self {
+            SeekWhence::Data => "data",
+            SeekWhence::Hole => "hole",
+        })
+    }
+}
+
+/// Query of `GET /seek/{path}`: where to start looking.
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct SeekQuery {
+    pub whence: SeekWhence,
+    pub offset: u64,
+}
+
+/// Reply to `GET /seek/{path}`: the first matching offset at or after the
+/// requested one.
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct SeekResult {
+    pub offset: u64,
+}
+
+/// Body of `POST /mknod`. `mode` carries the `S_IFMT` node type bits as well
+/// as the permissions; `rdev` only describes device nodes.
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct MknodRequest {
+    pub path: String,
+    pub mode: u32,
+    #[serde(default)]
+    pub rdev: u64,
+}
+
+/// Body of `POST /write-temp`: stages a write that replaces `path` only
+/// when it is committed.
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct TempWriteRequest {
+    pub path: String,
+}
+
+/// Reply to `POST /write-temp`; names the staged write in later requests.
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct TempWriteHandle {
+    pub handle: String,
 }
 
 #[derive(Clone, Debug, Deserialize, Serialize)]
@@ -58,6 +336,52 @@ fn replace_if_exists_by_default() -> bool {
 mod tests {
     use super::*;
 
+    #[test]
+    fn directory_entries_omit_and_default_a_missing_target() {
+        let entry: DirectoryEntry = serde_json::from_str(
+            r#"{"name":"a","type":"file","size":0,"modified_at":"0","mode":null,"uid":null,"gid":null}"#,
+        )
+        .unwrap();
+        assert!(entry.target.is_none());
+        assert!(entry.accessed_at.is_none() && entry.changed_at.is_none());
+        assert!(entry.nlink.is_none() && entry.name_encoding.is_none());
+        assert!(!serde_json::to_string(&entry).unwrap().contains("target"));
+    }
+
+    #[test]
+    fn entry_types_are_lowercase_and_unknown_ones_are_rejected() {
+        let entry = |type_: &str| {
+            serde_json::from_str::<DirectoryEntry>(&format!(
+                r#"{{"name":"a","type":"{type_}","size":0,"modified_at":"0","mode":null,"uid":null,"gid":null}}"#
+            ))
+        };
+        assert_eq!(entry("directory").unwrap().type_, EntryType::Directory);
+        assert_eq!(entry("fifo").unwrap().type_, EntryType::Fifo);
+        assert!(entry("socket").is_err());
+        assert!(entry("File").is_err());
+        assert_eq!(
+            serde_json::to_string(&EntryType::Symlink).unwrap(),
+            r#""symlink""#
+        );
+    }
+
+    #[test]
+    fn non_utf8_names_round_trip_through_the_escape() {
+        let raw = b"caf\xe9.txt";
+        let escaped = names::escape(raw);
+        assert_eq!(escaped, "\u{0}636166e92e747874");
+        assert!(names::is_escaped(&escaped));
+        assert_eq!(names::unescape(&escaped).unwrap().as_ref(), raw);
+
+        assert_eq!(names::escape("café.txt".as_bytes()), "café.txt");
+        assert_eq!(
+            names::unescape("café.txt").unwrap().as_ref(),
+            "café.txt".as_bytes()
+        );
+        assert!(names::unescape("\u{0}abc").is_none());
+        assert!(names::unescape("\u{0}zz").is_none());
+    }
+
     #[test]
     fn rename_defaults_to_replacing_the_destination() {
         let request: RenameRequest =
@@ -1,8 +1,11 @@
-use crate::AppState;
+use crate::{error::error_response, AppState};
 use axum::{
     body::Body,
     extract::State,
-    http::{header::AUTHORIZATION, Request, StatusCode},
+    http::{
+        header::{AUTHORIZATION, WWW_AUTHENTICATE},
+        Request, StatusCode,
+    },
     middleware::Next,
     response::{IntoResponse, Response},
 };
@@ -45,7 +48,12 @@ pub(crate) async fn require_authentication(
     if authorized {
         next.run(request).await
     } else {
-        (StatusCode::UNAUTHORIZED, "Missing or invalid bearer token").into_response()
+        // RFC 6750 asks 401 responses to name the scheme the client should retry with.
+        (
+            [(WWW_AUTHENTICATE, "Bearer realm=\"remote-fs\"")],
+            error_response(StatusCode::UNAUTHORIZED, "Missing or invalid bearer token"),
+        )
+            .into_response()
     }
 }
 
@@ -1,42 +1,301 @@
-use std::{env, net::SocketAddr, path::PathBuf};
+use std::{env, ffi::OsString, net::SocketAddr, path::PathBuf, time::Duration};
 
 const DEFAULT_STORAGE_ROOT: &str = "remote-storage";
 const DEFAULT_LISTEN_ADDR: &str = "127.0.0.1:3000";
+const DEFAULT_SHUTDOWN_TIMEOUT: Duration = Duration::from_secs(30);
 
 pub struct ServerConfig {
     pub storage_root: PathBuf,
     pub listen_addr: SocketAddr,
     pub auth_token: Option<String>,
+    // How long requests still running at SIGINT/SIGTERM may take to finish.
+    pub shutdown_timeout: Duration,
+    // Writes and resizes that would take a file past this many bytes are
+    // refused with 413; files already larger can still be read.
+    pub max_file_size: Option<u64>,
+}
+
+// Options given on the command line; each one overrides its environment
+// variable.
+#[derive(Debug, Default, PartialEq)]
+struct ServerArgs {
+    storage_root: Option<PathBuf>,
+    bind: Option<String>,
+}
+
+const USAGE: &str = "usage: server [--bind ADDR:PORT] [--root] [PATH]";
+
+// Reads `--root PATH`, `--root=PATH`, or a bare PATH, and `--bind ADDR:PORT`
+// or `--bind=ADDR:PORT`.
+fn server_args(mut args: impl Iterator<Item = String>) -> Result<ServerArgs, String> {
+    let mut parsed = ServerArgs::default();
+    while let Some(arg) = args.next() {
+        if arg == "--bind" {
+            let address = args
+                .next()
+                .ok_or_else(|| "--bind expects an ADDR:PORT such as 127.0.0.1:3000".to_string())?;
+            parsed.bind = Some(address);
+            continue;
+        }
+        if let Some(address) = arg.strip_prefix("--bind=") {
+            parsed.bind = Some(address.to_string());
+            continue;
+        }
+        let path = if arg == "--root" {
+            args.next()
+                .ok_or_else(|| "--root expects a directory path".to_string())?
+        } else if let Some(path) = arg.strip_prefix("--root=") {
+            path.to_string()
+        } else if arg.starts_with("--") {
+            return Err(format!("Unknown option '{arg}'; {USAGE}"));
+        } else {
+            arg
+        };
+        if parsed.storage_root.replace(PathBuf::from(path)).is_some() {
+            return Err("The storage root was given more than once".to_string());
+        }
+    }
+    Ok(parsed)
+}
+
+// Picks the listen address from `--bind`, then `RFS_BIND`, then
+// `REMOTE_FS_ADDR`, along with the name of where it came from for errors.
+fn listen_addr_source(
+    flag: Option<String>,
+    rfs_bind: Option<String>,
+    remote_fs_addr: Option<String>,
+) -> (&'static str, String) {
+    match (flag, rfs_bind, remote_fs_addr) {
+        (Some(address), _, _) => ("--bind", address),
+        (None, Some(address), _) => ("RFS_BIND", address),
+        (None, None, Some(address)) => ("REMOTE_FS_ADDR", address),
+        (None, None, None) => ("the default", DEFAULT_LISTEN_ADDR.to_string()),
+    }
+}
+
+// The storage root from `--root` or a bare argument, then `RFS_ROOT`, then
+// `REMOTE_FS_ROOT`, then the default.
+fn storage_root_from(
+    argument: Option<PathBuf>,
+    rfs_root: Option<OsString>,
+    remote_fs_root: Option<OsString>,
+) -> PathBuf {
+    argument
+        .or_else(|| rfs_root.map(PathBuf::from))
+        .or_else(|| remote_fs_root.map(PathBuf::from))
+        .unwrap_or_else(|| PathBuf::from(DEFAULT_STORAGE_ROOT))
+}
+
+// The bearer token from `RFS_TOKEN`, then `REMOTE_FS_TOKEN`; one that is
+// empty once trimmed counts as unset.
+fn auth_token_from(rfs_token: Option<String>, remote_fs_token: Option<String>) -> Option<String> {
+    [rfs_token, remote_fs_token]
+        .into_iter()
+        .flatten()
+        .map(|token| token.trim().to_string())
+        .find(|token| !token.is_empty())
 }
 
 impl ServerConfig {
     pub fn from_env_args() -> Result<Self, String> {
-        let storage_root = env::args()
-            .nth(1)
-            .or_else(|| env::var("REMOTE_FS_ROOT").ok())
-            .map(PathBuf::from)
-            .unwrap_or_else(|| PathBuf::from(DEFAULT_STORAGE_ROOT));
-        let address =
-            env::var("REMOTE_FS_ADDR").unwrap_or_else(|_| DEFAULT_LISTEN_ADDR.to_string());
-        let listen_addr = address
-            .parse::<SocketAddr>()
-            .map_err(|error| format!("Invalid REMOTE_FS_ADDR '{address}': {error}"))?;
-        let auth_token = env::var("REMOTE_FS_TOKEN")
-            .ok()
-            .map(|token| token.trim().to_string())
-            .filter(|token| !token.is_empty());
+        let args = server_args(env::args().skip(1))?;
+        let storage_root = storage_root_from(
+            args.storage_root,
+            env::var_os("RFS_ROOT"),
+            env::var_os("REMOTE_FS_ROOT"),
+        );
+        let (source, address) = listen_addr_source(
+            args.bind,
+            env::var("RFS_BIND").ok(),
+            env::var("REMOTE_FS_ADDR").ok(),
+        );
+        let listen_addr = parse_listen_addr(source, &address)?;
+        let auth_token =
+            auth_token_from(env::var("RFS_TOKEN").ok(), env::var("REMOTE_FS_TOKEN").ok());
 
         if !listen_addr.ip().is_loopback() && auth_token.is_none() {
             return Err(
-                "REMOTE_FS_TOKEN must be set when REMOTE_FS_ADDR listens on a non-loopback address"
+                "RFS_TOKEN or REMOTE_FS_TOKEN must be set when the server listens on a \
+                 non-loopback address"
                     .to_string(),
             );
         }
 
+        let shutdown_timeout =
+            parse_shutdown_timeout(env::var("REMOTE_FS_SHUTDOWN_TIMEOUT").ok().as_deref())?;
+        let max_file_size =
+            parse_max_file_size(env::var("REMOTE_FS_MAX_FILE_SIZE").ok().as_deref())?;
+
         Ok(ServerConfig {
             storage_root,
             listen_addr,
             auth_token,
+            shutdown_timeout,
+            max_file_size,
         })
     }
 }
+
+// Listen addresses are IP literals with a port, e.g. `0.0.0.0:3000` or
+// `[::1]:3000`.
+fn parse_listen_addr(source: &str, address: &str) -> Result<SocketAddr, String> {
+    address.trim().parse::<SocketAddr>().map_err(|_| {
+        format!(
+            "Invalid {source} '{address}': expected an IP address and port such as 127.0.0.1:3000"
+        )
+    })
+}
+
+fn parse_shutdown_timeout(seconds: Option<&str>) -> Result<Duration, String> {
+    let Some(seconds) = seconds else {
+        return Ok(DEFAULT_SHUTDOWN_TIMEOUT);
+    };
+    seconds
+        .trim()
+        .parse::<u64>()
+        .map(Duration::from_secs)
+        .map_err(|_| {
+            format!("Invalid REMOTE_FS_SHUTDOWN_TIMEOUT '{seconds}': expected whole seconds")
+        })
+}
+
+fn parse_max_file_size(bytes: Option<&str>) -> Result<Option<u64>, String> {
+    let Some(bytes) = bytes else {
+        return Ok(None);
+    };
+    match bytes.trim().parse::<u64>() {
+        Ok(limit) if limit > 0 => Ok(Some(limit)),
+        _ => Err(format!(
+            "Invalid REMOTE_FS_MAX_FILE_SIZE '{bytes}': expected a positive number of bytes"
+        )),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{
+        auth_token_from, listen_addr_source, parse_listen_addr, parse_max_file_size,
+        parse_shutdown_timeout, server_args, storage_root_from, DEFAULT_LISTEN_ADDR,
+        DEFAULT_STORAGE_ROOT,
+    };
+    use std::ffi::OsString;
+    use std::path::PathBuf;
+    use std::time::Duration;
+
+    fn parse(args: &[&str]) -> Result<Option<PathBuf>, String> {
+        server_args(args.iter().map(|arg| arg.to_string())).map(|args| args.storage_root)
+    }
+
+    #[test]
+    fn storage_root_accepts_flag_and_positional_forms() {
+        assert_eq!(parse(&[]).unwrap(), None);
+        assert_eq!(parse(&["data"]).unwrap(), Some(PathBuf::from("data")));
+        assert_eq!(
+            parse(&["--root", "data"]).unwrap(),
+            Some(PathBuf::from("data"))
+        );
+        assert_eq!(
+            parse(&["--root=data"]).unwrap(),
+            Some(PathBuf::from("data"))
+        );
+        assert!(parse(&["--root"]).is_err());
+        assert!(parse(&["--root", "a", "b"]).is_err());
+        assert!(parse(&["--bogus"]).is_err());
+    }
+
+    #[test]
+    fn bind_addresses_come_from_the_flag_and_must_be_ip_and_port() {
+        let args = server_args(
+            ["--bind", "[::1]:4000", "data"]
+                .map(String::from)
+                .into_iter(),
+        );
+        assert_eq!(args.as_ref().unwrap().bind.as_deref(), Some("[::1]:4000"));
+        assert_eq!(args.unwrap().storage_root, Some(PathBuf::from("data")));
+        assert!(server_args(["--bind".to_string()].into_iter()).is_err());
+
+        assert_eq!(
+            parse_listen_addr("--bind", "0.0.0.0:3000").unwrap(),
+            "0.0.0.0:3000".parse().unwrap()
+        );
+        let error = parse_listen_addr("REMOTE_FS_ADDR", "localhost").unwrap_err();
+        assert!(error.contains("REMOTE_FS_ADDR 'localhost'"));
+        assert!(parse_listen_addr("--bind", "127.0.0.1").is_err());
+
+        let some = |address: &str| Some(address.to_string());
+        assert_eq!(
+            listen_addr_source(some("[::1]:1"), some("[::1]:2"), some("[::1]:3")),
+            ("--bind", "[::1]:1".to_string())
+        );
+        assert_eq!(
+            listen_addr_source(None, some("[::1]:2"), some("[::1]:3")),
+            ("RFS_BIND", "[::1]:2".to_string())
+        );
+        assert_eq!(
+            listen_addr_source(None, None, some("[::1]:3")),
+            ("REMOTE_FS_ADDR", "[::1]:3".to_string())
+        );
+        assert_eq!(listen_addr_source(None, None, None).1, DEFAULT_LISTEN_ADDR);
+    }
+
+    #[test]
+    fn storage_roots_come_from_the_argument_then_rfs_root_then_remote_fs_root() {
+        let some = |path: &str| Some(OsString::from(path));
+        assert_eq!(
+            storage_root_from(Some(PathBuf::from("arg")), some("rfs"), some("remote")),
+            PathBuf::from("arg")
+        );
+        assert_eq!(
+            storage_root_from(None, some("rfs"), some("remote")),
+            PathBuf::from("rfs")
+        );
+        assert_eq!(
+            storage_root_from(None, None, some("remote")),
+            PathBuf::from("remote")
+        );
+        assert_eq!(
+            storage_root_from(None, None, None),
+            PathBuf::from(DEFAULT_STORAGE_ROOT)
+        );
+    }
+
+    #[test]
+    fn tokens_come_from_rfs_token_ahead_of_remote_fs_token() {
+        let some = |token: &str| Some(token.to_string());
+        assert_eq!(
+            auth_token_from(some("first"), some("second")).as_deref(),
+            Some("first")
+        );
+        assert_eq!(
+            auth_token_from(some("  "), some(" second\n")).as_deref(),
+            Some("second")
+        );
+        assert_eq!(
+            auth_token_from(None, some("second")).as_deref(),
+            Some("second")
+        );
+        assert_eq!(auth_token_from(some(""), None), None);
+        assert_eq!(auth_token_from(None, None), None);
+    }
+
+    #[test]
+    fn shutdown_timeouts_are_whole_seconds() {
+        assert_eq!(
+            parse_shutdown_timeout(None).unwrap(),
+            Duration::from_secs(30)
+        );
+        assert_eq!(
+            parse_shutdown_timeout(Some("5")).unwrap(),
+            Duration::from_secs(5)
+        );
+        assert!(parse_shutdown_timeout(Some("1.5")).is_err());
+    }
+
+    #[test]
+    fn max_file_sizes_are_positive_byte_counts() {
+        assert_eq!(parse_max_file_size(None).unwrap(), None);
+        assert_eq!(parse_max_file_size(Some("1048576")).unwrap(), Some(1048576));
+        assert!(parse_max_file_size(Some("0")).is_err());
+        assert!(parse_max_file_size(Some("1M")).is_err());
+    }
+}
@@ -1,9 +1,18 @@
 use axum::{
-    http::StatusCode,
+    body::to_bytes,
+    http::{
+        header::{CONTENT_LENGTH, CONTENT_TYPE},
+        StatusCode,
+    },
     response::{IntoResponse, Response},
+    Json,
 };
+use remote_fs_protocol::{error_codes, ErrorBody};
 use std::io;
 
+// Plain-text error bodies longer than this are cut short when wrapped in JSON.
+const MAX_TEXT_ERROR_BYTES: usize = 4096;
+
 #[derive(Debug)]
 pub(crate) enum StorageError {
     BadRequest(&'static str),
@@ -11,7 +20,10 @@ pub(crate) enum StorageError {
     Forbidden(&'static str),
     Conflict(&'static str),
     PreconditionFailed(&'static str),
+    RangeNotSatisfiable(&'static str),
     RequestBody(&'static str),
+    PayloadTooLarge(&'static str),
+    InsufficientStorage(&'static str),
     Io(io::Error),
 }
 
@@ -25,28 +37,140 @@ impl StorageError {
             | io::ErrorKind::NotADirectory
             | io::ErrorKind::IsADirectory => StorageError::BadRequest("Invalid path"),
             io::ErrorKind::DirectoryNotEmpty => StorageError::Conflict("Directory not empty"),
+            io::ErrorKind::FileTooLarge => StorageError::PayloadTooLarge("File too large"),
+            io::ErrorKind::StorageFull => {
+                StorageError::InsufficientStorage("No space left on device")
+            }
+            io::ErrorKind::QuotaExceeded => {
+                StorageError::InsufficientStorage("Disk quota exceeded")
+            }
             _ => StorageError::Io(error),
         }
     }
 }
 
+impl StorageError {
+    // The `error` code of the response, telling apart failures that share a
+    // status, such as a body that broke off and one that was malformed.
+    fn code(&self) -> &'static str {
+        match self {
+            StorageError::BadRequest(_) => error_codes::BAD_REQUEST,
+            StorageError::RequestBody(_) => error_codes::INVALID_BODY,
+            StorageError::NotFound(_) => error_codes::NOT_FOUND,
+            StorageError::Forbidden(_) => error_codes::FORBIDDEN,
+            StorageError::Conflict(_) => error_codes::CONFLICT,
+            StorageError::PreconditionFailed(_) => error_codes::PRECONDITION_FAILED,
+            StorageError::RangeNotSatisfiable(_) => error_codes::RANGE_NOT_SATISFIABLE,
+            StorageError::PayloadTooLarge(_) => error_codes::FILE_TOO_LARGE,
+            StorageError::InsufficientStorage(_) => error_codes::INSUFFICIENT_STORAGE,
+            StorageError::Io(_) => error_codes::STORAGE_ERROR,
+        }
+    }
+}
+
 impl IntoResponse for StorageError {
     fn into_response(self) -> Response {
-        match self {
-            StorageError::BadRequest(message) => (StatusCode::BAD_REQUEST, message).into_response(),
-            StorageError::NotFound(message) => (StatusCode::NOT_FOUND, message).into_response(),
-            StorageError::Forbidden(message) => (StatusCode::FORBIDDEN, message).into_response(),
-            StorageError::Conflict(message) => (StatusCode::CONFLICT, message).into_response(),
-            StorageError::PreconditionFailed(message) => {
-                (StatusCode::PRECONDITION_FAILED, message).into_response()
+        let code = self.code();
+        let (status, message) = match self {
+            StorageError::BadRequest(message) | StorageError::RequestBody(message) => {
+                (StatusCode::BAD_REQUEST, message)
+            }
+            StorageError::NotFound(message) => (StatusCode::NOT_FOUND, message),
+            StorageError::Forbidden(message) => (StatusCode::FORBIDDEN, message),
+            StorageError::Conflict(message) => (StatusCode::CONFLICT, message),
+            StorageError::PreconditionFailed(message) => (StatusCode::PRECONDITION_FAILED, message),
+            StorageError::RangeNotSatisfiable(message) => {
+                (StatusCode::RANGE_NOT_SATISFIABLE, message)
             }
-            StorageError::RequestBody(message) => {
-                (StatusCode::BAD_REQUEST, message).into_response()
+            StorageError::PayloadTooLarge(message) => (StatusCode::PAYLOAD_TOO_LARGE, message),
+            StorageError::InsufficientStorage(message) => {
+                (StatusCode::INSUFFICIENT_STORAGE, message)
             }
             StorageError::Io(error) => {
                 log::error!("Storage error: {error}");
-                (StatusCode::INTERNAL_SERVER_ERROR, "Storage error").into_response()
+                (StatusCode::INTERNAL_SERVER_ERROR, "Storage error")
             }
-        }
+        };
+        coded_error_response(status, code, message)
+    }
+}
+
+// An error response whose code is the status's own, for rejections that
+// have nothing more specific to say.
+pub(crate) fn error_response(status: StatusCode, message: impl Into<String>) -> Response {
+    coded_error_response(status, &status_code(status), message)
+}
+
+pub(crate) fn coded_error_response(
+    status: StatusCode,
+    code: &str,
+    message: impl Into<String>,
+) -> Response {
+    let body = ErrorBody {
+        error: code.to_string(),
+        message: message.into(),
+    };
+    (status, Json(body)).into_response()
+}
+
+fn status_code(status: StatusCode) -> String {
+    status
+        .canonical_reason()
+        .unwrap_or("error")
+        .to_ascii_lowercase()
+        .replace([' ', '-'], "_")
+}
+
+// Rejections made before a handler runs, such as a malformed query or JSON
+// body, and unknown routes answer in plain text or not at all; this gives
+// them the same JSON body as handler errors, keeping their other headers.
+pub(crate) async fn json_error_bodies(response: Response) -> Response {
+    let status = response.status();
+    let is_json = response
+        .headers()
+        .get(CONTENT_TYPE)
+        .is_some_and(|value| value.as_bytes().starts_with(b"application/json"));
+    if !(status.is_client_error() || status.is_server_error()) || is_json {
+        return response;
+    }
+
+    let (mut parts, body) = response.into_parts();
+    let text = match to_bytes(body, MAX_TEXT_ERROR_BYTES).await {
+        Ok(bytes) => String::from_utf8_lossy(&bytes).trim().to_string(),
+        Err(_) => String::new(),
+    };
+    let message = if text.is_empty() {
+        status.canonical_reason().unwrap_or_default().to_string()
+    } else {
+        text
+    };
+    parts.headers.remove(CONTENT_TYPE);
+    parts.headers.remove(CONTENT_LENGTH);
+    let mut wrapped = error_response(status, message);
+    wrapped.headers_mut().extend(parts.headers);
+    wrapped
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn storage_errors_pick_their_own_codes_over_the_status() {
+        let code = |error: StorageError| error.code();
+        assert_eq!(code(StorageError::BadRequest("bad")), "bad_request");
+        assert_eq!(code(StorageError::RequestBody("cut")), "invalid_body");
+        assert_eq!(code(StorageError::PayloadTooLarge("big")), "file_too_large");
+        assert_eq!(
+            code(StorageError::Io(io::Error::other("disk"))),
+            "storage_error"
+        );
+
+        // Rejections without a variant fall back to the reason phrase.
+        assert_eq!(
+            status_code(StatusCode::METHOD_NOT_ALLOWED),
+            "method_not_allowed"
+        );
+        assert_eq!(status_code(StatusCode::from_u16(599).unwrap()), "error");
     }
 }
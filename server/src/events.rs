@@ -0,0 +1,44 @@
+use crate::AppState;
+use axum::{
+    extract::State,
+    response::sse::{Event, KeepAlive, Sse},
+};
+use futures_util::{stream, Stream, StreamExt};
+use std::{convert::Infallible, sync::Arc};
+use tokio::sync::broadcast::error::RecvError;
+
+// Changes buffered per subscriber before a slow client is told to reset its
+// whole cache instead.
+pub(crate) const CHANGE_FEED_CAPACITY: usize = 1024;
+
+impl AppState {
+    // Announces that the entry at `path` changed. Nobody may be listening.
+    pub(crate) fn publish_change(&self, path: &str) {
+        self.listings.invalidate(path);
+        self.names.invalidate(path);
+        let _ = self.changes.send(path.trim_matches('/').to_string());
+    }
+}
+
+// `GET /events`: a Server-Sent Events stream with one `changed` event per
+// mutated path, or `reset` when this subscriber fell behind and missed some.
+// The stream ends when the server starts shutting down.
+pub(crate) async fn watch_changes(
+    State(state): State<Arc<AppState>>,
+) -> Sse<impl Stream<Item = Result<Event, Infallible>>> {
+    let events = stream::unfold(state.changes.subscribe(), |mut changes| async move {
+        let event = match changes.recv().await {
+            Ok(path) => Event::default().event("changed").data(format!("/{path}")),
+            Err(RecvError::Lagged(missed)) => {
+                log::warn!("Change subscriber missed {missed} events; asking it to reset");
+                Event::default().event("reset").data("/")
+            }
+            Err(RecvError::Closed) => return None,
+        };
+        Some((Ok(event), changes))
+    })
+    .take_until(state.shutdown.clone().cancelled_owned());
+
+    log::info!("Change subscriber connected");
+    Sse::new(events).keep_alive(KeepAlive::default())
+}
@@ -1,22 +1,36 @@
 use crate::{
-    error::StorageError,
+    error::{error_response, StorageError},
+    listing_snapshots::ListingSnapshots,
     metadata::{
-        apply_metadata_headers, directory_entry_from_metadata, entry_metadata_for_path,
-        parse_optional_u64_header,
+        apply_metadata_headers, apply_mode, directory_entry_from_metadata, entry_metadata_for_path,
+        entry_metadata_from_metadata, entry_type, filesystem_stats, is_fifo, name_encoding,
+        parse_optional_u64_header, wire_name,
     },
-    transaction::create_file_atomically,
-    AppState, INTERNAL_DIR_NAME,
+    transaction::{self, create_file_atomically},
+    xattr, AppState, INTERNAL_DIR_NAME,
 };
 use axum::{
-    body::Body,
-    extract::{Path as AxumPath, State},
-    http::{header::IF_NONE_MATCH, HeaderMap, StatusCode},
+    body::{Body, Bytes},
+    extract::{Path as AxumPath, Query, State},
+    http::{
+        header::{
+            ACCEPT_RANGES, CONTENT_LENGTH, CONTENT_RANGE, CONTENT_TYPE, ETAG, IF_MATCH,
+            IF_NONE_MATCH, RANGE,
+        },
+        HeaderMap, StatusCode,
+    },
     response::{IntoResponse, Response},
     Json,
 };
 use futures_util::StreamExt;
-use remote_fs_protocol::{headers, DirectoryEntry, RemoteMetadata, RenameRequest};
-use std::{io, io::SeekFrom, sync::Arc};
+use remote_fs_protocol::{
+    headers, CopyRequest, CopyResult, DirectoryEntry, EntryType, FallocateRequest, FilesystemStats,
+    LinkRequest, MknodRequest, NameEntry, RemoteMetadata, RenameRequest, SeekQuery, SeekResult,
+    SeekWhence, SymlinkRequest, TempWriteHandle, TempWriteRequest, MAX_CHECKSUMMED_READ,
+};
+use serde::Deserialize;
+use sha2::{Digest, Sha256};
+use std::{collections::VecDeque, future::Future, io, io::SeekFrom, path::Path, sync::Arc};
 use tokio::{
     fs::{self, OpenOptions},
     io::{AsyncReadExt, AsyncSeekExt, AsyncWriteExt},
@@ -24,8 +38,26 @@ use tokio::{
 use tokio_util::io::ReaderStream;
 
 const TRANSFER_BUFFER_SIZE: usize = 4 * 1024 * 1024;
+// Deeper `?depth=` requests are clamped so one listing cannot walk an
+// arbitrarily large tree.
+const MAX_LIST_DEPTH: u32 = 8;
+// `S_IFMT` node type bits carried in `MknodRequest::mode`.
+const NODE_TYPE_MASK: u32 = 0o170000;
+const REGULAR_NODE: u32 = 0o100000;
+const FIFO_NODE: u32 = 0o010000;
 
-async fn list_entries(state: &AppState, path: &str) -> Result<Vec<DirectoryEntry>, StorageError> {
+// Reads the directory at `path`, skipping the server's internal directory in
+// the root, and keeps what `entry` makes of each child, sorted by name.
+async fn read_directory<T, F, Fut>(
+    state: &AppState,
+    path: &str,
+    name_of: fn(&T) -> &str,
+    mut entry: F,
+) -> Result<Vec<T>, StorageError>
+where
+    F: FnMut(String, fs::DirEntry) -> Fut,
+    Fut: Future<Output = Result<Option<T>, StorageError>>,
+{
     let directory_path = state.resolve_path(path)?;
     let metadata = fs::metadata(&directory_path)
         .await
@@ -40,26 +72,46 @@ async fn list_entries(state: &AppState, path: &str) -> Result<Vec<DirectoryEntry
         .map_err(|error| StorageError::from_io(error, "Directory not found"))?;
     let mut entries = Vec::new();
 
-    while let Some(entry) = read_dir
+    while let Some(child) = read_dir
         .next_entry()
         .await
         .map_err(|error| StorageError::from_io(error, "Could not read directory"))?
     {
-        let name = entry.file_name().to_string_lossy().to_string();
+        let name = wire_name(&child.file_name());
         if path.trim_matches('/').is_empty() && name == INTERNAL_DIR_NAME {
             continue;
         }
-        let metadata = entry
-            .metadata()
-            .await
-            .map_err(|error| StorageError::from_io(error, "Could not read entry"))?;
-
-        if let Some(directory_entry) = directory_entry_from_metadata(name, metadata) {
-            entries.push(directory_entry);
-        }
+        entries.extend(entry(name, child).await?);
     }
 
-    entries.sort_by(|left, right| left.name.cmp(&right.name));
+    entries.sort_by(|left, right| name_of(left).cmp(name_of(right)));
+    Ok(entries)
+}
+
+async fn list_entries(state: &AppState, path: &str) -> Result<Vec<DirectoryEntry>, StorageError> {
+    let entries = read_directory(
+        state,
+        path,
+        |entry: &DirectoryEntry| &entry.name,
+        |name, child| async move {
+            let metadata = child
+                .metadata()
+                .await
+                .map_err(|error| StorageError::from_io(error, "Could not read entry"))?;
+
+            let is_symlink = metadata.is_symlink();
+            let mut directory_entry = directory_entry_from_metadata(name, metadata);
+            if let Some(directory_entry) = directory_entry.as_mut().filter(|_| is_symlink) {
+                directory_entry.target = fs::read_link(child.path())
+                    .await
+                    .ok()
+                    .map(|target| target.to_string_lossy().into_owned());
+            }
+            Ok(directory_entry)
+        },
+    )
+    .await?;
+
     log::info!(
         "Listed /{} ({} entries)",
         path.trim_matches('/'),
@@ -68,6 +120,34 @@ async fn list_entries(state: &AppState, path: &str) -> Result<Vec<DirectoryEntry
     Ok(entries)
 }
 
+// Names and types as `readdir` reports them, so no entry is `stat`ed.
+async fn list_names(state: &AppState, path: &str) -> Result<Vec<NameEntry>, StorageError> {
+    let entries = read_directory(
+        state,
+        path,
+        |entry: &NameEntry| &entry.name,
+        |name, child| async move {
+            let file_type = child
+                .file_type()
+                .await
+                .map_err(|error| StorageError::from_io(error, "Could not read entry"))?;
+            Ok(entry_type(file_type).map(|type_| NameEntry {
+                name_encoding: name_encoding(&name),
+                name,
+                type_,
+            }))
+        },
+    )
+    .await?;
+
+    log::info!(
+        "Listed names in /{} ({} entries)",
+        path.trim_matches('/'),
+        entries.len()
+    );
+    Ok(entries)
+}
+
 pub(crate) async fn make_directory(
     AxumPath(path): AxumPath<String>,
     headers_map: HeaderMap,
@@ -81,11 +161,149 @@ pub(crate) async fn make_directory(
 
     apply_metadata_headers(&directory_path, &headers_map).await?;
     let metadata = entry_metadata_for_path(&directory_path).await?;
+    state.publish_change(&path);
     log::info!("Created directory /{}", path.trim_matches('/'));
 
     Ok((StatusCode::CREATED, Json(metadata)))
 }
 
+/// Outcome of a single-range `Range: bytes=...` request header.
+#[derive(Debug, PartialEq, Eq)]
+enum ByteRange {
+    Satisfiable { start: u64, end: u64 },
+    Unsatisfiable,
+}
+
+/// Parse one RFC 9110 byte range against the current file length. Multiple or
+/// malformed ranges return `None` so the caller can ignore the header.
+fn parse_byte_range(value: &str, file_size: u64) -> Option<ByteRange> {
+    let spec = value.trim().strip_prefix("bytes=")?.trim();
+    if spec.contains(',') {
+        return None;
+    }
+    let (first, last) = spec.split_once('-')?;
+    let (first, last) = (first.trim(), last.trim());
+
+    if first.is_empty() {
+        let suffix = last.parse::<u64>().ok()?;
+        if suffix == 0 || file_size == 0 {
+            return Some(ByteRange::Unsatisfiable);
+        }
+        return Some(ByteRange::Satisfiable {
+            start: file_size.saturating_sub(suffix),
+            end: file_size - 1,
+        });
+    }
+
+    let start = first.parse::<u64>().ok()?;
+    let end = if last.is_empty() {
+        u64::MAX
+    } else {
+        last.parse::<u64>().ok()?
+    };
+    if end < start {
+        return None;
+    }
+    if start >= file_size {
+        return Some(ByteRange::Unsatisfiable);
+    }
+    Some(ByteRange::Satisfiable {
+        start,
+        end: end.min(file_size - 1),
+    })
+}
+
+/// Parse a `Content-Range: bytes start-end/total` request header into the
+/// inclusive range of the file the body covers; the total may be `*`.
+fn parse_content_range(value: &str) -> Option<(u64, u64)> {
+    let (range, total) = value.trim().strip_prefix("bytes ")?.split_once('/')?;
+    let (start, end) = range.trim().split_once('-')?;
+    let start = start.trim().parse::<u64>().ok()?;
+    let end = end.trim().parse::<u64>().ok()?;
+    let total = total.trim();
+    if end < start || (total != "*" && total.parse::<u64>().ok()? <= end) {
+        return None;
+    }
+    Some((start, end))
+}
+
+// Where a `PUT /files` body goes: `X-File-Offset`, or the start of a
+// `Content-Range`. A range must agree with the offset header and with the
+// body's `Content-Length` when either is sent.
+fn write_offset(headers_map: &HeaderMap) -> Result<u64, StorageError> {
+    let offset = parse_optional_u64_header(headers_map, headers::FILE_OFFSET)?;
+    let Some(value) = headers_map.get(CONTENT_RANGE) else {
+        return Ok(offset.unwrap_or(0));
+    };
+    let (start, end) = value
+        .to_str()
+        .ok()
+        .and_then(parse_content_range)
+        .ok_or(StorageError::BadRequest("Invalid Content-Range header"))?;
+    if offset.is_some_and(|offset| offset != start) {
+        return Err(StorageError::BadRequest(
+            "Content-Range does not start at X-File-Offset",
+        ));
+    }
+    if parse_optional_u64_header(headers_map, CONTENT_LENGTH.as_str())?
+        .is_some_and(|length| length != end - start + 1)
+    {
+        return Err(StorageError::BadRequest(
+            "Content-Range does not match the body length",
+        ));
+    }
+    Ok(start)
+}
+
+// `NO_ATIME` reads use `O_NOATIME` on Linux. Only the file's owner or a
+// privileged server may, so other files are read with a normal open.
+async fn open_for_read(path: &Path, no_atime: bool) -> io::Result<fs::File> {
+    #[cfg(target_os = "linux")]
+    if no_atime {
+        match OpenOptions::new()
+            .read(true)
+            .custom_flags(libc::O_NOATIME)
+            .open(path)
+            .await
+        {
+            Err(error) if error.raw_os_error() == Some(libc::EPERM) => {}
+            result => return result,
+        }
+    }
+    #[cfg(not(target_os = "linux"))]
+    let _ = no_atime;
+    fs::File::open(path).await
+}
+
+// Tags a file's size and modification time, so a reader fetching it in
+// several requests can ask with `If-Match` that it has not changed since the
+// first one.
+fn file_etag(metadata: &std::fs::Metadata) -> String {
+    let modified = metadata
+        .modified()
+        .ok()
+        .and_then(|time| time.duration_since(std::time::UNIX_EPOCH).ok())
+        .unwrap_or_default();
+    format!(
+        "\"{:x}-{:x}.{:x}\"",
+        metadata.len(),
+        modified.as_secs(),
+        modified.subsec_nanos()
+    )
+}
+
+fn if_match_fails(request_headers: &HeaderMap, etag: &str) -> bool {
+    let mut candidates = request_headers
+        .get_all(IF_MATCH)
+        .iter()
+        .filter_map(|value| value.to_str().ok())
+        .flat_map(|value| value.split(','))
+        .map(str::trim)
+        .peekable();
+    candidates.peek().is_some()
+        && !candidates.any(|candidate| candidate == "*" || candidate == etag)
+}
+
 pub(crate) async fn get_file(
     AxumPath(path): AxumPath<String>,
     headers_map: HeaderMap,
@@ -100,26 +318,64 @@ pub(crate) async fn get_file(
         return Err(StorageError::BadRequest("Path is not a file"));
     }
 
-    let offset = parse_optional_u64_header(&headers_map, headers::FILE_OFFSET)?.unwrap_or(0);
-    let requested_size = parse_optional_u64_header(&headers_map, headers::FILE_SIZE)?;
+    let file_size = metadata.len();
+    let etag = file_etag(&metadata);
+    if if_match_fails(&headers_map, &etag) {
+        return Err(StorageError::PreconditionFailed(
+            "File changed since it was tagged",
+        ));
+    }
+    let uses_protocol_range = headers_map.contains_key(headers::FILE_OFFSET)
+        || headers_map.contains_key(headers::FILE_SIZE);
+    let byte_range = if uses_protocol_range {
+        None
+    } else {
+        headers_map
+            .get(RANGE)
+            .and_then(|value| value.to_str().ok())
+            .and_then(|value| parse_byte_range(value, file_size))
+    };
+
+    let (status, offset, requested_size) = match byte_range {
+        Some(ByteRange::Unsatisfiable) => {
+            return Ok((
+                StatusCode::RANGE_NOT_SATISFIABLE,
+                [(CONTENT_RANGE, format!("bytes */{file_size}"))],
+            )
+                .into_response());
+        }
+        Some(ByteRange::Satisfiable { start, end }) => {
+            (StatusCode::PARTIAL_CONTENT, start, Some(end - start + 1))
+        }
+        None => (
+            StatusCode::OK,
+            parse_optional_u64_header(&headers_map, headers::FILE_OFFSET)?.unwrap_or(0),
+            parse_optional_u64_header(&headers_map, headers::FILE_SIZE)?,
+        ),
+    };
 
-    if offset >= metadata.len() {
+    if offset >= file_size {
         log::info!(
             "Read /{} (offset: {}, bytes: 0)",
             path.trim_matches('/'),
             offset
         );
-        return Ok((StatusCode::OK, Body::empty()).into_response());
+        return Ok((
+            StatusCode::OK,
+            [(ACCEPT_RANGES, "bytes".to_string()), (ETAG, etag)],
+            Body::empty(),
+        )
+            .into_response());
     }
 
-    let mut file = fs::File::open(&file_path)
+    let mut file = open_for_read(&file_path, headers_map.contains_key(headers::NO_ATIME))
         .await
         .map_err(|error| StorageError::from_io(error, "File not found"))?;
     file.seek(SeekFrom::Start(offset))
         .await
         .map_err(|error| StorageError::from_io(error, "Could not seek file"))?;
 
-    let remaining_size = metadata.len() - offset;
+    let remaining_size = file_size - offset;
     let response_size = requested_size
         .map(|size| size.min(remaining_size))
         .unwrap_or(remaining_size);
@@ -129,9 +385,55 @@ pub(crate) async fn get_file(
         offset,
         response_size
     );
-    let stream = ReaderStream::with_capacity(file.take(response_size), TRANSFER_BUFFER_SIZE);
+    let (body, checksum) = if headers_map.contains_key(headers::WANT_CONTENT_SHA256)
+        // Checksummed reads are hashed in memory before the headers go out.
+        && response_size <= MAX_CHECKSUMMED_READ
+    {
+        let mut bytes = Vec::with_capacity(response_size as usize);
+        file.take(response_size)
+            .read_to_end(&mut bytes)
+            .await
+            .map_err(|error| StorageError::from_io(error, "File not found"))?;
+        let checksum = sha256_hex(&bytes);
+        (Body::from(bytes), Some(checksum))
+    } else {
+        let stream = ReaderStream::with_capacity(file.take(response_size), TRANSFER_BUFFER_SIZE);
+        (Body::from_stream(stream), None)
+    };
+    let mut response = (
+        status,
+        [
+            (ACCEPT_RANGES, "bytes".to_string()),
+            (CONTENT_LENGTH, response_size.to_string()),
+            (ETAG, etag),
+        ],
+        body,
+    )
+        .into_response();
+    if let Some(checksum) = checksum {
+        response.headers_mut().insert(
+            headers::CONTENT_SHA256,
+            checksum
+                .parse()
+                .expect("hex digests are valid header values"),
+        );
+    }
+    if status == StatusCode::PARTIAL_CONTENT {
+        let content_range = format!(
+            "bytes {}-{}/{}",
+            offset,
+            offset + response_size - 1,
+            file_size
+        );
+        response.headers_mut().insert(
+            CONTENT_RANGE,
+            content_range
+                .parse()
+                .expect("formatted content range is a valid header value"),
+        );
+    }
 
-    Ok((StatusCode::OK, Body::from_stream(stream)).into_response())
+    Ok(response)
 }
 
 pub(crate) async fn write_file(
@@ -141,8 +443,14 @@ pub(crate) async fn write_file(
     body: Body,
 ) -> Result<Response, StorageError> {
     let file_path = state.resolve_non_root_path(&path)?;
-    let offset = parse_optional_u64_header(&headers_map, headers::FILE_OFFSET)?.unwrap_or(0);
+    let offset = write_offset(&headers_map)?;
     let truncate_size = parse_optional_u64_header(&headers_map, headers::FILE_TRUNCATE)?;
+    if let Some(size) = truncate_size {
+        state.check_file_size(size)?;
+    }
+    if let Some(length) = parse_optional_u64_header(&headers_map, CONTENT_LENGTH.as_str())? {
+        state.check_file_size(offset.saturating_add(length))?;
+    }
 
     if headers_map
         .get(IF_NONE_MATCH)
@@ -162,6 +470,14 @@ pub(crate) async fn write_file(
         offset
     );
 
+    // Opening a FIFO would block until something reads the other end.
+    if fs::symlink_metadata(&file_path)
+        .await
+        .is_ok_and(|metadata| is_fifo(&metadata))
+    {
+        return Err(StorageError::BadRequest("Path is not a file"));
+    }
+
     if let Some(parent) = file_path.parent() {
         fs::create_dir_all(parent)
             .await
@@ -188,11 +504,14 @@ pub(crate) async fn write_file(
             .map_err(|error| StorageError::from_io(error, "Could not flush file"))?;
         drop(file);
         let metadata = entry_metadata_for_path(&file_path).await?;
+        state.publish_change(&path);
         log::info!("Resized /{} to {} bytes", path.trim_matches('/'), size);
         return Ok((StatusCode::OK, Json(metadata)).into_response());
     }
 
+    let mut bytes_received = 0;
     let mut bytes_written = 0;
+    let mut write_error = None;
     let mut write_buffer = Vec::with_capacity(TRANSFER_BUFFER_SIZE);
 
     file.seek(SeekFrom::Start(offset))
@@ -200,31 +519,69 @@ pub(crate) async fn write_file(
         .map_err(|error| StorageError::from_io(error, "Could not seek file"))?;
 
     while let Some(chunk) = body_stream.next().await {
-        let chunk = chunk.map_err(|_| StorageError::RequestBody("Could not read request body"))?;
-        bytes_written += chunk.len();
+        let mut chunk =
+            chunk.map_err(|_| StorageError::RequestBody("Could not read request body"))?;
+        let received_before = bytes_received as u64;
+        bytes_received += chunk.len();
+        // Bodies without a length are cut off where they cross the limit,
+        // keeping the part below it as a short write.
+        let over_limit = state
+            .check_file_size(offset.saturating_add(bytes_received as u64))
+            .is_err();
+        if over_limit {
+            let room = state
+                .max_file_size
+                .unwrap_or(u64::MAX)
+                .saturating_sub(offset)
+                .saturating_sub(received_before);
+            chunk.truncate(usize::try_from(room).unwrap_or(usize::MAX));
+        }
         if write_buffer.len() + chunk.len() > TRANSFER_BUFFER_SIZE && !write_buffer.is_empty() {
-            file.write_all(&write_buffer)
-                .await
-                .map_err(|error| StorageError::from_io(error, "Could not write file"))?;
+            if let Err(error) = write_counted(&mut file, &write_buffer, &mut bytes_written).await {
+                write_error = Some(error);
+                break;
+            }
             write_buffer.clear();
         }
 
         if chunk.len() >= TRANSFER_BUFFER_SIZE {
-            file.write_all(&chunk)
-                .await
-                .map_err(|error| StorageError::from_io(error, "Could not write file"))?;
+            if let Err(error) = write_counted(&mut file, &chunk, &mut bytes_written).await {
+                write_error = Some(error);
+                break;
+            }
         } else {
             write_buffer.extend_from_slice(&chunk);
         }
+        if over_limit {
+            write_error = Some(io::ErrorKind::FileTooLarge.into());
+            break;
+        }
     }
 
-    if !write_buffer.is_empty() {
-        file.write_all(&write_buffer)
-            .await
-            .map_err(|error| StorageError::from_io(error, "Could not write file"))?;
+    let below_limit = write_error
+        .as_ref()
+        .is_none_or(|error| error.kind() == io::ErrorKind::FileTooLarge);
+    if below_limit && !write_buffer.is_empty() {
+        if let Err(error) = write_counted(&mut file, &write_buffer, &mut bytes_written).await {
+            write_error = Some(error);
+        }
     }
 
-    if offset == 0 && bytes_written == 0 {
+    // Bytes stored before a failure such as a full disk are kept and reported
+    // as a short write; a write that stored nothing fails outright.
+    if let Some(error) = write_error {
+        if bytes_written == 0 {
+            return Err(StorageError::from_io(error, "Could not write file"));
+        }
+        log::warn!(
+            "Short write to /{}: stored {} of the bytes at offset {}: {error}",
+            path.trim_matches('/'),
+            bytes_written,
+            offset
+        );
+    }
+
+    if offset == 0 && bytes_received == 0 {
         file.set_len(0)
             .await
             .map_err(|error| StorageError::from_io(error, "Could not truncate file"))?;
@@ -234,6 +591,7 @@ pub(crate) async fn write_file(
             .map_err(|error| StorageError::from_io(error, "Could not flush file"))?;
         drop(file);
         let metadata = entry_metadata_for_path(&file_path).await?;
+        state.publish_change(&path);
         log::info!("Created or truncated /{}", path.trim_matches('/'));
         return Ok((StatusCode::OK, Json(metadata)).into_response());
     }
@@ -244,6 +602,7 @@ pub(crate) async fn write_file(
         .map_err(|error| StorageError::from_io(error, "Could not flush file"))?;
     drop(file);
     let metadata = entry_metadata_for_path(&file_path).await?;
+    state.publish_change(&path);
     log::info!(
         "Wrote /{} (offset: {}, bytes: {})",
         path.trim_matches('/'),
@@ -251,7 +610,22 @@ pub(crate) async fn write_file(
         bytes_written
     );
 
-    Ok((StatusCode::OK, Json(metadata)).into_response())
+    Ok((
+        StatusCode::OK,
+        [(headers::BYTES_WRITTEN, bytes_written.to_string())],
+        Json(metadata),
+    )
+        .into_response())
+}
+
+// Counts `bytes` into `written` only once the file has taken all of them, so
+// a failed write never overstates what was stored.
+async fn write_counted(file: &mut fs::File, bytes: &[u8], written: &mut u64) -> io::Result<()> {
+    file.write_all(bytes).await?;
+    // Tokio reports a background write's failure on the next operation.
+    file.flush().await?;
+    *written += bytes.len() as u64;
+    Ok(())
 }
 
 pub(crate) async fn get_metadata(
@@ -264,6 +638,72 @@ pub(crate) async fn get_metadata(
     Ok(Json(metadata))
 }
 
+// One entry in the shape a listing would report it, so clients can revalidate
+// a single cached inode without re-reading its parent directory.
+pub(crate) async fn stat_root(
+    State(state): State<Arc<AppState>>,
+) -> Result<Json<DirectoryEntry>, StorageError> {
+    let metadata = fs::metadata(&state.root_dir)
+        .await
+        .map_err(|error| StorageError::from_io(error, "Path not found"))?;
+    directory_entry_from_metadata(String::new(), metadata)
+        .map(Json)
+        .ok_or(StorageError::NotFound("Path not found"))
+}
+
+pub(crate) async fn stat_entry(
+    AxumPath(path): AxumPath<String>,
+    State(state): State<Arc<AppState>>,
+) -> Result<Json<DirectoryEntry>, StorageError> {
+    let entry_path = state.resolve_link_path(&path)?;
+    let metadata = fs::symlink_metadata(&entry_path)
+        .await
+        .map_err(|error| StorageError::from_io(error, "Path not found"))?;
+    let name = entry_path.file_name().map(wire_name).unwrap_or_default();
+
+    let is_symlink = metadata.is_symlink();
+    let mut entry = directory_entry_from_metadata(name, metadata)
+        .ok_or(StorageError::NotFound("Path not found"))?;
+    if is_symlink {
+        entry.target = fs::read_link(&entry_path)
+            .await
+            .ok()
+            .map(|target| target.to_string_lossy().into_owned());
+    }
+    Ok(Json(entry))
+}
+
+pub(crate) async fn get_statfs(
+    State(state): State<Arc<AppState>>,
+) -> Result<Json<FilesystemStats>, StorageError> {
+    Ok(Json(filesystem_stats(&state.root_dir)?))
+}
+
+// Liveness only: answering at all means the process is serving requests.
+pub(crate) async fn health() -> &'static str {
+    "ok"
+}
+
+// Ready once the storage root and transaction directory can be listed, so a
+// missing or unreadable mount behind the root is reported before clients
+// start failing.
+pub(crate) async fn readiness(State(state): State<Arc<AppState>>) -> Response {
+    let ready = fs::read_dir(&state.root_dir).await.is_ok()
+        && fs::read_dir(&state.transaction_dir).await.is_ok();
+    if ready {
+        (StatusCode::OK, "ready").into_response()
+    } else {
+        log::warn!(
+            "Readiness check failed: {} is not accessible",
+            state.root_dir.display()
+        );
+        error_response(
+            StatusCode::SERVICE_UNAVAILABLE,
+            "Storage root is not accessible",
+        )
+    }
+}
+
 pub(crate) async fn update_metadata(
     AxumPath(path): AxumPath<String>,
     headers_map: HeaderMap,
@@ -276,6 +716,7 @@ pub(crate) async fn update_metadata(
 
     apply_metadata_headers(&target_path, &headers_map).await?;
     let metadata = entry_metadata_for_path(&target_path).await?;
+    state.publish_change(&path);
     log::info!("Updated metadata for /{}", path.trim_matches('/'));
 
     Ok((StatusCode::OK, Json(metadata)))
@@ -285,18 +726,22 @@ pub(crate) async fn delete_path(
     AxumPath(path): AxumPath<String>,
     State(state): State<Arc<AppState>>,
 ) -> Result<impl IntoResponse, StorageError> {
-    let target_path = state.resolve_non_root_path(&path)?;
-    let metadata = fs::metadata(&target_path)
+    let target_path = state.resolve_link_path(&path)?;
+    let metadata = fs::symlink_metadata(&target_path)
         .await
         .map_err(|error| StorageError::from_io(error, "Path not found"))?;
 
-    if !metadata.is_file() {
+    if metadata.is_dir() {
+        return Err(StorageError::Conflict("Path is a directory"));
+    }
+    if !metadata.is_file() && !metadata.is_symlink() && !is_fifo(&metadata) {
         return Err(StorageError::BadRequest("Path is not a file"));
     }
 
     fs::remove_file(&target_path)
         .await
         .map_err(|error| StorageError::from_io(error, "Could not remove file"))?;
+    state.publish_change(&path);
     log::info!("Deleted file /{}", path.trim_matches('/'));
 
     Ok(StatusCode::NO_CONTENT)
@@ -318,6 +763,7 @@ pub(crate) async fn delete_directory(
     fs::remove_dir(&target_path)
         .await
         .map_err(|error| StorageError::from_io(error, "Could not remove directory"))?;
+    state.publish_change(&path);
     log::info!("Deleted directory /{}", path.trim_matches('/'));
 
     Ok(StatusCode::NO_CONTENT)
@@ -327,14 +773,14 @@ pub(crate) async fn rename_entry(
     State(state): State<Arc<AppState>>,
     Json(payload): Json<RenameRequest>,
 ) -> Result<impl IntoResponse, StorageError> {
-    let from_path = state.resolve_non_root_path(&payload.from)?;
-    let to_path = state.resolve_non_root_path(&payload.to)?;
+    let from_path = state.resolve_link_path(&payload.from)?;
+    let to_path = state.resolve_link_path(&payload.to)?;
 
     if from_path == to_path {
         return Ok(StatusCode::OK);
     }
 
-    let from_metadata = fs::metadata(&from_path)
+    let from_metadata = fs::symlink_metadata(&from_path)
         .await
         .map_err(|error| StorageError::from_io(error, "Source path not found"))?;
 
@@ -366,6 +812,8 @@ pub(crate) async fn rename_entry(
     fs::rename(&from_path, &to_path)
         .await
         .map_err(|error| StorageError::from_io(error, "Could not rename path"))?;
+    state.publish_change(&payload.from);
+    state.publish_change(&payload.to);
     log::info!(
         "Renamed /{} to /{}",
         payload.from.trim_matches('/'),
@@ -375,15 +823,852 @@ pub(crate) async fn rename_entry(
     Ok(StatusCode::OK)
 }
 
+pub(crate) async fn begin_temp_write(
+    State(state): State<Arc<AppState>>,
+    Json(payload): Json<TempWriteRequest>,
+) -> Result<impl IntoResponse, StorageError> {
+    let handle = transaction::begin_temp_write(&state, &payload.path).await?;
+    log::debug!(
+        "Staging write {handle} for /{}",
+        payload.path.trim_matches('/')
+    );
+    Ok((StatusCode::CREATED, Json(TempWriteHandle { handle })))
+}
+
+pub(crate) async fn append_temp_write(
+    AxumPath(handle): AxumPath<String>,
+    headers_map: HeaderMap,
+    State(state): State<Arc<AppState>>,
+    body: Body,
+) -> Result<impl IntoResponse, StorageError> {
+    let offset = parse_optional_u64_header(&headers_map, headers::FILE_OFFSET)?;
+    let truncate = parse_optional_u64_header(&headers_map, headers::FILE_TRUNCATE)?;
+    transaction::append_temp_write(&state, &handle, offset, truncate, body).await?;
+    Ok(StatusCode::NO_CONTENT)
+}
+
+pub(crate) async fn commit_temp_write(
+    AxumPath(handle): AxumPath<String>,
+    State(state): State<Arc<AppState>>,
+) -> Result<Response, StorageError> {
+    transaction::commit_temp_write(&state, &handle).await
+}
+
+pub(crate) async fn abort_temp_write(
+    AxumPath(handle): AxumPath<String>,
+    State(state): State<Arc<AppState>>,
+) -> Result<impl IntoResponse, StorageError> {
+    transaction::abort_temp_write(&state, &handle).await?;
+    Ok(StatusCode::NO_CONTENT)
+}
+
+// Adds a hard link. The source may be a symbolic link, which is linked
+// itself rather than followed; directories cannot be linked.
+pub(crate) async fn link_entry(
+    State(state): State<Arc<AppState>>,
+    Json(payload): Json<LinkRequest>,
+) -> Result<impl IntoResponse, StorageError> {
+    let from_path = state.resolve_link_path(&payload.from)?;
+    let to_path = state.resolve_non_root_path(&payload.to)?;
+
+    let from_metadata = fs::symlink_metadata(&from_path)
+        .await
+        .map_err(|error| StorageError::from_io(error, "Source path not found"))?;
+    if from_metadata.is_dir() {
+        return Err(StorageError::BadRequest("Cannot hard link a directory"));
+    }
+
+    fs::hard_link(&from_path, &to_path)
+        .await
+        .map_err(|error| StorageError::from_io(error, "Parent directory not found"))?;
+    let metadata = fs::symlink_metadata(&to_path)
+        .await
+        .ok()
+        .and_then(|metadata| entry_metadata_from_metadata(&to_path, metadata))
+        .ok_or(StorageError::NotFound("Path not found"))?;
+    // The source's link count changed too.
+    state.publish_change(&payload.from);
+    state.publish_change(&payload.to);
+    log::info!(
+        "Linked /{} to /{}",
+        payload.to.trim_matches('/'),
+        payload.from.trim_matches('/')
+    );
+
+    Ok((StatusCode::CREATED, Json(metadata)))
+}
+
+pub(crate) async fn copy_range(
+    State(state): State<Arc<AppState>>,
+    Json(payload): Json<CopyRequest>,
+) -> Result<Json<CopyResult>, StorageError> {
+    let from_path = state.resolve_non_root_path(&payload.from)?;
+    let to_path = state.resolve_non_root_path(&payload.to)?;
+    let CopyRequest {
+        from_offset,
+        to_offset,
+        length,
+        ..
+    } = payload;
+    if from_path == to_path
+        && from_offset < to_offset.saturating_add(length)
+        && to_offset < from_offset.saturating_add(length)
+    {
+        return Err(StorageError::BadRequest(
+            "Source and destination ranges overlap",
+        ));
+    }
+    let mut source_size = 0;
+    for path in [&to_path, &from_path] {
+        let metadata = fs::symlink_metadata(path)
+            .await
+            .map_err(|error| StorageError::from_io(error, "Path not found"))?;
+        if !metadata.is_file() {
+            return Err(StorageError::BadRequest("Path is not a file"));
+        }
+        source_size = metadata.len();
+    }
+    // Only the bytes the source has count towards the destination's size.
+    let copied_length = length.min(source_size.saturating_sub(from_offset));
+    state.check_file_size(to_offset.saturating_add(copied_length))?;
+
+    let copied = tokio::task::spawn_blocking(move || {
+        use std::io::{Read, Seek};
+
+        let mut source = std::fs::File::open(&from_path)?;
+        let mut destination = std::fs::OpenOptions::new().write(true).open(&to_path)?;
+        source.seek(SeekFrom::Start(from_offset))?;
+        destination.seek(SeekFrom::Start(to_offset))?;
+        // Between two files `io::copy` uses copy_file_range(2) where the
+        // platform has it, so the bytes stay in the kernel and filesystems
+        // that can share extents do so.
+        let copied = io::copy(&mut source.take(length), &mut destination)?;
+        destination.sync_all()?;
+        Ok(copied)
+    })
+    .await
+    .map_err(|error| StorageError::Io(io::Error::other(error)))?
+    .map_err(|error| StorageError::from_io(error, "Path not found"))?;
+    state.publish_change(&payload.to);
+    log::info!(
+        "Copied {} bytes of /{} to /{}",
+        copied,
+        payload.from.trim_matches('/'),
+        payload.to.trim_matches('/')
+    );
+
+    Ok(Json(CopyResult { copied }))
+}
+
+pub(crate) async fn read_symlink(
+    AxumPath(path): AxumPath<String>,
+    State(state): State<Arc<AppState>>,
+) -> Result<String, StorageError> {
+    let link_path = state.resolve_link_path(&path)?;
+    let metadata = fs::symlink_metadata(&link_path)
+        .await
+        .map_err(|error| StorageError::from_io(error, "Path not found"))?;
+    if !metadata.is_symlink() {
+        return Err(StorageError::BadRequest("Path is not a symbolic link"));
+    }
+
+    let target = fs::read_link(&link_path)
+        .await
+        .map_err(|error| StorageError::from_io(error, "Path not found"))?;
+    Ok(target.to_string_lossy().into_owned())
+}
+
+// The link is stored verbatim and never followed by the server, so targets
+// outside the storage root only resolve on the client side.
+pub(crate) async fn create_symlink(
+    State(state): State<Arc<AppState>>,
+    Json(payload): Json<SymlinkRequest>,
+) -> Result<impl IntoResponse, StorageError> {
+    let link_path = state.resolve_non_root_path(&payload.path)?;
+    if payload.target.is_empty() || payload.target.contains('\0') {
+        return Err(StorageError::BadRequest("Invalid symlink target"));
+    }
+
+    symlink(&payload.target, &link_path)
+        .await
+        .map_err(|error| StorageError::from_io(error, "Parent directory not found"))?;
+    let metadata = fs::symlink_metadata(&link_path)
+        .await
+        .ok()
+        .and_then(|metadata| entry_metadata_from_metadata(&link_path, metadata))
+        .ok_or(StorageError::NotFound("Path not found"))?;
+    state.publish_change(&payload.path);
+    log::info!(
+        "Created symlink /{} -> {}",
+        payload.path.trim_matches('/'),
+        payload.target
+    );
+
+    Ok((StatusCode::CREATED, Json(metadata)))
+}
+
+#[cfg(unix)]
+async fn symlink(target: &str, link_path: &std::path::Path) -> io::Result<()> {
+    fs::symlink(target, link_path).await
+}
+
+#[cfg(not(unix))]
+async fn symlink(_target: &str, _link_path: &std::path::Path) -> io::Result<()> {
+    Err(io::Error::new(
+        io::ErrorKind::Unsupported,
+        "symbolic links are not supported by this server",
+    ))
+}
+
+// Creates an empty regular file or a FIFO. Device nodes need privileges the
+// server should not exercise on a client's behalf, so they are refused.
+pub(crate) async fn make_node(
+    State(state): State<Arc<AppState>>,
+    Json(payload): Json<MknodRequest>,
+) -> Result<impl IntoResponse, StorageError> {
+    let node_path = state.resolve_non_root_path(&payload.path)?;
+
+    match payload.mode & NODE_TYPE_MASK {
+        REGULAR_NODE => OpenOptions::new()
+            .write(true)
+            .create_new(true)
+            .open(&node_path)
+            .await
+            .map(drop),
+        FIFO_NODE => fifo(&node_path).await,
+        _ => return Err(StorageError::BadRequest("Unsupported node type")),
+    }
+    .map_err(|error| StorageError::from_io(error, "Parent directory not found"))?;
+    apply_mode(&node_path, payload.mode).await?;
+
+    let metadata = fs::symlink_metadata(&node_path)
+        .await
+        .ok()
+        .and_then(|metadata| entry_metadata_from_metadata(&node_path, metadata))
+        .ok_or(StorageError::NotFound("Path not found"))?;
+    state.publish_change(&payload.path);
+    log::info!(
+        "Created {} /{}",
+        metadata.type_,
+        payload.path.trim_matches('/')
+    );
+
+    Ok((StatusCode::CREATED, Json(metadata)))
+}
+
+#[cfg(unix)]
+async fn fifo(path: &std::path::Path) -> io::Result<()> {
+    use std::os::unix::ffi::OsStrExt;
+
+    let path = std::ffi::CString::new(path.as_os_str().as_bytes())
+        .map_err(|_| io::Error::from(io::ErrorKind::InvalidInput))?;
+    // The server's umask may strip bits here; `apply_mode` sets them after.
+    if unsafe { libc::mkfifo(path.as_ptr(), 0o600) } == 0 {
+        Ok(())
+    } else {
+        Err(io::Error::last_os_error())
+    }
+}
+
+#[cfg(not(unix))]
+async fn fifo(_path: &std::path::Path) -> io::Result<()> {
+    Err(io::Error::new(
+        io::ErrorKind::Unsupported,
+        "FIFOs are not supported by this server",
+    ))
+}
+
+pub(crate) async fn preallocate_file(
+    AxumPath(path): AxumPath<String>,
+    State(state): State<Arc<AppState>>,
+    Json(payload): Json<FallocateRequest>,
+) -> Result<Json<RemoteMetadata>, StorageError> {
+    let file_path = state.resolve_non_root_path(&path)?;
+    let end = payload
+        .offset
+        .checked_add(payload.length)
+        .filter(|end| i64::try_from(*end).is_ok())
+        .ok_or(StorageError::BadRequest("Range is too large"))?;
+    let metadata = fs::symlink_metadata(&file_path)
+        .await
+        .map_err(|error| StorageError::from_io(error, "Path not found"))?;
+    if !metadata.is_file() {
+        return Err(StorageError::BadRequest("Path is not a file"));
+    }
+    if !payload.keep_size {
+        state.check_file_size(end)?;
+    }
+
+    let blocking_path = file_path.clone();
+    let keep_size = payload.keep_size;
+    tokio::task::spawn_blocking(move || {
+        let file = std::fs::OpenOptions::new()
+            .write(true)
+            .open(&blocking_path)?;
+        preallocate(&file, payload.offset, payload.length, keep_size)?;
+        // Filesystems that cannot reserve space still get the requested size.
+        if !keep_size && file.metadata()?.len() < end {
+            file.set_len(end)?;
+        }
+        file.sync_all()
+    })
+    .await
+    .map_err(|error| StorageError::Io(io::Error::other(error)))?
+    .map_err(|error| StorageError::from_io(error, "Path not found"))?;
+
+    let metadata = entry_metadata_for_path(&file_path).await?;
+    state.publish_change(&path);
+    log::info!(
+        "Preallocated {} bytes at offset {} of /{}{}",
+        payload.length,
+        payload.offset,
+        path.trim_matches('/'),
+        if keep_size { " (keeping its size)" } else { "" }
+    );
+    Ok(Json(metadata))
+}
+
+// Reserves blocks with fallocate(2). Filesystems without it report success,
+// leaving the caller to fall back to `set_len`; a full disk is still an error.
+#[cfg(target_os = "linux")]
+fn preallocate(file: &std::fs::File, offset: u64, length: u64, keep_size: bool) -> io::Result<()> {
+    use std::os::fd::AsRawFd;
+
+    let mode = if keep_size {
+        libc::FALLOC_FL_KEEP_SIZE
+    } else {
+        0
+    };
+    if unsafe { libc::fallocate(file.as_raw_fd(), mode, offset as i64, length as i64) } == 0 {
+        return Ok(());
+    }
+    let error = io::Error::last_os_error();
+    match error.raw_os_error() {
+        Some(libc::EOPNOTSUPP) => Ok(()),
+        _ => Err(error),
+    }
+}
+
+#[cfg(not(target_os = "linux"))]
+fn preallocate(
+    _file: &std::fs::File,
+    _offset: u64,
+    _length: u64,
+    _keep_size: bool,
+) -> io::Result<()> {
+    Ok(())
+}
+
+pub(crate) async fn seek_file(
+    AxumPath(path): AxumPath<String>,
+    State(state): State<Arc<AppState>>,
+    Query(query): Query<SeekQuery>,
+) -> Result<Json<SeekResult>, StorageError> {
+    let file_path = state.resolve_non_root_path(&path)?;
+    let metadata = fs::symlink_metadata(&file_path)
+        .await
+        .map_err(|error| StorageError::from_io(error, "Path not found"))?;
+    if !metadata.is_file() {
+        return Err(StorageError::BadRequest("Path is not a file"));
+    }
+
+    let found = tokio::task::spawn_blocking(move || {
+        let file = std::fs::File::open(&file_path)?;
+        seek_sparse(&file, query.offset, query.whence)
+    })
+    .await
+    .map_err(|error| StorageError::Io(io::Error::other(error)))?
+    .map_err(|error| StorageError::from_io(error, "Path not found"))?;
+    match found {
+        Some(offset) => Ok(Json(SeekResult { offset })),
+        None => Err(StorageError::RangeNotSatisfiable(match query.whence {
+            SeekWhence::Data => "No data at or after offset",
+            SeekWhence::Hole => "Offset is past the end of the file",
+        })),
+    }
+}
+
+// Finds the next data or hole offset with lseek(2); `None` when there is
+// none, as at or past the end of the file. Filesystems without a sparse map
+// report the whole file as data.
+#[cfg(target_os = "linux")]
+fn seek_sparse(file: &std::fs::File, offset: u64, whence: SeekWhence) -> io::Result<Option<u64>> {
+    use std::os::fd::AsRawFd;
+
+    let Ok(start) = i64::try_from(offset) else {
+        return Ok(None);
+    };
+    let whence = match whence {
+        SeekWhence::Data => libc::SEEK_DATA,
+        SeekWhence::Hole => libc::SEEK_HOLE,
+    };
+    let found = unsafe { libc::lseek(file.as_raw_fd(), start, whence) };
+    if found >= 0 {
+        return Ok(Some(found as u64));
+    }
+    let error = io::Error::last_os_error();
+    match error.raw_os_error() {
+        Some(libc::ENXIO) => Ok(None),
+        _ => Err(error),
+    }
+}
+
+// Without a sparse map, the whole file is data and its end the only hole.
+#[cfg(not(target_os = "linux"))]
+fn seek_sparse(file: &std::fs::File, offset: u64, whence: SeekWhence) -> io::Result<Option<u64>> {
+    let size = file.metadata()?.len();
+    Ok((offset < size).then_some(match whence {
+        SeekWhence::Data => offset,
+        SeekWhence::Hole => size,
+    }))
+}
+
+#[derive(Deserialize)]
+pub(crate) struct XattrQuery {
+    name: Option<String>,
+    // `create` or `replace`; omitted means either is fine.
+    flags: Option<String>,
+}
+
+impl XattrQuery {
+    fn required_name(&self) -> Result<&str, StorageError> {
+        self.name
+            .as_deref()
+            .ok_or(StorageError::BadRequest("Missing attribute name"))
+    }
+}
+
+// Without `name`, lists the attribute names instead of reading one value.
+pub(crate) async fn get_xattr(
+    path: Option<AxumPath<String>>,
+    Query(query): Query<XattrQuery>,
+    State(state): State<Arc<AppState>>,
+) -> Result<Response, StorageError> {
+    let target_path = state.resolve_path(&path.map(|AxumPath(path)| path).unwrap_or_default())?;
+    match query.name.as_deref() {
+        Some(name) => Ok(xattr::get(&target_path, name)?.into_response()),
+        None => Ok(Json(xattr::list(&target_path)?).into_response()),
+    }
+}
+
+pub(crate) async fn put_xattr(
+    path: Option<AxumPath<String>>,
+    Query(query): Query<XattrQuery>,
+    State(state): State<Arc<AppState>>,
+    value: Bytes,
+) -> Result<StatusCode, StorageError> {
+    let path = path.map(|AxumPath(path)| path).unwrap_or_default();
+    let target_path = state.resolve_path(&path)?;
+    let mode = match query.flags.as_deref() {
+        None => xattr::SetMode::Upsert,
+        Some("create") => xattr::SetMode::Create,
+        Some("replace") => xattr::SetMode::Replace,
+        Some(_) => return Err(StorageError::BadRequest("Invalid attribute flags")),
+    };
+
+    let name = query.required_name()?;
+    xattr::set(&target_path, name, &value, mode)?;
+    state.publish_change(&path);
+    log::info!("Set attribute {name} on /{}", path.trim_matches('/'));
+    Ok(StatusCode::NO_CONTENT)
+}
+
+pub(crate) async fn delete_xattr(
+    path: Option<AxumPath<String>>,
+    Query(query): Query<XattrQuery>,
+    State(state): State<Arc<AppState>>,
+) -> Result<StatusCode, StorageError> {
+    let path = path.map(|AxumPath(path)| path).unwrap_or_default();
+    let target_path = state.resolve_path(&path)?;
+
+    let name = query.required_name()?;
+    xattr::remove(&target_path, name)?;
+    state.publish_change(&path);
+    log::info!("Removed attribute {name} from /{}", path.trim_matches('/'));
+    Ok(StatusCode::NO_CONTENT)
+}
+
+#[derive(Deserialize)]
+pub(crate) struct ListQuery {
+    // Levels of the subtree to include; 1 (the default) lists only children.
+    depth: Option<u32>,
+    // Resume after the entry a previous page's `X-Next-Cursor` named.
+    cursor: Option<String>,
+    limit: Option<usize>,
+    sort: Option<ListSort>,
+}
+
+// Orders a listing can be returned in, each ascending. Ties are broken by
+// name, so every order is the same from one request to the next and pages
+// resume where the previous one stopped.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+enum ListSort {
+    #[default]
+    Name,
+    Mtime,
+    Size,
+}
+
+impl ListSort {
+    // What entries are ordered by before their name; name order has none.
+    fn key(self, entry: &DirectoryEntry) -> u64 {
+        match self {
+            ListSort::Name => 0,
+            ListSort::Mtime => entry.modified_at.parse().unwrap_or(0),
+            ListSort::Size => entry.size,
+        }
+    }
+
+    // Cursors in name order are just the name; other orders put the key in
+    // front, as in `1024/a.txt`.
+    fn cursor_for(self, entry: &DirectoryEntry) -> String {
+        match self {
+            ListSort::Name => encode_cursor(&entry.name),
+            _ => encode_cursor(&format!("{}/{}", self.key(entry), entry.name)),
+        }
+    }
+
+    fn position_after(self, cursor: &str) -> Result<(u64, String), StorageError> {
+        let decoded = decode_cursor(cursor)?;
+        if self == ListSort::Name {
+            return Ok((0, decoded));
+        }
+        decoded
+            .split_once('/')
+            .and_then(|(key, name)| Some((key.parse().ok()?, name.to_string())))
+            .ok_or(StorageError::BadRequest("Invalid listing cursor"))
+    }
+}
+
+fn sha256_hex(bytes: &[u8]) -> String {
+    Sha256::digest(bytes)
+        .iter()
+        .map(|byte| format!("{byte:02x}"))
+        .collect()
+}
+
+// Cursors are the hex-encoded position of the last entry on a page: its name,
+// after its sort key when there is one. A page resumes strictly after it even
+// if entries were added or removed in between, and the header value stays
+// ASCII.
+fn encode_cursor(name: &str) -> String {
+    name.bytes().map(|byte| format!("{byte:02x}")).collect()
+}
+
+fn decode_cursor(cursor: &str) -> Result<String, StorageError> {
+    let invalid = || StorageError::BadRequest("Invalid listing cursor");
+    if !cursor.len().is_multiple_of(2) {
+        return Err(invalid());
+    }
+    let bytes = (0..cursor.len())
+        .step_by(2)
+        .map(|index| {
+            cursor
+                .get(index..index + 2)
+                .and_then(|pair| u8::from_str_radix(pair, 16).ok())
+                .ok_or_else(invalid)
+        })
+        .collect::<Result<Vec<u8>, _>>()?;
+    String::from_utf8(bytes).map_err(|_| invalid())
+}
+
+// Tags hash the exact response, so a change to any listed entry's name, type,
+// size, times or ownership yields a new one. FNV-1a keeps them stable across
+// server restarts.
+fn listing_etag(body: &[u8], next_cursor: Option<&str>) -> String {
+    let mut hash: u64 = 0xcbf2_9ce4_8422_2325;
+    let cursor = next_cursor.unwrap_or_default().as_bytes();
+    for byte in body.iter().chain([&0]).chain(cursor) {
+        hash ^= u64::from(*byte);
+        hash = hash.wrapping_mul(0x0000_0100_0000_01b3);
+    }
+    format!("\"{hash:016x}\"")
+}
+
+fn matches_etag(request_headers: &HeaderMap, etag: &str) -> bool {
+    request_headers
+        .get_all(IF_NONE_MATCH)
+        .iter()
+        .filter_map(|value| value.to_str().ok())
+        .flat_map(|value| value.split(','))
+        .any(|candidate| {
+            let candidate = candidate.trim();
+            candidate == "*" || candidate.trim_start_matches("W/") == etag
+        })
+}
+
+// One page of a listing, shared by `GET /list` and `GET /names`.
+struct ListingPage<T> {
+    entries: Vec<T>,
+    headers: HeaderMap,
+    next_cursor: Option<String>,
+}
+
+// Serves the page after `cursor` from the snapshot that page's request left,
+// or from `list_after`, which lists the entries after `cursor` in page order.
+// `scope` tells apart listings of `path` in different orders or depths.
+async fn listing_page<T, Fut>(
+    snapshots: &ListingSnapshots<T>,
+    path: &str,
+    scope: String,
+    cursor: Option<&str>,
+    limit: Option<usize>,
+    cursor_for: impl Fn(&T) -> String,
+    list_after: impl FnOnce() -> Fut,
+) -> Result<ListingPage<T>, StorageError>
+where
+    Fut: Future<Output = Result<Vec<T>, StorageError>>,
+{
+    if limit == Some(0) {
+        return Err(StorageError::BadRequest("Limit must be at least 1"));
+    }
+    let snapshot_key = |cursor: &str| format!("{}\n{scope}\n{cursor}", path.trim_matches('/'));
+    let snapshot = cursor.and_then(|cursor| snapshots.take(&snapshot_key(cursor)));
+    let mut remaining = match snapshot {
+        Some(remaining) => remaining,
+        None => VecDeque::from(list_after().await?),
+    };
+
+    let page_len = limit.unwrap_or(usize::MAX).min(remaining.len());
+    let entries: Vec<T> = remaining.drain(..page_len).collect();
+    let mut headers = HeaderMap::new();
+    let mut next_cursor = None;
+    if let Some(last) = entries.last().filter(|_| !remaining.is_empty()) {
+        let cursor = cursor_for(last);
+        headers.insert(
+            headers::NEXT_CURSOR,
+            cursor.parse().expect("hex cursors are valid header values"),
+        );
+        snapshots.store(snapshot_key(&cursor), path, remaining);
+        next_cursor = Some(cursor);
+    }
+    Ok(ListingPage {
+        entries,
+        headers,
+        next_cursor,
+    })
+}
+
+async fn list_page(
+    state: &AppState,
+    path: &str,
+    query: &ListQuery,
+    request_headers: &HeaderMap,
+) -> Result<Response, StorageError> {
+    let sort = query.sort.unwrap_or_default();
+    let ListingPage {
+        entries,
+        headers: mut response_headers,
+        next_cursor,
+    } = listing_page(
+        &state.listings,
+        path,
+        format!("{:?} {sort:?}", query.depth),
+        query.cursor.as_deref(),
+        query.limit,
+        |entry| sort.cursor_for(entry),
+        || async {
+            let mut entries = list_tree(state, path, query).await?;
+            if sort != ListSort::Name {
+                entries.sort_by(|left, right| {
+                    (sort.key(left), &left.name).cmp(&(sort.key(right), &right.name))
+                });
+            }
+            if let Some(cursor) = &query.cursor {
+                let after = sort.position_after(cursor)?;
+                entries.retain(|entry| (sort.key(entry), &entry.name) > (after.0, &after.1));
+            }
+            Ok(entries)
+        },
+    )
+    .await?;
+
+    let body =
+        serde_json::to_vec(&entries).map_err(|error| StorageError::Io(io::Error::other(error)))?;
+    let etag = listing_etag(&body, next_cursor.as_deref());
+    response_headers.insert(
+        ETAG,
+        etag.parse()
+            .expect("quoted hex tags are valid header values"),
+    );
+    if matches_etag(request_headers, &etag) {
+        return Ok((StatusCode::NOT_MODIFIED, response_headers).into_response());
+    }
+    response_headers.insert(CONTENT_TYPE, "application/json".parse().unwrap());
+    Ok((response_headers, body).into_response())
+}
+
+// With `depth > 1`, descendants are included with `name` set to their path
+// relative to the listed directory, e.g. `docs/a.txt`. Symbolic links are
+// reported but never descended into.
+async fn list_tree(
+    state: &AppState,
+    path: &str,
+    query: &ListQuery,
+) -> Result<Vec<DirectoryEntry>, StorageError> {
+    let depth = match query.depth {
+        Some(0) => return Err(StorageError::BadRequest("Depth must be at least 1")),
+        Some(depth) => depth.min(MAX_LIST_DEPTH),
+        None => 1,
+    };
+
+    let mut entries = list_entries(state, path).await?;
+    let mut pending: Vec<(String, u32)> = entries
+        .iter()
+        .filter(|entry| entry.type_ == EntryType::Directory)
+        .map(|entry| (entry.name.clone(), 2))
+        .collect();
+
+    while let Some((relative, level)) = pending.pop() {
+        if level > depth {
+            continue;
+        }
+        let child_path = format!("{}/{}", path.trim_matches('/'), relative);
+        for mut entry in list_entries(state, &child_path).await? {
+            entry.name = format!("{relative}/{}", entry.name);
+            entry.name_encoding = name_encoding(&entry.name);
+            if entry.type_ == EntryType::Directory {
+                pending.push((entry.name.clone(), level + 1));
+            }
+            entries.push(entry);
+        }
+    }
+
+    entries.sort_by(|left, right| left.name.cmp(&right.name));
+    Ok(entries)
+}
+
 pub(crate) async fn list_root(
+    Query(query): Query<ListQuery>,
+    request_headers: HeaderMap,
     State(state): State<Arc<AppState>>,
-) -> Result<Json<Vec<DirectoryEntry>>, StorageError> {
-    Ok(Json(list_entries(&state, "").await?))
+) -> Result<Response, StorageError> {
+    list_page(&state, "", &query, &request_headers).await
 }
 
 pub(crate) async fn list_path(
     AxumPath(path): AxumPath<String>,
+    Query(query): Query<ListQuery>,
+    request_headers: HeaderMap,
     State(state): State<Arc<AppState>>,
-) -> Result<Json<Vec<DirectoryEntry>>, StorageError> {
-    Ok(Json(list_entries(&state, &path).await?))
+) -> Result<Response, StorageError> {
+    list_page(&state, &path, &query, &request_headers).await
+}
+
+#[derive(Deserialize)]
+pub(crate) struct NamesQuery {
+    cursor: Option<String>,
+    limit: Option<usize>,
+}
+
+// Pages in name order with the same cursors as `GET /list`.
+async fn names_page(
+    state: &AppState,
+    path: &str,
+    query: &NamesQuery,
+) -> Result<Response, StorageError> {
+    let page = listing_page(
+        &state.names,
+        path,
+        String::new(),
+        query.cursor.as_deref(),
+        query.limit,
+        |entry| encode_cursor(&entry.name),
+        || async {
+            let mut entries = list_names(state, path).await?;
+            if let Some(cursor) = &query.cursor {
+                let after = decode_cursor(cursor)?;
+                entries.retain(|entry| entry.name > after);
+            }
+            Ok(entries)
+        },
+    )
+    .await?;
+    Ok((page.headers, Json(page.entries)).into_response())
+}
+
+pub(crate) async fn list_names_root(
+    Query(query): Query<NamesQuery>,
+    State(state): State<Arc<AppState>>,
+) -> Result<Response, StorageError> {
+    names_page(&state, "", &query).await
+}
+
+pub(crate) async fn list_names_path(
+    AxumPath(path): AxumPath<String>,
+    Query(query): Query<NamesQuery>,
+    State(state): State<Arc<AppState>>,
+) -> Result<Response, StorageError> {
+    names_page(&state, &path, &query).await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{
+        decode_cursor, encode_cursor, parse_byte_range, parse_content_range, ByteRange, ListSort,
+    };
+
+    #[test]
+    fn byte_ranges_clamp_to_the_file_and_ignore_unsupported_forms() {
+        assert_eq!(
+            parse_byte_range("bytes=2-5", 10),
+            Some(ByteRange::Satisfiable { start: 2, end: 5 })
+        );
+        assert_eq!(
+            parse_byte_range("bytes=4-", 10),
+            Some(ByteRange::Satisfiable { start: 4, end: 9 })
+        );
+        assert_eq!(
+            parse_byte_range("bytes=-3", 10),
+            Some(ByteRange::Satisfiable { start: 7, end: 9 })
+        );
+        assert_eq!(
+            parse_byte_range("bytes=8-100", 10),
+            Some(ByteRange::Satisfiable { start: 8, end: 9 })
+        );
+        assert_eq!(
+            parse_byte_range("bytes=10-", 10),
+            Some(ByteRange::Unsatisfiable)
+        );
+        assert_eq!(parse_byte_range("bytes=0-1,4-5", 10), None);
+        assert_eq!(parse_byte_range("items=0-1", 10), None);
+        assert_eq!(parse_byte_range("bytes=5-2", 10), None);
+    }
+
+    #[test]
+    fn content_ranges_name_the_bytes_a_write_covers() {
+        assert_eq!(parse_content_range("bytes 4-9/*"), Some((4, 9)));
+        assert_eq!(parse_content_range("bytes 0-0/10"), Some((0, 0)));
+        assert_eq!(parse_content_range("bytes 4-9/9"), None);
+        assert_eq!(parse_content_range("bytes 9-4/*"), None);
+        assert_eq!(parse_content_range("bytes */10"), None);
+        assert_eq!(parse_content_range("items 0-1/*"), None);
+    }
+
+    #[test]
+    fn listing_cursors_round_trip_and_reject_garbage() {
+        let cursor = encode_cursor("caffè report.txt");
+        assert!(cursor.bytes().all(|byte| byte.is_ascii_hexdigit()));
+        assert_eq!(decode_cursor(&cursor).unwrap(), "caffè report.txt");
+        assert!(decode_cursor("abc").is_err());
+        assert!(decode_cursor("zz").is_err());
+        assert!(decode_cursor("ff").is_err());
+
+        assert_eq!(
+            ListSort::Size
+                .position_after(&encode_cursor("1024/docs/a.txt"))
+                .unwrap(),
+            (1024, "docs/a.txt".to_string())
+        );
+        assert!(ListSort::Mtime
+            .position_after(&encode_cursor("a.txt"))
+            .is_err());
+        assert_eq!(
+            ListSort::Name
+                .position_after(&encode_cursor("1024/a.txt"))
+                .unwrap(),
+            (0, "1024/a.txt".to_string())
+        );
+    }
 }
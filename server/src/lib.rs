@@ -1,32 +1,45 @@
 mod auth;
 mod config;
+mod content_type;
 mod error;
+mod events;
 mod handlers;
+mod listing_snapshots;
 mod metadata;
 mod path_security;
 mod transaction;
+mod xattr;
 
 use auth::require_authentication;
 pub use config::ServerConfig;
 #[cfg(test)]
 pub(crate) use error::StorageError;
+use error::{coded_error_response, json_error_bodies};
+use events::{watch_changes, CHANGE_FEED_CAPACITY};
 use handlers::{
-    delete_directory, delete_path, get_file, get_metadata, list_path, list_root, make_directory,
-    rename_entry, update_metadata, write_file,
+    abort_temp_write, append_temp_write, begin_temp_write, commit_temp_write, copy_range,
+    create_symlink, delete_directory, delete_path, delete_xattr, get_file, get_metadata,
+    get_statfs, get_xattr, health, link_entry, list_names_path, list_names_root, list_path,
+    list_root, make_directory, make_node, preallocate_file, put_xattr, read_symlink, readiness,
+    rename_entry, seek_file, stat_entry, stat_root, update_metadata, write_file,
 };
 
 use axum::{
     body::Body,
-    http::{HeaderValue, Request},
+    http::{HeaderValue, Request, StatusCode},
     middleware::{self, Next},
     response::Response,
-    routing::{delete, get, post},
+    routing::{delete, get, post, put},
     Router,
 };
-use remote_fs_protocol::{PROTOCOL_VERSION, PROTOCOL_VERSION_HEADER};
+use remote_fs_protocol::{
+    error_codes, headers, DirectoryEntry, NameEntry, PROTOCOL_VERSION, PROTOCOL_VERSION_HEADER,
+};
+use std::{collections::HashMap, io, path::PathBuf, sync::Arc, time::Instant};
 #[cfg(test)]
 use std::{env, time::UNIX_EPOCH};
-use std::{io, path::PathBuf, sync::Arc};
+use tokio_util::sync::CancellationToken;
+use tower_http::compression::CompressionLayer;
 
 pub(crate) const INTERNAL_DIR_NAME: &str = ".remote-fs-transactions";
 
@@ -35,18 +48,51 @@ pub(crate) struct AppState {
     pub(crate) transaction_dir: PathBuf,
     pub(crate) auth_token: Option<String>,
     pub(crate) mutation_lock: tokio::sync::Mutex<()>,
+    // Staged `/write-temp` handles and the API path each one replaces.
+    pub(crate) temp_writes: std::sync::Mutex<HashMap<String, transaction::TempWrite>>,
+    // Paths changed by mutating requests, fanned out to `/events` subscribers.
+    pub(crate) changes: tokio::sync::broadcast::Sender<String>,
+    // Unreturned rest of `GET /list` and `GET /names` pages, so paging
+    // through a directory is not quadratic.
+    pub(crate) listings: listing_snapshots::ListingSnapshots<DirectoryEntry>,
+    pub(crate) names: listing_snapshots::ListingSnapshots<NameEntry>,
+    // Cancelled once shutdown starts so open event streams do not hold the
+    // graceful drain until its timeout.
+    pub(crate) shutdown: CancellationToken,
+    // Set by `REMOTE_FS_MAX_FILE_SIZE`: no write or resize may take a file
+    // past this many bytes.
+    pub(crate) max_file_size: Option<u64>,
 }
 
 impl AppState {
+    // The root is canonicalized so resolved paths can be checked against it.
     pub(crate) fn with_auth(root_dir: PathBuf, auth_token: Option<String>) -> Self {
+        let root_dir = std::fs::canonicalize(&root_dir).unwrap_or(root_dir);
         let transaction_dir = root_dir.join(INTERNAL_DIR_NAME);
         std::fs::create_dir_all(&transaction_dir)
             .expect("failed to create server transaction directory");
+        transaction::remove_abandoned_temp_writes(&transaction_dir);
         AppState {
             root_dir,
             transaction_dir,
             auth_token,
             mutation_lock: tokio::sync::Mutex::new(()),
+            temp_writes: std::sync::Mutex::new(HashMap::new()),
+            changes: tokio::sync::broadcast::channel(CHANGE_FEED_CAPACITY).0,
+            listings: Default::default(),
+            names: Default::default(),
+            shutdown: CancellationToken::new(),
+            max_file_size: None,
+        }
+    }
+
+    // 413 when a write or resize reaching `end` would pass the size limit.
+    pub(crate) fn check_file_size(&self, end: u64) -> Result<(), error::StorageError> {
+        match self.max_file_size {
+            Some(limit) if end > limit => {
+                Err(error::StorageError::PayloadTooLarge("File too large"))
+            }
+            _ => Ok(()),
         }
     }
 }
@@ -60,12 +106,48 @@ async fn add_protocol_version(request: Request<Body>, next: Next) -> Response {
     response
 }
 
+// Logs every request with the client's `X-Request-Id`, which is echoed on the
+// response so both logs can be matched. Server errors are logged as warnings.
+async fn log_request(request: Request<Body>, next: Next) -> Response {
+    let request_id = request.headers().get(headers::REQUEST_ID).cloned();
+    let (method, uri) = (request.method().clone(), request.uri().clone());
+    let started = Instant::now();
+    let mut response = next.run(request).await;
+    let elapsed = started.elapsed();
+
+    let status = response.status();
+    let id = request_id
+        .as_ref()
+        .and_then(|id| id.to_str().ok())
+        .unwrap_or("-");
+    if status.is_server_error() {
+        log::warn!("{method} {uri} -> {status} in {elapsed:?} [{id}]");
+    } else {
+        log::debug!("{method} {uri} -> {status} in {elapsed:?} [{id}]");
+    }
+    if let Some(request_id) = request_id {
+        response
+            .headers_mut()
+            .insert(headers::REQUEST_ID, request_id);
+    }
+    response
+}
+
 pub(crate) fn build_app(shared_state: Arc<AppState>) -> Router {
     let authentication_state = shared_state.clone();
+    // Probes are merged after authentication so load balancers and mounting
+    // clients can check the server without a token.
+    let probes = Router::new()
+        .route("/healthz", get(health))
+        .route("/readyz", get(readiness))
+        .with_state(shared_state.clone())
+        .layer(middleware::from_fn(add_protocol_version));
 
     Router::new()
         .route("/list/", get(list_root))
         .route("/list/*path", get(list_path))
+        .route("/names/", get(list_names_root))
+        .route("/names/*path", get(list_names_path))
         .route(
             "/files/*path",
             get(get_file).put(write_file).delete(delete_path),
@@ -73,13 +155,52 @@ pub(crate) fn build_app(shared_state: Arc<AppState>) -> Router {
         .route("/directories/*path", delete(delete_directory))
         .route("/metadata/*path", get(get_metadata).patch(update_metadata))
         .route("/mkdir/*path", post(make_directory))
+        .route("/mknod", post(make_node))
         .route("/rename", post(rename_entry))
+        .route("/link", post(link_entry))
+        .route("/copy", post(copy_range))
+        .route("/fallocate/*path", post(preallocate_file))
+        .route("/seek/*path", get(seek_file))
+        .route("/write-temp", post(begin_temp_write))
+        .route(
+            "/write-temp/:handle",
+            put(append_temp_write).delete(abort_temp_write),
+        )
+        .route("/commit/:handle", post(commit_temp_write))
+        .route("/readlink/*path", get(read_symlink))
+        .route("/symlink", post(create_symlink))
+        .route("/stat/", get(stat_root))
+        .route("/stat/*path", get(stat_entry))
+        .route("/statfs", get(get_statfs))
+        .route("/events", get(watch_changes))
+        .route(
+            "/xattr/",
+            get(get_xattr).put(put_xattr).delete(delete_xattr),
+        )
+        .route(
+            "/xattr/*path",
+            get(get_xattr).put(put_xattr).delete(delete_xattr),
+        )
+        .fallback(|| async {
+            coded_error_response(
+                StatusCode::NOT_FOUND,
+                error_codes::UNKNOWN_ENDPOINT,
+                "No such endpoint",
+            )
+        })
         .with_state(shared_state)
+        // Inside compression, so plain-text rejections are read uncompressed.
+        .layer(middleware::map_response(json_error_bodies))
+        // Negotiated per request from `Accept-Encoding`; tiny bodies and
+        // clients that do not ask for compression are sent as-is.
+        .layer(CompressionLayer::new())
         .layer(middleware::from_fn(add_protocol_version))
         .layer(middleware::from_fn_with_state(
             authentication_state,
             require_authentication,
         ))
+        .merge(probes)
+        .layer(middleware::from_fn(log_request))
 }
 
 async fn shutdown_signal() {
@@ -107,16 +228,36 @@ async fn shutdown_signal() {
 }
 
 pub async fn run(config: ServerConfig) -> Result<(), Box<dyn std::error::Error>> {
+    let listener = tokio::net::TcpListener::bind(&config.listen_addr)
+        .await
+        .map_err(|error| {
+            io::Error::new(
+                error.kind(),
+                format!("could not listen on {}: {error}", config.listen_addr),
+            )
+        })?;
+    serve(listener, config).await
+}
+
+/// Serves on a listener that is already bound, such as one on an ephemeral
+/// port; `config.listen_addr` is not used.
+pub async fn serve(
+    listener: tokio::net::TcpListener,
+    config: ServerConfig,
+) -> Result<(), Box<dyn std::error::Error>> {
     std::fs::create_dir_all(&config.storage_root)?;
     let storage_root = std::fs::canonicalize(&config.storage_root)?;
-    let app = build_app(Arc::new(AppState::with_auth(
-        storage_root.clone(),
-        config.auth_token.clone(),
-    )));
+    let mut state = AppState::with_auth(storage_root.clone(), config.auth_token.clone());
+    state.max_file_size = config.max_file_size;
+    let state = Arc::new(state);
+    let app = build_app(state.clone());
 
     log::info!("Server storage root: {}", storage_root.display());
-    log::info!("Server listening on {}", config.listen_addr);
+    log::info!("Server listening on {}", listener.local_addr()?);
     log::info!("Protocol version: {PROTOCOL_VERSION}");
+    if let Some(limit) = config.max_file_size {
+        log::info!("Largest file size: {limit} bytes");
+    }
     log::info!(
         "Bearer-token authentication: {}",
         if config.auth_token.is_some() {
@@ -126,10 +267,29 @@ pub async fn run(config: ServerConfig) -> Result<(), Box<dyn std::error::Error>>
         }
     );
 
-    let listener = tokio::net::TcpListener::bind(&config.listen_addr).await?;
-    axum::serve(listener, app)
-        .with_graceful_shutdown(shutdown_signal())
-        .await?;
+    // After the signal the listener closes and requests already running,
+    // such as a large upload, get `shutdown_timeout` to finish; any still
+    // going after that are dropped.
+    let (signalled, draining) = tokio::sync::oneshot::channel();
+    let server = axum::serve(listener, app).with_graceful_shutdown(async move {
+        shutdown_signal().await;
+        state.shutdown.cancel();
+        let _ = signalled.send(());
+    });
+    let timeout = config.shutdown_timeout;
+    let deadline = async move {
+        if draining.await.is_err() {
+            std::future::pending::<()>().await;
+        }
+        log::info!("Waiting up to {timeout:?} for in-flight requests to finish.");
+        tokio::time::sleep(timeout).await;
+    };
+    tokio::select! {
+        result = async move { server.await } => result?,
+        () = deadline => {
+            log::warn!("In-flight requests were still running after {timeout:?}; exiting anyway.");
+        }
+    }
     Ok(())
 }
 
@@ -0,0 +1,75 @@
+use std::{
+    collections::{HashMap, VecDeque},
+    sync::Mutex,
+    time::{Duration, Instant},
+};
+
+// Listings paged through at once; the oldest is dropped past this.
+const MAX_SNAPSHOTS: usize = 64;
+// A reader slower than this between pages lists the directory again.
+const SNAPSHOT_TTL: Duration = Duration::from_secs(60);
+
+// The sorted entries a paged listing has not returned yet, keyed by the
+// cursor that resumes it, so each later page is served without reading and
+// sorting the directory again. Any change published under the listed
+// directory drops its snapshots, and the next page falls back to listing.
+pub(crate) struct ListingSnapshots<T> {
+    snapshots: Mutex<HashMap<String, Snapshot<T>>>,
+}
+
+struct Snapshot<T> {
+    directory: String,
+    remaining: VecDeque<T>,
+    created_at: Instant,
+}
+
+impl<T> Default for ListingSnapshots<T> {
+    fn default() -> Self {
+        ListingSnapshots {
+            snapshots: Mutex::new(HashMap::new()),
+        }
+    }
+}
+
+impl<T> ListingSnapshots<T> {
+    pub(crate) fn take(&self, key: &str) -> Option<VecDeque<T>> {
+        let snapshot = self.snapshots.lock().unwrap().remove(key)?;
+        (snapshot.created_at.elapsed() <= SNAPSHOT_TTL).then_some(snapshot.remaining)
+    }
+
+    pub(crate) fn store(&self, key: String, directory: &str, remaining: VecDeque<T>) {
+        let mut snapshots = self.snapshots.lock().unwrap();
+        snapshots.retain(|_, snapshot| snapshot.created_at.elapsed() <= SNAPSHOT_TTL);
+        if snapshots.len() >= MAX_SNAPSHOTS {
+            let oldest = snapshots
+                .iter()
+                .min_by_key(|(_, snapshot)| snapshot.created_at)
+                .map(|(key, _)| key.clone());
+            if let Some(oldest) = oldest {
+                snapshots.remove(&oldest);
+            }
+        }
+        snapshots.insert(
+            key,
+            Snapshot {
+                directory: directory.trim_matches('/').to_string(),
+                remaining,
+                created_at: Instant::now(),
+            },
+        );
+    }
+
+    // Drops snapshots of every directory at or above `path`; deeper listings
+    // include descendants, so any ancestor's may show it.
+    pub(crate) fn invalidate(&self, path: &str) {
+        let path = path.trim_matches('/');
+        self.snapshots.lock().unwrap().retain(|_, snapshot| {
+            let directory = &snapshot.directory;
+            !(directory.is_empty()
+                || path == directory
+                || path
+                    .strip_prefix(directory.as_str())
+                    .is_some_and(|rest| rest.starts_with('/')))
+        });
+    }
+}
@@ -1,7 +1,10 @@
-use crate::error::StorageError;
+use crate::{content_type::content_type, error::StorageError};
 use axum::http::HeaderMap;
-use remote_fs_protocol::{headers, DirectoryEntry, RemoteMetadata};
+use remote_fs_protocol::{
+    headers, names, DirectoryEntry, EntryType, FilesystemStats, RemoteMetadata,
+};
 use std::{
+    ffi::OsStr,
     path::Path,
     time::{SystemTime, UNIX_EPOCH},
 };
@@ -65,22 +68,48 @@ fn metadata_gid(_metadata: &std::fs::Metadata) -> Option<u32> {
     None
 }
 
-fn entry_type(metadata: &std::fs::Metadata) -> Option<String> {
-    if metadata.is_dir() {
-        Some("directory".to_string())
-    } else if metadata.is_file() {
-        Some("file".to_string())
+pub(crate) fn is_fifo(metadata: &std::fs::Metadata) -> bool {
+    is_fifo_type(metadata.file_type())
+}
+
+#[cfg(unix)]
+fn is_fifo_type(file_type: std::fs::FileType) -> bool {
+    use std::os::unix::fs::FileTypeExt;
+
+    file_type.is_fifo()
+}
+
+#[cfg(not(unix))]
+fn is_fifo_type(_file_type: std::fs::FileType) -> bool {
+    false
+}
+
+// Takes the type alone so name-only listings can use the one `readdir`
+// reports without a `stat` per entry.
+pub(crate) fn entry_type(file_type: std::fs::FileType) -> Option<EntryType> {
+    if file_type.is_symlink() {
+        Some(EntryType::Symlink)
+    } else if file_type.is_dir() {
+        Some(EntryType::Directory)
+    } else if file_type.is_file() {
+        Some(EntryType::File)
+    } else if is_fifo_type(file_type) {
+        Some(EntryType::Fifo)
     } else {
         None
     }
 }
 
-pub(crate) fn entry_metadata_from_metadata(metadata: std::fs::Metadata) -> Option<RemoteMetadata> {
-    let type_ = entry_type(&metadata)?;
+pub(crate) fn entry_metadata_from_metadata(
+    path: &Path,
+    metadata: std::fs::Metadata,
+) -> Option<RemoteMetadata> {
+    let type_ = entry_type(metadata.file_type())?;
 
     Some(RemoteMetadata {
         type_,
-        size: if metadata.is_file() {
+        // A link's length is that of its target string, as `lstat` reports.
+        size: if metadata.is_file() || metadata.is_symlink() {
             metadata.len()
         } else {
             0
@@ -92,6 +121,11 @@ pub(crate) fn entry_metadata_from_metadata(metadata: std::fs::Metadata) -> Optio
         mode: Some(metadata_mode(&metadata)),
         uid: metadata_uid(&metadata),
         gid: metadata_gid(&metadata),
+        accessed_at: metadata.accessed().ok().map(format_modified_at),
+        changed_at: metadata_changed_at(&metadata),
+        nlink: metadata_nlink(&metadata),
+        blocks: metadata_blocks(&metadata),
+        content_type: content_type(path, &metadata),
     })
 }
 
@@ -100,16 +134,48 @@ pub(crate) async fn entry_metadata_for_path(path: &Path) -> Result<RemoteMetadat
         .await
         .map_err(|error| StorageError::from_io(error, "Path not found"))?;
 
-    entry_metadata_from_metadata(metadata).ok_or(StorageError::BadRequest("Unsupported file type"))
+    entry_metadata_from_metadata(path, metadata)
+        .ok_or(StorageError::BadRequest("Unsupported file type"))
+}
+
+#[cfg(unix)]
+fn metadata_nlink(metadata: &std::fs::Metadata) -> Option<u64> {
+    Some(metadata.nlink())
+}
+
+#[cfg(not(unix))]
+fn metadata_nlink(_metadata: &std::fs::Metadata) -> Option<u64> {
+    None
+}
+
+#[cfg(unix)]
+fn metadata_blocks(metadata: &std::fs::Metadata) -> Option<u64> {
+    Some(metadata.blocks())
+}
+
+#[cfg(not(unix))]
+fn metadata_blocks(_metadata: &std::fs::Metadata) -> Option<u64> {
+    None
+}
+
+#[cfg(unix)]
+fn metadata_changed_at(metadata: &std::fs::Metadata) -> Option<String> {
+    Some(metadata.ctime().max(0).to_string())
+}
+
+#[cfg(not(unix))]
+fn metadata_changed_at(_metadata: &std::fs::Metadata) -> Option<String> {
+    None
 }
 
 pub(crate) fn directory_entry_from_metadata(
     name: String,
     metadata: std::fs::Metadata,
 ) -> Option<DirectoryEntry> {
-    let entry = entry_metadata_from_metadata(metadata)?;
+    let entry = entry_metadata_from_metadata(Path::new(&name), metadata)?;
 
     Some(DirectoryEntry {
+        name_encoding: name_encoding(&name),
         name,
         type_: entry.type_,
         size: entry.size,
@@ -117,9 +183,33 @@ pub(crate) fn directory_entry_from_metadata(
         mode: entry.mode,
         uid: entry.uid,
         gid: entry.gid,
+        target: None,
+        accessed_at: entry.accessed_at,
+        changed_at: entry.changed_at,
+        nlink: entry.nlink,
+        blocks: entry.blocks,
+        content_type: entry.content_type,
     })
 }
 
+// Listed names carry a non-UTF-8 component in the protocol's escaped form
+// instead of a lossy replacement that could not be looked up again.
+#[cfg(unix)]
+pub(crate) fn wire_name(name: &OsStr) -> String {
+    names::escape(name.as_bytes()).into_owned()
+}
+
+#[cfg(not(unix))]
+pub(crate) fn wire_name(name: &OsStr) -> String {
+    name.to_string_lossy().into_owned()
+}
+
+pub(crate) fn name_encoding(name: &str) -> Option<String> {
+    name.split('/')
+        .any(names::is_escaped)
+        .then(|| names::ESCAPED.to_string())
+}
+
 pub(crate) fn parse_optional_u64_header(
     headers_map: &HeaderMap,
     name: &'static str,
@@ -166,13 +256,49 @@ fn parse_optional_mode_header(headers_map: &HeaderMap) -> Result<Option<u32>, St
         .transpose()
 }
 
+// `statvfs` field widths vary by platform, so the conversions are only
+// no-ops on some targets.
+#[cfg(unix)]
+#[allow(clippy::useless_conversion)]
+pub(crate) fn filesystem_stats(root: &Path) -> Result<FilesystemStats, StorageError> {
+    let c_path =
+        path_to_cstring(root).map_err(|error| StorageError::from_io(error, "Invalid path"))?;
+    let mut stats = std::mem::MaybeUninit::<libc::statvfs>::uninit();
+    let result = unsafe { libc::statvfs(c_path.as_ptr(), stats.as_mut_ptr()) };
+    if result != 0 {
+        return Err(StorageError::from_io(
+            io::Error::last_os_error(),
+            "Storage root not found",
+        ));
+    }
+    let stats = unsafe { stats.assume_init() };
+
+    Ok(FilesystemStats {
+        block_size: u32::try_from(stats.f_frsize).unwrap_or(u32::MAX),
+        blocks: u64::from(stats.f_blocks),
+        blocks_free: u64::from(stats.f_bfree),
+        blocks_available: u64::from(stats.f_bavail),
+        files: u64::from(stats.f_files),
+        files_free: u64::from(stats.f_ffree),
+        name_max: u32::try_from(stats.f_namemax).unwrap_or(u32::MAX),
+    })
+}
+
+// Clients fall back to synthetic statistics when the endpoint reports 404.
+#[cfg(not(unix))]
+pub(crate) fn filesystem_stats(_root: &Path) -> Result<FilesystemStats, StorageError> {
+    Err(StorageError::NotFound(
+        "Filesystem statistics are unavailable",
+    ))
+}
+
 #[cfg(unix)]
 fn path_to_cstring(path: &Path) -> Result<CString, io::Error> {
     CString::new(path.as_os_str().as_bytes())
         .map_err(|_| io::Error::new(io::ErrorKind::InvalidInput, "path contains NUL byte"))
 }
 
-async fn apply_mode(path: &Path, mode: u32) -> Result<(), StorageError> {
+pub(crate) async fn apply_mode(path: &Path, mode: u32) -> Result<(), StorageError> {
     #[cfg(unix)]
     {
         let mut permissions = fs::metadata(path)
@@ -243,7 +369,11 @@ fn timespec_from_system_time(time: SystemTime) -> libc::timespec {
     }
 }
 
-fn apply_modified_time(path: &Path, modified_at: u64) -> Result<(), StorageError> {
+fn apply_file_times(
+    path: &Path,
+    accessed_at: Option<u64>,
+    modified_at: Option<u64>,
+) -> Result<(), StorageError> {
     #[cfg(windows)]
     {
         use std::fs::{FileTimes, OpenOptions as StdOpenOptions};
@@ -252,23 +382,30 @@ fn apply_modified_time(path: &Path, modified_at: u64) -> Result<(), StorageError
         const FILE_WRITE_ATTRIBUTES: u32 = 0x0000_0100;
         const FILE_FLAG_BACKUP_SEMANTICS: u32 = 0x0200_0000;
 
-        let requested_time = UNIX_EPOCH
-            .checked_add(std::time::Duration::from_secs(modified_at))
-            .ok_or(StorageError::BadRequest(
-                "Modification time is out of range",
-            ))?;
+        let requested_time = |seconds: u64| {
+            UNIX_EPOCH
+                .checked_add(std::time::Duration::from_secs(seconds))
+                .ok_or(StorageError::BadRequest("File time is out of range"))
+        };
+        let mut times = FileTimes::new();
+        if let Some(accessed_at) = accessed_at {
+            times = times.set_accessed(requested_time(accessed_at)?);
+        }
+        if let Some(modified_at) = modified_at {
+            times = times.set_modified(requested_time(modified_at)?);
+        }
         let file = StdOpenOptions::new()
             .access_mode(FILE_WRITE_ATTRIBUTES)
             .custom_flags(FILE_FLAG_BACKUP_SEMANTICS)
             .open(path)
             .map_err(|error| StorageError::from_io(error, "Could not open path"))?;
 
-        file.set_times(FileTimes::new().set_modified(requested_time))
-            .map_err(|error| StorageError::from_io(error, "Could not update modification time"))?;
+        file.set_times(times)
+            .map_err(|error| StorageError::from_io(error, "Could not update file times"))?;
     }
 
     #[cfg(all(not(unix), not(windows)))]
-    let _ = (path, modified_at);
+    let _ = (path, accessed_at, modified_at);
 
     #[cfg(unix)]
     {
@@ -276,20 +413,25 @@ fn apply_modified_time(path: &Path, modified_at: u64) -> Result<(), StorageError
             path_to_cstring(path).map_err(|error| StorageError::from_io(error, "Invalid path"))?;
         let metadata = std::fs::metadata(path)
             .map_err(|error| StorageError::from_io(error, "Path not found"))?;
-        let accessed_at = metadata.accessed().unwrap_or(UNIX_EPOCH);
+        // Keep whichever timestamp was not requested at its current value.
+        let timespec_or_current =
+            |requested: Option<u64>, current: io::Result<SystemTime>| match requested {
+                Some(seconds) => libc::timespec {
+                    tv_sec: seconds as libc::time_t,
+                    tv_nsec: 0,
+                },
+                None => timespec_from_system_time(current.unwrap_or(UNIX_EPOCH)),
+            };
         let times = [
-            timespec_from_system_time(accessed_at),
-            libc::timespec {
-                tv_sec: modified_at as libc::time_t,
-                tv_nsec: 0,
-            },
+            timespec_or_current(accessed_at, metadata.accessed()),
+            timespec_or_current(modified_at, metadata.modified()),
         ];
 
         let result = unsafe { libc::utimensat(libc::AT_FDCWD, c_path.as_ptr(), times.as_ptr(), 0) };
         if result != 0 {
             return Err(StorageError::from_io(
                 io::Error::last_os_error(),
-                "Could not update modification time",
+                "Could not update file times",
             ));
         }
     }
@@ -309,8 +451,10 @@ pub(crate) async fn apply_metadata_headers(
     let gid = parse_optional_u32_header(headers_map, headers::FILE_GID)?;
     apply_owner(path, uid, gid)?;
 
-    if let Some(modified_at) = parse_optional_u64_header(headers_map, headers::FILE_MTIME)? {
-        apply_modified_time(path, modified_at)?;
+    let accessed_at = parse_optional_u64_header(headers_map, headers::FILE_ATIME)?;
+    let modified_at = parse_optional_u64_header(headers_map, headers::FILE_MTIME)?;
+    if accessed_at.is_some() || modified_at.is_some() {
+        apply_file_times(path, accessed_at, modified_at)?;
     }
 
     Ok(())
@@ -339,7 +483,8 @@ mod tests {
             std::process::id()
         ));
         std::fs::write(&path, b"owner test").unwrap();
-        let metadata = entry_metadata_from_metadata(std::fs::metadata(&path).unwrap()).unwrap();
+        let metadata =
+            entry_metadata_from_metadata(&path, std::fs::metadata(&path).unwrap()).unwrap();
         std::fs::remove_file(path).unwrap();
 
         assert_eq!(metadata.uid, None);
@@ -1,41 +1,96 @@
 use crate::{error::StorageError, AppState, INTERNAL_DIR_NAME};
+use remote_fs_protocol::names;
 use std::{
+    borrow::Cow,
+    ffi::OsStr,
     io,
     path::{Component, Path, PathBuf},
 };
 
+const ESCAPES_ROOT: &str = "Path escapes the storage root";
+
 impl AppState {
     /// Resolve an API path below the storage root without following a link or
     /// reparse-point component outside that root.
     pub(crate) fn resolve_path(&self, path: &str) -> Result<PathBuf, StorageError> {
         let relative_path = sanitize_api_path(path)?;
         reject_internal_path(&relative_path)?;
-        self.reject_link_components(&relative_path)?;
-        Ok(self.root_dir.join(relative_path))
+        self.reject_link_components(&relative_path, false)?;
+        self.confine_to_root(self.root_dir.join(relative_path), false)
     }
 
     /// Resolve a mutable API path and reject the storage root itself.
     pub(crate) fn resolve_non_root_path(&self, path: &str) -> Result<PathBuf, StorageError> {
+        self.resolve_entry(path, false)
+    }
+
+    /// Like `resolve_non_root_path`, but the final component may itself be a
+    /// symbolic link. Callers must act on the link (lstat, unlink, rename,
+    /// readlink) and never follow it.
+    pub(crate) fn resolve_link_path(&self, path: &str) -> Result<PathBuf, StorageError> {
+        self.resolve_entry(path, true)
+    }
+
+    fn resolve_entry(&self, path: &str, allow_final_link: bool) -> Result<PathBuf, StorageError> {
         let relative_path = sanitize_api_path(path)?;
         if relative_path.as_os_str().is_empty() {
             return Err(StorageError::BadRequest("Path cannot be empty"));
         }
 
         reject_internal_path(&relative_path)?;
-        self.reject_link_components(&relative_path)?;
-        Ok(self.root_dir.join(relative_path))
+        self.reject_link_components(&relative_path, allow_final_link)?;
+        self.confine_to_root(self.root_dir.join(relative_path), allow_final_link)
+    }
+
+    // Last check after the lexical and link checks: the deepest existing
+    // ancestor of `target` must canonicalize to the storage root or below it.
+    // A final link that callers never follow is judged by its parent.
+    fn confine_to_root(
+        &self,
+        target: PathBuf,
+        allow_final_link: bool,
+    ) -> Result<PathBuf, StorageError> {
+        let mut existing = if allow_final_link {
+            target.parent().unwrap_or(&target)
+        } else {
+            target.as_path()
+        };
+        let canonical = loop {
+            match std::fs::canonicalize(existing) {
+                Ok(canonical) => break canonical,
+                Err(error) if error.kind() == io::ErrorKind::NotFound => {
+                    existing = existing
+                        .parent()
+                        .ok_or(StorageError::Forbidden(ESCAPES_ROOT))?;
+                }
+                Err(error) => return Err(StorageError::from_io(error, "Could not inspect path")),
+            }
+        };
+
+        if canonical.starts_with(&self.root_dir) {
+            Ok(target)
+        } else {
+            Err(StorageError::Forbidden(ESCAPES_ROOT))
+        }
     }
 
-    fn reject_link_components(&self, relative_path: &Path) -> Result<(), StorageError> {
+    fn reject_link_components(
+        &self,
+        relative_path: &Path,
+        allow_final_link: bool,
+    ) -> Result<(), StorageError> {
         let mut current = self.root_dir.clone();
+        let mut components = relative_path.components().peekable();
 
-        for component in relative_path.components() {
+        while let Some(component) = components.next() {
             let Component::Normal(part) = component else {
                 return Err(StorageError::BadRequest("Invalid path"));
             };
             current.push(part);
+            let is_final = components.peek().is_none();
 
             match std::fs::symlink_metadata(&current) {
+                Ok(metadata) if is_final && allow_final_link && metadata.is_symlink() => {}
                 Ok(metadata) if is_link_or_reparse_point(&metadata) => {
                     return Err(StorageError::Forbidden(
                         "Symbolic links and reparse points are not allowed",
@@ -63,14 +118,50 @@ fn sanitize_api_path(path: &str) -> Result<PathBuf, StorageError> {
 
     for component in Path::new(trimmed_path).components() {
         match component {
-            Component::Normal(part) => relative_path.push(part),
-            _ => return Err(StorageError::BadRequest("Invalid path")),
+            Component::Normal(part) => relative_path.push(unescape_component(part)?),
+            Component::ParentDir | Component::RootDir | Component::Prefix(_) => {
+                return Err(StorageError::Forbidden(ESCAPES_ROOT))
+            }
+            Component::CurDir => return Err(StorageError::BadRequest("Invalid path")),
         }
     }
 
     Ok(relative_path)
 }
 
+// Decodes a component the client escaped because it is not valid UTF-8. The
+// escape must decode to bytes that really are invalid UTF-8, so it can never
+// smuggle in a separator, `..`, or a second spelling of an ordinary name.
+#[cfg(unix)]
+fn unescape_component(part: &OsStr) -> Result<Cow<'_, OsStr>, StorageError> {
+    use std::os::unix::ffi::OsStrExt;
+
+    let Some(component) = part.to_str().filter(|part| names::is_escaped(part)) else {
+        return Ok(Cow::Borrowed(part));
+    };
+    match names::unescape(component) {
+        Some(bytes)
+            if std::str::from_utf8(&bytes).is_err()
+                && !bytes.contains(&b'/')
+                && !bytes.contains(&0) =>
+        {
+            Ok(Cow::Owned(OsStr::from_bytes(&bytes).to_os_string()))
+        }
+        _ => Err(StorageError::BadRequest("Invalid path")),
+    }
+}
+
+// Other platforms have no non-UTF-8 names to address.
+#[cfg(not(unix))]
+fn unescape_component(part: &OsStr) -> Result<Cow<'_, OsStr>, StorageError> {
+    match part.to_str() {
+        Some(component) if names::is_escaped(component) => {
+            Err(StorageError::BadRequest("Invalid path"))
+        }
+        _ => Ok(Cow::Borrowed(part)),
+    }
+}
+
 fn reject_internal_path(path: &Path) -> Result<(), StorageError> {
     if path
         .components()
@@ -114,5 +205,20 @@ mod tests {
         assert!(sanitize_api_path("docs/../../outside").is_err());
     }
 
+    #[cfg(unix)]
+    #[test]
+    fn path_sanitizer_decodes_only_genuinely_non_utf8_escapes() {
+        use std::os::unix::ffi::OsStrExt;
+
+        assert_eq!(
+            sanitize_api_path("docs/\0ff61").unwrap(),
+            Path::new("docs").join(std::ffi::OsStr::from_bytes(b"\xffa"))
+        );
+        // `..` and `a/b` spelled as escapes.
+        assert!(sanitize_api_path("\u{0}2e2e").is_err());
+        assert!(sanitize_api_path("\u{0}612f62").is_err());
+        assert!(sanitize_api_path("\u{0}xyz").is_err());
+    }
+
     use std::path::Path;
 }
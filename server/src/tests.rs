@@ -69,6 +69,114 @@ async fn test_successful_responses_advertise_protocol_version() {
     );
 }
 
+#[tokio::test]
+async fn test_request_ids_are_echoed_even_on_rejected_requests() {
+    let root = TestRoot::new("request-id");
+    let app = app_for_root_with_token(root.path(), "secret");
+
+    for uri in ["/healthz", "/list/"] {
+        let response = app
+            .clone()
+            .oneshot(
+                Request::builder()
+                    .uri(uri)
+                    .header(remote_fs_protocol::headers::REQUEST_ID, "req-42")
+                    .body(Body::empty())
+                    .unwrap(),
+            )
+            .await
+            .unwrap();
+        assert_eq!(
+            response
+                .headers()
+                .get(remote_fs_protocol::headers::REQUEST_ID)
+                .unwrap(),
+            "req-42"
+        );
+    }
+}
+
+#[tokio::test]
+async fn test_health_probes_skip_authentication() {
+    let root = TestRoot::new("health-probes");
+    let app = app_for_root_with_token(root.path(), "secret");
+
+    for (uri, expected) in [("/healthz", "ok"), ("/readyz", "ready")] {
+        let response = app
+            .clone()
+            .oneshot(Request::builder().uri(uri).body(Body::empty()).unwrap())
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+        let body = to_bytes(response.into_body(), usize::MAX).await.unwrap();
+        assert_eq!(body, expected);
+    }
+
+    let response = app
+        .oneshot(
+            Request::builder()
+                .uri("/list/")
+                .body(Body::empty())
+                .unwrap(),
+        )
+        .await
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+}
+
+#[tokio::test]
+async fn test_readiness_fails_when_storage_root_disappears() {
+    let root = TestRoot::new("readiness");
+    let app = app_for_root(root.path());
+    std::fs::remove_dir_all(root.path()).unwrap();
+
+    let response = app
+        .clone()
+        .oneshot(
+            Request::builder()
+                .uri("/readyz")
+                .body(Body::empty())
+                .unwrap(),
+        )
+        .await
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::SERVICE_UNAVAILABLE);
+
+    let response = app
+        .oneshot(
+            Request::builder()
+                .uri("/healthz")
+                .body(Body::empty())
+                .unwrap(),
+        )
+        .await
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+}
+
+#[cfg(unix)]
+#[tokio::test]
+async fn test_statfs_reports_storage_root_capacity() {
+    let root = TestRoot::new("statfs");
+    let response = app_for_root(root.path())
+        .oneshot(
+            Request::builder()
+                .uri("/statfs")
+                .body(Body::empty())
+                .unwrap(),
+        )
+        .await
+        .unwrap();
+
+    assert_eq!(response.status(), StatusCode::OK);
+    let body = to_bytes(response.into_body(), usize::MAX).await.unwrap();
+    let stats: remote_fs_protocol::FilesystemStats = serde_json::from_slice(&body).unwrap();
+    assert!(stats.block_size > 0);
+    assert!(stats.blocks >= stats.blocks_free);
+    assert!(stats.blocks_free >= stats.blocks_available);
+    assert!(stats.name_max > 0);
+}
+
 #[tokio::test]
 async fn test_conditional_put_atomically_creates_file() {
     let root = TestRoot::new("conditional-create");
@@ -239,6 +347,104 @@ async fn test_file_chunked_upload() {
     assert_eq!(saved_file, b"Hello World!");
 }
 
+#[tokio::test]
+async fn test_reads_carry_a_sha256_only_when_asked() {
+    let root = TestRoot::new("read-checksum");
+    std::fs::write(root.path.join("a.txt"), b"hello world").unwrap();
+    let app = app_for_root(root.path());
+    let read = |want_checksum: bool| {
+        let mut request = Request::builder()
+            .uri("/files/a.txt")
+            .header("X-File-Offset", "6")
+            .header("X-File-Size", "5");
+        if want_checksum {
+            request = request.header("X-Want-Content-SHA256", "1");
+        }
+        request.body(Body::empty()).unwrap()
+    };
+
+    let response = app.clone().oneshot(read(false)).await.unwrap();
+    assert!(!response.headers().contains_key("X-Content-SHA256"));
+
+    // The digest covers exactly the returned range, "world".
+    let response = app.oneshot(read(true)).await.unwrap();
+    assert_eq!(
+        response.headers()["X-Content-SHA256"],
+        "486ea46224d1bb4fb680f34f7c9ad96a8f24ec88be73ea8e5a6c65260e9cb8a7"
+    );
+    let body = to_bytes(response.into_body(), usize::MAX).await.unwrap();
+    assert_eq!(&body[..], b"world");
+}
+
+#[tokio::test]
+async fn test_write_past_eof_extends_file_with_zeroes() {
+    let root = TestRoot::new("write-past-eof");
+    std::fs::write(root.path.join("sparse.bin"), b"abc").unwrap();
+
+    let app = app_for_root(root.path());
+    let request = Request::builder()
+        .method(Method::PUT)
+        .uri("/files/sparse.bin")
+        .header("X-File-Offset", "6")
+        .body(Body::from("xy"))
+        .unwrap();
+    let response = app.oneshot(request).await.unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+
+    // The returned metadata carries the extended length for the client attr cache.
+    let body = to_bytes(response.into_body(), usize::MAX).await.unwrap();
+    let metadata: serde_json::Value = serde_json::from_slice(&body).unwrap();
+    assert_eq!(metadata["size"], 8);
+    assert_eq!(
+        std::fs::read(root.path.join("sparse.bin")).unwrap(),
+        b"abc\0\0\0xy"
+    );
+}
+
+#[tokio::test]
+async fn test_content_range_writes_in_place_and_must_match_the_body() {
+    // 1. A ranged write replaces only the bytes it names.
+    let root = TestRoot::new("content-range-write");
+    std::fs::write(root.path.join("db.bin"), b"hello world!").unwrap();
+    let app = app_for_root(root.path());
+    let put = |range: &str, extra: Option<(&'static str, &'static str)>, body: &'static str| {
+        let mut request = Request::builder()
+            .method(Method::PUT)
+            .uri("/files/db.bin")
+            .header("Content-Range", range);
+        if let Some((name, value)) = extra {
+            request = request.header(name, value);
+        }
+        request.body(Body::from(body)).unwrap()
+    };
+
+    let response = app
+        .clone()
+        .oneshot(put("bytes 6-10/*", None, "WORLD"))
+        .await
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+    assert_eq!(response.headers()["X-Bytes-Written"], "5");
+    assert_eq!(
+        std::fs::read(root.path.join("db.bin")).unwrap(),
+        b"hello WORLD!"
+    );
+
+    // 2. Ranges that disagree with the body or the offset header change nothing.
+    for request in [
+        put("bytes 0-9/*", Some(("Content-Length", "1")), "H"),
+        put("bytes 0-0/*", Some(("X-File-Offset", "3")), "H"),
+        put("bytes 5-0/*", None, "H"),
+    ] {
+        let response = app.clone().oneshot(request).await.unwrap();
+        assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+    }
+    assert_eq!(
+        std::fs::read(root.path.join("db.bin")).unwrap(),
+        b"hello WORLD!"
+    );
+}
+
 #[tokio::test]
 async fn test_large_upload_exceeds_default_body_limit() {
     // 1. Build a body larger than Axum's default buffered-body limit.
@@ -259,6 +465,67 @@ async fn test_large_upload_exceeds_default_body_limit() {
     assert_eq!(std::fs::read(root.path.join("large.bin")).unwrap(), data);
 }
 
+#[tokio::test]
+async fn test_writes_past_the_file_size_limit_are_refused() {
+    let root = TestRoot::new("max-file-size");
+    let mut state = AppState::with_auth(root.path(), None);
+    state.max_file_size = Some(8);
+    let app = build_app(Arc::new(state));
+    let put = |offset: &str, body: &'static [u8]| {
+        Request::builder()
+            .method(Method::PUT)
+            .uri("/files/a.bin")
+            .header("X-File-Offset", offset)
+            .body(Body::from(body))
+            .unwrap()
+    };
+
+    let response = app.clone().oneshot(put("0", b"12345678")).await.unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+    // A write crossing the limit stores the part below it.
+    let response = app.clone().oneshot(put("4", b"5678X")).await.unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+    assert_eq!(response.headers()["X-Bytes-Written"], "4");
+    let response = app.clone().oneshot(put("8", b"9")).await.unwrap();
+    assert_eq!(response.status(), StatusCode::PAYLOAD_TOO_LARGE);
+    let body = to_bytes(response.into_body(), usize::MAX).await.unwrap();
+    let error: serde_json::Value = serde_json::from_slice(&body).unwrap();
+    assert_eq!(error["error"], "file_too_large");
+
+    let truncate = Request::builder()
+        .method(Method::PUT)
+        .uri("/files/a.bin")
+        .header("X-File-Truncate", "9")
+        .body(Body::empty())
+        .unwrap();
+    let response = app.clone().oneshot(truncate).await.unwrap();
+    assert_eq!(response.status(), StatusCode::PAYLOAD_TOO_LARGE);
+    let preallocate = Request::builder()
+        .method(Method::POST)
+        .uri("/fallocate/a.bin")
+        .header("Content-Type", "application/json")
+        .body(Body::from(
+            json!({"offset": 0, "length": 16, "keep_size": false}).to_string(),
+        ))
+        .unwrap();
+    let response = app.clone().oneshot(preallocate).await.unwrap();
+    assert_eq!(response.status(), StatusCode::PAYLOAD_TOO_LARGE);
+    assert_eq!(std::fs::read(root.path.join("a.bin")).unwrap(), b"12345678");
+
+    let streamed = Request::builder()
+        .method(Method::PUT)
+        .uri("/files/b.bin")
+        .body(Body::from_stream(futures_util::stream::iter([
+            Ok::<Bytes, io::Error>(Bytes::from_static(b"abc")),
+            Ok(Bytes::from_static(b"defghij")),
+        ])))
+        .unwrap();
+    let response = app.oneshot(streamed).await.unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+    assert_eq!(response.headers()["X-Bytes-Written"], "8");
+    assert_eq!(std::fs::read(root.path.join("b.bin")).unwrap(), b"abcdefgh");
+}
+
 #[tokio::test]
 async fn test_upload_over_100_mb_streams_to_disk() {
     // Stream a body larger than 100 MiB without keeping a second full copy for verification.
@@ -350,6 +617,7 @@ async fn test_write_returns_real_metadata() {
         .unwrap();
     let response = app.oneshot(request).await.unwrap();
     assert_eq!(response.status(), StatusCode::OK);
+    assert_eq!(response.headers()["X-Bytes-Written"], "8");
 
     // 2. Verify the server returns metadata from the saved file.
     let body = to_bytes(response.into_body(), usize::MAX).await.unwrap();
@@ -495,6 +763,22 @@ async fn test_delete_directory_rejects_non_empty_directory() {
     );
 }
 
+#[tokio::test]
+async fn test_delete_directory_rejects_regular_file() {
+    let root = TestRoot::new("delete-directory-file");
+    std::fs::write(root.path.join("old.txt"), b"old").unwrap();
+
+    let app = app_for_root(root.path());
+    let request = Request::builder()
+        .method(Method::DELETE)
+        .uri("/directories/old.txt")
+        .body(Body::empty())
+        .unwrap();
+    let response = app.oneshot(request).await.unwrap();
+    assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+    assert!(root.path.join("old.txt").is_file());
+}
+
 #[tokio::test]
 async fn test_rename_moves_directory_tree() {
     // 1. Seed a directory tree directly on disk.
@@ -525,122 +809,1346 @@ async fn test_rename_moves_directory_tree() {
 }
 
 #[tokio::test]
-async fn test_rename_rejects_moving_directory_inside_itself() {
-    // 1. Seed a directory tree directly on disk.
-    let root = TestRoot::new("rename-inside-self");
-    std::fs::create_dir_all(root.path.join("docs/archive")).unwrap();
-
-    // 2. Try to move the directory inside its own subtree.
+async fn test_staged_writes_replace_the_destination_only_on_commit() {
+    // 1. Seed an existing file with restrictive permissions.
+    let root = TestRoot::new("staged-write");
+    let destination = root.path.join("report.txt");
+    std::fs::write(&destination, b"old contents").unwrap();
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt;
+        std::fs::set_permissions(&destination, std::fs::Permissions::from_mode(0o600)).unwrap();
+    }
     let app = app_for_root(root.path());
+
+    // 2. Stage two appends without touching the destination.
     let request = Request::builder()
         .method(Method::POST)
-        .uri("/rename")
+        .uri("/write-temp")
         .header("content-type", "application/json")
-        .body(Body::from(
-            json!({ "from": "docs", "to": "docs/archive/docs" }).to_string(),
-        ))
+        .body(Body::from(json!({ "path": "report.txt" }).to_string()))
         .unwrap();
-    let response = app.oneshot(request).await.unwrap();
-    assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+    let response = app.clone().oneshot(request).await.unwrap();
+    assert_eq!(response.status(), StatusCode::CREATED);
+    let body = to_bytes(response.into_body(), usize::MAX).await.unwrap();
+    let handle = serde_json::from_slice::<serde_json::Value>(&body).unwrap()["handle"]
+        .as_str()
+        .unwrap()
+        .to_string();
+    for chunk in ["new ", "contents"] {
+        let request = Request::builder()
+            .method(Method::PUT)
+            .uri(format!("/write-temp/{handle}"))
+            .body(Body::from(chunk))
+            .unwrap();
+        let response = app.clone().oneshot(request).await.unwrap();
+        assert_eq!(response.status(), StatusCode::NO_CONTENT);
+    }
+    assert_eq!(std::fs::read(&destination).unwrap(), b"old contents");
+
+    // 3. Commit and verify the new contents kept the old permissions.
+    let commit = || {
+        Request::builder()
+            .method(Method::POST)
+            .uri(format!("/commit/{handle}"))
+            .body(Body::empty())
+            .unwrap()
+    };
+    let response = app.clone().oneshot(commit()).await.unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+    assert_eq!(std::fs::read(&destination).unwrap(), b"new contents");
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt;
+        let mode = std::fs::metadata(&destination)
+            .unwrap()
+            .permissions()
+            .mode();
+        assert_eq!(mode & 0o777, 0o600);
+    }
 
-    // 3. Verify the original tree is still in place.
-    assert!(root.path.join("docs/archive").is_dir());
+    // 4. A handle commits once, and leaves nothing behind in the transaction directory.
+    let response = app.oneshot(commit()).await.unwrap();
+    assert_eq!(response.status(), StatusCode::NOT_FOUND);
+    let leftovers = std::fs::read_dir(root.path.join(INTERNAL_DIR_NAME))
+        .unwrap()
+        .count();
+    assert_eq!(leftovers, 0);
 }
 
-#[tokio::test]
-async fn test_rename_does_not_delete_existing_non_empty_directory() {
-    let root = TestRoot::new("rename-existing-dir");
-    std::fs::create_dir(root.path.join("source")).unwrap();
-    std::fs::create_dir_all(root.path.join("target/archive")).unwrap();
-    std::fs::write(root.path.join("target/archive/old.txt"), b"old").unwrap();
-
-    let app = app_for_root(root.path());
+// Stages `chunks` for `path` through `/write-temp` and returns the handle.
+async fn stage_write(app: &Router, path: &str, chunks: &[&'static str]) -> String {
     let request = Request::builder()
         .method(Method::POST)
-        .uri("/rename")
+        .uri("/write-temp")
         .header("content-type", "application/json")
-        .body(Body::from(
-            json!({ "from": "source", "to": "target" }).to_string(),
-        ))
+        .body(Body::from(json!({ "path": path }).to_string()))
         .unwrap();
-    let response = app.oneshot(request).await.unwrap();
-    assert!(response.status().is_client_error());
+    let response = app.clone().oneshot(request).await.unwrap();
+    assert_eq!(response.status(), StatusCode::CREATED);
+    let body = to_bytes(response.into_body(), usize::MAX).await.unwrap();
+    let handle = serde_json::from_slice::<serde_json::Value>(&body).unwrap()["handle"]
+        .as_str()
+        .unwrap()
+        .to_string();
+    for chunk in chunks {
+        let request = Request::builder()
+            .method(Method::PUT)
+            .uri(format!("/write-temp/{handle}"))
+            .body(Body::from(*chunk))
+            .unwrap();
+        let response = app.clone().oneshot(request).await.unwrap();
+        assert_eq!(response.status(), StatusCode::NO_CONTENT);
+    }
+    handle
+}
 
-    assert!(root.path.join("source").is_dir());
-    assert_eq!(
-        std::fs::read(root.path.join("target/archive/old.txt")).unwrap(),
-        b"old"
-    );
+async fn commit_staged(app: &Router, handle: &str) -> StatusCode {
+    let request = Request::builder()
+        .method(Method::POST)
+        .uri(format!("/commit/{handle}"))
+        .body(Body::empty())
+        .unwrap();
+    app.clone().oneshot(request).await.unwrap().status()
 }
 
+#[cfg(any(target_os = "linux", target_os = "macos"))]
 #[tokio::test]
-async fn test_written_file_survives_new_router_with_same_root() {
-    // 1. Write a file through one router instance.
-    let root = TestRoot::new("persistence");
+async fn test_staged_commits_keep_hard_links_and_extended_attributes() {
+    use std::os::unix::fs::MetadataExt;
+
+    let root = TestRoot::new("staged-write-identity");
+    let destination = root.path.join("a.txt");
+    std::fs::write(&destination, b"old").unwrap();
     let app = app_for_root(root.path());
-    let write = Request::builder()
+
+    // 1. A renamed-in replacement still carries the old file's attributes.
+    xattr::set(&destination, "user.tag", b"kept", xattr::SetMode::Upsert).unwrap();
+    let handle = stage_write(&app, "a.txt", &["new"]).await;
+    assert_eq!(commit_staged(&app, &handle).await, StatusCode::OK);
+    assert_eq!(std::fs::read(&destination).unwrap(), b"new");
+    assert_eq!(xattr::get(&destination, "user.tag").unwrap(), b"kept");
+
+    // 2. A file with another name is rewritten in place, so both names agree.
+    let link = root.path.join("b.txt");
+    std::fs::hard_link(&destination, &link).unwrap();
+    let inode = std::fs::metadata(&destination).unwrap().ino();
+    let handle = stage_write(&app, "a.txt", &["linked ", "contents"]).await;
+    assert_eq!(commit_staged(&app, &handle).await, StatusCode::OK);
+    assert_eq!(std::fs::read(&link).unwrap(), b"linked contents");
+    assert_eq!(std::fs::metadata(&destination).unwrap().ino(), inode);
+    assert_eq!(std::fs::metadata(&destination).unwrap().nlink(), 2);
+}
+
+#[tokio::test]
+async fn test_staged_writes_resize_and_expire_when_idle() {
+    let root = TestRoot::new("staged-write-idle");
+    let state = Arc::new(AppState::with_auth(root.path(), None));
+    let app = build_app(state.clone());
+
+    // 1. A truncating write resizes the staged bytes, not the destination.
+    let handle = stage_write(&app, "a.txt", &["0123456789"]).await;
+    let request = Request::builder()
         .method(Method::PUT)
-        .uri("/files/persistent.txt")
-        .body(Body::from("still here"))
+        .uri(format!("/write-temp/{handle}"))
+        .header("X-File-Truncate", "4")
+        .body(Body::empty())
         .unwrap();
-    assert_eq!(app.oneshot(write).await.unwrap().status(), StatusCode::OK);
+    let response = app.clone().oneshot(request).await.unwrap();
+    assert_eq!(response.status(), StatusCode::NO_CONTENT);
+    assert_eq!(commit_staged(&app, &handle).await, StatusCode::OK);
+    assert_eq!(std::fs::read(root.path.join("a.txt")).unwrap(), b"0123");
+
+    // 2. Handles left unused past the idle timeout are discarded with their files.
+    let handle = stage_write(&app, "b.txt", &["abandoned"]).await;
+    transaction::expire_idle_temp_writes(
+        &state,
+        std::time::Instant::now() + transaction::TEMP_WRITE_IDLE_TIMEOUT,
+    );
+    assert_eq!(commit_staged(&app, &handle).await, StatusCode::NOT_FOUND);
+    let leftovers = std::fs::read_dir(root.path.join(INTERNAL_DIR_NAME))
+        .unwrap()
+        .count();
+    assert_eq!(leftovers, 0);
+}
 
-    // 2. Build a fresh router and read the same file from disk.
+#[tokio::test]
+async fn test_event_stream_reports_each_changed_path() {
+    use futures_util::StreamExt;
+
+    let root = TestRoot::new("events");
     let app = app_for_root(root.path());
-    let read = Request::builder()
-        .method(Method::GET)
-        .uri("/files/persistent.txt")
-        .body(Body::empty())
+    let response = app
+        .clone()
+        .oneshot(
+            Request::builder()
+                .uri("/events")
+                .body(Body::empty())
+                .unwrap(),
+        )
+        .await
         .unwrap();
-    let response = app.oneshot(read).await.unwrap();
     assert_eq!(response.status(), StatusCode::OK);
+    assert_eq!(response.headers()["content-type"], "text/event-stream");
+    let mut events = response.into_body().into_data_stream();
+
+    for request in [
+        Request::builder()
+            .method(Method::POST)
+            .uri("/mkdir/docs")
+            .body(Body::empty())
+            .unwrap(),
+        Request::builder()
+            .method(Method::PUT)
+            .uri("/files/docs/a.txt")
+            .body(Body::from("hello"))
+            .unwrap(),
+    ] {
+        let response = app.clone().oneshot(request).await.unwrap();
+        assert!(response.status().is_success());
+    }
 
-    let body = to_bytes(response.into_body(), usize::MAX).await.unwrap();
-    assert_eq!(&body[..], b"still here");
+    let mut received = String::new();
+    while received.matches("event: changed").count() < 2 {
+        let chunk = events.next().await.unwrap().unwrap();
+        received.push_str(std::str::from_utf8(&chunk).unwrap());
+    }
+    assert_eq!(
+        received,
+        "event: changed\ndata: /docs\n\nevent: changed\ndata: /docs/a.txt\n\n"
+    );
 }
 
-#[test]
-fn test_path_traversal_is_rejected() {
-    // 1. Build state with any root; validation should reject before disk access.
-    let root = TestRoot::new("path-validation");
-    let state = AppState::with_auth(root.path(), None);
+#[tokio::test]
+async fn test_copy_overwrites_a_range_and_stops_at_source_eof() {
+    // 1. Seed a source and a longer destination.
+    let root = TestRoot::new("copy-range");
+    std::fs::write(root.path.join("source.txt"), b"0123456789").unwrap();
+    std::fs::write(root.path.join("copy.txt"), b"abcdefghij").unwrap();
+    let app = app_for_root(root.path());
+    let copy = |body: serde_json::Value| {
+        Request::builder()
+            .method(Method::POST)
+            .uri("/copy")
+            .header("content-type", "application/json")
+            .body(Body::from(body.to_string()))
+            .unwrap()
+    };
+
+    // 2. Ask for more bytes than remain after the source offset.
+    let response = app
+        .clone()
+        .oneshot(copy(json!({
+            "from": "source.txt",
+            "to": "copy.txt",
+            "from_offset": 6,
+            "to_offset": 2,
+            "length": 100
+        })))
+        .await
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+    let body = to_bytes(response.into_body(), usize::MAX).await.unwrap();
+    let result: serde_json::Value = serde_json::from_slice(&body).unwrap();
+    assert_eq!(result["copied"], 4);
+    assert_eq!(
+        std::fs::read(root.path.join("copy.txt")).unwrap(),
+        b"ab6789ghij"
+    );
 
-    // 2. Ensure parent-directory components cannot escape the storage root.
-    assert!(state.resolve_path("../Cargo.toml").is_err());
-    assert!(state.resolve_path("docs/../../Cargo.toml").is_err());
+    // 3. Overlapping ranges within one file are refused.
+    let response = app
+        .oneshot(copy(json!({
+            "from": "source.txt",
+            "to": "source.txt",
+            "from_offset": 0,
+            "to_offset": 4,
+            "length": 5
+        })))
+        .await
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+    assert_eq!(
+        std::fs::read(root.path.join("source.txt")).unwrap(),
+        b"0123456789"
+    );
 }
 
-#[test]
-fn test_link_components_are_rejected() {
-    let root = TestRoot::new("link-root");
-    let outside = TestRoot::new("link-outside");
-    std::fs::write(outside.path.join("secret.txt"), b"secret").unwrap();
+#[tokio::test]
+async fn test_fallocate_grows_a_file_unless_asked_to_keep_its_size() {
+    // 1. Seed a short file.
+    let root = TestRoot::new("fallocate");
+    std::fs::write(root.path.join("db.bin"), b"data").unwrap();
+    let app = app_for_root(root.path());
+    let fallocate = |body: serde_json::Value| {
+        Request::builder()
+            .method(Method::POST)
+            .uri("/fallocate/db.bin")
+            .header("content-type", "application/json")
+            .body(Body::from(body.to_string()))
+            .unwrap()
+    };
+
+    // 2. Reserving past EOF with `keep_size` leaves the size alone.
+    let response = app
+        .clone()
+        .oneshot(fallocate(
+            json!({ "offset": 0, "length": 8192, "keep_size": true }),
+        ))
+        .await
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+    assert_eq!(
+        std::fs::metadata(root.path.join("db.bin")).unwrap().len(),
+        4
+    );
+
+    // 3. A plain reservation extends the file with zeroes.
+    let response = app
+        .oneshot(fallocate(json!({ "offset": 4096, "length": 4096 })))
+        .await
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+    let body = to_bytes(response.into_body(), usize::MAX).await.unwrap();
+    let metadata: serde_json::Value = serde_json::from_slice(&body).unwrap();
+    assert_eq!(metadata["size"], 8192);
+    let contents = std::fs::read(root.path.join("db.bin")).unwrap();
+    assert_eq!(&contents[..4], b"data");
+    assert!(contents[4..].iter().all(|byte| *byte == 0));
+}
+
+#[tokio::test]
+async fn test_seek_finds_data_and_holes_up_to_the_end_of_a_file() {
+    // 1. Seed a file with data, a hole, and more data.
+    let root = TestRoot::new("seek");
+    let size = 3 << 20;
+    {
+        use std::io::{Seek, SeekFrom, Write};
+        let mut file = std::fs::File::create(root.path.join("sparse.bin")).unwrap();
+        file.write_all(&[1; 4096]).unwrap();
+        file.seek(SeekFrom::Start(2 << 20)).unwrap();
+        file.write_all(&vec![2; 1 << 20]).unwrap();
+    }
+    let app = app_for_root(root.path());
+    let seek = |whence: &str, offset: u64| {
+        let app = app.clone();
+        let uri = format!("/seek/sparse.bin?whence={whence}&offset={offset}");
+        async move {
+            let response = app
+                .oneshot(Request::builder().uri(uri).body(Body::empty()).unwrap())
+                .await
+                .unwrap();
+            let status = response.status();
+            let body = to_bytes(response.into_body(), usize::MAX).await.unwrap();
+            let found = serde_json::from_slice::<serde_json::Value>(&body)
+                .ok()
+                .and_then(|result| result["offset"].as_u64());
+            (status, found)
+        }
+    };
+
+    // 2. Data starts where it is; the first hole follows it. Filesystems
+    //    without a sparse map report the whole file as data.
+    assert_eq!(seek("data", 0).await, (StatusCode::OK, Some(0)));
+    let (status, hole) = seek("hole", 0).await;
+    assert_eq!(status, StatusCode::OK);
+    assert!((4096..=size).contains(&hole.unwrap()));
+    let (_, data) = seek("data", 1 << 20).await;
+    assert!(matches!(data, Some(offset) if offset == 1 << 20 || offset == 2 << 20));
+    assert_eq!(seek("hole", size - 1).await, (StatusCode::OK, Some(size)));
+
+    // 3. Nothing lies at or past the end.
+    assert_eq!(
+        seek("data", size).await.0,
+        StatusCode::RANGE_NOT_SATISFIABLE
+    );
+    assert_eq!(
+        seek("hole", size).await.0,
+        StatusCode::RANGE_NOT_SATISFIABLE
+    );
+
+    // 4. Only regular files have a sparse map.
+    std::fs::create_dir(root.path.join("dir")).unwrap();
+    let response = app
+        .oneshot(
+            Request::builder()
+                .uri("/seek/dir?whence=data&offset=0")
+                .body(Body::empty())
+                .unwrap(),
+        )
+        .await
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+}
+
+#[tokio::test]
+async fn test_rename_rejects_moving_directory_inside_itself() {
+    // 1. Seed a directory tree directly on disk.
+    let root = TestRoot::new("rename-inside-self");
+    std::fs::create_dir_all(root.path.join("docs/archive")).unwrap();
+
+    // 2. Try to move the directory inside its own subtree.
+    let app = app_for_root(root.path());
+    let request = Request::builder()
+        .method(Method::POST)
+        .uri("/rename")
+        .header("content-type", "application/json")
+        .body(Body::from(
+            json!({ "from": "docs", "to": "docs/archive/docs" }).to_string(),
+        ))
+        .unwrap();
+    let response = app.oneshot(request).await.unwrap();
+    assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+
+    // 3. Verify the original tree is still in place.
+    assert!(root.path.join("docs/archive").is_dir());
+}
+
+#[tokio::test]
+async fn test_rename_does_not_delete_existing_non_empty_directory() {
+    let root = TestRoot::new("rename-existing-dir");
+    std::fs::create_dir(root.path.join("source")).unwrap();
+    std::fs::create_dir_all(root.path.join("target/archive")).unwrap();
+    std::fs::write(root.path.join("target/archive/old.txt"), b"old").unwrap();
+
+    let app = app_for_root(root.path());
+    let request = Request::builder()
+        .method(Method::POST)
+        .uri("/rename")
+        .header("content-type", "application/json")
+        .body(Body::from(
+            json!({ "from": "source", "to": "target" }).to_string(),
+        ))
+        .unwrap();
+    let response = app.oneshot(request).await.unwrap();
+    assert!(response.status().is_client_error());
+
+    assert!(root.path.join("source").is_dir());
+    assert_eq!(
+        std::fs::read(root.path.join("target/archive/old.txt")).unwrap(),
+        b"old"
+    );
+}
+
+#[tokio::test]
+async fn test_written_file_survives_new_router_with_same_root() {
+    // 1. Write a file through one router instance.
+    let root = TestRoot::new("persistence");
+    let app = app_for_root(root.path());
+    let write = Request::builder()
+        .method(Method::PUT)
+        .uri("/files/persistent.txt")
+        .body(Body::from("still here"))
+        .unwrap();
+    assert_eq!(app.oneshot(write).await.unwrap().status(), StatusCode::OK);
+
+    // 2. Build a fresh router and read the same file from disk.
+    let app = app_for_root(root.path());
+    let read = Request::builder()
+        .method(Method::GET)
+        .uri("/files/persistent.txt")
+        .body(Body::empty())
+        .unwrap();
+    let response = app.oneshot(read).await.unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+
+    let body = to_bytes(response.into_body(), usize::MAX).await.unwrap();
+    assert_eq!(&body[..], b"still here");
+}
+
+#[test]
+fn test_path_traversal_is_rejected() {
+    // 1. Build state with any root; validation should reject before disk access.
+    let root = TestRoot::new("path-validation");
+    let state = AppState::with_auth(root.path(), None);
+
+    // 2. Ensure parent-directory components cannot escape the storage root.
+    assert!(state.resolve_path("../Cargo.toml").is_err());
+    assert!(state.resolve_path("docs/../../Cargo.toml").is_err());
+}
+
+#[tokio::test]
+async fn test_percent_encoded_parent_directory_is_forbidden() {
+    let root = TestRoot::new("encoded-traversal");
+    let app = app_for_root(root.path());
+    let request = Request::builder()
+        .uri("/files/docs/%2E%2E/%2E%2E/Cargo.toml")
+        .body(Body::empty())
+        .unwrap();
+
+    assert_eq!(
+        app.oneshot(request).await.unwrap().status(),
+        StatusCode::FORBIDDEN
+    );
+}
+
+#[test]
+fn test_link_components_are_rejected() {
+    let root = TestRoot::new("link-root");
+    let outside = TestRoot::new("link-outside");
+    std::fs::write(outside.path.join("secret.txt"), b"secret").unwrap();
     let link = root.path.join("escape");
 
     #[cfg(unix)]
-    std::os::unix::fs::symlink(&outside.path, &link).unwrap();
+    std::os::unix::fs::symlink(&outside.path, &link).unwrap();
+
+    #[cfg(windows)]
+    {
+        let status = std::process::Command::new("cmd")
+            .args(["/C", "mklink", "/J"])
+            .arg(&link)
+            .arg(&outside.path)
+            .status()
+            .unwrap();
+        assert!(status.success(), "failed to create test junction");
+    }
+
+    let state = AppState::with_auth(root.path(), None);
+    assert!(matches!(
+        state.resolve_path("escape/secret.txt"),
+        Err(StorageError::Forbidden(_))
+    ));
+
+    #[cfg(unix)]
+    std::fs::remove_file(link).unwrap();
+    #[cfg(windows)]
+    std::fs::remove_dir(link).unwrap();
+}
+
+#[cfg(unix)]
+#[test]
+fn test_resolved_paths_stay_below_a_canonical_root() {
+    // 1. Serve a root that is itself reached through a symbolic link.
+    let root = TestRoot::new("canonical-root");
+    let outside = TestRoot::new("canonical-outside");
+    let alias = outside.path.join("alias");
+    std::os::unix::fs::symlink(&root.path, &alias).unwrap();
+    std::fs::write(root.path.join("a.txt"), b"a").unwrap();
+    let state = AppState::with_auth(alias, None);
+
+    // 2. Paths resolve below the real root, including ones not created yet.
+    let canonical = std::fs::canonicalize(&root.path).unwrap();
+    assert_eq!(
+        state.resolve_path("a.txt").unwrap(),
+        canonical.join("a.txt")
+    );
+    assert!(state.resolve_non_root_path("new/b.txt").is_ok());
+
+    // 3. A final link may point anywhere, since it is never followed.
+    std::os::unix::fs::symlink(&outside.path, root.path.join("out")).unwrap();
+    assert!(state.resolve_link_path("out").is_ok());
+    assert!(matches!(
+        state.resolve_path("out/anything"),
+        Err(StorageError::Forbidden(_))
+    ));
+}
+
+#[cfg(unix)]
+#[tokio::test]
+async fn test_hard_links_share_contents_and_report_link_counts() {
+    let root = TestRoot::new("hard-link");
+    std::fs::write(root.path.join("original.txt"), b"shared").unwrap();
+    std::fs::create_dir(root.path.join("dir")).unwrap();
+    let app = app_for_root(root.path());
+    let link = |from: &str, to: &str| {
+        Request::builder()
+            .method(Method::POST)
+            .uri("/link")
+            .header("content-type", "application/json")
+            .body(Body::from(json!({ "from": from, "to": to }).to_string()))
+            .unwrap()
+    };
+
+    let response = app
+        .clone()
+        .oneshot(link("original.txt", "alias.txt"))
+        .await
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::CREATED);
+    let body = to_bytes(response.into_body(), usize::MAX).await.unwrap();
+    let metadata: serde_json::Value = serde_json::from_slice(&body).unwrap();
+    assert_eq!(metadata["nlink"], 2);
+    assert_eq!(
+        std::fs::read(root.path.join("alias.txt")).unwrap(),
+        b"shared"
+    );
+
+    // Existing names and directories are refused.
+    let response = app
+        .clone()
+        .oneshot(link("original.txt", "alias.txt"))
+        .await
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::CONFLICT);
+    let response = app.clone().oneshot(link("dir", "dir-link")).await.unwrap();
+    assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+
+    // Deleting one name leaves the other with a single link.
+    let delete = Request::builder()
+        .method(Method::DELETE)
+        .uri("/files/original.txt")
+        .body(Body::empty())
+        .unwrap();
+    assert_eq!(
+        app.clone().oneshot(delete).await.unwrap().status(),
+        StatusCode::NO_CONTENT
+    );
+    let stat = Request::builder()
+        .uri("/stat/alias.txt")
+        .body(Body::empty())
+        .unwrap();
+    let body = to_bytes(app.oneshot(stat).await.unwrap().into_body(), usize::MAX)
+        .await
+        .unwrap();
+    let entry: serde_json::Value = serde_json::from_slice(&body).unwrap();
+    assert_eq!(entry["nlink"], 1);
+    assert_eq!(entry["size"], 6);
+}
+
+#[cfg(unix)]
+#[tokio::test]
+async fn test_listings_report_allocated_blocks_for_sparse_and_empty_files() {
+    let root = TestRoot::new("blocks");
+    std::fs::File::create(root.path.join("sparse.bin"))
+        .unwrap()
+        .set_len(64 * 1024 * 1024)
+        .unwrap();
+    std::fs::write(root.path.join("empty.txt"), b"").unwrap();
+    std::fs::write(root.path.join("data.bin"), vec![7u8; 64 * 1024]).unwrap();
+
+    let response = app_for_root(root.path())
+        .oneshot(
+            Request::builder()
+                .uri("/list/")
+                .body(Body::empty())
+                .unwrap(),
+        )
+        .await
+        .unwrap();
+    let body = to_bytes(response.into_body(), usize::MAX).await.unwrap();
+    let entries: Vec<serde_json::Value> = serde_json::from_slice(&body).unwrap();
+    let blocks = |name: &str| {
+        entries.iter().find(|entry| entry["name"] == name).unwrap()["blocks"]
+            .as_u64()
+            .unwrap()
+    };
+
+    assert_eq!(blocks("empty.txt"), 0);
+    assert!(blocks("sparse.bin") < 64 * 1024 * 1024 / 512);
+    assert!(blocks("data.bin") >= 64 * 1024 / 512);
+}
+
+#[tokio::test]
+async fn test_listings_and_metadata_guess_content_types_from_extensions() {
+    let root = TestRoot::new("content-type");
+    std::fs::write(root.path.join("photo.JPG"), b"not really a jpeg").unwrap();
+    std::fs::write(root.path.join("notes.unknown"), b"").unwrap();
+    std::fs::create_dir(root.path.join("site.html")).unwrap();
+    let app = app_for_root(root.path());
+
+    let response = app
+        .clone()
+        .oneshot(
+            Request::builder()
+                .uri("/list/")
+                .body(Body::empty())
+                .unwrap(),
+        )
+        .await
+        .unwrap();
+    let body = to_bytes(response.into_body(), usize::MAX).await.unwrap();
+    let entries: Vec<serde_json::Value> = serde_json::from_slice(&body).unwrap();
+    let content_type = |name: &str| {
+        entries.iter().find(|entry| entry["name"] == name).unwrap()["content_type"].clone()
+    };
+    assert_eq!(content_type("photo.JPG"), "image/jpeg");
+    assert!(content_type("notes.unknown").is_null());
+    assert!(content_type("site.html").is_null());
+
+    let response = app
+        .oneshot(
+            Request::builder()
+                .uri("/metadata/photo.JPG")
+                .body(Body::empty())
+                .unwrap(),
+        )
+        .await
+        .unwrap();
+    let body = to_bytes(response.into_body(), usize::MAX).await.unwrap();
+    let metadata: serde_json::Value = serde_json::from_slice(&body).unwrap();
+    assert_eq!(metadata["content_type"], "image/jpeg");
+}
+
+#[cfg(unix)]
+#[tokio::test]
+async fn test_mknod_creates_files_and_fifos_but_not_devices() {
+    use std::os::unix::fs::{FileTypeExt, PermissionsExt};
+
+    let root = TestRoot::new("mknod");
+    let app = app_for_root(root.path());
+    let mknod = |path: &str, mode: u32| {
+        Request::builder()
+            .method(Method::POST)
+            .uri("/mknod")
+            .header("content-type", "application/json")
+            .body(Body::from(
+                json!({ "path": path, "mode": mode, "rdev": 0 }).to_string(),
+            ))
+            .unwrap()
+    };
+
+    let response = app.clone().oneshot(mknod("pipe", 0o010640)).await.unwrap();
+    assert_eq!(response.status(), StatusCode::CREATED);
+    let body = to_bytes(response.into_body(), usize::MAX).await.unwrap();
+    let metadata: serde_json::Value = serde_json::from_slice(&body).unwrap();
+    assert_eq!(metadata["type"], "fifo");
+    assert_eq!(metadata["mode"], 0o640);
+    let pipe = std::fs::symlink_metadata(root.path.join("pipe")).unwrap();
+    assert!(pipe.file_type().is_fifo());
+    assert_eq!(pipe.permissions().mode() & 0o7777, 0o640);
+
+    let response = app.clone().oneshot(mknod("plain", 0o100600)).await.unwrap();
+    assert_eq!(response.status(), StatusCode::CREATED);
+    assert!(root.path.join("plain").is_file());
+
+    // Device nodes are refused and existing names are never replaced.
+    let response = app
+        .clone()
+        .oneshot(mknod("device", 0o020600))
+        .await
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+    let response = app.clone().oneshot(mknod("pipe", 0o100600)).await.unwrap();
+    assert_eq!(response.status(), StatusCode::CONFLICT);
+
+    // FIFOs appear in listings, refuse content I/O, and can be deleted.
+    let list = Request::builder()
+        .uri("/list/")
+        .body(Body::empty())
+        .unwrap();
+    let body = to_bytes(
+        app.clone().oneshot(list).await.unwrap().into_body(),
+        usize::MAX,
+    )
+    .await
+    .unwrap();
+    let entries: serde_json::Value = serde_json::from_slice(&body).unwrap();
+    assert!(entries
+        .as_array()
+        .unwrap()
+        .iter()
+        .any(|entry| entry["name"] == "pipe" && entry["type"] == "fifo"));
+
+    let write = Request::builder()
+        .method(Method::PUT)
+        .uri("/files/pipe")
+        .body(Body::from("blocked"))
+        .unwrap();
+    assert_eq!(
+        app.clone().oneshot(write).await.unwrap().status(),
+        StatusCode::BAD_REQUEST
+    );
+    let delete = Request::builder()
+        .method(Method::DELETE)
+        .uri("/files/pipe")
+        .body(Body::empty())
+        .unwrap();
+    assert_eq!(
+        app.oneshot(delete).await.unwrap().status(),
+        StatusCode::NO_CONTENT
+    );
+    assert!(!root.path.join("pipe").exists());
+}
+
+#[cfg(unix)]
+#[tokio::test]
+async fn test_symlinks_are_created_listed_and_read_without_being_followed() {
+    // 1. Create a link that points outside the storage root.
+    let root = TestRoot::new("symlink");
+    let outside = TestRoot::new("symlink-outside");
+    std::fs::write(outside.path.join("secret.txt"), b"secret").unwrap();
+    let target = outside.path.join("secret.txt").display().to_string();
+    let app = app_for_root(root.path());
+
+    let create = Request::builder()
+        .method(Method::POST)
+        .uri("/symlink")
+        .header("content-type", "application/json")
+        .body(Body::from(
+            json!({ "path": "link", "target": target }).to_string(),
+        ))
+        .unwrap();
+    let response = app.clone().oneshot(create).await.unwrap();
+    assert_eq!(response.status(), StatusCode::CREATED);
+    let body = to_bytes(response.into_body(), usize::MAX).await.unwrap();
+    let metadata: serde_json::Value = serde_json::from_slice(&body).unwrap();
+    assert_eq!(metadata["type"], "symlink");
+
+    // 2. Listings and readlink report the stored target.
+    let list = Request::builder()
+        .uri("/list/")
+        .body(Body::empty())
+        .unwrap();
+    let body = to_bytes(
+        app.clone().oneshot(list).await.unwrap().into_body(),
+        usize::MAX,
+    )
+    .await
+    .unwrap();
+    let entries: serde_json::Value = serde_json::from_slice(&body).unwrap();
+    assert_eq!(entries[0]["name"], "link");
+    assert_eq!(entries[0]["type"], "symlink");
+    assert_eq!(entries[0]["target"], target.as_str());
+
+    let readlink = Request::builder()
+        .uri("/readlink/link")
+        .body(Body::empty())
+        .unwrap();
+    let response = app.clone().oneshot(readlink).await.unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+    let body = to_bytes(response.into_body(), usize::MAX).await.unwrap();
+    assert_eq!(body, target.as_bytes());
+
+    // 3. The server still refuses to follow the link for file contents.
+    let read = Request::builder()
+        .uri("/files/link")
+        .body(Body::empty())
+        .unwrap();
+    assert_eq!(
+        app.clone().oneshot(read).await.unwrap().status(),
+        StatusCode::FORBIDDEN
+    );
+
+    // 4. Deleting removes the link itself, never the target.
+    let delete = Request::builder()
+        .method(Method::DELETE)
+        .uri("/files/link")
+        .body(Body::empty())
+        .unwrap();
+    assert_eq!(
+        app.oneshot(delete).await.unwrap().status(),
+        StatusCode::NO_CONTENT
+    );
+    assert!(std::fs::symlink_metadata(root.path.join("link")).is_err());
+    assert!(outside.path.join("secret.txt").exists());
+}
+
+#[tokio::test]
+async fn test_list_depth_includes_descendants_with_relative_names() {
+    // 1. Build a three-level tree.
+    let root = TestRoot::new("list-depth");
+    std::fs::create_dir_all(root.path.join("a/b/c")).unwrap();
+    std::fs::write(root.path.join("a/one.txt"), b"1").unwrap();
+    std::fs::write(root.path.join("a/b/two.txt"), b"22").unwrap();
+    std::fs::write(root.path.join("a/b/c/three.txt"), b"333").unwrap();
+    let app = app_for_root(root.path());
+
+    // 2. Depth 2 below the root stops after the children of `a`.
+    let list = Request::builder()
+        .uri("/list/?depth=2")
+        .body(Body::empty())
+        .unwrap();
+    let body = to_bytes(
+        app.clone().oneshot(list).await.unwrap().into_body(),
+        usize::MAX,
+    )
+    .await
+    .unwrap();
+    let entries: Vec<serde_json::Value> = serde_json::from_slice(&body).unwrap();
+    let names: Vec<&str> = entries
+        .iter()
+        .map(|entry| entry["name"].as_str().unwrap())
+        .collect();
+    assert_eq!(names, ["a", "a/b", "a/one.txt"]);
+
+    // 3. Names stay relative to a nested listing.
+    let list = Request::builder()
+        .uri("/list/a/b?depth=3")
+        .body(Body::empty())
+        .unwrap();
+    let body = to_bytes(
+        app.clone().oneshot(list).await.unwrap().into_body(),
+        usize::MAX,
+    )
+    .await
+    .unwrap();
+    let entries: Vec<serde_json::Value> = serde_json::from_slice(&body).unwrap();
+    let names: Vec<&str> = entries
+        .iter()
+        .map(|entry| entry["name"].as_str().unwrap())
+        .collect();
+    assert_eq!(names, ["c", "c/three.txt", "two.txt"]);
+
+    // 4. Depth zero is rejected.
+    let list = Request::builder()
+        .uri("/list/?depth=0")
+        .body(Body::empty())
+        .unwrap();
+    assert_eq!(
+        app.oneshot(list).await.unwrap().status(),
+        StatusCode::BAD_REQUEST
+    );
+}
+
+#[tokio::test]
+async fn test_listings_are_compressed_only_when_requested() {
+    let root = TestRoot::new("compression");
+    for index in 0..20 {
+        std::fs::write(root.path.join(format!("file-{index}.txt")), b"").unwrap();
+    }
+    let app = app_for_root(root.path());
+
+    let gzip = Request::builder()
+        .uri("/list/")
+        .header("accept-encoding", "gzip")
+        .body(Body::empty())
+        .unwrap();
+    let response = app.clone().oneshot(gzip).await.unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+    assert_eq!(response.headers()["content-encoding"], "gzip");
+
+    let plain = Request::builder()
+        .uri("/list/")
+        .body(Body::empty())
+        .unwrap();
+    let response = app.oneshot(plain).await.unwrap();
+    assert!(!response.headers().contains_key("content-encoding"));
+    let body = to_bytes(response.into_body(), usize::MAX).await.unwrap();
+    let entries: Vec<serde_json::Value> = serde_json::from_slice(&body).unwrap();
+    assert_eq!(entries.len(), 20);
+}
+
+#[tokio::test]
+async fn test_list_pages_resume_after_the_cursor() {
+    // 1. Five files listed two at a time.
+    let root = TestRoot::new("list-pages");
+    for name in ["a", "b", "c", "d", "e"] {
+        std::fs::write(root.path.join(name), b"").unwrap();
+    }
+    let app = app_for_root(root.path());
+
+    let mut names = Vec::new();
+    let mut cursor: Option<String> = None;
+    let mut pages = 0;
+    loop {
+        let uri = match &cursor {
+            Some(cursor) => format!("/list/?limit=2&cursor={cursor}"),
+            None => "/list/?limit=2".to_string(),
+        };
+        let response = app
+            .clone()
+            .oneshot(Request::builder().uri(uri).body(Body::empty()).unwrap())
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+        cursor = response
+            .headers()
+            .get("X-Next-Cursor")
+            .map(|value| value.to_str().unwrap().to_string());
+        let body = to_bytes(response.into_body(), usize::MAX).await.unwrap();
+        let entries: Vec<serde_json::Value> = serde_json::from_slice(&body).unwrap();
+        names.extend(
+            entries
+                .iter()
+                .map(|entry| entry["name"].as_str().unwrap().to_string()),
+        );
+        pages += 1;
+        if cursor.is_none() {
+            break;
+        }
+
+        // 2. An entry created behind the cursor is neither repeated nor shifts later pages.
+        std::fs::write(root.path.join("0"), b"").unwrap();
+    }
+
+    assert_eq!(names, ["a", "b", "c", "d", "e"]);
+    assert_eq!(pages, 3);
+}
+
+// Fetches one `GET /list` page and returns its names and next cursor.
+async fn list_page(app: &Router, uri: String) -> (Vec<String>, Option<String>) {
+    let response = app
+        .clone()
+        .oneshot(Request::builder().uri(uri).body(Body::empty()).unwrap())
+        .await
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+    let cursor = response
+        .headers()
+        .get("X-Next-Cursor")
+        .map(|value| value.to_str().unwrap().to_string());
+    let body = to_bytes(response.into_body(), usize::MAX).await.unwrap();
+    let entries: Vec<serde_json::Value> = serde_json::from_slice(&body).unwrap();
+    let names = entries
+        .iter()
+        .map(|entry| entry["name"].as_str().unwrap().to_string())
+        .collect();
+    (names, cursor)
+}
+
+#[tokio::test]
+async fn test_later_list_pages_come_from_a_snapshot_until_the_directory_changes() {
+    // 1. The first page snapshots the rest of the listing.
+    let root = TestRoot::new("list-snapshots");
+    for name in ["a", "b", "c", "d", "e", "f"] {
+        std::fs::write(root.path.join(name), b"").unwrap();
+    }
+    let app = app_for_root(root.path());
+    let (names, cursor) = list_page(&app, "/list/?limit=2".to_string()).await;
+    assert_eq!(names, ["a", "b"]);
+
+    // 2. A change made behind the server's back is not read from disk.
+    std::fs::write(root.path.join("cc"), b"").unwrap();
+    let (names, cursor) =
+        list_page(&app, format!("/list/?limit=2&cursor={}", cursor.unwrap())).await;
+    assert_eq!(names, ["c", "d"]);
+
+    // 3. A change through the API drops the snapshot, so the rest is listed again.
+    let write = Request::builder()
+        .method(Method::PUT)
+        .uri("/files/ee")
+        .body(Body::empty())
+        .unwrap();
+    assert_eq!(
+        app.clone().oneshot(write).await.unwrap().status(),
+        StatusCode::OK
+    );
+    let (names, cursor) =
+        list_page(&app, format!("/list/?limit=2&cursor={}", cursor.unwrap())).await;
+    assert_eq!(names, ["e", "ee"]);
+    let (names, cursor) =
+        list_page(&app, format!("/list/?limit=2&cursor={}", cursor.unwrap())).await;
+    assert_eq!((names, cursor), (vec!["f".to_string()], None));
+}
+
+#[tokio::test]
+async fn test_names_lists_types_without_attributes_in_pages() {
+    // 1. A file, a directory, and a symbolic link.
+    let root = TestRoot::new("names");
+    std::fs::write(root.path.join("a.txt"), b"hello").unwrap();
+    std::fs::create_dir(root.path.join("docs")).unwrap();
+    #[cfg(unix)]
+    std::os::unix::fs::symlink("a.txt", root.path.join("link")).unwrap();
+    let app = app_for_root(root.path());
 
-    #[cfg(windows)]
-    {
-        let status = std::process::Command::new("cmd")
-            .args(["/C", "mklink", "/J"])
-            .arg(&link)
-            .arg(&outside.path)
-            .status()
+    let response = app
+        .clone()
+        .oneshot(
+            Request::builder()
+                .uri("/names/")
+                .body(Body::empty())
+                .unwrap(),
+        )
+        .await
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+    let body = to_bytes(response.into_body(), usize::MAX).await.unwrap();
+    let entries: serde_json::Value = serde_json::from_slice(&body).unwrap();
+    let mut expected = vec![
+        json!({"name": "a.txt", "type": "file"}),
+        json!({"name": "docs", "type": "directory"}),
+    ];
+    if cfg!(unix) {
+        expected.push(json!({"name": "link", "type": "symlink"}));
+    }
+    assert_eq!(entries, serde_json::Value::Array(expected));
+
+    // 2. Pages resume after the cursor, as listings do.
+    let response = app
+        .clone()
+        .oneshot(
+            Request::builder()
+                .uri("/names/?limit=1")
+                .body(Body::empty())
+                .unwrap(),
+        )
+        .await
+        .unwrap();
+    let cursor = response.headers()["X-Next-Cursor"]
+        .to_str()
+        .unwrap()
+        .to_string();
+    let response = app
+        .clone()
+        .oneshot(
+            Request::builder()
+                .uri(format!("/names/?limit=1&cursor={cursor}"))
+                .body(Body::empty())
+                .unwrap(),
+        )
+        .await
+        .unwrap();
+    let body = to_bytes(response.into_body(), usize::MAX).await.unwrap();
+    let entries: serde_json::Value = serde_json::from_slice(&body).unwrap();
+    assert_eq!(entries, json!([{"name": "docs", "type": "directory"}]));
+
+    // 3. Files and missing paths are not directories to enumerate.
+    for (uri, status) in [
+        ("/names/a.txt", StatusCode::BAD_REQUEST),
+        ("/names/missing", StatusCode::NOT_FOUND),
+    ] {
+        let response = app
+            .clone()
+            .oneshot(Request::builder().uri(uri).body(Body::empty()).unwrap())
+            .await
             .unwrap();
-        assert!(status.success(), "failed to create test junction");
+        assert_eq!(response.status(), status, "{uri}");
     }
+}
 
-    let state = AppState::with_auth(root.path(), None);
-    assert!(matches!(
-        state.resolve_path("escape/secret.txt"),
-        Err(StorageError::Forbidden(_))
-    ));
+#[tokio::test]
+async fn test_list_sort_orders_by_size_or_mtime_and_pages_by_that_order() {
+    // 1. Sizes and modification times that run against the name order.
+    let root = TestRoot::new("list-sort");
+    for (name, size, mtime) in [
+        ("a", 30, 300),
+        ("b", 10, 100),
+        ("c", 20, 100),
+        ("d", 10, 200),
+    ] {
+        let file = std::fs::File::create(root.path.join(name)).unwrap();
+        file.set_len(size).unwrap();
+        file.set_modified(std::time::UNIX_EPOCH + std::time::Duration::from_secs(mtime))
+            .unwrap();
+    }
+    let app = app_for_root(root.path());
+    let list = |uri: String| {
+        let app = app.clone();
+        async move {
+            let response = app
+                .oneshot(Request::builder().uri(uri).body(Body::empty()).unwrap())
+                .await
+                .unwrap();
+            let status = response.status();
+            let cursor = response
+                .headers()
+                .get("X-Next-Cursor")
+                .map(|value| value.to_str().unwrap().to_string());
+            let body = to_bytes(response.into_body(), usize::MAX).await.unwrap();
+            let names: Vec<String> = serde_json::from_slice::<Vec<serde_json::Value>>(&body)
+                .map(|entries| {
+                    entries
+                        .iter()
+                        .map(|entry| entry["name"].as_str().unwrap().to_string())
+                        .collect()
+                })
+                .unwrap_or_default();
+            (status, names, cursor)
+        }
+    };
+
+    // 2. Ties keep name order, so each order is the same on every request.
+    assert_eq!(
+        list("/list/?sort=name".into()).await.1,
+        ["a", "b", "c", "d"]
+    );
+    assert_eq!(
+        list("/list/?sort=size".into()).await.1,
+        ["b", "d", "c", "a"]
+    );
+    assert_eq!(
+        list("/list/?sort=mtime".into()).await.1,
+        ["b", "c", "d", "a"]
+    );
+
+    // 3. Pages follow the requested order.
+    let mut names = Vec::new();
+    let mut cursor: Option<String> = None;
+    loop {
+        let uri = match &cursor {
+            Some(cursor) => format!("/list/?sort=size&limit=3&cursor={cursor}"),
+            None => "/list/?sort=size&limit=3".to_string(),
+        };
+        let (status, page, next) = list(uri).await;
+        assert_eq!(status, StatusCode::OK);
+        names.extend(page);
+        cursor = next;
+        if cursor.is_none() {
+            break;
+        }
+    }
+    assert_eq!(names, ["b", "d", "c", "a"]);
+
+    // 4. Unknown orders are rejected.
+    assert_eq!(
+        list("/list/?sort=owner".into()).await.0,
+        StatusCode::BAD_REQUEST
+    );
+}
+
+#[tokio::test]
+async fn test_list_etag_answers_unchanged_listings_with_not_modified() {
+    // 1. A first listing carries an ETag.
+    let root = TestRoot::new("list-etag");
+    std::fs::write(root.path.join("a.txt"), b"a").unwrap();
+    let app = app_for_root(root.path());
+    let list = |etag: Option<&str>| {
+        let mut request = Request::builder().uri("/list/?limit=10");
+        if let Some(etag) = etag {
+            request = request.header("If-None-Match", etag);
+        }
+        request.body(Body::empty()).unwrap()
+    };
+
+    let response = app.clone().oneshot(list(None)).await.unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+    let etag = response.headers()["ETag"].to_str().unwrap().to_string();
+
+    // 2. Revalidating the same contents returns 304 with no body.
+    let response = app.clone().oneshot(list(Some(&etag))).await.unwrap();
+    assert_eq!(response.status(), StatusCode::NOT_MODIFIED);
+    assert_eq!(response.headers()["ETag"].to_str().unwrap(), etag);
+    let body = to_bytes(response.into_body(), usize::MAX).await.unwrap();
+    assert!(body.is_empty());
+
+    // 3. A new entry changes the tag and the full listing is sent again.
+    std::fs::write(root.path.join("b.txt"), b"b").unwrap();
+    let response = app.clone().oneshot(list(Some(&etag))).await.unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+    assert_ne!(response.headers()["ETag"].to_str().unwrap(), etag);
+    let body = to_bytes(response.into_body(), usize::MAX).await.unwrap();
+    let entries: Vec<serde_json::Value> = serde_json::from_slice(&body).unwrap();
+    assert_eq!(entries.len(), 2);
+}
+
+#[tokio::test]
+async fn test_stat_reports_one_entry_like_a_listing() {
+    // 1. Stat a file written through the API.
+    let root = TestRoot::new("stat");
+    std::fs::create_dir(root.path.join("docs")).unwrap();
+    std::fs::write(root.path.join("docs/a.txt"), b"hello").unwrap();
+    let app = app_for_root(root.path());
 
+    let stat = Request::builder()
+        .uri("/stat/docs/a.txt")
+        .body(Body::empty())
+        .unwrap();
+    let response = app.clone().oneshot(stat).await.unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+    let body = to_bytes(response.into_body(), usize::MAX).await.unwrap();
+    let entry: serde_json::Value = serde_json::from_slice(&body).unwrap();
+    assert_eq!(entry["name"], "a.txt");
+    assert_eq!(entry["type"], "file");
+    assert_eq!(entry["size"], 5);
+    assert!(entry["accessed_at"].is_string());
     #[cfg(unix)]
-    std::fs::remove_file(link).unwrap();
-    #[cfg(windows)]
-    std::fs::remove_dir(link).unwrap();
+    assert!(entry["changed_at"].is_string());
+
+    // 2. The root has its own route and reports a nameless directory.
+    let stat_root = Request::builder()
+        .uri("/stat/")
+        .body(Body::empty())
+        .unwrap();
+    let body = to_bytes(
+        app.clone().oneshot(stat_root).await.unwrap().into_body(),
+        usize::MAX,
+    )
+    .await
+    .unwrap();
+    let entry: serde_json::Value = serde_json::from_slice(&body).unwrap();
+    assert_eq!(entry["name"], "");
+    assert_eq!(entry["type"], "directory");
+
+    // 3. A missing path is reported as 404.
+    let missing = Request::builder()
+        .uri("/stat/docs/missing.txt")
+        .body(Body::empty())
+        .unwrap();
+    assert_eq!(
+        app.oneshot(missing).await.unwrap().status(),
+        StatusCode::NOT_FOUND
+    );
+}
+
+#[cfg(any(target_os = "linux", target_os = "macos"))]
+#[tokio::test]
+async fn test_xattrs_round_trip_through_the_api() {
+    let root = TestRoot::new("xattr");
+    std::fs::write(root.path.join("tagged.txt"), b"data").unwrap();
+    let app = app_for_root(root.path());
+    let send = |method: Method, uri: &str, body: &'static str| {
+        app.clone().oneshot(
+            Request::builder()
+                .method(method)
+                .uri(uri)
+                .body(Body::from(body))
+                .unwrap(),
+        )
+    };
+
+    // 1. Create-only writes refuse to replace an existing attribute.
+    let uri = "/xattr/tagged.txt?name=user.comment";
+    let created = send(Method::PUT, &format!("{uri}&flags=create"), "hello");
+    assert_eq!(created.await.unwrap().status(), StatusCode::NO_CONTENT);
+    let again = send(Method::PUT, &format!("{uri}&flags=create"), "again");
+    assert_eq!(again.await.unwrap().status(), StatusCode::CONFLICT);
+
+    // 2. The value and the name listing come back unchanged.
+    let response = send(Method::GET, uri, "").await.unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+    let body = to_bytes(response.into_body(), usize::MAX).await.unwrap();
+    assert_eq!(body, Bytes::from_static(b"hello"));
+
+    let response = send(Method::GET, "/xattr/tagged.txt", "").await.unwrap();
+    let body = to_bytes(response.into_body(), usize::MAX).await.unwrap();
+    let names: Vec<String> = serde_json::from_slice(&body).unwrap();
+    assert!(names.contains(&"user.comment".to_string()));
+
+    // 3. Removed attributes read as missing.
+    let removed = send(Method::DELETE, uri, "").await.unwrap();
+    assert_eq!(removed.status(), StatusCode::NO_CONTENT);
+    let missing = send(Method::GET, uri, "").await.unwrap();
+    assert_eq!(missing.status(), StatusCode::NOT_FOUND);
+}
+
+#[cfg(target_os = "linux")]
+#[tokio::test]
+async fn test_xattrs_outside_the_user_namespace_are_rejected() {
+    let root = TestRoot::new("xattr-namespace");
+    std::fs::write(root.path.join("file.txt"), b"data").unwrap();
+    let request = Request::builder()
+        .method(Method::PUT)
+        .uri("/xattr/file.txt?name=trusted.secret")
+        .body(Body::from("x"))
+        .unwrap();
+
+    assert_eq!(
+        app_for_root(root.path())
+            .oneshot(request)
+            .await
+            .unwrap()
+            .status(),
+        StatusCode::BAD_REQUEST
+    );
+}
+
+#[tokio::test]
+async fn test_errors_answer_with_a_json_code_and_message() {
+    let root = TestRoot::new("error-bodies");
+    let app = app_for_root(root.path());
+
+    // Handler errors, rejected queries, and unknown routes all share one shape.
+    for (uri, status, code) in [
+        ("/files/missing.txt", StatusCode::NOT_FOUND, "not_found"),
+        ("/list/?sort=bogus", StatusCode::BAD_REQUEST, "bad_request"),
+        (
+            "/no-such-endpoint",
+            StatusCode::NOT_FOUND,
+            "unknown_endpoint",
+        ),
+    ] {
+        let response = app
+            .clone()
+            .oneshot(Request::builder().uri(uri).body(Body::empty()).unwrap())
+            .await
+            .unwrap();
+        assert_eq!(response.status(), status, "{uri}");
+        assert_eq!(response.headers()["content-type"], "application/json");
+        let body = to_bytes(response.into_body(), usize::MAX).await.unwrap();
+        let error: serde_json::Value = serde_json::from_slice(&body).unwrap();
+        assert_eq!(error["error"], code, "{uri}");
+        assert!(!error["message"].as_str().unwrap().is_empty(), "{uri}");
+    }
 }
 
 #[tokio::test]
@@ -652,9 +2160,16 @@ async fn test_bearer_token_authentication() {
         .uri("/list/")
         .body(Body::empty())
         .unwrap();
+    let response = app.clone().oneshot(missing).await.unwrap();
+    assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
     assert_eq!(
-        app.clone().oneshot(missing).await.unwrap().status(),
-        StatusCode::UNAUTHORIZED
+        response.headers()["www-authenticate"],
+        "Bearer realm=\"remote-fs\""
+    );
+    let body = to_bytes(response.into_body(), usize::MAX).await.unwrap();
+    assert_eq!(
+        serde_json::from_slice::<serde_json::Value>(&body).unwrap(),
+        json!({"error": "unauthorized", "message": "Missing or invalid bearer token"})
     );
 
     let incorrect = Request::builder()
@@ -791,6 +2306,97 @@ async fn test_metadata_patch_updates_modification_time() {
     assert_eq!(modified_at, REQUESTED_MTIME);
 }
 
+#[tokio::test]
+async fn test_metadata_patch_updates_access_time_only() {
+    const REQUESTED_ATIME: u64 = 978_307_200;
+
+    let root = TestRoot::new("metadata-atime");
+    let path = root.path.join("atime.txt");
+    std::fs::write(&path, b"atime").unwrap();
+    let modified_before = std::fs::metadata(&path).unwrap().modified().unwrap();
+    let app = app_for_root(root.path());
+    let request = Request::builder()
+        .method(Method::PATCH)
+        .uri("/metadata/atime.txt")
+        .header("X-File-Atime", REQUESTED_ATIME.to_string())
+        .body(Body::empty())
+        .unwrap();
+
+    assert_eq!(app.oneshot(request).await.unwrap().status(), StatusCode::OK);
+    let metadata = std::fs::metadata(&path).unwrap();
+    let accessed_at = metadata
+        .accessed()
+        .unwrap()
+        .duration_since(UNIX_EPOCH)
+        .unwrap()
+        .as_secs();
+    assert_eq!(accessed_at, REQUESTED_ATIME);
+    assert_eq!(metadata.modified().unwrap(), modified_before);
+}
+
+#[cfg(target_os = "linux")]
+#[tokio::test]
+async fn test_no_atime_reads_leave_the_reported_access_time() {
+    const OLD_ATIME: u64 = 978_307_200;
+
+    let root = TestRoot::new("no-atime-read");
+    let app = app_for_root(root.path());
+    let directory = &root.path;
+    // Backdates the file's access time, reads it, and returns the access
+    // time reported afterwards.
+    let read_after_backdating = |name: &'static str, no_atime: bool| {
+        let app = app.clone();
+        async move {
+            std::fs::write(directory.join(name), b"unread").unwrap();
+            let backdate = Request::builder()
+                .method(Method::PATCH)
+                .uri(format!("/metadata/{name}"))
+                .header("X-File-Atime", OLD_ATIME.to_string())
+                .body(Body::empty())
+                .unwrap();
+            assert_eq!(
+                app.clone().oneshot(backdate).await.unwrap().status(),
+                StatusCode::OK
+            );
+
+            let mut read = Request::builder().uri(format!("/files/{name}"));
+            if no_atime {
+                read = read.header("X-No-Atime", "1");
+            }
+            let response = app
+                .clone()
+                .oneshot(read.body(Body::empty()).unwrap())
+                .await
+                .unwrap();
+            assert_eq!(
+                &to_bytes(response.into_body(), usize::MAX).await.unwrap()[..],
+                b"unread"
+            );
+
+            let request = Request::builder()
+                .uri(format!("/metadata/{name}"))
+                .body(Body::empty())
+                .unwrap();
+            let response = app.oneshot(request).await.unwrap();
+            let body = to_bytes(response.into_body(), usize::MAX).await.unwrap();
+            let metadata: serde_json::Value = serde_json::from_slice(&body).unwrap();
+            metadata["accessed_at"].as_str().unwrap().to_string()
+        }
+    };
+
+    // An access time older than the mtime is updated by any plain read,
+    // even on `relatime` mounts, but not on `noatime` ones, where there is
+    // nothing to show.
+    if read_after_backdating("control.txt", false).await == OLD_ATIME.to_string() {
+        eprintln!("skipping: plain reads leave access times alone on this filesystem");
+        return;
+    }
+    assert_eq!(
+        read_after_backdating("atime.txt", true).await,
+        OLD_ATIME.to_string()
+    );
+}
+
 #[tokio::test]
 async fn test_metadata_get_returns_one_path_without_listing_parent() {
     let root = TestRoot::new("metadata-get");
@@ -820,3 +2426,184 @@ async fn test_metadata_get_returns_one_path_without_listing_parent() {
         StatusCode::NOT_FOUND
     );
 }
+
+#[tokio::test]
+async fn test_range_read_past_eof_is_truncated_or_empty() {
+    let root = TestRoot::new("range-read-eof");
+    std::fs::write(root.path.join("short.txt"), b"0123456789").unwrap();
+    let app = app_for_root(root.path());
+
+    let overlapping = Request::builder()
+        .uri("/files/short.txt")
+        .header("X-File-Offset", "6")
+        .header("X-File-Size", "100")
+        .body(Body::empty())
+        .unwrap();
+    let response = app.clone().oneshot(overlapping).await.unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+    assert_eq!(
+        &to_bytes(response.into_body(), usize::MAX).await.unwrap()[..],
+        b"6789"
+    );
+
+    for offset in ["10", "4096"] {
+        let past_eof = Request::builder()
+            .uri("/files/short.txt")
+            .header("X-File-Offset", offset)
+            .header("X-File-Size", "16")
+            .body(Body::empty())
+            .unwrap();
+        let response = app.clone().oneshot(past_eof).await.unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+        assert!(to_bytes(response.into_body(), usize::MAX)
+            .await
+            .unwrap()
+            .is_empty());
+    }
+}
+
+#[tokio::test]
+async fn test_reads_carry_an_etag_that_if_match_holds_the_file_to() {
+    let root = TestRoot::new("read-if-match");
+    std::fs::write(root.path.join("a.txt"), b"0123456789").unwrap();
+    let app = app_for_root(root.path());
+    let read = |if_match: Option<&str>| {
+        let mut request = Request::builder()
+            .uri("/files/a.txt")
+            .header("X-File-Offset", "4")
+            .header("X-File-Size", "4");
+        if let Some(etag) = if_match {
+            request = request.header("If-Match", etag);
+        }
+        request.body(Body::empty()).unwrap()
+    };
+
+    let response = app.clone().oneshot(read(None)).await.unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+    let etag = response.headers()["etag"].to_str().unwrap().to_string();
+    let response = app.clone().oneshot(read(Some(&etag))).await.unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+    assert_eq!(response.headers()["etag"], etag.as_str());
+    assert_eq!(
+        &to_bytes(response.into_body(), usize::MAX).await.unwrap()[..],
+        b"4567"
+    );
+
+    std::fs::write(root.path.join("a.txt"), b"0123456789abcdef").unwrap();
+    let response = app.clone().oneshot(read(Some(&etag))).await.unwrap();
+    assert_eq!(response.status(), StatusCode::PRECONDITION_FAILED);
+    let response = app.oneshot(read(Some("*"))).await.unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+}
+
+#[tokio::test]
+async fn test_standard_range_header_returns_partial_content() {
+    let root = TestRoot::new("http-range");
+    std::fs::write(root.path.join("letters.txt"), b"abcdefghijklmnopqrstuvwxyz").unwrap();
+    let app = app_for_root(root.path());
+
+    let partial = Request::builder()
+        .uri("/files/letters.txt")
+        .header("range", "bytes=5-8")
+        .body(Body::empty())
+        .unwrap();
+    let response = app.clone().oneshot(partial).await.unwrap();
+    assert_eq!(response.status(), StatusCode::PARTIAL_CONTENT);
+    assert_eq!(
+        response.headers().get("content-range").unwrap(),
+        "bytes 5-8/26"
+    );
+    assert_eq!(
+        &to_bytes(response.into_body(), usize::MAX).await.unwrap()[..],
+        b"fghi"
+    );
+
+    let unsatisfiable = Request::builder()
+        .uri("/files/letters.txt")
+        .header("range", "bytes=26-")
+        .body(Body::empty())
+        .unwrap();
+    let response = app.clone().oneshot(unsatisfiable).await.unwrap();
+    assert_eq!(response.status(), StatusCode::RANGE_NOT_SATISFIABLE);
+    assert_eq!(
+        response.headers().get("content-range").unwrap(),
+        "bytes */26"
+    );
+
+    let whole = Request::builder()
+        .uri("/files/letters.txt")
+        .body(Body::empty())
+        .unwrap();
+    let response = app.oneshot(whole).await.unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+    assert_eq!(response.headers().get("accept-ranges").unwrap(), "bytes");
+}
+
+#[tokio::test]
+async fn test_delete_file_rejects_directory_with_conflict() {
+    let root = TestRoot::new("delete-file-directory");
+    std::fs::create_dir(root.path.join("docs")).unwrap();
+
+    let app = app_for_root(root.path());
+    let request = Request::builder()
+        .method(Method::DELETE)
+        .uri("/files/docs")
+        .body(Body::empty())
+        .unwrap();
+    assert_eq!(
+        app.clone().oneshot(request).await.unwrap().status(),
+        StatusCode::CONFLICT
+    );
+    assert!(root.path.join("docs").is_dir());
+
+    let missing = Request::builder()
+        .method(Method::DELETE)
+        .uri("/files/missing.txt")
+        .body(Body::empty())
+        .unwrap();
+    assert_eq!(
+        app.oneshot(missing).await.unwrap().status(),
+        StatusCode::NOT_FOUND
+    );
+}
+
+#[cfg(unix)]
+#[tokio::test]
+async fn test_non_utf8_names_are_listed_escaped_and_addressable() {
+    use std::os::unix::ffi::OsStrExt;
+
+    let root = TestRoot::new("non-utf8-names");
+    let raw_name = std::ffi::OsStr::from_bytes(b"caf\xe9.txt");
+    std::fs::write(root.path.join(raw_name), "latin-1").unwrap();
+
+    let app = app_for_root(root.path());
+    let list = Request::builder()
+        .uri("/list/")
+        .body(Body::empty())
+        .unwrap();
+    let response = app.clone().oneshot(list).await.unwrap();
+    let body = to_bytes(response.into_body(), usize::MAX).await.unwrap();
+    let entries: serde_json::Value = serde_json::from_slice(&body).unwrap();
+    assert_eq!(entries[0]["name"], "\u{0}636166e92e747874");
+    assert_eq!(entries[0]["name_encoding"], "nul-hex");
+
+    let read = Request::builder()
+        .uri("/files/%00636166e92e747874")
+        .body(Body::empty())
+        .unwrap();
+    let response = app.clone().oneshot(read).await.unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+    let body = to_bytes(response.into_body(), usize::MAX).await.unwrap();
+    assert_eq!(&body[..], b"latin-1");
+
+    let rename = Request::builder()
+        .method(Method::POST)
+        .uri("/rename")
+        .header("content-type", "application/json")
+        .body(Body::from(
+            json!({"from": "\u{0}636166e92e747874", "to": "cafe.txt"}).to_string(),
+        ))
+        .unwrap();
+    assert_eq!(app.oneshot(rename).await.unwrap().status(), StatusCode::OK);
+    assert!(root.path.join("cafe.txt").is_file());
+}
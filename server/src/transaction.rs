@@ -1,13 +1,18 @@
-//! Atomic create-only commits.
+//! Atomic create-only commits and staged replacements.
 //!
 //! Invariant: a conditional create is never visible at its destination until
 //! its complete body and metadata have been synced. A destination that already
 //! exists is never overwritten.
+//!
+//! A staged write (`/write-temp`) collects its bytes in a private file and is
+//! renamed over its destination on commit, so readers see either the old or
+//! the complete new contents. A destination with other hard links is instead
+//! overwritten in place, since a rename would leave those names behind.
 
 use crate::{
     error::StorageError,
     metadata::{apply_metadata_headers, entry_metadata_for_path},
-    AppState,
+    xattr, AppState,
 };
 use axum::{
     body::Body,
@@ -17,13 +22,14 @@ use axum::{
 };
 use futures_util::StreamExt;
 use std::{
-    io,
+    io::{self, SeekFrom},
     path::{Path, PathBuf},
     sync::atomic::{AtomicU64, Ordering},
+    time::{Duration, Instant},
 };
 use tokio::{
     fs::{self, OpenOptions},
-    io::AsyncWriteExt,
+    io::{AsyncSeekExt, AsyncWriteExt},
 };
 
 static TRANSACTION_COUNTER: AtomicU64 = AtomicU64::new(0);
@@ -66,6 +72,7 @@ pub(crate) async fn create_file_atomically(
         .map_err(|error| StorageError::from_io(error, "Could not create transaction file"))?;
 
     let mut body_stream = body.into_data_stream();
+    let mut bytes_received = 0_u64;
     while let Some(chunk) = body_stream.next().await {
         let chunk = match chunk {
             Ok(chunk) => chunk,
@@ -75,6 +82,12 @@ pub(crate) async fn create_file_atomically(
                 return Err(StorageError::RequestBody("Could not read request body"));
             }
         };
+        bytes_received += chunk.len() as u64;
+        if let Err(error) = state.check_file_size(bytes_received) {
+            drop(transaction_file);
+            let _ = fs::remove_file(&transaction_path).await;
+            return Err(error);
+        }
         if let Err(error) = transaction_file.write_all(&chunk).await {
             drop(transaction_file);
             let _ = fs::remove_file(&transaction_path).await;
@@ -168,6 +181,300 @@ pub(crate) async fn create_file_atomically(
     }
 
     let metadata = entry_metadata_for_path(file_path).await?;
+    state.publish_change(path);
     log::info!("Atomically created /{}", path.trim_matches('/'));
     Ok((StatusCode::CREATED, Json(metadata)).into_response())
 }
+
+const TEMP_WRITE_PREFIX: &str = "write-";
+// A staged write no request has used for this long is taken for abandoned,
+// such as by a client that crashed, and discarded.
+pub(crate) const TEMP_WRITE_IDLE_TIMEOUT: Duration = Duration::from_secs(60 * 60);
+
+// A staged write's destination path, and when a request last used it.
+pub(crate) struct TempWrite {
+    path: String,
+    last_used: Instant,
+}
+
+fn temp_write_path(state: &AppState, handle: &str) -> PathBuf {
+    state
+        .transaction_dir
+        .join(format!("{TEMP_WRITE_PREFIX}{handle}.tmp"))
+}
+
+// Staged writes live only in server memory, so their files are orphaned by a
+// restart and are removed when the next server starts.
+pub(crate) fn remove_abandoned_temp_writes(transaction_dir: &Path) {
+    let Ok(entries) = std::fs::read_dir(transaction_dir) else {
+        return;
+    };
+    for entry in entries.flatten() {
+        let name = entry.file_name();
+        if name.to_string_lossy().starts_with(TEMP_WRITE_PREFIX) {
+            if let Err(error) = std::fs::remove_file(entry.path()) {
+                log::warn!("Could not remove abandoned staged write {name:?}: {error}");
+            }
+        }
+    }
+}
+
+// Discards staged writes idle for `TEMP_WRITE_IDLE_TIMEOUT` at `now`. Runs
+// whenever a staged write begins, so abandoned ones do not pile up until the
+// next restart.
+pub(crate) fn expire_idle_temp_writes(state: &AppState, now: Instant) {
+    let expired: Vec<String> = {
+        let mut temp_writes = state.temp_writes.lock().unwrap();
+        let expired = temp_writes
+            .iter()
+            .filter(|(_, temp_write)| {
+                now.saturating_duration_since(temp_write.last_used) >= TEMP_WRITE_IDLE_TIMEOUT
+            })
+            .map(|(handle, _)| handle.clone())
+            .collect::<Vec<_>>();
+        for handle in &expired {
+            temp_writes.remove(handle);
+        }
+        expired
+    };
+    for handle in expired {
+        log::warn!("Discarding staged write {handle}, unused for {TEMP_WRITE_IDLE_TIMEOUT:?}");
+        if let Err(error) = std::fs::remove_file(temp_write_path(state, &handle)) {
+            log::warn!("Could not remove staged write {handle}: {error}");
+        }
+    }
+}
+
+// Marks a staged write used now; `false` when there is no such handle.
+fn touch_temp_write(state: &AppState, handle: &str) -> bool {
+    match state.temp_writes.lock().unwrap().get_mut(handle) {
+        Some(temp_write) => {
+            temp_write.last_used = Instant::now();
+            true
+        }
+        None => false,
+    }
+}
+
+// The destination is only checked here; it is resolved again on commit.
+pub(crate) async fn begin_temp_write(state: &AppState, path: &str) -> Result<String, StorageError> {
+    expire_idle_temp_writes(state, Instant::now());
+    let file_path = state.resolve_non_root_path(path)?;
+    if fs::symlink_metadata(&file_path)
+        .await
+        .is_ok_and(|metadata| !metadata.is_file())
+    {
+        return Err(StorageError::BadRequest("Path is not a file"));
+    }
+
+    let handle = format!(
+        "{}-{}",
+        std::process::id(),
+        TRANSACTION_COUNTER.fetch_add(1, Ordering::Relaxed)
+    );
+    OpenOptions::new()
+        .create_new(true)
+        .write(true)
+        .open(temp_write_path(state, &handle))
+        .await
+        .map_err(|error| StorageError::from_io(error, "Could not create staged write"))?;
+    state.temp_writes.lock().unwrap().insert(
+        handle.clone(),
+        TempWrite {
+            path: path.trim_matches('/').to_string(),
+            last_used: Instant::now(),
+        },
+    );
+    Ok(handle)
+}
+
+// Writes at `offset`, or after the bytes already staged when it is `None`.
+// With `truncate` the staged file is resized to it and the body is ignored.
+pub(crate) async fn append_temp_write(
+    state: &AppState,
+    handle: &str,
+    offset: Option<u64>,
+    truncate: Option<u64>,
+    body: Body,
+) -> Result<(), StorageError> {
+    if !touch_temp_write(state, handle) {
+        return Err(StorageError::NotFound("Staged write not found"));
+    }
+    let mut file = OpenOptions::new()
+        .write(true)
+        .open(temp_write_path(state, handle))
+        .await
+        .map_err(|error| StorageError::from_io(error, "Staged write not found"))?;
+    if let Some(size) = truncate {
+        state.check_file_size(size)?;
+        return file
+            .set_len(size)
+            .await
+            .map_err(|error| StorageError::from_io(error, "Could not resize staged write"));
+    }
+    let position = match offset {
+        Some(offset) => SeekFrom::Start(offset),
+        None => SeekFrom::End(0),
+    };
+    let mut end = file
+        .seek(position)
+        .await
+        .map_err(|error| StorageError::from_io(error, "Could not seek staged write"))?;
+
+    let mut body_stream = body.into_data_stream();
+    while let Some(chunk) = body_stream.next().await {
+        let chunk = chunk.map_err(|_| StorageError::RequestBody("Could not read request body"))?;
+        end += chunk.len() as u64;
+        state.check_file_size(end)?;
+        file.write_all(&chunk)
+            .await
+            .map_err(|error| StorageError::from_io(error, "Could not write staged write"))?;
+    }
+    file.flush()
+        .await
+        .map_err(|error| StorageError::from_io(error, "Could not write staged write"))
+}
+
+pub(crate) async fn abort_temp_write(state: &AppState, handle: &str) -> Result<(), StorageError> {
+    if state.temp_writes.lock().unwrap().remove(handle).is_none() {
+        return Err(StorageError::NotFound("Staged write not found"));
+    }
+    fs::remove_file(temp_write_path(state, handle))
+        .await
+        .map_err(|error| StorageError::from_io(error, "Staged write not found"))
+}
+
+// Syncs the staged bytes and renames them over the destination. A replaced
+// file's permission bits, ownership, and extended attributes carry over to
+// the new contents; one with other hard links keeps its inode instead.
+pub(crate) async fn commit_temp_write(
+    state: &AppState,
+    handle: &str,
+) -> Result<Response, StorageError> {
+    let Some(TempWrite { path, .. }) = state.temp_writes.lock().unwrap().remove(handle) else {
+        return Err(StorageError::NotFound("Staged write not found"));
+    };
+    let temp_path = temp_write_path(state, handle);
+    let result = commit_staged_file(state, &path, &temp_path).await;
+    if result.is_err() {
+        let _ = fs::remove_file(&temp_path).await;
+    }
+    result
+}
+
+async fn commit_staged_file(
+    state: &AppState,
+    path: &str,
+    temp_path: &Path,
+) -> Result<Response, StorageError> {
+    let file_path = state.resolve_non_root_path(path)?;
+    let parent = file_path
+        .parent()
+        .ok_or(StorageError::BadRequest("Path has no parent directory"))?;
+
+    let _mutation_guard = state.mutation_lock.lock().await;
+    let existing = match fs::symlink_metadata(&file_path).await {
+        Ok(metadata) if !metadata.is_file() => {
+            return Err(StorageError::BadRequest("Path is not a file"));
+        }
+        Ok(metadata) => Some(metadata),
+        Err(error) if error.kind() == io::ErrorKind::NotFound => None,
+        Err(error) => {
+            return Err(StorageError::from_io(
+                error,
+                "Could not inspect destination path",
+            ));
+        }
+    };
+    if let Some(metadata) = &existing {
+        if link_count(metadata) > 1 {
+            overwrite_in_place(temp_path, &file_path).await?;
+            let _ = fs::remove_file(temp_path).await;
+            let metadata = entry_metadata_for_path(&file_path).await?;
+            state.publish_change(path);
+            log::info!("Committed staged write to /{path} in place, keeping its hard links");
+            return Ok((StatusCode::OK, Json(metadata)).into_response());
+        }
+        carry_over_attributes(temp_path, &file_path, metadata).await?;
+    }
+    OpenOptions::new()
+        .write(true)
+        .open(temp_path)
+        .await
+        .map_err(|error| StorageError::from_io(error, "Staged write not found"))?
+        .sync_all()
+        .await
+        .map_err(|error| StorageError::from_io(error, "Could not sync staged write"))?;
+
+    fs::create_dir_all(parent)
+        .await
+        .map_err(|error| StorageError::from_io(error, "Could not create parent directory"))?;
+    fs::rename(temp_path, &file_path)
+        .await
+        .map_err(|error| StorageError::from_io(error, "Could not commit staged write"))?;
+    sync_directory(parent.to_path_buf()).await?;
+
+    let metadata = entry_metadata_for_path(&file_path).await?;
+    state.publish_change(path);
+    log::info!("Committed staged write to /{path}");
+    Ok((StatusCode::OK, Json(metadata)).into_response())
+}
+
+// Copies the staged bytes into the destination's own inode. Readers may see
+// it part written, which is the price of keeping its other names.
+async fn overwrite_in_place(temp_path: &Path, file_path: &Path) -> Result<(), StorageError> {
+    let (temp_path, file_path) = (temp_path.to_path_buf(), file_path.to_path_buf());
+    tokio::task::spawn_blocking(move || {
+        let mut source = std::fs::File::open(&temp_path)?;
+        let mut destination = std::fs::OpenOptions::new()
+            .write(true)
+            .truncate(true)
+            .open(&file_path)?;
+        io::copy(&mut source, &mut destination)?;
+        destination.sync_all()
+    })
+    .await
+    .map_err(|error| StorageError::Io(io::Error::other(error)))?
+    .map_err(|error| StorageError::from_io(error, "Could not commit staged write"))
+}
+
+// Gives the staged file the permission bits, owner, group, and extended
+// attributes of the file it replaces. A server that may not give files away
+// keeps its own ownership, as a plain write by it would.
+async fn carry_over_attributes(
+    temp_path: &Path,
+    file_path: &Path,
+    metadata: &std::fs::Metadata,
+) -> Result<(), StorageError> {
+    fs::set_permissions(temp_path, metadata.permissions())
+        .await
+        .map_err(|error| StorageError::from_io(error, "Could not copy permissions"))?;
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::MetadataExt;
+
+        if let Err(error) =
+            std::os::unix::fs::chown(temp_path, Some(metadata.uid()), Some(metadata.gid()))
+        {
+            log::debug!(
+                "Could not keep the ownership of {}: {error}",
+                file_path.display()
+            );
+        }
+    }
+    for name in xattr::list(file_path)? {
+        let value = xattr::get(file_path, &name)?;
+        xattr::set(temp_path, &name, &value, xattr::SetMode::Upsert)?;
+    }
+    Ok(())
+}
+
+#[cfg(unix)]
+fn link_count(metadata: &std::fs::Metadata) -> u64 {
+    std::os::unix::fs::MetadataExt::nlink(metadata)
+}
+
+#[cfg(not(unix))]
+fn link_count(_metadata: &std::fs::Metadata) -> u64 {
+    1
+}
@@ -0,0 +1,256 @@
+#[cfg(any(target_os = "linux", target_os = "macos"))]
+pub(crate) use native::{get, list, remove, set};
+#[cfg(not(any(target_os = "linux", target_os = "macos")))]
+use {crate::error::StorageError, std::path::Path};
+
+// How `set` treats an attribute that may or may not exist yet.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub(crate) enum SetMode {
+    Upsert,
+    Create,
+    Replace,
+}
+
+// Linux servers expose only the unprivileged `user.` namespace, so a client
+// can never read or change `security.*` or `trusted.*` entries even when the
+// server runs as root. Other platforms have no privileged namespaces.
+fn is_exposed_name(name: &str) -> bool {
+    !name.is_empty()
+        && !name.contains('\0')
+        && (cfg!(not(target_os = "linux")) || name.starts_with("user."))
+}
+
+// Platforms without an xattr syscall layer report every attribute as missing
+// and refuse writes, which clients surface as ENODATA / ENOTSUP.
+#[cfg(not(any(target_os = "linux", target_os = "macos")))]
+pub(crate) fn get(_path: &Path, _name: &str) -> Result<Vec<u8>, StorageError> {
+    Err(StorageError::NotFound("Attribute not found"))
+}
+
+#[cfg(not(any(target_os = "linux", target_os = "macos")))]
+pub(crate) fn list(_path: &Path) -> Result<Vec<String>, StorageError> {
+    Ok(Vec::new())
+}
+
+#[cfg(not(any(target_os = "linux", target_os = "macos")))]
+pub(crate) fn set(
+    _path: &Path,
+    _name: &str,
+    _value: &[u8],
+    _mode: SetMode,
+) -> Result<(), StorageError> {
+    Err(StorageError::BadRequest(
+        "Extended attributes are not supported",
+    ))
+}
+
+#[cfg(not(any(target_os = "linux", target_os = "macos")))]
+pub(crate) fn remove(_path: &Path, _name: &str) -> Result<(), StorageError> {
+    Err(StorageError::NotFound("Attribute not found"))
+}
+
+#[cfg(any(target_os = "linux", target_os = "macos"))]
+mod native {
+    use super::{is_exposed_name, SetMode};
+    use crate::error::StorageError;
+    use std::{io, path::Path};
+
+    pub(crate) fn get(path: &Path, name: &str) -> Result<Vec<u8>, StorageError> {
+        if !is_exposed_name(name) {
+            return Err(StorageError::NotFound("Attribute not found"));
+        }
+        let (path, name) = (c_path(path)?, c_name(name)?);
+        read_sized(|buffer| sys::get(&path, &name, buffer)).map_err(storage_error)
+    }
+
+    pub(crate) fn list(path: &Path) -> Result<Vec<String>, StorageError> {
+        let path = c_path(path)?;
+        let names = read_sized(|buffer| sys::list(&path, buffer)).map_err(storage_error)?;
+
+        Ok(names
+            .split(|byte| *byte == 0)
+            .filter_map(|name| std::str::from_utf8(name).ok())
+            .filter(|name| is_exposed_name(name))
+            .map(str::to_string)
+            .collect())
+    }
+
+    pub(crate) fn set(
+        path: &Path,
+        name: &str,
+        value: &[u8],
+        mode: SetMode,
+    ) -> Result<(), StorageError> {
+        if !is_exposed_name(name) {
+            return Err(StorageError::BadRequest("Unsupported attribute namespace"));
+        }
+        let (path, name) = (c_path(path)?, c_name(name)?);
+        sys::set(&path, &name, value, mode).map_err(storage_error)
+    }
+
+    pub(crate) fn remove(path: &Path, name: &str) -> Result<(), StorageError> {
+        if !is_exposed_name(name) {
+            return Err(StorageError::NotFound("Attribute not found"));
+        }
+        let (path, name) = (c_path(path)?, c_name(name)?);
+        sys::remove(&path, &name).map_err(storage_error)
+    }
+
+    // Sizes the buffer with an empty probe first and retries if the value grew
+    // between the two calls.
+    fn read_sized(mut read: impl FnMut(&mut [u8]) -> io::Result<usize>) -> io::Result<Vec<u8>> {
+        loop {
+            let mut buffer = vec![0; read(&mut [])?];
+            match read(&mut buffer) {
+                Ok(len) => {
+                    buffer.truncate(len);
+                    return Ok(buffer);
+                }
+                Err(error) if error.raw_os_error() == Some(libc::ERANGE) => continue,
+                Err(error) => return Err(error),
+            }
+        }
+    }
+
+    fn storage_error(error: io::Error) -> StorageError {
+        match error.raw_os_error() {
+            Some(code) if code == sys::MISSING_ATTRIBUTE => {
+                StorageError::NotFound("Attribute not found")
+            }
+            Some(code) if code == libc::ENOTSUP || code == libc::EOPNOTSUPP => {
+                StorageError::BadRequest("Extended attributes are not supported")
+            }
+            Some(libc::E2BIG | libc::ERANGE | libc::ENOSPC) => {
+                StorageError::BadRequest("Attribute value is too large")
+            }
+            _ if error.kind() == io::ErrorKind::Unsupported => {
+                StorageError::BadRequest("Extended attributes are not supported")
+            }
+            _ => StorageError::from_io(error, "Path not found"),
+        }
+    }
+
+    fn c_path(path: &Path) -> Result<std::ffi::CString, StorageError> {
+        use std::os::unix::ffi::OsStrExt;
+
+        std::ffi::CString::new(path.as_os_str().as_bytes())
+            .map_err(|_| StorageError::BadRequest("Invalid path"))
+    }
+
+    fn c_name(name: &str) -> Result<std::ffi::CString, StorageError> {
+        std::ffi::CString::new(name).map_err(|_| StorageError::BadRequest("Invalid attribute name"))
+    }
+
+    fn result_len(result: isize) -> io::Result<usize> {
+        usize::try_from(result).map_err(|_| io::Error::last_os_error())
+    }
+
+    fn result_unit(result: libc::c_int) -> io::Result<()> {
+        if result == 0 {
+            Ok(())
+        } else {
+            Err(io::Error::last_os_error())
+        }
+    }
+
+    fn set_flags(mode: SetMode) -> libc::c_int {
+        match mode {
+            SetMode::Upsert => 0,
+            SetMode::Create => libc::XATTR_CREATE,
+            SetMode::Replace => libc::XATTR_REPLACE,
+        }
+    }
+
+    // The `l*` variants never follow a symbolic link in the final component.
+    #[cfg(target_os = "linux")]
+    mod sys {
+        use super::{result_len, result_unit, set_flags, SetMode};
+        use std::{ffi::CStr, io};
+
+        pub(super) const MISSING_ATTRIBUTE: i32 = libc::ENODATA;
+
+        pub(super) fn get(path: &CStr, name: &CStr, buffer: &mut [u8]) -> io::Result<usize> {
+            result_len(unsafe {
+                libc::lgetxattr(
+                    path.as_ptr(),
+                    name.as_ptr(),
+                    buffer.as_mut_ptr().cast(),
+                    buffer.len(),
+                )
+            })
+        }
+
+        pub(super) fn list(path: &CStr, buffer: &mut [u8]) -> io::Result<usize> {
+            result_len(unsafe {
+                libc::llistxattr(path.as_ptr(), buffer.as_mut_ptr().cast(), buffer.len())
+            })
+        }
+
+        pub(super) fn set(path: &CStr, name: &CStr, value: &[u8], mode: SetMode) -> io::Result<()> {
+            result_unit(unsafe {
+                libc::lsetxattr(
+                    path.as_ptr(),
+                    name.as_ptr(),
+                    value.as_ptr().cast(),
+                    value.len(),
+                    set_flags(mode),
+                )
+            })
+        }
+
+        pub(super) fn remove(path: &CStr, name: &CStr) -> io::Result<()> {
+            result_unit(unsafe { libc::lremovexattr(path.as_ptr(), name.as_ptr()) })
+        }
+    }
+
+    #[cfg(target_os = "macos")]
+    mod sys {
+        use super::{result_len, result_unit, set_flags, SetMode};
+        use std::{ffi::CStr, io};
+
+        pub(super) const MISSING_ATTRIBUTE: i32 = libc::ENOATTR;
+
+        pub(super) fn get(path: &CStr, name: &CStr, buffer: &mut [u8]) -> io::Result<usize> {
+            result_len(unsafe {
+                libc::getxattr(
+                    path.as_ptr(),
+                    name.as_ptr(),
+                    buffer.as_mut_ptr().cast(),
+                    buffer.len(),
+                    0,
+                    libc::XATTR_NOFOLLOW,
+                )
+            })
+        }
+
+        pub(super) fn list(path: &CStr, buffer: &mut [u8]) -> io::Result<usize> {
+            result_len(unsafe {
+                libc::listxattr(
+                    path.as_ptr(),
+                    buffer.as_mut_ptr().cast(),
+                    buffer.len(),
+                    libc::XATTR_NOFOLLOW,
+                )
+            })
+        }
+
+        pub(super) fn set(path: &CStr, name: &CStr, value: &[u8], mode: SetMode) -> io::Result<()> {
+            result_unit(unsafe {
+                libc::setxattr(
+                    path.as_ptr(),
+                    name.as_ptr(),
+                    value.as_ptr().cast(),
+                    value.len(),
+                    0,
+                    libc::XATTR_NOFOLLOW | set_flags(mode),
+                )
+            })
+        }
+
+        pub(super) fn remove(path: &CStr, name: &CStr) -> io::Result<()> {
+            result_unit(unsafe {
+                libc::removexattr(path.as_ptr(), name.as_ptr(), libc::XATTR_NOFOLLOW)
+            })
+        }
+    }
+}